@@ -1,29 +1,87 @@
 use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::time::Instant;
 use std::{time::Duration};
 
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::event::{Event, KeyEvent, KeyEventKind, MouseEvent, MouseEventKind, EnableMouseCapture, DisableMouseCapture, EnableBracketedPaste, DisableBracketedPaste};
 use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen, self};
+use crossterm::cursor::Show;
 use crossterm::{event, queue};
 
-use crate::renderer::{Renderer};
+use crate::args::{FileArg, FilePosition};
+use crate::buffer::Point;
+use crate::document::{Document, Indent};
+use crate::clipboard::Clipboard;
+use crate::command::Command;
+use crate::config::Config;
+use crate::editor::register::Registers;
+use crate::editor::status_line::StatusLine;
+use crate::editor::undo_store::UndoStore;
+use crate::editor::gutter::Gutter;
+use crate::editor::view::EditorView;
+use crate::keymap::{Keymap, KeyResult, PendingKeys, GLOBAL_CONTEXT};
+use crate::keymap::chord::KeyChord;
+use crate::renderer::{Renderer, Rect, ResetCursorShape};
+use crate::renderer::background::Background;
+use crate::syntax::SyntaxSet;
+use crate::theme::{Theme, DEFAULT_DARK_THEME, DEFAULT_LIGHT_THEME, DEFAULT_THEME};
+use crate::tui::event::{self as node_event, NodeMouseEvent, NodeEvent};
 use crate::tui::node::Node;
 use crate::tui::style::Style;
 use crate::tui::style::border::Border;
 use crate::tui::style::display::{Display, FlexDirection};
 use crate::tui::style::flex::Flex;
-use crate::tui::style::position::{Position2D, Position};
 use crate::tui::style::size::{Size2D, Size};
+use crate::workspace_search::replace::FileReplace;
+use crate::workspace_search::results::SearchResults;
 
 const FPS: u64 = 60;
+const RESIZE_STEP: i32 = 2;
+/// How long to wait for the terminal to say what its background is.
+const BACKGROUND_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// An editor view and the nodes it is drawn in: its own, which takes
+/// focus, and the gutter's beside it, inside a frame laid out with the
+/// other editors.
+struct Editor {
+    frame: Rc<RefCell<Node>>,
+    node: Rc<RefCell<Node>>,
+    view: Rc<RefCell<EditorView>>,
+    gutter_node: Rc<RefCell<Node>>,
+    gutter: Rc<RefCell<Gutter>>,
+}
+
+/// The workspace search results and the node they are drawn in.
+struct Results {
+    node: Rc<RefCell<Node>>,
+    widget: Rc<RefCell<SearchResults>>,
+}
 
 pub struct App {
-    root_ui: Node,
+    root_ui: Rc<RefCell<Node>>,
+    main_ui: Rc<RefCell<Node>>,
     renderer: Rc<RefCell<Renderer>>,
     term_size: (u16, u16),
     running: bool,
-    last: Instant
+    last: Instant,
+    hover_path: Vec<Rc<RefCell<Node>>>,
+    mouse_capture: Option<Rc<RefCell<Node>>>,
+    focused: Option<Rc<RefCell<Node>>>,
+    config: Config,
+    keymap: Keymap,
+    syntax_set: SyntaxSet,
+    theme: Theme,
+    pending_keys: PendingKeys,
+    editors: Vec<Editor>,
+    results: Option<Results>,
+    registers: Rc<RefCell<Registers>>,
+    undo_store: Option<UndoStore>,
+    status_line: Rc<RefCell<StatusLine>>,
 }
 
 impl Drop for App {
@@ -32,59 +90,568 @@ impl Drop for App {
     }
 }
 
+impl Default for App {
+    fn default() -> Self {
+        App::new(Vec::new())
+    }
+}
+
 impl App {
-    pub fn new() -> Self {
+    /// Opens each file in its own split, or a scratch document if none.
+    pub fn new(files: Vec<FileArg>) -> Self {
         let renderer = Rc::new(RefCell::new(Renderer::new()));
         let boundaries = renderer.borrow().boundaries();
         let term_size = ( boundaries.width, boundaries.height );
 
-        let child_style = *Style::default()
-            .set_size(Size2D(
-                Size::Auto, 
-                Size::Percent(50.0)
-            ))
-            .set_flex(Flex::Value(1.0))
-            .set_border(Border::Line);
+        let mut errors = Vec::new();
 
-        let mut root_ui = Node::default();
+        let config = Config::load().unwrap_or_else(|err| {
+            errors.push(err);
+            Config::default()
+        });
 
-        root_ui.get_style()
-            .set_display(Display::Flex(FlexDirection::Row))
+        let keymap = Keymap::load().unwrap_or_else(|err| {
+            errors.push(err);
+            Keymap::default()
+        });
+
+        let syntax_set = SyntaxSet::load().unwrap_or_else(|err| {
+            errors.push(err);
+            SyntaxSet::default()
+        });
+
+        // without a theme set, one is picked for the background on start
+        let theme = Theme::load(config.theme.as_deref().unwrap_or(DEFAULT_THEME)).unwrap_or_else(|err| {
+            errors.push(err);
+            Theme::default()
+        });
+
+        if let Some(colors) = config.terminal.colors {
+            renderer.borrow_mut().set_color_depth(colors);
+        }
+
+        let mut clipboard = Clipboard::new(&config.clipboard);
+        clipboard.set_terminal(renderer.clone());
+        let registers = Registers::new(clipboard, config.clipboard.sync);
+        let undo_store = UndoStore::new(&config.undo);
+
+        let root_ui = Rc::new(RefCell::new(Node::default()));
+        let main_ui = Rc::new(RefCell::new(Node::default()));
+        let status_ui = Rc::new(RefCell::new(Node::default()));
+        let status_line = Rc::new(RefCell::new(StatusLine::default()));
+
+        root_ui.borrow_mut().get_style()
+            .set_display(Display::Flex(FlexDirection::Column))
             .set_size(Size2D(
                 Size::Percent(100.0), 
                 Size::Percent(100.0)
+            ));
+
+        main_ui.borrow_mut().get_style()
+            .set_display(Display::Flex(FlexDirection::Row))
+            .set_size(Size2D(
+                Size::Percent(100.0), 
+                Size::Auto
             ))
-            .set_border(Border::Line)
+            .set_flex(Flex::Value(1.0))
             .set_flex_border(Border::Line);
 
-        let child_0 = Rc::new(RefCell::new(Node::default()));
-        let child_1 = Rc::new(RefCell::new(Node::default()));
-
-        child_0.borrow_mut().set_style(child_style);
-        child_1.borrow_mut().set_style(child_style);
+        status_ui.borrow_mut()
+            .set_widget(status_line.clone())
+            .get_style()
+            .set_size(Size2D(
+                Size::Percent(100.0), 
+                Size::Exact(1)
+            ));
 
-        root_ui
-            .add_child(child_0)
-            .add_child(child_1);
+        root_ui.borrow_mut()
+            .add_child(main_ui.clone())
+            .add_child(status_ui);
 
-        App {
+        let mut app = App {
             root_ui,
+            main_ui,
             renderer,
             term_size,
             running: false,
             last: Instant::now(),
+            hover_path: Vec::new(),
+            mouse_capture: None,
+            focused: None,
+            config,
+            keymap,
+            syntax_set,
+            theme: Theme::default(),
+            pending_keys: PendingKeys::default(),
+            editors: Vec::new(),
+            results: None,
+            registers: Rc::new(RefCell::new(registers)),
+            undo_store,
+            status_line,
+        };
+
+        if !errors.is_empty() {
+            app.status_line.borrow_mut().set_error(errors.join("; "));
+        }
+
+        app.set_theme(theme);
+        app.open_files(files);
+        app
+    }
+
+    fn editor_style() -> Style {
+        *Style::default()
+            .set_size(Size2D(
+                Size::Auto, 
+                Size::Percent(100.0)
+            ))
+            .set_flex(Flex::Value(1.0))
+            .set_border(Border::Line)
+    }
+
+    /// Draws everything in `theme` from now on.
+    fn set_theme(&mut self, theme: Theme) {
+        self.renderer.borrow_mut().set_base_style(theme.editor);
+        self.root_ui.borrow_mut().set_theme(&theme);
+        self.theme = theme;
+    }
+
+    /// Switches to the light or dark theme for the terminal's background,
+    /// unless the config names a theme. Reads the terminal's reply, so
+    /// must come after raw mode is on and before any events are read.
+    fn detect_theme(&mut self) {
+        if self.config.theme.is_some() {
+            return;
+        }
+
+        let Some(background) = self.config.terminal.background.or_else(|| Background::detect(BACKGROUND_TIMEOUT)) else {
+            return;
+        };
+
+        let name = match background {
+            Background::Light => self.config.light_theme.as_deref().unwrap_or(DEFAULT_LIGHT_THEME),
+            Background::Dark => self.config.dark_theme.as_deref().unwrap_or(DEFAULT_DARK_THEME),
+        };
+
+        match Theme::load(name) {
+            Ok(theme) => self.set_theme(theme),
+            Err(err) => {
+                self.status_line.borrow_mut().set_error(err);
+            }
+        }
+    }
+
+    /// Switches to the theme `step` places on from the current one in the
+    /// list of themes, wrapping around.
+    fn cycle_theme(&mut self, step: isize) {
+        let names = Theme::names();
+        let index = names.iter().position(|name| *name == self.theme.name).unwrap_or(0);
+        let name = &names[(index as isize + step).rem_euclid(names.len() as isize) as usize];
+
+        match Theme::load(name) {
+            Ok(theme) => {
+                self.status_line.borrow_mut().set_message(format!("Theme: {}", name));
+                self.set_theme(theme);
+            }
+            Err(err) => {
+                self.status_line.borrow_mut().set_error(err);
+            }
+        }
+    }
+
+    fn open_files(&mut self, files: Vec<FileArg>) {
+        for file in files {
+            let Some(document) = self.open_document(&file.path) else {
+                continue;
+            };
+
+            let point = match file.position {
+                Some(FilePosition::Point(point)) => point,
+                Some(FilePosition::LastLine) => Point::new(document.get_buffer().len_lines() - 1, 0),
+                None => Point::default(),
+            };
+
+            let node = self.create_editor_node(Rc::new(RefCell::new(document)));
+            self.main_ui.borrow_mut().add_child(node);
+
+            if let Some(editor) = self.editors.last() {
+                editor.view.borrow_mut().set_cursor_point(point);
+            }
+        }
+
+        if self.editors.is_empty() {
+            let mut document = Document::new_file(None);
+            document.set_indent(self.config.editor.indent());
+
+            let node = self.create_editor_node(Rc::new(RefCell::new(document)));
+            self.main_ui.borrow_mut().add_child(node);
+        }
+    }
+
+    /// Opens the file at `path` with any undo history stored for it,
+    /// reporting failures on the status line.
+    fn open_document(&mut self, path: &Path) -> Option<Document> {
+        let mut document = match Document::open(path) {
+            Ok(document) => document,
+            Err(err) => {
+                self.status_line.borrow_mut()
+                    .set_error(format!("{}: {}", path.display(), err));
+                return None;
+            }
+        };
+
+        let indent = match self.config.editor.detect_indent {
+            true => Indent::detect(document.get_buffer(), self.config.editor.indent()),
+            false => self.config.editor.indent(),
+        };
+        document.set_indent(indent);
+
+        if let Some(store) = &self.undo_store {
+            match store.load(&document) {
+                Ok(Some(history)) => {
+                    document.set_history(history);
+                }
+                Ok(None) => (),
+                Err(err) => {
+                    self.status_line.borrow_mut()
+                        .set_error(format!("Could not load undo history of {}: {}", path.display(), err));
+                }
+            }
+        }
+
+        Some(document)
+    }
+
+    /// Creates a frame with a gutter and a view of `document`, registers
+    /// it as an editor, and returns the frame to lay out.
+    fn create_editor_node(&mut self, document: Rc<RefCell<Document>>) -> Rc<RefCell<Node>> {
+        let grammar = document.borrow().get_path().and_then(|path| self.syntax_set.for_path(path));
+
+        // settings for the language go by its grammar, then the extension
+        let name = grammar.as_ref().map(|grammar| grammar.get_name().to_lowercase());
+        let extension = document.borrow().get_path()
+            .and_then(|path| path.extension())
+            .map(|extension| extension.to_string_lossy().into_owned());
+        let names: Vec<&str> = name.iter().chain(&extension).map(String::as_str).collect();
+
+        let mut view = EditorView::new(document, self.registers.clone(), self.config.editor);
+        view.set_search_config(self.config.search);
+        view.set_language(self.config.language(&names));
+        view.set_grammar(grammar);
+
+        let view = Rc::new(RefCell::new(view));
+        let gutter = Rc::new(RefCell::new(Gutter::new(view.clone(), self.config.editor.line_numbers)));
+        let frame = Rc::new(RefCell::new(Node::default()));
+        let gutter_node = Rc::new(RefCell::new(Node::default()));
+        let node = Rc::new(RefCell::new(Node::default()));
+
+        gutter_node.borrow_mut()
+            .set_widget(gutter.clone())
+            .get_style()
+            .set_size(Size2D(
+                Size::Exact(gutter.borrow().width()),
+                Size::Percent(100.0)
+            ));
+
+        node.borrow_mut()
+            .set_widget(view.clone())
+            .set_focusable(true)
+            .get_style()
+            .set_size(Size2D(
+                Size::Auto,
+                Size::Percent(100.0)
+            ))
+            .set_flex(Flex::Value(1.0));
+
+        frame.borrow_mut()
+            .set_style(App::editor_style())
+            .add_child(gutter_node.clone())
+            .add_child(node.clone())
+            .set_focus_within(true)
+            .set_theme(&self.theme)
+            .get_style()
+            .set_display(Display::Flex(FlexDirection::Row));
+
+        self.editors.push(Editor { frame: frame.clone(), node, view, gutter_node, gutter });
+        frame
+    }
+
+    /// Sizes the gutters to what they have to show, before the editors
+    /// are laid out.
+    fn update_gutters(&mut self) {
+        for editor in self.editors.iter() {
+            let width = editor.gutter.borrow().width();
+
+            editor.gutter_node.borrow_mut()
+                .get_style()
+                .set_size(Size2D(
+                    Size::Exact(width),
+                    Size::Percent(100.0)
+                ));
+        }
+    }
+
+    /// Cycles how all gutters number lines.
+    fn cycle_line_numbers(&mut self) {
+        let line_numbers = self.config.editor.line_numbers.next();
+        self.config.editor.line_numbers = line_numbers;
+
+        for editor in self.editors.iter() {
+            editor.gutter.borrow_mut().set_line_numbers(line_numbers);
         }
+
+        self.status_line.borrow_mut().set_message(format!("Line numbers: {}", line_numbers.name()));
+    }
+
+    /// The node `node` is laid out as: the frame of an editor for its
+    /// view's node, or else the node itself.
+    fn layout_node(&self, node: &Rc<RefCell<Node>>) -> Rc<RefCell<Node>> {
+        self.editors.iter()
+            .find(|editor| Rc::ptr_eq(&editor.node, node))
+            .map_or_else(|| node.clone(), |editor| editor.frame.clone())
+    }
+
+    /// The editor showing the file at `path`, if one is open.
+    fn editor_for(&self, path: &Path) -> Option<&Editor> {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+        self.editors.iter().find(|editor| {
+            let view = editor.view.borrow();
+            let document = view.get_document();
+            let document = document.borrow();
+
+            document.get_path().is_some_and(|other| {
+                fs::canonicalize(other).unwrap_or_else(|_| other.to_path_buf()) == path
+            })
+        })
+    }
+
+    fn focused_editor(&self) -> Option<Rc<RefCell<EditorView>>> {
+        let focused = self.focused.as_ref()?;
+
+        self.editors.iter()
+            .find(|editor| Rc::ptr_eq(&editor.node, focused))
+            .map(|editor| editor.view.clone())
+    }
+
+    fn save_focused(&mut self) {
+        let Some(view) = self.focused_editor() else {
+            return;
+        };
+
+        let document = view.borrow().get_document();
+        let mut document = document.borrow_mut();
+
+        if let Err(err) = document.save() {
+            self.status_line.borrow_mut()
+                .set_error(format!("Could not save {}: {}", document.display_name(), err));
+            return;
+        }
+
+        let stored = self.undo_store.as_ref()
+            .map_or(Ok(()), |store| store.save(&document));
+
+        match stored {
+            Ok(()) => self.status_line.borrow_mut()
+                .set_message(format!("Wrote {}", document.display_name())),
+            Err(err) => self.status_line.borrow_mut()
+                .set_error(format!("Wrote {}, but could not save its undo history: {}", document.display_name(), err)),
+        };
+    }
+
+    /// Opens the workspace search results beside the editors, or focuses
+    /// them if already open, and asks for a pattern.
+    fn open_results(&mut self) {
+        if self.results.is_none() {
+            let root = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+            let widget = Rc::new(RefCell::new(SearchResults::new(root, self.config.search)));
+            let node = Rc::new(RefCell::new(Node::default()));
+
+            node.borrow_mut()
+                .set_style(App::editor_style())
+                .set_widget(widget.clone())
+                .set_focusable(true)
+                .set_theme(&self.theme);
+
+            self.main_ui.borrow_mut().add_child(node.clone());
+            self.results = Some(Results { node, widget });
+        }
+
+        let Some(results) = &self.results else {
+            return;
+        };
+
+        let node = results.node.clone();
+        results.widget.borrow_mut().start_search();
+        self.focus(node);
+    }
+
+    fn close_results(&mut self) {
+        let Some(results) = self.results.take() else {
+            return;
+        };
+
+        let path = Node::path_to(&self.root_ui, &results.node);
+
+        if let [.., parent, _] = path.as_slice() {
+            let index = parent.borrow().get_children().iter()
+                .position(|child| Rc::ptr_eq(child, &results.node));
+
+            if let Some(index) = index {
+                parent.borrow_mut().remove_child(index);
+            }
+        }
+
+        if self.focused.as_ref().is_some_and(|focused| Rc::ptr_eq(focused, &results.node)) {
+            self.focused = None;
+            self.focus_next(1);
+        }
+    }
+
+    /// Takes in what the workspace search found, and opens or applies what
+    /// was picked from it.
+    fn poll_results(&mut self) {
+        let Some(results) = &self.results else {
+            return;
+        };
+
+        let widget = results.widget.clone();
+        let mut widget = widget.borrow_mut();
+        widget.poll();
+
+        if let Some(err) = widget.take_error() {
+            self.status_line.borrow_mut().set_error(err);
+        }
+
+        if let Some(message) = widget.take_message() {
+            self.status_line.borrow_mut().set_message(message);
+        }
+
+        let jump = widget.take_jump();
+        let applied = widget.take_applied();
+        drop(widget);
+
+        if let Some((path, point)) = jump {
+            self.open_at(&path, point);
+        }
+
+        for replace in applied {
+            self.apply_replace(&replace);
+        }
+    }
+
+    /// Focuses an editor on the file at `path`, opening one beside the
+    /// others if none shows it yet, and moves its cursor to `point`.
+    fn open_at(&mut self, path: &Path, point: Point) {
+        let (node, view) = match self.editor_for(path) {
+            Some(editor) => (editor.node.clone(), editor.view.clone()),
+            None => {
+                let Some(document) = self.open_document(path) else {
+                    return;
+                };
+
+                let frame = self.create_editor_node(Rc::new(RefCell::new(document)));
+                let mut main_ui = self.main_ui.borrow_mut();
+
+                // new editors go before the results
+                let index = self.results.as_ref()
+                    .and_then(|results| main_ui.get_children().iter().position(|child| Rc::ptr_eq(child, &results.node)))
+                    .unwrap_or(main_ui.get_children().len());
+
+                main_ui.insert_child(index, frame);
+                drop(main_ui);
+
+                let editor = self.editors.last().expect("Just created");
+                (editor.node.clone(), editor.view.clone())
+            }
+        };
+
+        view.borrow_mut().set_cursor_point(point);
+        self.focus(node);
+    }
+
+    /// Applies replacements to the open editor showing the file, where
+    /// they can be undone, or else straight to the file.
+    fn apply_replace(&mut self, replace: &FileReplace) {
+        let path = replace.get_path();
+        let name = path.strip_prefix(env::current_dir().unwrap_or_default()).unwrap_or(path);
+
+        let Some(editor) = self.editor_for(path) else {
+            if let Err(err) = replace.apply_to_file() {
+                self.status_line.borrow_mut()
+                    .set_error(format!("Could not replace in {}: {}", name.display(), err));
+            }
+
+            return;
+        };
+
+        let view = editor.view.clone();
+        let document = view.borrow().get_document();
+
+        let edits = replace.buffer_edits(document.borrow().get_buffer());
+
+        match edits {
+            Some(edits) => view.borrow_mut().replace_ranges(&edits),
+            None => {
+                self.status_line.borrow_mut()
+                    .set_error(format!("Skipped {} as it changed since the preview", name.display()));
+            }
+        }
+    }
+
+    fn update_status_line(&mut self) {
+        if let Some(err) = self.registers.borrow_mut().take_error() {
+            self.status_line.borrow_mut().set_error(err);
+        }
+
+        let Some(view) = self.focused_editor() else {
+            return;
+        };
+
+        if let Some(err) = view.borrow_mut().take_error() {
+            self.status_line.borrow_mut().set_error(err);
+        }
+
+        if let Some(message) = view.borrow_mut().take_message() {
+            self.status_line.borrow_mut().set_message(message);
+        }
+
+        let search = view.borrow_mut().search_status();
+
+        let view = view.borrow();
+        let document = view.get_document();
+        let document = document.borrow();
+
+        self.status_line.borrow_mut()
+            .set_file(document.display_name(), document.is_modified())
+            .set_mode(view.get_mode().name())
+            .set_point(view.cursor_point())
+            .set_cursors(view.cursor_count())
+            .set_search(search);
     }
 
     pub fn start (&mut self) {
+        // a panic puts the terminal back first, so its message can be read
+        let hook = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            let mut stdout = io::stdout();
+            let _ = restore_term(&mut stdout).and_then(|_| stdout.flush());
+            hook(info);
+        }));
+
         self.setup_term();
+        self.detect_theme();
         self.draw();
+        self.focus_next(1);
         self.start_loop();
     }
 
     fn draw(&mut self) {
+        self.update_status_line();
+        self.update_gutters();
         self.renderer.borrow_mut().clear();
-        self.root_ui.draw_root(self.renderer.clone());
+        self.root_ui.borrow_mut().draw_root(self.renderer.clone());
     }
     
     fn update_term_size(&mut self) -> bool {
@@ -104,6 +671,8 @@ impl App {
 
         while self.running {
             self.process_event();
+            self.process_key_timeout();
+            self.poll_results();
 
             if self.update_term_size() {
                 self.draw();
@@ -119,30 +688,401 @@ impl App {
     fn process_event(&mut self) {
         if event::poll(Duration::from_nanos(1_000_000_000 / FPS)).unwrap() {
         // if event::poll(Duration::ZERO).unwrap() {
-            self.process_key_event();
+            match event::read() {
+                Ok(Event::Key(event)) => self.process_key_event(event),
+                Ok(Event::Mouse(event)) => self.process_mouse_event(event),
+                Ok(Event::Paste(text)) => {
+                    self.dispatch_to_focused(&NodeEvent::Paste(text));
+                }
+                Ok(Event::Resize(width, height)) => self.process_resize_event(width, height),
+                _ => ()
+            }
         } 
     }
 
-    fn process_key_event(&mut self) {
-        if let Ok(Event::Key(event)) = event::read() { 
-            match event {
-                KeyEvent {
-                    code: KeyCode::Char('q'),
-                    kind: KeyEventKind::Press,
-                    ..
-                } => self.running = false,
-                KeyEvent {
-                    code: KeyCode::Char('r'),
-                    kind: KeyEventKind::Press,
-                    ..
-                } => self.draw(),
-                _ => ()
+    /// Dispatches along the path to the focused node, or to the root when
+    /// nothing has focus. Returns true if a node consumed the event.
+    fn dispatch_to_focused(&mut self, event: &NodeEvent) -> bool {
+        let path = match &self.focused {
+            Some(focused) => Node::path_to(&self.root_ui, focused),
+            None => vec![self.root_ui.clone()],
+        };
+
+        node_event::dispatch(&path, event)
+    }
+
+    fn process_resize_event(&mut self, width: u16, height: u16) {
+        node_event::broadcast(&self.root_ui, &NodeEvent::Resize(width, height));
+
+        if self.update_term_size() {
+            self.draw();
+        }
+    }
+
+    fn process_key_event(&mut self, event: KeyEvent) {
+        if event.kind == KeyEventKind::Release {
+            return;
+        }
+
+        self.status_line.borrow_mut().clear_message();
+
+        let contexts = self.key_contexts();
+        let contexts: Vec<&str> = contexts.iter().map(|context| context.as_str()).collect();
+        let result = self.keymap.feed(&mut self.pending_keys, &contexts, KeyChord::from_event(&event));
+
+        self.process_key_result(result);
+    }
+
+    fn process_key_timeout(&mut self) {
+        let contexts = self.key_contexts();
+        let contexts: Vec<&str> = contexts.iter().map(|context| context.as_str()).collect();
+
+        if let Some(result) = self.keymap.check_timeout(&mut self.pending_keys, &contexts) {
+            self.process_key_result(result);
+        }
+    }
+
+    fn process_key_result(&mut self, result: KeyResult) {
+        match result {
+            KeyResult::Command(command) => self.run_command(command),
+            KeyResult::Pending => (),
+            KeyResult::Unbound(keys) => {
+                for key in keys {
+                    self.dispatch_to_focused(&NodeEvent::Key(KeyEvent::new(key.code, key.modifiers)));
+                }
             }
+        }
+    }
+
+    /// Keymap contexts of the widgets on the focus path, innermost first.
+    fn key_contexts(&self) -> Vec<String> {
+        let path = match &self.focused {
+            Some(focused) => Node::path_to(&self.root_ui, focused),
+            None => Vec::new(),
         };
+
+        let mut contexts: Vec<String> = path.iter()
+            .rev()
+            .filter_map(|node| node.borrow().get_widget())
+            .filter_map(|widget| widget.borrow().key_context())
+            .map(|context| context.to_string())
+            .collect();
+
+        contexts.push(GLOBAL_CONTEXT.to_string());
+        contexts
+    }
+
+    /// Offers the command to the focused node first, running the global
+    /// action only when no node consumed it.
+    fn run_command(&mut self, command: Command) {
+        if self.dispatch_to_focused(&NodeEvent::Command(command)) {
+            return;
+        }
+
+        match command {
+            Command::Quit => self.quit(),
+            Command::ForceQuit => self.running = false,
+            Command::Redraw => {
+                self.renderer.borrow_mut().invalidate();
+                self.draw();
+            }
+            Command::Save => self.save_focused(),
+            Command::FocusNext => self.focus_next(1),
+            Command::FocusPrevious => self.focus_next(-1),
+            Command::FocusLeft => self.focus_direction(-1, 0),
+            Command::FocusRight => self.focus_direction(1, 0),
+            Command::FocusUp => self.focus_direction(0, -1),
+            Command::FocusDown => self.focus_direction(0, 1),
+            Command::ShrinkWidth => self.resize_focused(FlexDirection::Row, -RESIZE_STEP),
+            Command::GrowWidth => self.resize_focused(FlexDirection::Row, RESIZE_STEP),
+            Command::ShrinkHeight => self.resize_focused(FlexDirection::Column, -RESIZE_STEP),
+            Command::GrowHeight => self.resize_focused(FlexDirection::Column, RESIZE_STEP),
+            Command::SplitRight => self.split_focused(FlexDirection::Row),
+            Command::SplitDown => self.split_focused(FlexDirection::Column),
+            Command::WorkspaceSearch => self.open_results(),
+            Command::CloseResults => self.close_results(),
+            Command::NextTheme => self.cycle_theme(1),
+            Command::PreviousTheme => self.cycle_theme(-1),
+            Command::CycleLineNumbers => self.cycle_line_numbers(),
+            // editing commands only mean something to a focused editor
+            _ => ()
+        }
+    }
+
+    /// Stops the app, unless a document has unsaved changes.
+    fn quit(&mut self) {
+        let mut modified: Vec<String> = Vec::new();
+
+        for editor in self.editors.iter() {
+            let document = editor.view.borrow().get_document();
+            let document = document.borrow();
+            let name = document.display_name();
+
+            // documents open in several splits are only named once
+            if document.is_modified() && !modified.contains(&name) {
+                modified.push(name);
+            }
+        }
+
+        if modified.is_empty() {
+            self.running = false;
+            return;
+        }
+
+        self.status_line.borrow_mut()
+            .set_error(format!("Unsaved changes in {}; force_quit discards them", modified.join(", ")));
+    }
+
+    /// Adds a node beside the focused one along `direction` and focuses it.
+    /// The focused node is wrapped in a new flex node when its parent does
+    /// not already lay out in that direction.
+    fn split_focused(&mut self, direction: FlexDirection) {
+        let Some(focused) = self.focused.as_ref().map(|focused| self.layout_node(focused)) else {
+            return;
+        };
+
+        let path = Node::path_to(&self.root_ui, &focused);
+
+        let [.., parent, _] = path.as_slice() else {
+            return;
+        };
+
+        let Some(index) = parent.borrow().get_children().iter()
+            .position(|child| Rc::ptr_eq(child, &focused)) else {
+            return;
+        };
+
+        let mut style = *focused.borrow_mut().get_style();
+
+        // a split of an editor shows the same document
+        let (sibling, target) = match self.focused_editor() {
+            Some(view) => {
+                let frame = self.create_editor_node(view.borrow().get_document());
+                (frame, self.editors.last().expect("Just created").node.clone())
+            }
+            None => {
+                let node = Rc::new(RefCell::new(Node::default()));
+                node.borrow_mut().set_focusable(true);
+                (node.clone(), node)
+            }
+        };
+
+        if parent.borrow_mut().get_style().get_display() == Display::Flex(direction) {
+            if let Flex::Value(val) = style.get_flex() {
+                style.set_flex(Flex::Value(val / 2.0));
+            }
+
+            focused.borrow_mut().set_style(style);
+            sibling.borrow_mut().set_style(style);
+            parent.borrow_mut().insert_child(index + 1, sibling.clone());
+        } else {
+            let container = Rc::new(RefCell::new(Node::default()));
+
+            container.borrow_mut().set_style(style)
+                .get_style()
+                .set_display(Display::Flex(direction))
+                .set_border(Border::None)
+                .set_flex_border(Border::None);
+
+            let Size2D (width, height) = style.get_size();
+
+            style.set_flex(Flex::Value(1.0))
+                .set_size(match direction {
+                    FlexDirection::Row => Size2D (width, Size::Percent(100.0)),
+                    FlexDirection::Column => Size2D (Size::Percent(100.0), height),
+                });
+
+            focused.borrow_mut().set_style(style);
+            sibling.borrow_mut().set_style(style);
+
+            container.borrow_mut()
+                .add_child(focused.clone())
+                .add_child(sibling.clone());
+
+            parent.borrow_mut().replace_child(index, container);
+        }
+
+        self.focus(target);
+    }
+
+    /// Grows the split containing the focused node along `direction` by
+    /// `cells`, shrinking its neighbour. Negative values shrink it.
+    fn resize_focused(&mut self, direction: FlexDirection, cells: i32) {
+        let Some(focused) = self.focused.as_ref().map(|focused| self.layout_node(focused)) else {
+            return;
+        };
+
+        let path = Node::path_to(&self.root_ui, &focused);
+
+        for pair in path.windows(2).rev() {
+            let (parent, child) = (&pair[0], &pair[1]);
+            let mut parent = parent.borrow_mut();
+
+            if parent.get_style().get_display() != Display::Flex(direction) {
+                continue;
+            }
+
+            let count = parent.get_children().len();
+            let Some(index) = parent.get_children().iter()
+                .position(|other| Rc::ptr_eq(other, child)) else {
+                return;
+            };
+
+            if index + 1 < count {
+                parent.move_flex_border(index, cells);
+            } else if index > 0 {
+                parent.move_flex_border(index - 1, -cells);
+            }
+
+            return;
+        }
+    }
+
+    fn process_mouse_event(&mut self, event: MouseEvent) {
+        let path = Node::hit_test(&self.root_ui, event.column, event.row);
+        let node_event = NodeEvent::Mouse(NodeMouseEvent {
+            kind: event.kind,
+            x: event.column as i32,
+            y: event.row as i32,
+            column: event.column,
+            row: event.row,
+            modifiers: event.modifiers,
+        });
+
+        match event.kind {
+            MouseEventKind::Down(_) => {
+                self.update_hover(path.clone());
+
+                let focusable = path.iter()
+                    .rev()
+                    .find(|node| node.borrow().is_focusable());
+
+                if let Some(node) = focusable {
+                    self.focus(node.clone());
+                }
+
+                self.mouse_capture = path.last().cloned();
+                node_event::dispatch(&path, &node_event);
+            }
+            // drags and releases go to the node the press started in,
+            // even once the pointer has left it
+            MouseEventKind::Drag(_) => {
+                if let Some(target) = &self.mouse_capture {
+                    node_event::dispatch(&Node::path_to(&self.root_ui, target), &node_event);
+                }
+            }
+            MouseEventKind::Up(_) => {
+                if let Some(target) = self.mouse_capture.take() {
+                    node_event::dispatch(&Node::path_to(&self.root_ui, &target), &node_event);
+                }
+
+                self.update_hover(path);
+            }
+            _ => {
+                if event.kind == MouseEventKind::Moved {
+                    self.update_hover(path.clone());
+                }
+
+                node_event::dispatch(&path, &node_event);
+            }
+        }
+    }
+
+    fn update_hover(&mut self, path: Vec<Rc<RefCell<Node>>>) {
+        Node::move_hover(&self.hover_path, &path);
+        self.hover_path = path;
+    }
+
+    /// Moves focus `step` places along the focus chain, wrapping around.
+    fn focus_next(&mut self, step: i32) {
+        let chain = Node::focus_chain(&self.root_ui);
+
+        if chain.is_empty() {
+            return;
+        }
+
+        let current = self.focused.as_ref().and_then(|focused| {
+            chain.iter().position(|node| Rc::ptr_eq(node, focused))
+        });
+
+        let index = match current {
+            Some(index) => (index as i32 + step).rem_euclid(chain.len() as i32) as usize,
+            None if step < 0 => chain.len() - 1,
+            None => 0,
+        };
+
+        self.focus(chain[index].clone());
+    }
+
+    /// Moves focus to the nearest focusable node in the direction of
+    /// `(dx, dy)` from the focused one.
+    fn focus_direction(&mut self, dx: i32, dy: i32) {
+        let Some(focused) = self.focused.clone() else {
+            return self.focus_next(1);
+        };
+
+        let from = focused.borrow().get_rect();
+
+        let nearest = Node::focus_chain(&self.root_ui).into_iter()
+            .filter(|node| !Rc::ptr_eq(node, &focused))
+            .filter_map(|node| {
+                let distance = App::directional_distance(from, node.borrow().get_rect(), dx, dy)?;
+                Some((distance, node))
+            })
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, node)| node);
+
+        if let Some(node) = nearest {
+            self.focus(node);
+        }
+    }
+
+    /// Distance from `from` to `to` when moving in the direction of
+    /// `(dx, dy)`, or None if `to` does not lie in that direction. Rects
+    /// that overlap `from` across the direction come before those that do
+    /// not, and distance along the direction weighs more than misalignment
+    /// across it.
+    fn directional_distance(from: Rect, to: Rect, dx: i32, dy: i32) -> Option<(bool, i32)> {
+        let (from_x, from_y, from_w, from_h) = (from.x as i32, from.y as i32, from.width as i32, from.height as i32);
+        let (to_x, to_y, to_w, to_h) = (to.x as i32, to.y as i32, to.width as i32, to.height as i32);
+
+        let overlaps_x = to_x < from_x + from_w && from_x < to_x + to_w;
+        let overlaps_y = to_y < from_y + from_h && from_y < to_y + to_h;
+
+        let (main, cross, overlaps) = match (dx.signum(), dy.signum()) {
+            (1, _) => (to_x - (from_x + from_w), (to_y * 2 + to_h) - (from_y * 2 + from_h), overlaps_y),
+            (-1, _) => (from_x - (to_x + to_w), (to_y * 2 + to_h) - (from_y * 2 + from_h), overlaps_y),
+            (_, 1) => (to_y - (from_y + from_h), (to_x * 2 + to_w) - (from_x * 2 + from_w), overlaps_x),
+            (_, -1) => (from_y - (to_y + to_h), (to_x * 2 + to_w) - (from_x * 2 + from_w), overlaps_x),
+            _ => return None,
+        };
+
+        if main < 0 {
+            return None;
+        }
+
+        Some((!overlaps, main * 4 + cross.abs()))
+    }
+
+    fn focus(&mut self, node: Rc<RefCell<Node>>) {
+        if let Some(focused) = &self.focused {
+            if Rc::ptr_eq(focused, &node) {
+                return;
+            }
+        }
+
+        if let Some(focused) = self.focused.take() {
+            focused.borrow_mut().set_focused(false);
+            node_event::dispatch(&Node::path_to(&self.root_ui, &focused), &NodeEvent::FocusLost);
+        }
+
+        node.borrow_mut().set_focused(true);
+        self.focused = Some(node);
+        self.dispatch_to_focused(&NodeEvent::FocusGained);
     }
 
     fn setup_term (&mut self) {
-        queue!(self.renderer.borrow_mut().get_stdout_buf(), EnterAlternateScreen)
+        queue!(self.renderer.borrow_mut().get_stdout_buf(), EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste)
             .expect("Could not enter alternate screen");
 
         terminal::enable_raw_mode()
@@ -150,10 +1090,58 @@ impl App {
     }
 
     fn cleanup_term (&mut self) {
-        terminal::disable_raw_mode()
-            .expect("Could not disable raw mode");
+        restore_term(self.renderer.borrow_mut().get_stdout_buf())
+            .expect("Could not restore the terminal");
+    }
+}
+
+/// Undoes `setup_term`, and shows the cursor in the terminal's own shape
+/// again after frames hid or reshaped it.
+fn restore_term(out: &mut impl Write) -> io::Result<()> {
+    terminal::disable_raw_mode()?;
+    queue!(out, DisableBracketedPaste, DisableMouseCapture, Show, ResetCursorShape, LeaveAlternateScreen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: u16, y: u16, width: u16, height: u16) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    #[test]
+    fn overlapping_rects_come_before_nearer_ones() {
+        let from = rect(0, 0, 10, 10);
+        let aligned = App::directional_distance(from, rect(30, 0, 10, 10), 1, 0);
+        let diagonal = App::directional_distance(from, rect(11, 20, 10, 10), 1, 0);
+
+        assert!(aligned.is_some() && diagonal.is_some());
+        assert!(aligned < diagonal);
+    }
+
+    #[test]
+    fn nearer_and_better_aligned_rects_come_first() {
+        let from = rect(10, 10, 10, 10);
 
-        queue!(self.renderer.borrow_mut().get_stdout_buf(), LeaveAlternateScreen)
-            .expect("Could not leave alternate screen");
+        // both overlap, one is closer below
+        let near = App::directional_distance(from, rect(10, 21, 10, 5), 0, 1);
+        let far = App::directional_distance(from, rect(10, 30, 10, 5), 0, 1);
+        assert!(near < far);
+
+        // both sit right above, one is centred on `from`
+        let centred = App::directional_distance(from, rect(10, 0, 10, 9), 0, -1);
+        let offset = App::directional_distance(from, rect(15, 0, 10, 9), 0, -1);
+        assert!(centred < offset);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn rects_behind_are_not_in_the_direction() {
+        let from = rect(10, 10, 10, 10);
+
+        assert_eq!(App::directional_distance(from, rect(0, 10, 9, 10), 1, 0), None);
+        assert_eq!(App::directional_distance(from, rect(15, 10, 10, 10), 1, 0), None);
+        assert_eq!(App::directional_distance(from, rect(0, 10, 9, 10), 0, 0), None);
+        assert!(App::directional_distance(from, rect(0, 10, 9, 10), -1, 0).is_some());
+    }
+}
@@ -0,0 +1,146 @@
+use std::path::{Path, PathBuf};
+
+use crate::buffer::Point;
+
+/// A file to open and where to put the cursor in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileArg {
+    pub path: PathBuf,
+    pub position: Option<FilePosition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilePosition {
+    Point(Point),
+    LastLine,
+}
+
+/// Parses file arguments in the forms `file`, `+line file`, `+ file` for
+/// the last line, and `file:line` or `file:line:col`. Lines and columns are
+/// one based on the command line.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Vec<FileArg>, String> {
+    let mut files = Vec::new();
+    let mut position = None;
+    let mut only_files = false;
+
+    for arg in args {
+        if !only_files && arg == "--" {
+            only_files = true;
+            continue;
+        }
+
+        if !only_files {
+            if let Some(line) = arg.strip_prefix('+') {
+                position = Some(match line {
+                    "" => FilePosition::LastLine,
+                    line => FilePosition::Point(Point::new(parse_number(line, &arg)?, 0)),
+                });
+
+                continue;
+            }
+
+            if arg.starts_with('-') {
+                return Err(format!("Unknown option '{}'", arg));
+            }
+        }
+
+        let (path, suffix_position) = split_position(&arg);
+
+        files.push(FileArg {
+            path,
+            position: position.take().or(suffix_position),
+        });
+    }
+
+    if position.is_some() {
+        return Err("Expected a file after '+line'".to_string());
+    }
+
+    Ok(files)
+}
+
+/// Splits a `:line` or `:line:col` suffix off a path, unless the whole
+/// argument names an existing file.
+fn split_position(arg: &str) -> (PathBuf, Option<FilePosition>) {
+    if Path::new(arg).exists() {
+        return (PathBuf::from(arg), None);
+    }
+
+    let mut parts = arg.rsplitn(3, ':');
+    let last = parts.next().and_then(|part| part.parse::<usize>().ok());
+    let middle = parts.next();
+    let rest = parts.next();
+
+    match (rest, middle.and_then(|part| part.parse::<usize>().ok()), last) {
+        (Some(path), Some(line), Some(column)) if !path.is_empty() => (
+            PathBuf::from(path),
+            Some(FilePosition::Point(Point::new(line.saturating_sub(1), column.saturating_sub(1)))),
+        ),
+        (_, _, Some(line)) => match arg.rsplit_once(':') {
+            Some((path, _)) if !path.is_empty() => (
+                PathBuf::from(path),
+                Some(FilePosition::Point(Point::new(line.saturating_sub(1), 0))),
+            ),
+            _ => (PathBuf::from(arg), None),
+        },
+        _ => (PathBuf::from(arg), None),
+    }
+}
+
+fn parse_number(text: &str, arg: &str) -> Result<usize, String> {
+    text.parse::<usize>()
+        .map(|number| number.saturating_sub(1))
+        .map_err(|_| format!("Invalid line number in '{}'", arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Vec<FileArg>, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn file(path: &str, position: Option<FilePosition>) -> FileArg {
+        FileArg { path: PathBuf::from(path), position }
+    }
+
+    fn point(line: usize, column: usize) -> Option<FilePosition> {
+        Some(FilePosition::Point(Point::new(line, column)))
+    }
+
+    #[test]
+    fn takes_positions_after_the_path() {
+        assert_eq!(parse(&["src/main.rs:12:3"]), Ok(vec![file("src/main.rs", point(11, 2))]));
+        assert_eq!(parse(&["src/main.rs:12"]), Ok(vec![file("src/main.rs", point(11, 0))]));
+        assert_eq!(parse(&["notes:todo"]), Ok(vec![file("notes:todo", None)]));
+        assert_eq!(parse(&[":12"]), Ok(vec![file(":12", None)]));
+    }
+
+    #[test]
+    fn takes_positions_before_the_path() {
+        assert_eq!(parse(&["+12", "a.rs", "b.rs"]), Ok(vec![file("a.rs", point(11, 0)), file("b.rs", None)]));
+        assert_eq!(parse(&["+", "a.rs"]), Ok(vec![file("a.rs", Some(FilePosition::LastLine))]));
+        // the earlier position wins over a suffix
+        assert_eq!(parse(&["+2", "a.rs:5"]), Ok(vec![file("a.rs", point(1, 0))]));
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse(&["+x", "a.rs"]).is_err());
+        assert!(parse(&["a.rs", "+3"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+        assert_eq!(parse(&["--", "-a", "+1"]), Ok(vec![file("-a", None), file("+1", None)]));
+    }
+
+    #[test]
+    fn existing_files_keep_their_colons() {
+        let path = std::env::temp_dir().join(format!("text-editor-args-{}:7", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let arg = path.to_string_lossy().into_owned();
+
+        assert_eq!(split_position(&arg), (path.clone(), None));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(split_position(&arg).1, point(6, 0));
+    }
+}
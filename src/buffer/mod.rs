@@ -0,0 +1,532 @@
+use std::{collections::VecDeque, ops::Range};
+
+use ropey::{Rope, RopeSlice};
+use serde::{Deserialize, Serialize};
+
+/// A position in a buffer by line and char column, both zero based.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Point {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Point {
+    pub fn new(line: usize, column: usize) -> Self {
+        Point { line, column }
+    }
+}
+
+/// One edit: `removed` was replaced by `inserted` at `position`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub position: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Change {
+    /// The change that undoes this one.
+    pub fn invert(&self) -> Change {
+        Change {
+            position: self.position,
+            removed: self.inserted.clone(),
+            inserted: self.removed.clone(),
+        }
+    }
+
+    /// Where a position ends up after the change. Positions inside the
+    /// removed text move to its start, and those at an insertion move past
+    /// it.
+    pub fn map_position(&self, position: usize) -> usize {
+        let removed_end = self.position + self.removed.chars().count();
+
+        if position < self.position {
+            position
+        } else if position >= removed_end {
+            position - (removed_end - self.position) + self.inserted.chars().count()
+        } else {
+            self.position
+        }
+    }
+
+    /// Folds `next` into this change when it continues it, as typing or
+    /// deleting backwards does. Returns false if it does not.
+    pub fn merge(&mut self, next: &Change) -> bool {
+        let inserted_end = self.position + self.inserted.chars().count();
+
+        if self.removed.is_empty() && next.removed.is_empty() && next.position == inserted_end {
+            self.inserted.push_str(&next.inserted);
+            return true;
+        }
+
+        let next_end = next.position + next.removed.chars().count();
+
+        if self.inserted.is_empty() && next.inserted.is_empty() && next_end == self.position {
+            self.removed.insert_str(0, &next.removed);
+            self.position = next.position;
+            return true;
+        }
+
+        false
+    }
+}
+
+/// Edits kept for `line_edits_since`, beyond which anything following the
+/// buffer starts over.
+const LINE_EDIT_LOG: usize = 1024;
+
+/// The lines one edit touched, for state kept per line to catch up with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineEdit {
+    /// The first line changed.
+    pub start: usize,
+    /// The last line the edit touched before it was made.
+    pub old_end: usize,
+    /// The last line the edit covers after it was made.
+    pub new_end: usize,
+    /// Whether the edit only added or removed whole lines at the start of
+    /// `start`, so the text that was on it moves with the lines after.
+    pub whole_lines: bool,
+}
+
+impl LineEdit {
+    /// The line the text of a line from before the edit is on after it, or
+    /// None if the edit removed or rewrote it.
+    pub fn map_line(&self, line: usize) -> Option<usize> {
+        let (kept, removed_end) = match self.whole_lines {
+            true => (self.start, self.old_end),
+            false => (self.start + 1, self.old_end + 1),
+        };
+
+        match line {
+            line if line < kept => Some(line),
+            line if line < removed_end => None,
+            line => Some(line - self.old_end + self.new_end),
+        }
+    }
+}
+
+/// Text storage for the editor, backed by a rope so that edits anywhere
+/// in the text stay cheap however large it grows.
+///
+/// Offsets are in chars unless a method says otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct Buffer {
+    rope: Rope,
+    version: u64,
+    /// Edits since they were last taken, for the undo history.
+    changes: Vec<Change>,
+    /// The most recent edits by line, with the version each one made.
+    line_edits: VecDeque<(u64, LineEdit)>,
+}
+
+/// A read only copy of a buffer at one point in time. Taking one is cheap
+/// as it shares storage with the buffer until either is edited.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    rope: Rope,
+    version: u64,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Buffer::default()
+    }
+
+    pub fn from_text(text: &str) -> Self {
+        Buffer {
+            rope: Rope::from_str(text),
+            version: 0,
+            changes: Vec::new(),
+            line_edits: VecDeque::new(),
+        }
+    }
+
+    /// Increases with every edit, so views can tell when to recompute.
+    pub fn get_version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            rope: self.rope.clone(),
+            version: self.version,
+        }
+    }
+
+    /// Replaces the whole text, for example to restore a snapshot.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.record(Change {
+            position: 0,
+            removed: self.rope.to_string(),
+            inserted: snapshot.rope.to_string(),
+        });
+
+        self.rope = snapshot.rope.clone();
+        self.version += 1;
+    }
+
+    pub fn insert(&mut self, char_idx: usize, text: &str) {
+        let char_idx = char_idx.min(self.rope.len_chars());
+
+        if text.is_empty() {
+            return;
+        }
+
+        self.record(Change {
+            position: char_idx,
+            removed: String::new(),
+            inserted: text.to_string(),
+        });
+
+        self.rope.insert(char_idx, text);
+        self.version += 1;
+    }
+
+    pub fn remove(&mut self, range: Range<usize>) {
+        let len = self.rope.len_chars();
+        let range = range.start.min(len)..range.end.min(len);
+
+        if range.is_empty() {
+            return;
+        }
+
+        self.record(Change {
+            position: range.start,
+            removed: self.rope.slice(range.clone()).to_string(),
+            inserted: String::new(),
+        });
+
+        self.rope.remove(range);
+        self.version += 1;
+    }
+
+    pub fn replace(&mut self, range: Range<usize>, text: &str) {
+        self.remove(range.clone());
+        self.insert(range.start, text);
+    }
+
+    /// Makes a change, such as one taken from the undo history.
+    pub fn apply(&mut self, change: &Change) {
+        let removed = change.removed.chars().count();
+
+        self.remove(change.position..change.position + removed);
+        self.insert(change.position, &change.inserted);
+    }
+
+    /// Keeps a change made at the current version, before it is applied
+    /// to the rope.
+    fn record(&mut self, change: Change) {
+        let start = self.rope.char_to_line(change.position);
+        let removed_end = change.position + change.removed.chars().count();
+        let lines = |text: &str| text.is_empty() || text.ends_with('\n');
+
+        let edit = LineEdit {
+            start,
+            old_end: self.rope.char_to_line(removed_end),
+            new_end: start + change.inserted.matches('\n').count(),
+            whole_lines: change.position == self.rope.line_to_char(start)
+                && (change.removed.is_empty() || change.inserted.is_empty())
+                && lines(&change.removed) && lines(&change.inserted),
+        };
+
+        if self.line_edits.len() == LINE_EDIT_LOG {
+            self.line_edits.pop_front();
+        }
+
+        self.line_edits.push_back((self.version + 1, edit));
+        self.changes.push(change);
+    }
+
+    /// The edits made after `version`, oldest first, or None if they are
+    /// no longer all known.
+    pub fn line_edits_since(&self, version: u64) -> Option<impl Iterator<Item = LineEdit> + '_> {
+        let known = match self.line_edits.front() {
+            Some((first, _)) => *first <= version + 1,
+            None => version == self.version,
+        };
+
+        if !known || version > self.version {
+            return None;
+        }
+
+        let start = self.line_edits.partition_point(|(edit_version, _)| *edit_version <= version);
+        Some(self.line_edits.range(start..).map(|(_, edit)| *edit))
+    }
+
+    /// The edits made since they were last taken, oldest first.
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// The edits made since `changes_len` returned `start`, to follow
+    /// positions through them.
+    pub fn changes_since(&self, start: usize) -> &[Change] {
+        &self.changes[start.min(self.changes.len())..]
+    }
+
+    pub fn changes_len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn has_changes(&self) -> bool {
+        !self.changes.is_empty()
+    }
+
+    pub fn get_rope(&self) -> &Rope {
+        &self.rope
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        self.rope.len_bytes()
+    }
+
+    /// The number of lines, counting the empty line after a trailing newline.
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    pub fn line(&self, line: usize) -> RopeSlice<'_> {
+        self.rope.line(line)
+    }
+
+    pub fn lines(&self) -> ropey::iter::Lines<'_> {
+        self.rope.lines()
+    }
+
+    /// Lines starting at `line`, which may be one past the last line.
+    pub fn lines_at(&self, line: usize) -> ropey::iter::Lines<'_> {
+        self.rope.lines_at(line.min(self.rope.len_lines()))
+    }
+
+    pub fn char(&self, char_idx: usize) -> char {
+        self.rope.char(char_idx)
+    }
+
+    pub fn chars_at(&self, char_idx: usize) -> ropey::iter::Chars<'_> {
+        self.rope.chars_at(char_idx.min(self.rope.len_chars()))
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> RopeSlice<'_> {
+        self.rope.slice(range)
+    }
+
+    /// Length of a line in chars, not counting its line ending.
+    pub fn line_len(&self, line: usize) -> usize {
+        line_len(self.rope.line(line))
+    }
+
+    /// The text of a line without its line ending.
+    pub fn line_text(&self, line: usize) -> String {
+        let line = self.rope.line(line);
+        line.slice(..line_len(line)).to_string()
+    }
+
+    pub fn line_to_char(&self, line: usize) -> usize {
+        self.rope.line_to_char(line.min(self.rope.len_lines()))
+    }
+
+    pub fn char_to_line(&self, char_idx: usize) -> usize {
+        self.rope.char_to_line(char_idx.min(self.rope.len_chars()))
+    }
+
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        self.rope.char_to_byte(char_idx.min(self.rope.len_chars()))
+    }
+
+    pub fn byte_to_char(&self, byte_idx: usize) -> usize {
+        self.rope.byte_to_char(byte_idx.min(self.rope.len_bytes()))
+    }
+
+    pub fn char_to_point(&self, char_idx: usize) -> Point {
+        let char_idx = char_idx.min(self.rope.len_chars());
+        let line = self.rope.char_to_line(char_idx);
+
+        Point {
+            line,
+            column: char_idx - self.rope.line_to_char(line),
+        }
+    }
+
+    /// The char offset of a point, clamped into the buffer and to the end
+    /// of its line.
+    pub fn point_to_char(&self, point: Point) -> usize {
+        let line = point.line.min(self.rope.len_lines() - 1);
+
+        self.rope.line_to_char(line) + point.column.min(self.line_len(line))
+    }
+
+    pub fn point_to_byte(&self, point: Point) -> usize {
+        self.char_to_byte(self.point_to_char(point))
+    }
+
+    pub fn byte_to_point(&self, byte_idx: usize) -> Point {
+        self.char_to_point(self.byte_to_char(byte_idx))
+    }
+}
+
+impl Snapshot {
+    pub fn get_version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn get_rope(&self) -> &Rope {
+        &self.rope
+    }
+
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    pub fn line(&self, line: usize) -> RopeSlice<'_> {
+        self.rope.line(line)
+    }
+
+    pub fn lines(&self) -> ropey::iter::Lines<'_> {
+        self.rope.lines()
+    }
+}
+
+/// The byte index after the char at `byte`, or one past `byte` at the end
+/// of the text, so empty matches can move on.
+pub fn next_char(text: &str, byte: usize) -> usize {
+    match text.get(byte..).and_then(|rest| rest.chars().next()) {
+        Some(c) => byte + c.len_utf8(),
+        None => byte + 1,
+    }
+}
+
+/// Length of a line in chars, not counting its line ending.
+pub fn line_len(line: RopeSlice) -> usize {
+    let len = line.len_chars();
+
+    match (len.checked_sub(2).map(|i| line.char(i)), len.checked_sub(1).map(|i| line.char(i))) {
+        (Some('\r'), Some('\n')) => len - 2,
+        (_, Some('\n' | '\r' | '\u{0B}' | '\u{0C}' | '\u{85}' | '\u{2028}' | '\u{2029}')) => len - 1,
+        _ => len,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(position: usize, removed: &str, inserted: &str) -> Change {
+        Change { position, removed: removed.to_string(), inserted: inserted.to_string() }
+    }
+
+    #[test]
+    fn converts_points_and_chars() {
+        let buffer = Buffer::from_text("ab\ncdé\n\nf");
+
+        assert_eq!(buffer.char_to_point(0), Point::new(0, 0));
+        assert_eq!(buffer.char_to_point(3), Point::new(1, 0));
+        assert_eq!(buffer.char_to_point(6), Point::new(1, 3));
+        assert_eq!(buffer.char_to_point(7), Point::new(2, 0));
+        assert_eq!(buffer.char_to_point(100), Point::new(3, 1));
+
+        assert_eq!(buffer.point_to_char(Point::new(1, 2)), 5);
+        // clamped to the end of the line, and to the last line
+        assert_eq!(buffer.point_to_char(Point::new(0, 10)), 2);
+        assert_eq!(buffer.point_to_char(Point::new(9, 0)), 8);
+
+        assert_eq!(buffer.point_to_byte(Point::new(3, 0)), 9);
+        assert_eq!(buffer.byte_to_point(7), Point::new(1, 3));
+    }
+
+    #[test]
+    fn lines_leave_out_their_endings() {
+        let buffer = Buffer::from_text("one\r\ntwo\nthree");
+
+        assert_eq!(buffer.len_lines(), 3);
+        assert_eq!(buffer.line_len(0), 3);
+        assert_eq!(buffer.line_text(0), "one");
+        assert_eq!(buffer.line_text(2), "three");
+    }
+
+    #[test]
+    fn edits_are_recorded() {
+        let mut buffer = Buffer::from_text("hello world");
+        buffer.replace(0..5, "goodbye");
+        buffer.remove(7..13);
+
+        assert_eq!(buffer.get_rope().to_string(), "goodbye");
+        assert_eq!(buffer.get_version(), 3);
+
+        let changes = buffer.take_changes();
+        assert_eq!(changes, vec![change(0, "hello", ""), change(0, "", "goodbye"), change(7, " world", "")]);
+        assert!(!buffer.has_changes());
+
+        for change in changes.iter().rev() {
+            buffer.apply(&change.invert());
+        }
+
+        assert_eq!(buffer.get_rope().to_string(), "hello world");
+    }
+
+    #[test]
+    fn merges_typing_and_deleting_backwards() {
+        let mut typed = change(4, "", "ab");
+        assert!(typed.merge(&change(6, "", "c")));
+        assert_eq!(typed, change(4, "", "abc"));
+        assert!(!typed.merge(&change(2, "", "x")));
+
+        let mut deleted = change(5, "c", "");
+        assert!(deleted.merge(&change(3, "ab", "")));
+        assert_eq!(deleted, change(3, "abc", ""));
+        assert!(!deleted.merge(&change(3, "d", "")));
+
+        assert!(!change(0, "x", "y").merge(&change(1, "", "z")));
+    }
+
+    #[test]
+    fn maps_positions_through_a_change() {
+        let change = change(2, "abc", "x");
+
+        assert_eq!(change.map_position(1), 1);
+        assert_eq!(change.map_position(3), 2);
+        assert_eq!(change.map_position(5), 3);
+        assert_eq!(change.map_position(9), 7);
+    }
+
+    #[test]
+    fn line_edits_since_a_version() {
+        let mut buffer = Buffer::from_text("a\nb\nc");
+        buffer.insert(2, "x\ny\n");
+        buffer.remove(0..2);
+
+        let edits: Vec<LineEdit> = buffer.line_edits_since(0).unwrap().collect();
+        assert_eq!(edits, vec![
+            LineEdit { start: 1, old_end: 1, new_end: 3, whole_lines: true },
+            LineEdit { start: 0, old_end: 1, new_end: 0, whole_lines: true },
+        ]);
+        assert_eq!(buffer.line_edits_since(1).unwrap().count(), 1);
+        assert!(buffer.line_edits_since(5).is_none());
+    }
+
+    #[test]
+    fn maps_lines_across_edits() {
+        let mut buffer = Buffer::from_text("a
+b
+c
+d");
+        buffer.insert(3, "x
+y");
+        buffer.insert(buffer.line_to_char(3), "z
+");
+        buffer.remove(0..5);
+
+        let edits: Vec<LineEdit> = buffer.line_edits_since(0).unwrap().collect();
+
+        // within a line the line's text is rewritten, before whole lines
+        // it moves
+        let mapped = |edit: &LineEdit| (0..5).map(|line| edit.map_line(line)).collect::<Vec<_>>();
+        assert_eq!(mapped(&edits[0]), [Some(0), Some(1), Some(3), Some(4), Some(5)]);
+        assert_eq!(mapped(&edits[1]), [Some(0), Some(1), Some(2), Some(4), Some(5)]);
+        assert_eq!(mapped(&edits[2]), [None, None, Some(0), Some(1), Some(2)]);
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    cell::RefCell,
+    env,
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+    rc::Rc,
+};
+
+use crate::{config::{ClipboardConfig, ClipboardProvider}, renderer::Renderer};
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Clipboard commands tried in order when none is configured, with the
+/// environment variable that must be set for them to work.
+const DETECTED_COMMANDS: &[(Option<&str>, &[&str], &[&str])] = &[
+    (Some("WAYLAND_DISPLAY"), &["wl-copy"], &["wl-paste", "--no-newline"]),
+    (Some("DISPLAY"), &["xclip", "-selection", "clipboard"], &["xclip", "-selection", "clipboard", "-o"]),
+    (Some("DISPLAY"), &["xsel", "--clipboard", "--input"], &["xsel", "--clipboard", "--output"]),
+    (None, &["pbcopy"], &["pbpaste"]),
+];
+
+/// The system clipboard, reached through the terminal with OSC 52 or
+/// through external commands.
+#[derive(Debug, Clone, Default)]
+pub struct Clipboard {
+    osc52: bool,
+    copy_command: Option<Vec<String>>,
+    paste_command: Option<Vec<String>>,
+    /// Where OSC 52 is written, in turn with the frames drawn there.
+    terminal: Option<Rc<RefCell<Renderer>>>,
+}
+
+impl Clipboard {
+    /// Uses the configured commands, or the first known clipboard command
+    /// found on the path.
+    pub fn new(config: &ClipboardConfig) -> Self {
+        let (osc52, commands) = match config.provider {
+            ClipboardProvider::Auto => (true, true),
+            ClipboardProvider::Osc52 => (true, false),
+            ClipboardProvider::Command => (false, true),
+            ClipboardProvider::None => (false, false),
+        };
+
+        let detected = match commands && (config.copy_command.is_none() || config.paste_command.is_none()) {
+            true => detect_commands(),
+            false => None,
+        };
+
+        let (copy_command, paste_command) = match commands {
+            true => (
+                config.copy_command.clone().or_else(|| detected.as_ref().map(|(copy, _)| copy.clone())),
+                config.paste_command.clone().or_else(|| detected.map(|(_, paste)| paste)),
+            ),
+            false => (None, None),
+        };
+
+        Clipboard { osc52, copy_command, paste_command, terminal: None }
+    }
+
+    /// Copies with OSC 52 through the renderer's output, which it needs to
+    /// do so at all.
+    pub fn set_terminal(&mut self, renderer: Rc<RefCell<Renderer>>) -> &mut Self {
+        self.terminal = Some(renderer);
+        self
+    }
+
+    /// Whether anything can be copied out of the editor.
+    pub fn can_copy(&self) -> bool {
+        (self.osc52 && self.terminal.is_some()) || self.copy_command.is_some()
+    }
+
+    pub fn can_paste(&self) -> bool {
+        self.paste_command.is_some()
+    }
+
+    /// Copies text with the command, and with OSC 52 as well since the
+    /// command may reach a different machine's clipboard over SSH.
+    pub fn copy(&self, text: &str) -> io::Result<()> {
+        if let Some(terminal) = self.terminal.as_ref().filter(|_| self.osc52) {
+            let mut renderer = terminal.borrow_mut();
+            let out = renderer.get_stdout_buf();
+            write!(out, "{}", osc52(text))?;
+            out.flush()?;
+        }
+
+        match &self.copy_command {
+            Some(command) => run_copy(command, text),
+            None => Ok(()),
+        }
+    }
+
+    /// The clipboard's text, or None without a paste command.
+    pub fn paste(&self) -> io::Result<Option<String>> {
+        let Some(command) = &self.paste_command else {
+            return Ok(None);
+        };
+
+        let output = Command::new(&command[0])
+            .args(&command[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()?;
+
+        if !output.status.success() {
+            return Err(io::Error::other(format!("'{}' failed with {}", command[0], output.status)));
+        }
+
+        let text = String::from_utf8_lossy(&output.stdout);
+        Ok(Some(text.replace("\r\n", "\n")))
+    }
+}
+
+fn run_copy(command: &[String], text: &str) -> io::Result<()> {
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // closing stdin tells the command the text is complete
+    let write_result = child.stdin.take()
+        .map_or(Ok(()), |mut stdin| stdin.write_all(text.as_bytes()));
+    let status = child.wait()?;
+    write_result?;
+
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!("'{}' failed with {}", command[0], status))),
+    }
+}
+
+fn detect_commands() -> Option<(Vec<String>, Vec<String>)> {
+    let to_strings = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+
+    DETECTED_COMMANDS.iter()
+        .find(|(var, copy, _)| {
+            var.is_none_or(|var| env::var_os(var).is_some_and(|value| !value.is_empty()))
+                && find_in_path(copy[0])
+        })
+        .map(|(_, copy, paste)| (to_strings(copy), to_strings(paste)))
+}
+
+fn find_in_path(program: &str) -> bool {
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+
+    env::split_paths(&path).any(|dir| is_executable(&dir.join(program)))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata().is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// The escape sequence asking the terminal to put text on the clipboard.
+pub fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64(text.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter()
+            .enumerate()
+            .fold(0u32, |group, (i, byte)| group | (*byte as u32) << (16 - i * 8));
+
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(BASE64_CHARS[(group >> (18 - i * 6)) as usize & 63] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, process};
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("text-editor-clipboard-{}-{}", process::id(), name))
+    }
+
+    fn commands(copy: &[&str], paste: &[&str]) -> Clipboard {
+        let to_strings = |args: &[&str]| Some(args.iter().map(|arg| arg.to_string()).collect());
+
+        Clipboard::new(&ClipboardConfig {
+            provider: ClipboardProvider::Command,
+            copy_command: to_strings(copy),
+            paste_command: to_strings(paste),
+            ..ClipboardConfig::default()
+        })
+    }
+
+    #[test]
+    fn copies_and_pastes_with_commands() {
+        let path = temp_path("round-trip");
+        let file = path.to_string_lossy();
+        let clipboard = commands(&["sh", "-c", &format!("cat > '{}'", file)], &["cat", &file]);
+
+        assert!(clipboard.can_copy() && clipboard.can_paste());
+        clipboard.copy("héllo\nworld").unwrap();
+        assert_eq!(clipboard.paste().unwrap().as_deref(), Some("héllo\nworld"));
+
+        // pasted line endings come in as the buffer keeps them
+        fs::write(&path, "a\r\nb").unwrap();
+        assert_eq!(clipboard.paste().unwrap().as_deref(), Some("a\nb"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn failing_commands_are_errors() {
+        let clipboard = commands(&["sh", "-c", "cat > /dev/null; exit 3"], &["sh", "-c", "exit 1"]);
+        assert!(clipboard.copy("text").is_err());
+        assert!(clipboard.paste().is_err());
+
+        let clipboard = commands(&["text-editor-no-such-command"], &["text-editor-no-such-command"]);
+        assert_eq!(clipboard.copy("text").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(clipboard.paste().unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn osc52_needs_a_terminal() {
+        let clipboard = Clipboard::new(&ClipboardConfig { provider: ClipboardProvider::Osc52, ..ClipboardConfig::default() });
+        assert!(!clipboard.can_copy() && !clipboard.can_paste());
+
+        let clipboard = Clipboard::new(&ClipboardConfig { provider: ClipboardProvider::None, ..ClipboardConfig::default() });
+        assert!(!clipboard.can_copy());
+        assert_eq!(clipboard.paste().unwrap(), None);
+    }
+
+    #[test]
+    fn encodes_osc52() {
+        assert_eq!(osc52("hi"), "\x1b]52;c;aGk=\x07");
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar!"), "Zm9vYmFyIQ==");
+        assert_eq!(base64("é".as_bytes()), "w6k=");
+    }
+}
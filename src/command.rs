@@ -0,0 +1,258 @@
+/// A named action that keys can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    Quit,
+    ForceQuit,
+    Redraw,
+    Save,
+    FocusNext,
+    FocusPrevious,
+    FocusLeft,
+    FocusRight,
+    FocusUp,
+    FocusDown,
+    GrowWidth,
+    ShrinkWidth,
+    GrowHeight,
+    ShrinkHeight,
+    SplitRight,
+    SplitDown,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    BigWordForward,
+    BigWordBackward,
+    BigWordEnd,
+    LineStart,
+    LineEnd,
+    FirstNonBlank,
+    ParagraphForward,
+    ParagraphBackward,
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+    ScrollUp,
+    ScrollDown,
+    ScrollCursorTop,
+    ScrollCursorCenter,
+    ScrollCursorBottom,
+    GotoFirstLine,
+    GotoLastLine,
+    DisplayLineUp,
+    DisplayLineDown,
+    DisplayLineStart,
+    DisplayLineEnd,
+    GotoMatchingBracket,
+    NormalMode,
+    InsertMode,
+    Append,
+    InsertLineStart,
+    AppendLineEnd,
+    OpenLineBelow,
+    OpenLineAbove,
+    InsertNewline,
+    InsertTab,
+    DeleteBackward,
+    DeleteForward,
+    VisualMode,
+    SelectLine,
+    SelectWord,
+    SelectAll,
+    FlipSelection,
+    Copy,
+    Cut,
+    CopyLine,
+    CutLine,
+    PasteAfter,
+    PasteBefore,
+    CyclePaste,
+    Undo,
+    Redo,
+    Earlier,
+    Later,
+    EarlierMinutes,
+    LaterMinutes,
+    AddCursorAbove,
+    AddCursorBelow,
+    AddNextMatch,
+    SplitSelectionLines,
+    SelectMatches,
+    PromptAccept,
+    PromptCancel,
+    Search,
+    SearchBackward,
+    SearchNext,
+    SearchPrevious,
+    ToggleSearchRegex,
+    ToggleSearchWholeWord,
+    ToggleSearchCase,
+    TogglePreserveCase,
+    Replace,
+    ReplaceConfirm,
+    WorkspaceSearch,
+    WorkspaceReplace,
+    OpenResult,
+    CloseResults,
+    NextTheme,
+    PreviousTheme,
+    CycleLineNumbers,
+    ToggleBookmark,
+    ToggleSoftWrap,
+    FoldOpen,
+    FoldClose,
+    FoldToggle,
+    FoldOpenRecursive,
+    FoldCloseRecursive,
+    FoldToggleRecursive,
+    OpenAllFolds,
+    CloseAllFolds,
+    CreateFold,
+    DeleteFold,
+    DeleteAllFolds,
+    ToggleWhitespace,
+    ConvertIndentToSpaces,
+    ConvertIndentToTabs,
+    SetTabWidth,
+    ToggleUseTabs,
+}
+
+const COMMAND_NAMES: &[(Command, &str)] = &[
+    (Command::Quit, "quit"),
+    (Command::ForceQuit, "force_quit"),
+    (Command::Redraw, "redraw"),
+    (Command::Save, "save"),
+    (Command::FocusNext, "focus_next"),
+    (Command::FocusPrevious, "focus_previous"),
+    (Command::FocusLeft, "focus_left"),
+    (Command::FocusRight, "focus_right"),
+    (Command::FocusUp, "focus_up"),
+    (Command::FocusDown, "focus_down"),
+    (Command::GrowWidth, "grow_width"),
+    (Command::ShrinkWidth, "shrink_width"),
+    (Command::GrowHeight, "grow_height"),
+    (Command::ShrinkHeight, "shrink_height"),
+    (Command::SplitRight, "split_right"),
+    (Command::SplitDown, "split_down"),
+    (Command::MoveLeft, "move_left"),
+    (Command::MoveRight, "move_right"),
+    (Command::MoveUp, "move_up"),
+    (Command::MoveDown, "move_down"),
+    (Command::WordForward, "word_forward"),
+    (Command::WordBackward, "word_backward"),
+    (Command::WordEnd, "word_end"),
+    (Command::BigWordForward, "big_word_forward"),
+    (Command::BigWordBackward, "big_word_backward"),
+    (Command::BigWordEnd, "big_word_end"),
+    (Command::LineStart, "line_start"),
+    (Command::LineEnd, "line_end"),
+    (Command::FirstNonBlank, "first_non_blank"),
+    (Command::ParagraphForward, "paragraph_forward"),
+    (Command::ParagraphBackward, "paragraph_backward"),
+    (Command::PageUp, "page_up"),
+    (Command::PageDown, "page_down"),
+    (Command::HalfPageUp, "half_page_up"),
+    (Command::HalfPageDown, "half_page_down"),
+    (Command::ScrollUp, "scroll_up"),
+    (Command::ScrollDown, "scroll_down"),
+    (Command::ScrollCursorTop, "scroll_cursor_top"),
+    (Command::ScrollCursorCenter, "scroll_cursor_center"),
+    (Command::ScrollCursorBottom, "scroll_cursor_bottom"),
+    (Command::GotoFirstLine, "goto_first_line"),
+    (Command::GotoLastLine, "goto_last_line"),
+    (Command::DisplayLineUp, "display_line_up"),
+    (Command::DisplayLineDown, "display_line_down"),
+    (Command::DisplayLineStart, "display_line_start"),
+    (Command::DisplayLineEnd, "display_line_end"),
+    (Command::GotoMatchingBracket, "goto_matching_bracket"),
+    (Command::NormalMode, "normal_mode"),
+    (Command::InsertMode, "insert_mode"),
+    (Command::Append, "append"),
+    (Command::InsertLineStart, "insert_line_start"),
+    (Command::AppendLineEnd, "append_line_end"),
+    (Command::OpenLineBelow, "open_line_below"),
+    (Command::OpenLineAbove, "open_line_above"),
+    (Command::InsertNewline, "insert_newline"),
+    (Command::InsertTab, "insert_tab"),
+    (Command::DeleteBackward, "delete_backward"),
+    (Command::DeleteForward, "delete_forward"),
+    (Command::VisualMode, "visual_mode"),
+    (Command::SelectLine, "select_line"),
+    (Command::SelectWord, "select_word"),
+    (Command::SelectAll, "select_all"),
+    (Command::FlipSelection, "flip_selection"),
+    (Command::Copy, "copy"),
+    (Command::Cut, "cut"),
+    (Command::CopyLine, "copy_line"),
+    (Command::CutLine, "cut_line"),
+    (Command::PasteAfter, "paste_after"),
+    (Command::PasteBefore, "paste_before"),
+    (Command::CyclePaste, "cycle_paste"),
+    (Command::Undo, "undo"),
+    (Command::Redo, "redo"),
+    (Command::Earlier, "earlier"),
+    (Command::Later, "later"),
+    (Command::EarlierMinutes, "earlier_minutes"),
+    (Command::LaterMinutes, "later_minutes"),
+    (Command::AddCursorAbove, "add_cursor_above"),
+    (Command::AddCursorBelow, "add_cursor_below"),
+    (Command::AddNextMatch, "add_next_match"),
+    (Command::SplitSelectionLines, "split_selection_lines"),
+    (Command::SelectMatches, "select_matches"),
+    (Command::PromptAccept, "prompt_accept"),
+    (Command::PromptCancel, "prompt_cancel"),
+    (Command::Search, "search"),
+    (Command::SearchBackward, "search_backward"),
+    (Command::SearchNext, "search_next"),
+    (Command::SearchPrevious, "search_previous"),
+    (Command::ToggleSearchRegex, "toggle_search_regex"),
+    (Command::ToggleSearchWholeWord, "toggle_search_whole_word"),
+    (Command::ToggleSearchCase, "toggle_search_case"),
+    (Command::TogglePreserveCase, "toggle_preserve_case"),
+    (Command::Replace, "replace"),
+    (Command::ReplaceConfirm, "replace_confirm"),
+    (Command::WorkspaceSearch, "workspace_search"),
+    (Command::WorkspaceReplace, "workspace_replace"),
+    (Command::OpenResult, "open_result"),
+    (Command::CloseResults, "close_results"),
+    (Command::NextTheme, "next_theme"),
+    (Command::PreviousTheme, "previous_theme"),
+    (Command::CycleLineNumbers, "cycle_line_numbers"),
+    (Command::ToggleBookmark, "toggle_bookmark"),
+    (Command::ToggleSoftWrap, "toggle_soft_wrap"),
+    (Command::FoldOpen, "fold_open"),
+    (Command::FoldClose, "fold_close"),
+    (Command::FoldToggle, "fold_toggle"),
+    (Command::FoldOpenRecursive, "fold_open_recursive"),
+    (Command::FoldCloseRecursive, "fold_close_recursive"),
+    (Command::FoldToggleRecursive, "fold_toggle_recursive"),
+    (Command::OpenAllFolds, "open_all_folds"),
+    (Command::CloseAllFolds, "close_all_folds"),
+    (Command::CreateFold, "create_fold"),
+    (Command::DeleteFold, "delete_fold"),
+    (Command::DeleteAllFolds, "delete_all_folds"),
+    (Command::ToggleWhitespace, "toggle_whitespace"),
+    (Command::ConvertIndentToSpaces, "convert_indent_to_spaces"),
+    (Command::ConvertIndentToTabs, "convert_indent_to_tabs"),
+    (Command::SetTabWidth, "set_tab_width"),
+    (Command::ToggleUseTabs, "toggle_use_tabs"),
+];
+
+impl Command {
+    pub fn from_name(name: &str) -> Option<Command> {
+        COMMAND_NAMES.iter()
+            .find(|(_, command_name)| *command_name == name)
+            .map(|(command, _)| *command)
+    }
+
+    pub fn name(&self) -> &'static str {
+        COMMAND_NAMES.iter()
+            .find(|(command, _)| command == self)
+            .map(|(_, name)| *name)
+            .expect("Every command has a name")
+    }
+}
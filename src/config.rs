@@ -0,0 +1,597 @@
+use std::{collections::HashMap, env, fs, path::{Path, PathBuf}};
+
+use crate::{document::Indent, renderer::{background::Background, color::ColorDepth}};
+
+const APP_DIR: &str = "text-editor";
+const CONFIG_FILE: &str = "config.toml";
+
+/// `$XDG_CONFIG_HOME/text-editor`, falling back to `~/.config/text-editor`.
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_DATA_HOME/text-editor`, falling back to `~/.local/share/text-editor`.
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+fn xdg_dir(var: &str, home_fallback: &str) -> Option<PathBuf> {
+    let base = match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(home_fallback),
+    };
+
+    Some(base.join(APP_DIR))
+}
+
+/// How the gutter numbers lines.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineNumbers {
+    None,
+    #[default]
+    Absolute,
+    /// Distance from the cursor line.
+    Relative,
+    /// Distance from the cursor line, with the cursor line's own number.
+    Hybrid,
+}
+
+impl LineNumbers {
+    /// The next setting when cycling through them.
+    pub fn next(&self) -> LineNumbers {
+        match self {
+            LineNumbers::None => LineNumbers::Absolute,
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Hybrid,
+            LineNumbers::Hybrid => LineNumbers::None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineNumbers::None => "none",
+            LineNumbers::Absolute => "absolute",
+            LineNumbers::Relative => "relative",
+            LineNumbers::Hybrid => "hybrid",
+        }
+    }
+}
+
+/// Where folds come from, besides those made by hand.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldMethod {
+    /// Only folds made by hand.
+    Manual,
+    /// A line and the more indented lines after it.
+    #[default]
+    Indent,
+    /// Bracket pairs and runs of comment lines, as the highlighter sees
+    /// them.
+    Syntax,
+}
+
+/// Settings for editor views.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EditorConfig {
+    /// Lines kept visible above and below the cursor.
+    pub scroll_off: usize,
+    /// Columns kept visible left and right of the cursor.
+    pub side_scroll_off: usize,
+    pub line_numbers: LineNumbers,
+    /// Whether long lines continue on the rows below rather than running
+    /// off the side of the view.
+    pub soft_wrap: bool,
+    /// Column to wrap at when narrower than the view.
+    pub wrap_column: Option<usize>,
+    /// Shown at the start of each row a wrapped line continues on.
+    pub wrap_indicator: Option<char>,
+    /// Whether the rows a line continues on line up with its indent.
+    pub wrap_indent: bool,
+    pub fold_method: FoldMethod,
+    /// Cells between tab stops, and spaces to a level when indenting with
+    /// spaces.
+    pub tab_width: usize,
+    /// Whether new files are indented with tabs.
+    pub use_tabs: bool,
+    /// Whether files keep to the indent they already use, in place of the
+    /// two above.
+    pub detect_indent: bool,
+    /// Whether tabs and whitespace at the ends of lines show as markers.
+    pub show_whitespace: bool,
+    /// Shown at the start of each tab.
+    pub tab_marker: Option<char>,
+    /// Shown in place of each space or tab at the end of a line.
+    pub trailing_marker: Option<char>,
+}
+
+impl Default for EditorConfig {
+    fn default() -> Self {
+        EditorConfig {
+            scroll_off: 3,
+            side_scroll_off: 5,
+            line_numbers: LineNumbers::Absolute,
+            soft_wrap: false,
+            wrap_column: None,
+            wrap_indicator: Some('↪'),
+            wrap_indent: false,
+            fold_method: FoldMethod::Indent,
+            tab_width: 4,
+            use_tabs: false,
+            detect_indent: true,
+            show_whitespace: false,
+            tab_marker: Some('→'),
+            trailing_marker: Some('·'),
+        }
+    }
+}
+
+/// How copies reach the system clipboard.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardProvider {
+    /// OSC 52 for copies, and a clipboard command when one is found.
+    #[default]
+    Auto,
+    /// Only OSC 52, which terminals support for copying but rarely for
+    /// pasting.
+    Osc52,
+    /// Only the clipboard commands.
+    Command,
+    /// Copies stay inside the editor.
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardConfig {
+    pub provider: ClipboardProvider,
+    /// Whether the unnamed register is the system clipboard, rather than
+    /// only `+` and `*`.
+    pub sync: bool,
+    /// Command reading text to copy from stdin, found on the path if unset.
+    pub copy_command: Option<Vec<String>>,
+    /// Command writing the clipboard to stdout, found on the path if unset.
+    pub paste_command: Option<Vec<String>>,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        ClipboardConfig {
+            provider: ClipboardProvider::Auto,
+            sync: true,
+            copy_command: None,
+            paste_command: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndoConfig {
+    /// Whether undo history is kept in the data dir between sessions.
+    pub persist: bool,
+    /// Files larger than this in bytes keep history only while open.
+    pub max_file_size: u64,
+}
+
+impl Default for UndoConfig {
+    fn default() -> Self {
+        UndoConfig {
+            persist: true,
+            max_file_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+/// Defaults for searching in a buffer, which the search prompt can toggle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchConfig {
+    /// Whether patterns are regular expressions rather than literal text.
+    pub regex: bool,
+    /// Ignore case unless the pattern has an uppercase letter.
+    pub smart_case: bool,
+    /// Only match whole words.
+    pub whole_word: bool,
+    /// Carry on from the other end of the buffer after the last match.
+    pub wrap_around: bool,
+    /// Give replacements the case of the text they replace.
+    pub preserve_case: bool,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            regex: false,
+            smart_case: true,
+            whole_word: false,
+            wrap_around: true,
+            preserve_case: false,
+        }
+    }
+}
+
+/// Editing helpers for a kind of file, set under `[languages.<name>]` by
+/// the name of its grammar in lowercase or by its file extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanguageConfig {
+    /// Whether new lines take the indent of the line before, and lines
+    /// starting with a closing bracket that of the line it closes.
+    pub auto_indent: bool,
+    /// What lines end with for the line after to be indented once more.
+    pub indent_after: Vec<String>,
+    /// Chars that dedent their line when typed first on it.
+    pub dedent_on: Vec<char>,
+    /// Opening and closing chars typed together, the closing one being
+    /// typed over if it is typed next.
+    pub auto_pairs: Vec<(char, char)>,
+    /// Brackets matched up for highlighting and jumping between.
+    pub brackets: Vec<(char, char)>,
+}
+
+impl Default for LanguageConfig {
+    fn default() -> Self {
+        LanguageConfig {
+            auto_indent: true,
+            indent_after: vec!["{".to_string(), "[".to_string(), "(".to_string()],
+            dedent_on: vec!['}', ']', ')'],
+            auto_pairs: vec![('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')],
+            brackets: vec![('(', ')'), ('[', ']'), ('{', '}')],
+        }
+    }
+}
+
+impl LanguageConfig {
+    /// The settings for languages that differ from the defaults.
+    pub fn builtin(name: &str) -> Option<LanguageConfig> {
+        let default = LanguageConfig::default();
+
+        match name {
+            // quotes start lifetimes as often as chars
+            "rust" | "rs" => Some(LanguageConfig {
+                auto_pairs: default.auto_pairs.into_iter().filter(|(open, _)| *open != '\'').collect(),
+                ..default
+            }),
+            "python" | "py" => Some(LanguageConfig {
+                indent_after: [":", "{", "[", "("].map(str::to_string).to_vec(),
+                ..default
+            }),
+            "markdown" | "md" => Some(LanguageConfig {
+                indent_after: Vec::new(),
+                dedent_on: Vec::new(),
+                auto_pairs: vec![('(', ')'), ('[', ']'), ('{', '}'), ('`', '`')],
+                ..default
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// What the terminal can show, worked out at startup unless set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TerminalConfig {
+    pub colors: Option<ColorDepth>,
+    /// Picks between `light_theme` and `dark_theme` when no `theme` is set.
+    pub background: Option<Background>,
+}
+
+/// User settings, read from `config.toml` in the config dir.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub editor: EditorConfig,
+    pub clipboard: ClipboardConfig,
+    pub undo: UndoConfig,
+    pub search: SearchConfig,
+    pub terminal: TerminalConfig,
+    /// Settings by language name or file extension, over the built-in
+    /// ones.
+    pub languages: HashMap<String, LanguageConfig>,
+    /// Name of the theme to start with.
+    pub theme: Option<String>,
+    /// Theme to start with on a light background, when `theme` is unset.
+    pub light_theme: Option<String>,
+    /// Theme to start with on a dark background, when `theme` is unset.
+    pub dark_theme: Option<String>,
+}
+
+impl Config {
+    /// The default config with the user's config file applied over it.
+    pub fn load() -> Result<Config, String> {
+        let mut config = Config::default();
+
+        if let Some(path) = config_dir().map(|dir| dir.join(CONFIG_FILE)) {
+            if path.exists() {
+                config.load_file(&path)?;
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn load_file(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+
+        self.load_str(&text)
+            .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Applies settings from TOML:
+    ///
+    /// ```toml
+    /// theme = "tomorrow-night"
+    ///
+    /// [terminal]
+    /// colors = "256"
+    ///
+    /// [editor]
+    /// scroll_off = 5
+    /// line_numbers = "hybrid"
+    /// soft_wrap = true
+    /// wrap_indicator = ""
+    /// fold_method = "syntax"
+    /// tab_width = 8
+    /// show_whitespace = true
+    ///
+    /// [languages.python]
+    /// indent_after = [":"]
+    ///
+    /// [clipboard]
+    /// copy_command = ["xclip", "-selection", "clipboard"]
+    /// ```
+    pub fn load_str(&mut self, text: &str) -> Result<(), String> {
+        let table: toml::Table = text.parse()
+            .map_err(|err: toml::de::Error| err.message().to_string())?;
+
+        for (key, value) in table.iter() {
+            match (key.as_str(), value) {
+                ("editor", toml::Value::Table(editor)) => self.editor.load_table(editor)?,
+                ("clipboard", toml::Value::Table(clipboard)) => self.clipboard.load_table(clipboard)?,
+                ("undo", toml::Value::Table(undo)) => self.undo.load_table(undo)?,
+                ("search", toml::Value::Table(search)) => self.search.load_table(search)?,
+                ("terminal", toml::Value::Table(terminal)) => self.terminal.load_table(terminal)?,
+                ("languages", toml::Value::Table(languages)) => {
+                    for (name, value) in languages.iter() {
+                        let toml::Value::Table(table) = value else {
+                            return Err(format!("'languages.{}' must be a table", name));
+                        };
+
+                        self.languages.entry(name.clone())
+                            .or_insert_with(|| LanguageConfig::builtin(name).unwrap_or_default())
+                            .load_table(name, table)?;
+                    }
+                }
+                ("theme", toml::Value::String(theme)) => self.theme = Some(theme.clone()),
+                ("light_theme", toml::Value::String(theme)) => self.light_theme = Some(theme.clone()),
+                ("dark_theme", toml::Value::String(theme)) => self.dark_theme = Some(theme.clone()),
+                (key, _) => return Err(format!("Unexpected entry '{}'", key)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The editing helpers for a file, by the first of `names` with any
+    /// set, such as its grammar's name and its extension.
+    pub fn language(&self, names: &[&str]) -> LanguageConfig {
+        names.iter()
+            .find_map(|name| self.languages.get(*name).cloned())
+            .or_else(|| names.iter().find_map(|name| LanguageConfig::builtin(name)))
+            .unwrap_or_default()
+    }
+}
+
+impl EditorConfig {
+    /// The indent of files it cannot be told from.
+    pub fn indent(&self) -> Indent {
+        Indent {
+            width: self.tab_width,
+            tabs: self.use_tabs,
+        }
+    }
+
+    fn load_table(&mut self, table: &toml::Table) -> Result<(), String> {
+        for (key, value) in table.iter() {
+            match (key.as_str(), value) {
+                ("scroll_off", value) => self.scroll_off = unsigned(key, value)?,
+                ("side_scroll_off", value) => self.side_scroll_off = unsigned(key, value)?,
+                ("line_numbers", toml::Value::String(line_numbers)) => {
+                    self.line_numbers = match line_numbers.as_str() {
+                        "none" => LineNumbers::None,
+                        "absolute" => LineNumbers::Absolute,
+                        "relative" => LineNumbers::Relative,
+                        "hybrid" => LineNumbers::Hybrid,
+                        line_numbers => return Err(format!("Unknown line numbering '{}'", line_numbers)),
+                    };
+                }
+                ("soft_wrap", toml::Value::Boolean(soft_wrap)) => self.soft_wrap = *soft_wrap,
+                ("wrap_column", value) => {
+                    // 0 wraps at the view width
+                    self.wrap_column = Some(unsigned(key, value)?).filter(|column| *column > 0);
+                }
+                ("wrap_indicator", toml::Value::String(indicator)) => self.wrap_indicator = marker(key, indicator)?,
+                ("wrap_indent", toml::Value::Boolean(wrap_indent)) => self.wrap_indent = *wrap_indent,
+                ("fold_method", toml::Value::String(fold_method)) => {
+                    self.fold_method = match fold_method.as_str() {
+                        "manual" => FoldMethod::Manual,
+                        "indent" => FoldMethod::Indent,
+                        "syntax" => FoldMethod::Syntax,
+                        fold_method => return Err(format!("Unknown fold method '{}'", fold_method)),
+                    };
+                }
+                ("tab_width", value) => {
+                    self.tab_width = unsigned(key, value)?;
+
+                    if self.tab_width == 0 {
+                        return Err("'tab_width' must be at least 1".to_string());
+                    }
+                }
+                ("use_tabs", toml::Value::Boolean(use_tabs)) => self.use_tabs = *use_tabs,
+                ("detect_indent", toml::Value::Boolean(detect_indent)) => self.detect_indent = *detect_indent,
+                ("show_whitespace", toml::Value::Boolean(show_whitespace)) => self.show_whitespace = *show_whitespace,
+                ("tab_marker", toml::Value::String(tab_marker)) => self.tab_marker = marker(key, tab_marker)?,
+                ("trailing_marker", toml::Value::String(trailing_marker)) => {
+                    self.trailing_marker = marker(key, trailing_marker)?;
+                }
+                (key, _) => return Err(format!("Unexpected entry 'editor.{}'", key)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ClipboardConfig {
+    fn load_table(&mut self, table: &toml::Table) -> Result<(), String> {
+        for (key, value) in table.iter() {
+            match (key.as_str(), value) {
+                ("provider", toml::Value::String(provider)) => {
+                    self.provider = match provider.as_str() {
+                        "auto" => ClipboardProvider::Auto,
+                        "osc52" => ClipboardProvider::Osc52,
+                        "command" => ClipboardProvider::Command,
+                        "none" => ClipboardProvider::None,
+                        provider => return Err(format!("Unknown clipboard provider '{}'", provider)),
+                    };
+                }
+                ("sync", toml::Value::Boolean(sync)) => self.sync = *sync,
+                ("copy_command", command) => self.copy_command = Some(command_line(key, command)?),
+                ("paste_command", command) => self.paste_command = Some(command_line(key, command)?),
+                (key, _) => return Err(format!("Unexpected entry 'clipboard.{}'", key)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl UndoConfig {
+    fn load_table(&mut self, table: &toml::Table) -> Result<(), String> {
+        for (key, value) in table.iter() {
+            match (key.as_str(), value) {
+                ("persist", toml::Value::Boolean(persist)) => self.persist = *persist,
+                ("max_file_size", size) => self.max_file_size = unsigned(key, size)? as u64,
+                (key, _) => return Err(format!("Unexpected entry 'undo.{}'", key)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl SearchConfig {
+    fn load_table(&mut self, table: &toml::Table) -> Result<(), String> {
+        for (key, value) in table.iter() {
+            match (key.as_str(), value) {
+                ("regex", toml::Value::Boolean(regex)) => self.regex = *regex,
+                ("smart_case", toml::Value::Boolean(smart_case)) => self.smart_case = *smart_case,
+                ("whole_word", toml::Value::Boolean(whole_word)) => self.whole_word = *whole_word,
+                ("wrap_around", toml::Value::Boolean(wrap_around)) => self.wrap_around = *wrap_around,
+                ("preserve_case", toml::Value::Boolean(preserve_case)) => self.preserve_case = *preserve_case,
+                (key, _) => return Err(format!("Unexpected entry 'search.{}'", key)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl TerminalConfig {
+    fn load_table(&mut self, table: &toml::Table) -> Result<(), String> {
+        for (key, value) in table.iter() {
+            match (key.as_str(), value) {
+                ("colors", toml::Value::String(colors)) => {
+                    self.colors = match colors.as_str() {
+                        "auto" => None,
+                        "truecolor" | "24bit" => Some(ColorDepth::TrueColor),
+                        "256" => Some(ColorDepth::Ansi256),
+                        "16" => Some(ColorDepth::Ansi16),
+                        colors => return Err(format!("Unknown colour depth '{}'", colors)),
+                    };
+                }
+                ("background", toml::Value::String(background)) => {
+                    self.background = match background.as_str() {
+                        "auto" => None,
+                        "light" => Some(Background::Light),
+                        "dark" => Some(Background::Dark),
+                        background => return Err(format!("Unknown background '{}'", background)),
+                    };
+                }
+                (key, _) => return Err(format!("Unexpected entry 'terminal.{}'", key)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl LanguageConfig {
+    fn load_table(&mut self, name: &str, table: &toml::Table) -> Result<(), String> {
+        for (entry, value) in table.iter() {
+            let key = format!("languages.{}.{}", name, entry);
+            let strings = || -> Result<Vec<String>, String> {
+                value.as_array()
+                    .and_then(|array| array.iter().map(|item| item.as_str().map(str::to_string)).collect())
+                    .ok_or_else(|| format!("'{}' must be an array of strings", key))
+            };
+
+            // pairs are written as two chars, like "()"
+            let pairs = || -> Result<Vec<(char, char)>, String> {
+                strings()?.iter()
+                    .map(|pair| match pair.chars().collect::<Vec<char>>()[..] {
+                        [open, close] => Ok((open, close)),
+                        _ => Err(format!("'{}' must be pairs of chars, like \"()\"", key)),
+                    })
+                    .collect()
+            };
+
+            match (entry.as_str(), value) {
+                ("auto_indent", toml::Value::Boolean(auto_indent)) => self.auto_indent = *auto_indent,
+                ("indent_after", _) => self.indent_after = strings()?,
+                ("dedent_on", _) => {
+                    self.dedent_on = strings()?.iter()
+                        .map(|c| match c.chars().collect::<Vec<char>>()[..] {
+                            [c] => Ok(c),
+                            _ => Err(format!("'{}' must be single chars", key)),
+                        })
+                        .collect::<Result<_, _>>()?;
+                }
+                ("auto_pairs", _) => self.auto_pairs = pairs()?,
+                ("brackets", _) => self.brackets = pairs()?,
+                _ => return Err(format!("Unexpected entry '{}'", key)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A program and its arguments, given as a non-empty array of strings.
+fn command_line(key: &str, value: &toml::Value) -> Result<Vec<String>, String> {
+    let error = || format!("'{}' must be an array of strings naming a command", key);
+
+    let command = value.as_array()
+        .ok_or_else(error)?
+        .iter()
+        .map(|arg| arg.as_str().map(str::to_string))
+        .collect::<Option<Vec<String>>>()
+        .ok_or_else(error)?;
+
+    match command.is_empty() {
+        true => Err(error()),
+        false => Ok(command),
+    }
+}
+
+fn unsigned(key: &str, value: &toml::Value) -> Result<usize, String> {
+    match value {
+        toml::Value::Integer(value) if *value >= 0 => Ok(*value as usize),
+        _ => Err(format!("'{}' must be a non-negative integer", key)),
+    }
+}
+
+/// A char to show in place of something, or None for an empty string.
+fn marker(key: &str, text: &str) -> Result<Option<char>, String> {
+    let mut chars = text.chars();
+
+    match (chars.next(), chars.next()) {
+        (marker, None) => Ok(marker),
+        _ => Err(format!("'{}' must be a single char, or empty", key)),
+    }
+}
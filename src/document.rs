@@ -0,0 +1,430 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+
+use crate::{buffer::Buffer, editor::{history::{CursorState, History}, sign::Signs}};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    /// The ending used by most lines, preferring LF on a tie.
+    fn detect(text: &str) -> LineEnding {
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+
+        if crlf > lf {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+/// Lines looked at to tell how a file is indented.
+const DETECT_LINES: usize = 1000;
+
+/// How a document's lines are indented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Indent {
+    /// Cells between tab stops, and spaces to a level when indenting with
+    /// spaces.
+    pub width: usize,
+    pub tabs: bool,
+}
+
+impl Default for Indent {
+    fn default() -> Self {
+        Indent {
+            width: 4,
+            tabs: false,
+        }
+    }
+}
+
+impl Indent {
+    /// What indents a line one level.
+    pub fn unit(&self) -> String {
+        match self.tabs {
+            true => "\t".to_string(),
+            false => " ".repeat(self.width),
+        }
+    }
+
+    /// The indent used by most indented lines of `buffer`, going by the
+    /// most common step between the indents of lines that follow each
+    /// other. What the lines do not tell is taken from `default`.
+    pub fn detect(buffer: &Buffer, default: Indent) -> Indent {
+        let mut tabs = 0;
+        let mut spaces = 0;
+        let mut steps = [0; 9];
+        let mut previous = 0;
+
+        for line in 0..buffer.len_lines().min(DETECT_LINES) {
+            let text = buffer.line_text(line);
+
+            if text.trim().is_empty() {
+                continue;
+            }
+
+            if text.starts_with('\t') {
+                tabs += 1;
+                continue;
+            }
+
+            let indent = text.len() - text.trim_start_matches(' ').len();
+            spaces += (indent > 0) as usize;
+
+            // single spaces are more likely lining up than indenting
+            if let Some(step) = indent.checked_sub(previous).filter(|step| (2..steps.len()).contains(step)) {
+                steps[step] += 1;
+            }
+
+            previous = indent;
+        }
+
+        if tabs == 0 && spaces == 0 {
+            return default;
+        }
+
+        let width = (2..steps.len())
+            .filter(|step| steps[*step] > 0)
+            .max_by_key(|step| (steps[*step], std::cmp::Reverse(*step)))
+            .unwrap_or(default.width);
+
+        match tabs > spaces {
+            true => Indent { width: default.width, tabs: true },
+            false => Indent { width, tabs: false },
+        }
+    }
+}
+
+/// A buffer together with the file it was loaded from.
+///
+/// The buffer always holds LF line endings and no final newline; the
+/// file's own line ending and final newline are restored when saving.
+#[derive(Debug, Default)]
+pub struct Document {
+    buffer: Buffer,
+    path: Option<PathBuf>,
+    line_ending: LineEnding,
+    trailing_newline: bool,
+    indent: Indent,
+    history: History,
+    saved_revision: usize,
+    signs: Signs,
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Document::default()
+    }
+
+    /// An empty document for a file that does not exist yet, which is
+    /// created with a final newline.
+    pub fn new_file(path: Option<&Path>) -> Self {
+        Document {
+            path: path.map(Path::to_path_buf),
+            trailing_newline: true,
+            ..Document::new()
+        }
+    }
+
+    /// Loads the file at `path`, or starts an empty document that will be
+    /// created there on save if it does not exist yet.
+    pub fn open(path: &Path) -> io::Result<Document> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Document::new_file(Some(path))),
+            Err(err) => return Err(err),
+        };
+
+        let text = String::from_utf8(bytes)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "File is not valid UTF-8"))?;
+
+        let line_ending = LineEnding::detect(&text);
+
+        let mut text = match line_ending {
+            LineEnding::CrLf => text.replace("\r\n", "\n"),
+            LineEnding::Lf => text,
+        };
+
+        let trailing_newline = text.ends_with('\n');
+
+        if trailing_newline {
+            text.pop();
+        }
+
+        let buffer = Buffer::from_text(&text);
+
+        Ok(Document {
+            buffer,
+            path: Some(path.to_path_buf()),
+            line_ending,
+            trailing_newline,
+            ..Document::new()
+        })
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        let path = self.path.clone()
+            .ok_or_else(|| io::Error::other("No file name"))?;
+
+        self.save_as(&path)
+    }
+
+    pub fn save_as(&mut self, path: &Path) -> io::Result<()> {
+        let mut text = self.buffer.get_rope().to_string();
+
+        if self.trailing_newline {
+            text.push('\n');
+        }
+
+        if self.line_ending == LineEnding::CrLf {
+            text = text.replace('\n', "\r\n");
+        }
+
+        write_atomic(path, text.as_bytes())?;
+
+        self.path = Some(path.to_path_buf());
+        self.saved_revision = self.history.get_current();
+
+        Ok(())
+    }
+
+    pub fn get_buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn get_buffer_mut(&mut self) -> &mut Buffer {
+        &mut self.buffer
+    }
+
+    /// The signs on the buffer's lines, caught up with its edits.
+    pub fn signs(&mut self) -> &mut Signs {
+        self.signs.sync(&self.buffer);
+        &mut self.signs
+    }
+
+    pub fn get_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn get_line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    pub fn get_indent(&self) -> Indent {
+        self.indent
+    }
+
+    pub fn set_indent(&mut self, indent: Indent) -> &mut Self {
+        self.indent = indent;
+        self
+    }
+
+    pub fn get_history(&self) -> &History {
+        &self.history
+    }
+
+    /// Replaces the history with one whose current revision matches the
+    /// text, such as one stored when the file was last saved.
+    pub fn set_history(&mut self, history: History) -> &mut Self {
+        self.saved_revision = history.get_current();
+        self.history = history;
+        self
+    }
+
+    /// Whether the text differs from the file, which undoing back to the
+    /// saved revision makes false again.
+    pub fn is_modified(&self) -> bool {
+        self.history.get_current() != self.saved_revision || self.buffer.has_changes()
+    }
+
+    /// Records the buffer's edits since the last commit in the history,
+    /// merged into `merge_into` if it is the current revision. Returns the
+    /// revision holding them, or None without edits.
+    pub fn commit(&mut self, before: CursorState, after: CursorState, merge_into: Option<usize>) -> Option<usize> {
+        let changes = self.buffer.take_changes();
+
+        match changes.is_empty() {
+            true => None,
+            false => Some(self.history.commit(changes, before, after, merge_into)),
+        }
+    }
+
+    pub fn undo(&mut self) -> Option<CursorState> {
+        self.history.undo(&mut self.buffer)
+    }
+
+    pub fn redo(&mut self) -> Option<CursorState> {
+        self.history.redo(&mut self.buffer)
+    }
+
+    pub fn earlier(&mut self, steps: usize) -> Option<CursorState> {
+        self.history.earlier(&mut self.buffer, steps)
+    }
+
+    pub fn later(&mut self, steps: usize) -> Option<CursorState> {
+        self.history.later(&mut self.buffer, steps)
+    }
+
+    pub fn earlier_by(&mut self, duration: Duration) -> Option<CursorState> {
+        self.history.earlier_by(&mut self.buffer, duration)
+    }
+
+    pub fn later_by(&mut self, duration: Duration) -> Option<CursorState> {
+        self.history.later_by(&mut self.buffer, duration)
+    }
+
+    pub fn display_name(&self) -> String {
+        match &self.path {
+            Some(path) => path.display().to_string(),
+            None => "[scratch]".to_string(),
+        }
+    }
+}
+
+/// Writes to a temporary file beside `path` and renames it over `path`,
+/// so the file is never left half written. The existing file's
+/// permissions and, where allowed, ownership are carried over.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    // write through symlinks rather than replacing them
+    let path = match fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(err) => return Err(err),
+    };
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let file_name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a file path"))?;
+
+    let temp_path = dir.join(format!(".{}.{}.tmp", file_name.to_string_lossy(), process::id()));
+
+    let result = write_temp(&path, &temp_path, bytes)
+        .and_then(|_| fs::rename(&temp_path, &path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+fn write_temp(path: &Path, temp_path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file: File = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp_path)?;
+
+    file.write_all(bytes)?;
+
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{MetadataExt, fchown};
+
+            // only root can give files away, so failing here is expected
+            // for other users and not worth failing the save over
+            let _ = fchown(&file, Some(metadata.uid()), Some(metadata.gid()));
+        }
+    }
+
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path in the temp dir no other test uses.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("text-editor-document-{}-{}", process::id(), name))
+    }
+
+    /// Opens `bytes` as a file and saves it again, returning the document
+    /// and what was written.
+    fn round_trip(name: &str, bytes: &[u8]) -> (Document, Vec<u8>) {
+        let path = temp_path(name);
+        fs::write(&path, bytes).unwrap();
+
+        let mut document = Document::open(&path).unwrap();
+        document.save().unwrap();
+        let saved = fs::read(&path).unwrap();
+
+        fs::remove_file(&path).unwrap();
+        (document, saved)
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let (document, saved) = round_trip("crlf", b"one\r\ntwo\r\n");
+
+        assert_eq!(document.get_line_ending(), LineEnding::CrLf);
+        assert_eq!(document.get_buffer().get_rope().to_string(), "one\ntwo");
+        assert_eq!(saved, b"one\r\ntwo\r\n");
+    }
+
+    #[test]
+    fn keeps_the_final_newline_or_its_absence() {
+        assert_eq!(round_trip("final", b"a\nb\n").1, b"a\nb\n");
+        assert_eq!(round_trip("no-final", b"a\nb").1, b"a\nb");
+        assert_eq!(round_trip("only-newline", b"\n").1, b"\n");
+        assert_eq!(round_trip("empty", b"").1, b"");
+    }
+
+    #[test]
+    fn new_files_end_with_a_newline() {
+        let path = temp_path("new");
+        let mut document = Document::open(&path).unwrap();
+
+        assert_eq!(document.get_path(), Some(path.as_path()));
+        document.get_buffer_mut().insert(0, "text");
+        document.save().unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"text\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        let path = temp_path("invalid");
+        fs::write(&path, [0xff, 0xfe]).unwrap();
+
+        let err = Document::open(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn detects_the_indent() {
+        let detect = |text: &str| Indent::detect(&Buffer::from_text(text), Indent { width: 8, tabs: false });
+
+        assert_eq!(detect("a {\n  b {\n    c\n  }\n   (d)\n}"), Indent { width: 2, tabs: false });
+        assert_eq!(detect("a:\n    b:\n        c\n    d\n"), Indent { width: 4, tabs: false });
+        assert_eq!(detect("a {\n\tb {\n\t\tc\n\t}\n  d\n}"), Indent { width: 8, tabs: true });
+        assert_eq!(detect("a\nb\n\n"), Indent { width: 8, tabs: false });
+    }
+}
@@ -0,0 +1,149 @@
+use std::ops::Range;
+
+use crate::{buffer::Buffer, syntax::highlighter::Highlighter};
+
+/// Chars looked through for a matching bracket before giving up.
+const MAX_DISTANCE: usize = 100_000;
+
+/// The position of the bracket matching the one at `position`, passing
+/// over nested pairs of the same kind. With a highlighter, brackets in
+/// strings and comments only match each other and those in code only
+/// match each other.
+pub fn find_match(buffer: &Buffer, position: usize, brackets: &[(char, char)], highlighter: Option<&mut Highlighter>) -> Option<usize> {
+    if position >= buffer.len_chars() {
+        return None;
+    }
+
+    let c = buffer.char(position);
+    let &(open, close) = brackets.iter().find(|(open, close)| c == *open || c == *close)?;
+
+    // a pair of the same char has no nesting to go by
+    if open == close {
+        return None;
+    }
+
+    let mut text = TextRanges { highlighter, line: None };
+    let in_text = text.contains(buffer, position);
+    let mut counts = |(position, c): &(usize, char)| (*c != open && *c != close) || text.contains(buffer, *position) == in_text;
+
+    match c == open {
+        true => {
+            let chars = buffer.chars_at(position).enumerate().map(|(offset, c)| (position + offset, c));
+            scan(chars.filter(&mut counts), open, close)
+        }
+        false => {
+            let mut chars = buffer.chars_at(position + 1);
+            let chars = (0..=position).map_while(|offset| Some((position - offset, chars.prev()?)));
+            scan(chars.filter(&mut counts), close, open)
+        }
+    }
+}
+
+/// Which chars are in strings and comments as the highlighter sees them,
+/// worked out a line at a time.
+struct TextRanges<'a> {
+    highlighter: Option<&'a mut Highlighter>,
+    /// The line looked at last, the byte it starts at, and its byte ranges
+    /// in strings and comments.
+    line: Option<(usize, usize, Vec<Range<usize>>)>,
+}
+
+impl TextRanges<'_> {
+    fn contains(&mut self, buffer: &Buffer, position: usize) -> bool {
+        let Some(highlighter) = self.highlighter.as_deref_mut() else {
+            return false;
+        };
+
+        let line = buffer.char_to_line(position);
+
+        if self.line.as_ref().is_none_or(|(known, _, _)| *known != line) {
+            let grammar = highlighter.get_grammar().clone();
+            let is_text = |scope: Option<usize>| scope.is_some_and(|scope| {
+                let scope = &grammar.get_scopes()[scope];
+                scope.starts_with("comment") || scope.starts_with("string")
+            });
+
+            let ranges = highlighter.tokens(buffer, line..line + 1)
+                .pop()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|token| is_text(token.scope) || is_text(token.meta_scope))
+                .map(|token| token.range)
+                .collect();
+
+            self.line = Some((line, buffer.char_to_byte(buffer.line_to_char(line)), ranges));
+        }
+
+        let (_, start, ranges) = self.line.as_ref().expect("Looked at above");
+        let byte = buffer.char_to_byte(position) - start;
+        ranges.iter().any(|range| range.contains(&byte))
+    }
+}
+
+/// Counts `same` up and `other` down from the first char, to where they
+/// even out.
+fn scan(chars: impl Iterator<Item = (usize, char)>, same: char, other: char) -> Option<usize> {
+    let mut depth = 0;
+
+    for (position, c) in chars.take(MAX_DISTANCE) {
+        if c == same {
+            depth += 1;
+        } else if c == other {
+            depth -= 1;
+
+            if depth == 0 {
+                return Some(position);
+            }
+        }
+    }
+
+    None
+}
+
+/// The first bracket at or after `position` on its line.
+pub fn next_on_line(buffer: &Buffer, position: usize, brackets: &[(char, char)]) -> Option<usize> {
+    let line = buffer.char_to_line(position);
+    let end = buffer.line_to_char(line) + buffer.line_len(line);
+
+    buffer.chars_at(position)
+        .take(end.saturating_sub(position))
+        .position(|c| brackets.iter().any(|(open, close)| c == *open || c == *close))
+        .map(|offset| position + offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::syntax::{ScopeStyles, SyntaxSet};
+
+    const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+    #[test]
+    fn passes_over_nested_pairs_both_ways() {
+        let buffer = Buffer::from_text("f(a[(b)], {\n  (c)\n})");
+
+        assert_eq!(find_match(&buffer, 1, &BRACKETS, None), Some(19));
+        assert_eq!(find_match(&buffer, 19, &BRACKETS, None), Some(1));
+        assert_eq!(find_match(&buffer, 3, &BRACKETS, None), Some(7));
+        assert_eq!(find_match(&buffer, 10, &BRACKETS, None), Some(18));
+        assert_eq!(find_match(&buffer, 18, &BRACKETS, None), Some(10));
+        assert_eq!(find_match(&buffer, 0, &BRACKETS, None), None);
+        assert_eq!(find_match(&Buffer::from_text("(()"), 0, &BRACKETS, None), None);
+    }
+
+    #[test]
+    fn skips_brackets_in_strings_and_comments() {
+        let grammar = SyntaxSet::default().for_path(Path::new("main.rs")).expect("Rust is built in");
+        let mut highlighter = Highlighter::new(grammar, &ScopeStyles::default());
+        let buffer = Buffer::from_text("f(\"(\", // )\n    ')', /* ( */ x)");
+
+        assert_eq!(find_match(&buffer, 1, &BRACKETS, Some(&mut highlighter)), Some(30));
+        assert_eq!(find_match(&buffer, 30, &BRACKETS, Some(&mut highlighter)), Some(1));
+        assert_eq!(find_match(&buffer, 1, &BRACKETS, None), Some(17));
+
+        // from a string, only brackets in strings and comments count
+        assert_eq!(find_match(&buffer, 3, &BRACKETS, Some(&mut highlighter)), Some(10));
+    }
+}
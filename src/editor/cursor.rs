@@ -0,0 +1,435 @@
+use std::ops::Range;
+
+use crate::buffer::{Buffer, Point};
+
+use super::grapheme;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    BigWordForward,
+    BigWordBackward,
+    BigWordEnd,
+    LineStart,
+    LineEnd,
+    FirstNonBlank,
+    ParagraphForward,
+    ParagraphBackward,
+    PageUp,
+    PageDown,
+    BufferStart,
+    BufferEnd,
+    /// Zero based line number.
+    GotoLine(usize),
+}
+
+impl Motion {
+    fn is_vertical(&self) -> bool {
+        matches!(self, Motion::Up | Motion::Down | Motion::PageUp | Motion::PageDown)
+    }
+}
+
+/// A position in a buffer that remembers the screen column it wants to
+/// be in, so moving across short lines does not lose it.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    position: usize,
+    desired_column: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Whitespace,
+    Punctuation,
+    Word,
+}
+
+impl CharClass {
+    /// With `big`, any run of non-whitespace counts as one WORD.
+    fn of(c: char, big: bool) -> CharClass {
+        if c.is_whitespace() {
+            CharClass::Whitespace
+        } else if big || c.is_alphanumeric() || c == '_' {
+            CharClass::Word
+        } else {
+            CharClass::Punctuation
+        }
+    }
+}
+
+impl Cursor {
+    pub fn new(position: usize) -> Self {
+        Cursor {
+            position,
+            desired_column: None,
+        }
+    }
+
+    pub fn get_position(&self) -> usize {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: usize) -> &mut Self {
+        self.position = position;
+        self.desired_column = None;
+        self
+    }
+
+    pub fn point(&self, buffer: &Buffer) -> Point {
+        buffer.char_to_point(self.position)
+    }
+
+    /// The screen column of the cursor within its line.
+    pub fn display_column(&self, buffer: &Buffer, tab_width: usize) -> usize {
+        let point = self.point(buffer);
+        grapheme::column_to_display(&buffer.line_text(point.line), point.column, tab_width)
+    }
+
+    /// Moves `count` times, with pages `page_height` lines long and tab
+    /// stops every `tab_width` cells.
+    pub fn apply(&mut self, buffer: &Buffer, motion: Motion, count: usize, page_height: usize, tab_width: usize) {
+        let count = count.max(1);
+
+        if !motion.is_vertical() {
+            self.desired_column = None;
+        }
+
+        match motion {
+            Motion::Up => self.move_lines(buffer, -(count as isize), tab_width),
+            Motion::Down => self.move_lines(buffer, count as isize, tab_width),
+            Motion::PageUp => self.move_lines(buffer, -((count * page_height.max(1)) as isize), tab_width),
+            Motion::PageDown => self.move_lines(buffer, (count * page_height.max(1)) as isize, tab_width),
+            Motion::LineStart => self.position = buffer.line_to_char(self.point(buffer).line),
+            Motion::LineEnd => {
+                let line = self.point(buffer).line;
+                self.position = buffer.line_to_char(line) + buffer.line_len(line);
+                self.desired_column = Some(usize::MAX);
+            }
+            Motion::FirstNonBlank => self.position = first_non_blank(buffer, self.point(buffer).line),
+            Motion::BufferStart => self.position = first_non_blank(buffer, 0),
+            Motion::BufferEnd => self.position = first_non_blank(buffer, buffer.len_lines() - 1),
+            Motion::GotoLine(line) => {
+                self.position = first_non_blank(buffer, line.min(buffer.len_lines() - 1));
+            }
+            _ => {
+                for _ in 0..count {
+                    self.position = self.step(buffer, motion);
+                }
+            }
+        }
+    }
+
+    fn step(&self, buffer: &Buffer, motion: Motion) -> usize {
+        let position = self.position;
+
+        match motion {
+            Motion::Left | Motion::Right => {
+                let point = buffer.char_to_point(position);
+                let line = buffer.line_text(point.line);
+
+                let column = match motion {
+                    Motion::Left => grapheme::prev_boundary(&line, point.column),
+                    _ => grapheme::next_boundary(&line, point.column),
+                };
+
+                buffer.point_to_char(Point::new(point.line, column))
+            }
+            Motion::WordForward => word_forward(buffer, position, false),
+            Motion::BigWordForward => word_forward(buffer, position, true),
+            Motion::WordBackward => word_backward(buffer, position, false),
+            Motion::BigWordBackward => word_backward(buffer, position, true),
+            Motion::WordEnd => word_end(buffer, position, false),
+            Motion::BigWordEnd => word_end(buffer, position, true),
+            Motion::ParagraphForward => paragraph_forward(buffer, position),
+            Motion::ParagraphBackward => paragraph_backward(buffer, position),
+            _ => position,
+        }
+    }
+
+    /// Moves up or down by lines, landing as near the desired screen
+    /// column as the target line allows.
+    fn move_lines(&mut self, buffer: &Buffer, lines: isize, tab_width: usize) {
+        let desired = match self.desired_column {
+            Some(desired) => desired,
+            None => self.display_column(buffer, tab_width),
+        };
+
+        let last_line = buffer.len_lines() as isize - 1;
+        let line = (self.point(buffer).line as isize + lines).clamp(0, last_line) as usize;
+        let column = grapheme::display_to_column(&buffer.line_text(line), desired, tab_width);
+
+        self.position = buffer.point_to_char(Point::new(line, column));
+        self.desired_column = Some(desired);
+    }
+
+    /// Keeps the cursor on a grapheme rather than after the end of a
+    /// non-empty line, as in normal mode.
+    pub fn clamp_to_line(&mut self, buffer: &Buffer) {
+        let point = self.point(buffer);
+        let line = buffer.line_text(point.line);
+        let len = line.chars().count();
+
+        if len > 0 && point.column >= len {
+            let column = grapheme::prev_boundary(&line, len);
+            self.position = buffer.point_to_char(Point::new(point.line, column));
+        }
+    }
+}
+
+fn first_non_blank(buffer: &Buffer, line: usize) -> usize {
+    let text = buffer.line_text(line);
+
+    let column = text.chars()
+        .position(|c| !c.is_whitespace())
+        .unwrap_or(text.chars().count());
+
+    buffer.line_to_char(line) + column
+}
+
+fn class_at(buffer: &Buffer, position: usize, big: bool) -> CharClass {
+    CharClass::of(buffer.char(position), big)
+}
+
+/// Start of the next word, stopping early at an empty line.
+fn word_forward(buffer: &Buffer, position: usize, big: bool) -> usize {
+    let len = buffer.len_chars();
+    let mut position = position;
+
+    if position >= len {
+        return len;
+    }
+
+    let class = class_at(buffer, position, big);
+
+    if class != CharClass::Whitespace {
+        while position < len && class_at(buffer, position, big) == class {
+            position += 1;
+        }
+    }
+
+    while position < len && class_at(buffer, position, big) == CharClass::Whitespace {
+        if buffer.char(position) == '\n' && position + 1 < len && buffer.char(position + 1) == '\n' {
+            return position + 1;
+        }
+
+        position += 1;
+    }
+
+    position
+}
+
+/// Start of the current word, or of the previous one when already there.
+fn word_backward(buffer: &Buffer, position: usize, big: bool) -> usize {
+    let mut position = position.min(buffer.len_chars());
+
+    while position > 0 && class_at(buffer, position - 1, big) == CharClass::Whitespace {
+        position -= 1;
+    }
+
+    if position == 0 {
+        return 0;
+    }
+
+    let class = class_at(buffer, position - 1, big);
+
+    while position > 0 && class_at(buffer, position - 1, big) == class {
+        position -= 1;
+    }
+
+    position
+}
+
+/// Last char of the current word, or of the next one when already there.
+fn word_end(buffer: &Buffer, position: usize, big: bool) -> usize {
+    let len = buffer.len_chars();
+    let mut position = position + 1;
+
+    while position < len && class_at(buffer, position, big) == CharClass::Whitespace {
+        position += 1;
+    }
+
+    if position >= len {
+        return len.saturating_sub(1);
+    }
+
+    let class = class_at(buffer, position, big);
+
+    while position + 1 < len && class_at(buffer, position + 1, big) == class {
+        position += 1;
+    }
+
+    position
+}
+
+fn is_blank_line(buffer: &Buffer, line: usize) -> bool {
+    buffer.line_len(line) == 0
+}
+
+/// The next empty line after the current paragraph, or the end of the
+/// buffer.
+fn paragraph_forward(buffer: &Buffer, position: usize) -> usize {
+    let last_line = buffer.len_lines() - 1;
+    let mut line = buffer.char_to_line(position);
+
+    while line < last_line && is_blank_line(buffer, line) {
+        line += 1;
+    }
+
+    while line < last_line && !is_blank_line(buffer, line) {
+        line += 1;
+    }
+
+    if is_blank_line(buffer, line) {
+        buffer.line_to_char(line)
+    } else {
+        buffer.len_chars()
+    }
+}
+
+/// The previous empty line before the current paragraph, or the start of
+/// the buffer.
+fn paragraph_backward(buffer: &Buffer, position: usize) -> usize {
+    let mut line = buffer.char_to_line(position);
+
+    while line > 0 && is_blank_line(buffer, line) {
+        line -= 1;
+    }
+
+    while line > 0 && !is_blank_line(buffer, line) {
+        line -= 1;
+    }
+
+    buffer.line_to_char(line)
+}
+
+/// The run of same-class chars around `position`, such as a word or the
+/// whitespace between words, not crossing a line ending.
+pub fn word_range(buffer: &Buffer, position: usize) -> Range<usize> {
+    let line = buffer.char_to_line(position);
+    let line_start = buffer.line_to_char(line);
+    let line_end = line_start + buffer.line_len(line);
+
+    if position >= line_end {
+        return position..position;
+    }
+
+    let class = class_at(buffer, position, false);
+    let mut start = position;
+    let mut end = position + 1;
+
+    while start > line_start && class_at(buffer, start - 1, false) == class {
+        start -= 1;
+    }
+
+    while end < line_end && class_at(buffer, end, false) == class {
+        end += 1;
+    }
+
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Positions after each of `steps` moves from `start`.
+    fn moves(buffer: &Buffer, start: usize, motion: Motion, steps: usize) -> Vec<usize> {
+        let mut cursor = Cursor::new(start);
+
+        (0..steps).map(|_| {
+            cursor.apply(buffer, motion, 1, 10, 4);
+            cursor.get_position()
+        }).collect()
+    }
+
+    #[test]
+    fn moves_by_words() {
+        let buffer = Buffer::from_text("foo.bar  baz\n\nqux-quux end");
+
+        // an empty line counts as a word
+        assert_eq!(moves(&buffer, 0, Motion::WordForward, 7), [3, 4, 9, 13, 14, 17, 18]);
+        assert_eq!(moves(&buffer, 0, Motion::WordEnd, 6), [2, 3, 6, 11, 16, 17]);
+        assert_eq!(moves(&buffer, 25, Motion::WordBackward, 4), [23, 18, 17, 14]);
+    }
+
+    #[test]
+    fn moves_by_big_words() {
+        let buffer = Buffer::from_text("foo.bar  baz\n\nqux-quux end");
+
+        assert_eq!(moves(&buffer, 0, Motion::BigWordForward, 4), [9, 13, 14, 23]);
+        assert_eq!(moves(&buffer, 0, Motion::BigWordEnd, 3), [6, 11, 21]);
+        assert_eq!(moves(&buffer, 25, Motion::BigWordBackward, 3), [23, 14, 9]);
+
+        let mut cursor = Cursor::new(0);
+        cursor.apply(&buffer, Motion::BigWordForward, 3, 10, 4);
+        assert_eq!(cursor.get_position(), 14);
+    }
+
+    #[test]
+    fn moves_by_paragraphs() {
+        let buffer = Buffer::from_text("a\nb\n\nc\nd\n\n\ne");
+
+        assert_eq!(moves(&buffer, 0, Motion::ParagraphForward, 4), [4, 9, 12, 12]);
+        assert_eq!(moves(&buffer, 11, Motion::ParagraphBackward, 4), [10, 4, 0, 0]);
+    }
+
+    #[test]
+    fn keeps_the_column_across_short_lines() {
+        let buffer = Buffer::from_text("long line here\nab\n\nanother long line");
+        let mut cursor = Cursor::new(10);
+        let mut points = Vec::new();
+
+        for _ in 0..3 {
+            cursor.apply(&buffer, Motion::Down, 1, 10, 4);
+            points.push(cursor.point(&buffer));
+        }
+
+        assert_eq!(points, [Point::new(1, 2), Point::new(2, 0), Point::new(3, 10)]);
+
+        // moving sideways forgets the column
+        cursor.apply(&buffer, Motion::Left, 1, 10, 4);
+        cursor.apply(&buffer, Motion::Up, 3, 10, 4);
+        assert_eq!(cursor.point(&buffer), Point::new(0, 9));
+
+        // and line end sticks to the end of each line
+        cursor.apply(&buffer, Motion::LineEnd, 1, 10, 4);
+        cursor.apply(&buffer, Motion::Down, 1, 10, 4);
+        assert_eq!(cursor.point(&buffer), Point::new(1, 2));
+        cursor.apply(&buffer, Motion::Down, 2, 10, 4);
+        assert_eq!(cursor.point(&buffer), Point::new(3, 17));
+    }
+
+    #[test]
+    fn keeps_the_screen_column_over_wide_graphemes() {
+        let buffer = Buffer::from_text("日本語\nabcdef\n\tx");
+        let mut cursor = Cursor::new(2);
+
+        assert_eq!(cursor.display_column(&buffer, 4), 4);
+        cursor.apply(&buffer, Motion::Down, 1, 10, 4);
+        assert_eq!(cursor.point(&buffer), Point::new(1, 4));
+
+        // a screen column inside a wide char lands on it
+        cursor.set_position(buffer.point_to_char(Point::new(1, 3)));
+        cursor.apply(&buffer, Motion::Up, 1, 10, 4);
+        assert_eq!(cursor.point(&buffer), Point::new(0, 1));
+
+        // and one inside a tab lands on the tab
+        cursor.set_position(buffer.point_to_char(Point::new(1, 2)));
+        cursor.apply(&buffer, Motion::Down, 1, 10, 4);
+        assert_eq!(cursor.point(&buffer), Point::new(2, 0));
+    }
+
+    #[test]
+    fn steps_over_whole_graphemes() {
+        let buffer = Buffer::from_text("e\u{301}x");
+
+        assert_eq!(moves(&buffer, 0, Motion::Right, 2), [2, 3]);
+        assert_eq!(moves(&buffer, 3, Motion::Left, 2), [2, 0]);
+    }
+}
@@ -0,0 +1,506 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    buffer::{Buffer, LineEdit},
+    config::FoldMethod,
+    syntax::{grammar::Token, highlighter::Highlighter},
+};
+
+use super::grapheme;
+
+/// Lines that can be folded away behind the first of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fold {
+    pub start: usize,
+    /// The last line, after `start`.
+    pub end: usize,
+}
+
+impl Fold {
+    pub fn contains(&self, line: usize) -> bool {
+        self.start <= line && line <= self.end
+    }
+
+    /// Lines folded away behind the first.
+    pub fn hidden_lines(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Follows an edit, or None once it no longer spans lines.
+    fn edit(self, edit: LineEdit) -> Option<Fold> {
+        // a removed first line moves to the line after the edit, a
+        // removed last line to the last line of it or the one before
+        let after = edit.new_end + !edit.whole_lines as usize;
+        let start = edit.map_line(self.start).unwrap_or(self.start.min(after));
+        let end = match edit.map_line(self.end) {
+            Some(end) => end,
+            None => self.end.min(after.checked_sub(1)?),
+        };
+
+        let fold = Fold { start, end };
+        (fold.end > fold.start).then_some(fold)
+    }
+}
+
+/// The folds of a buffer, made by hand or found from its text, and which
+/// of them are closed. All of them keep to their lines as lines are added
+/// and removed around them.
+#[derive(Debug, Clone, Default)]
+pub struct Folds {
+    /// Folds made by hand, kept whatever the fold method.
+    manual: Vec<Fold>,
+    /// Folds found by the fold method.
+    found: Vec<Fold>,
+    /// The last scan for folds, kept so that after edits only the lines
+    /// from the first edited one on are scanned again.
+    scan: Option<Scan>,
+    /// First lines of the closed folds.
+    closed: BTreeSet<usize>,
+    /// The outermost closed folds, in order.
+    hidden: Vec<Fold>,
+    /// The buffer version the lines are for.
+    version: Option<u64>,
+}
+
+impl Folds {
+    /// Moves the folds along with the edits made to `buffer` since the
+    /// last sync.
+    pub fn sync(&mut self, buffer: &Buffer) {
+        if self.version == Some(buffer.get_version()) {
+            return;
+        }
+
+        let edits: Option<Vec<LineEdit>> = self.version
+            .and_then(|version| buffer.line_edits_since(version))
+            .map(Iterator::collect);
+
+        match edits {
+            Some(edits) => {
+                for edit in edits {
+                    self.manual = self.manual.iter().filter_map(|fold| fold.edit(edit)).collect();
+                    self.found = self.found.iter().filter_map(|fold| fold.edit(edit)).collect();
+
+                    // folds whose first line went stay open
+                    self.closed = std::mem::take(&mut self.closed).into_iter()
+                        .filter_map(|line| match edit.map_line(line) {
+                            Some(line) => Some(line),
+                            None if !edit.whole_lines && line <= edit.new_end => Some(line),
+                            None => None,
+                        })
+                        .collect();
+                }
+            }
+            // the found folds are found again, but what was closed is lost
+            None => {
+                self.found.clear();
+                self.closed.clear();
+            }
+        }
+
+        let len_lines = buffer.len_lines();
+        self.manual.retain(|fold| fold.end < len_lines);
+        self.found.retain(|fold| fold.end < len_lines);
+        self.version = Some(buffer.get_version());
+        self.update_hidden();
+    }
+
+    /// Finds the folds again if the text or the method changed since they
+    /// were last found, from the first line edited since. Tabs count to
+    /// the next multiple of `tab_width`.
+    pub fn find(&mut self, buffer: &Buffer, method: FoldMethod, tab_width: usize, highlighter: Option<&mut Highlighter>) {
+        self.sync(buffer);
+
+        let scan = match self.scan.take() {
+            Some(scan) if scan.method == method && scan.tab_width == tab_width => scan,
+            _ => Scan::new(method, tab_width),
+        };
+
+        let scan = self.scan.insert(scan);
+
+        if scan.version == Some(buffer.get_version()) {
+            return;
+        }
+
+        scan.update(buffer, highlighter);
+        self.found = scan.folds.clone();
+        self.update_hidden();
+    }
+
+    /// Every fold, in order of their first lines, outer ones first. Of
+    /// folds starting on the same line only the longest is kept.
+    pub fn all(&self) -> Vec<Fold> {
+        let mut folds: Vec<Fold> = self.manual.iter().chain(&self.found).copied().collect();
+
+        folds.sort_by_key(|fold| (fold.start, std::cmp::Reverse(fold.end)));
+        folds.dedup_by_key(|fold| fold.start);
+        folds
+    }
+
+    /// The folds containing `line`, outermost first.
+    pub fn around(&self, line: usize) -> Vec<Fold> {
+        self.all().into_iter().filter(|fold| fold.contains(line)).collect()
+    }
+
+    pub fn is_closed(&self, fold: Fold) -> bool {
+        self.closed.contains(&fold.start)
+    }
+
+    /// Whether any lines are folded away.
+    pub fn has_hidden(&self) -> bool {
+        !self.hidden.is_empty()
+    }
+
+    /// The closed fold `line` shows as or is hidden in.
+    pub fn folded(&self, line: usize) -> Option<Fold> {
+        let index = self.hidden.partition_point(|fold| fold.start <= line).checked_sub(1)?;
+        Some(self.hidden[index]).filter(|fold| fold.contains(line))
+    }
+
+    pub fn open(&mut self, fold: Fold) -> &mut Self {
+        self.closed.remove(&fold.start);
+        self.update_hidden();
+        self
+    }
+
+    pub fn close(&mut self, fold: Fold) -> &mut Self {
+        self.closed.insert(fold.start);
+        self.update_hidden();
+        self
+    }
+
+    pub fn open_all(&mut self) -> &mut Self {
+        self.closed.clear();
+        self.update_hidden();
+        self
+    }
+
+    pub fn close_all(&mut self) -> &mut Self {
+        self.closed = self.all().iter().map(|fold| fold.start).collect();
+        self.update_hidden();
+        self
+    }
+
+    /// Adds a fold by hand, closed.
+    pub fn add(&mut self, fold: Fold) -> &mut Self {
+        self.manual.retain(|manual| manual.start != fold.start);
+        self.manual.push(fold);
+        self.close(fold)
+    }
+
+    /// Removes the innermost fold made by hand around `line`.
+    pub fn remove(&mut self, line: usize) -> Option<Fold> {
+        let index = self.manual.iter()
+            .enumerate()
+            .filter(|(_, fold)| fold.contains(line))
+            .max_by_key(|(_, fold)| fold.start)?
+            .0;
+
+        let fold = self.manual.remove(index);
+        self.open(fold);
+        Some(fold)
+    }
+
+    /// Removes every fold made by hand.
+    pub fn clear(&mut self) -> &mut Self {
+        let manual = std::mem::take(&mut self.manual);
+
+        for fold in manual {
+            self.closed.remove(&fold.start);
+        }
+
+        self.update_hidden();
+        self
+    }
+
+    fn update_hidden(&mut self) {
+        let folds = self.all();
+
+        // closed folds that are gone are forgotten, so one found again
+        // later starts open
+        self.closed.retain(|line| folds.iter().any(|fold| fold.start == *line));
+        self.hidden.clear();
+
+        for fold in folds.into_iter().filter(|fold| self.closed.contains(&fold.start)) {
+            if self.hidden.last().is_some_and(|last| last.contains(fold.start)) {
+                continue;
+            }
+
+            self.hidden.push(fold);
+        }
+    }
+}
+
+/// Where a scan for folds is at the start of a line.
+#[derive(Debug, Clone, Default)]
+struct ScanState {
+    /// Lines of brackets not yet closed, with the brackets, innermost last.
+    brackets: Vec<(char, usize)>,
+    /// Lines that more indented lines may still follow, with their
+    /// indent, innermost last.
+    indents: Vec<(usize, usize)>,
+    /// The last line that is not blank.
+    last: usize,
+    /// The first and last lines of the run of comment lines going on.
+    comments: Option<(usize, usize)>,
+    /// How many folds were found before the line.
+    found: usize,
+}
+
+/// A scan of a buffer for folds by a fold method.
+#[derive(Debug, Clone)]
+struct Scan {
+    method: FoldMethod,
+    tab_width: usize,
+    /// The folds found, in the order they were.
+    folds: Vec<Fold>,
+    /// Where the scan was at the start of each line.
+    states: Vec<ScanState>,
+    /// The buffer version scanned.
+    version: Option<u64>,
+}
+
+impl Scan {
+    fn new(method: FoldMethod, tab_width: usize) -> Self {
+        Scan {
+            method,
+            tab_width,
+            folds: Vec::new(),
+            states: Vec::new(),
+            version: None,
+        }
+    }
+
+    /// Scans the buffer again from the first line edited since the last
+    /// scan, as the lines before it come out the same.
+    fn update(&mut self, buffer: &Buffer, mut highlighter: Option<&mut Highlighter>) {
+        let first = self.version
+            .and_then(|version| buffer.line_edits_since(version))
+            .map_or(0, |edits| edits.map(|edit| edit.start).min().unwrap_or(usize::MAX));
+
+        let from = first.min(self.states.len().saturating_sub(1));
+        let mut state = self.states.get(from).cloned().unwrap_or_default();
+
+        self.states.truncate(from);
+        self.folds.truncate(state.found);
+        self.version = Some(buffer.get_version());
+
+        if self.method == FoldMethod::Manual {
+            return;
+        }
+
+        for line in from..buffer.len_lines() {
+            state.found = self.folds.len();
+            self.states.push(state.clone());
+
+            let text = buffer.line_text(line);
+
+            match self.method {
+                FoldMethod::Manual => unreachable!("Manual folds are not scanned for"),
+                FoldMethod::Indent => self.indent_line(&mut state, line, &text),
+                FoldMethod::Syntax => {
+                    let tokens = match highlighter.as_deref_mut() {
+                        Some(highlighter) => highlighter.tokens(buffer, line..line + 1).pop().unwrap_or_default(),
+                        None => Vec::new(),
+                    };
+
+                    let scopes = highlighter.as_ref().map(|highlighter| highlighter.get_grammar().get_scopes());
+                    self.syntax_line(&mut state, line, &text, &tokens, scopes.unwrap_or_default());
+                }
+            }
+        }
+
+        // what is still open at the end of the text
+        self.close_indents(&mut state, None);
+        self.folds.extend(state.comments.filter(|(start, end)| end > start).map(|(start, end)| Fold { start, end }));
+    }
+
+    /// A line and the lines after it that are more indented, up to the
+    /// last of them that is not blank.
+    fn indent_line(&mut self, state: &mut ScanState, line: usize, text: &str) {
+        if text.trim().is_empty() {
+            return;
+        }
+
+        let indent = grapheme::layout(text, self.tab_width)
+            .take_while(|grapheme| grapheme.text.trim().is_empty())
+            .map(|grapheme| grapheme.width.max(1))
+            .sum();
+
+        self.close_indents(state, Some(indent));
+        state.indents.push((line, indent));
+        state.last = line;
+    }
+
+    /// Closes the open lines not less indented than `indent`, or all of
+    /// them.
+    fn close_indents(&mut self, state: &mut ScanState, indent: Option<usize>) {
+        while let Some(&(start, level)) = state.indents.last() {
+            if indent.is_some_and(|indent| indent > level) {
+                break;
+            }
+
+            state.indents.pop();
+
+            if state.last > start {
+                self.folds.push(Fold { start, end: state.last });
+            }
+        }
+    }
+
+    /// From each line with an opening bracket to the line before its
+    /// closing one, so the closing line stays in view, and runs of lines
+    /// with nothing but comments. Brackets in comments and strings are left
+    /// out when there are tokens to tell where those are.
+    fn syntax_line(&mut self, state: &mut ScanState, line: usize, text: &str, tokens: &[Token], scopes: &[String]) {
+        let is_text = |scope: Option<usize>| scope.is_some_and(|scope| {
+            scopes[scope].starts_with("comment") || scopes[scope].starts_with("string")
+        });
+        let is_comment = |scope: Option<usize>| scope.is_some_and(|scope| scopes[scope].starts_with("comment"));
+
+        // comments and strings by byte
+        let mut skip = vec![false; text.len()];
+        let mut comment = vec![false; text.len()];
+
+        for token in tokens {
+            let range = token.range.start.min(text.len())..token.range.end.min(text.len());
+            skip[range.clone()].fill(is_text(token.scope) || is_text(token.meta_scope));
+            comment[range].fill(is_comment(token.scope) || is_comment(token.meta_scope));
+        }
+
+        let mut code = false;
+        let mut commented = false;
+
+        for (byte, c) in text.char_indices() {
+            if !c.is_whitespace() {
+                code |= !comment[byte];
+                commented |= comment[byte];
+            }
+
+            if skip[byte] {
+                continue;
+            }
+
+            match c {
+                '{' | '[' | '(' => state.brackets.push((c, line)),
+                '}' | ']' | ')' => {
+                    let opening = match c {
+                        '}' => '{',
+                        ']' => '[',
+                        _ => '(',
+                    };
+
+                    // a stray closing bracket is passed over
+                    if let Some(index) = state.brackets.iter().rposition(|(c, _)| *c == opening) {
+                        let (_, start) = state.brackets[index];
+                        state.brackets.truncate(index);
+
+                        if line > start + 1 {
+                            self.folds.push(Fold { start, end: line - 1 });
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        state.comments = match (commented && !code, state.comments) {
+            (true, Some((start, _))) => Some((start, line)),
+            (true, None) => Some((line, line)),
+            (false, run) => {
+                self.folds.extend(run.filter(|(start, end)| end > start).map(|(start, end)| Fold { start, end }));
+                None
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fold(start: usize, end: usize) -> Fold {
+        Fold { start, end }
+    }
+
+    fn edit(start: usize, old_end: usize, new_end: usize, whole_lines: bool) -> LineEdit {
+        LineEdit { start, old_end, new_end, whole_lines }
+    }
+
+    #[test]
+    fn follows_edits() {
+        // lines broken inside the fold, and whole lines added above it
+        assert_eq!(fold(2, 5).edit(edit(3, 3, 5, false)), Some(fold(2, 7)));
+        assert_eq!(fold(2, 5).edit(edit(2, 2, 4, true)), Some(fold(4, 7)));
+        assert_eq!(fold(2, 5).edit(edit(0, 0, 1, false)), Some(fold(3, 6)));
+
+        // removed first and last lines move inside what is left
+        assert_eq!(fold(2, 5).edit(edit(2, 3, 2, true)), Some(fold(2, 4)));
+        assert_eq!(fold(2, 5).edit(edit(4, 6, 4, false)), Some(fold(2, 4)));
+        assert_eq!(fold(2, 3).edit(edit(3, 4, 3, true)), None);
+        assert_eq!(fold(0, 1).edit(edit(0, 1, 0, true)), None);
+    }
+
+    #[test]
+    fn closed_folds_keep_to_their_lines() {
+        let mut buffer = Buffer::from_text("a\nb\nc\nd\ne\nf");
+        let mut folds = Folds::default();
+        folds.sync(&buffer);
+        folds.add(fold(1, 3));
+
+        buffer.insert(buffer.line_to_char(1), "x\n");
+        folds.sync(&buffer);
+        assert_eq!(folds.all(), [fold(2, 4)]);
+        assert_eq!(folds.folded(3), Some(fold(2, 4)));
+
+        // joining the first line onto the one before leaves the fold open,
+        // from the line after
+        let end = buffer.line_to_char(2) - 1;
+        buffer.remove(end..end + 1);
+        folds.sync(&buffer);
+        assert_eq!(folds.all(), [fold(2, 3)]);
+        assert!(!folds.has_hidden());
+
+        buffer.remove(buffer.line_to_char(2)..buffer.line_to_char(4));
+        folds.sync(&buffer);
+        assert!(folds.all().is_empty());
+    }
+
+    #[test]
+    fn finds_indented_blocks() {
+        let buffer = Buffer::from_text("fn f() {\n    a\n\n    if b {\n        c\n    }\n}\nd");
+        let mut folds = Folds::default();
+        folds.find(&buffer, FoldMethod::Indent, 4, None);
+
+        assert_eq!(folds.all(), [fold(0, 5), fold(3, 4)]);
+    }
+
+    #[test]
+    fn finds_folds_again_from_the_first_edited_line() {
+        let text = "fn f() {\n    a(\n        b,\n    );\n}\n\n// c\n// d\nfn g() {\n    e\n}";
+
+        for method in [FoldMethod::Indent, FoldMethod::Syntax] {
+            let mut buffer = Buffer::from_text(text);
+            let mut folds = Folds::default();
+            folds.find(&buffer, method, 4, None);
+
+            let edits: [(usize, &str); 4] = [(3, "    x\n"), (8, "    y"), (1, "{\n"), (0, "// z\n")];
+
+            for (line, text) in edits {
+                buffer.insert(buffer.line_to_char(line), text);
+                folds.find(&buffer, method, 4, None);
+
+                let mut fresh = Folds::default();
+                fresh.find(&buffer, method, 4, None);
+                assert_eq!(folds.all(), fresh.all(), "{:?} after inserting {:?}", method, text);
+            }
+
+            let end = buffer.line_to_char(9);
+            buffer.remove(buffer.line_to_char(2)..end);
+            folds.find(&buffer, method, 4, None);
+
+            let mut fresh = Folds::default();
+            fresh.find(&buffer, method, 4, None);
+            assert_eq!(folds.all(), fresh.all(), "{:?} after a removal", method);
+        }
+    }
+}
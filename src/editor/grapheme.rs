@@ -0,0 +1,150 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// A grapheme cluster on a line, located by char column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grapheme<'a> {
+    pub column: usize,
+    pub text: &'a str,
+    pub width: usize,
+}
+
+impl Grapheme<'_> {
+    pub fn len_chars(&self) -> usize {
+        self.text.chars().count()
+    }
+}
+
+pub fn graphemes(line: &str) -> impl Iterator<Item = Grapheme<'_>> {
+    let mut column = 0;
+
+    line.graphemes(true).map(move |text| {
+        let grapheme = Grapheme {
+            column,
+            text,
+            width: width(text),
+        };
+
+        column += grapheme.len_chars();
+        grapheme
+    })
+}
+
+/// The graphemes of a line as laid out on screen, with tabs reaching to
+/// the next multiple of `tab_width`.
+pub fn layout(line: &str, tab_width: usize) -> impl Iterator<Item = Grapheme<'_>> {
+    let tab_width = tab_width.max(1);
+    let mut display = 0;
+
+    graphemes(line).map(move |mut grapheme| {
+        if grapheme.text == "\t" {
+            grapheme.width = tab_width - display % tab_width;
+        }
+
+        display += grapheme.width;
+        grapheme
+    })
+}
+
+/// Cells a grapheme takes on screen. Control chars are drawn as a single
+/// placeholder cell, as are tabs outside of a laid out line.
+pub fn width(grapheme: &str) -> usize {
+    if grapheme.starts_with(|c: char| c.is_control()) {
+        return 1;
+    }
+
+    grapheme.width().max(1)
+}
+
+/// The screen column a char column starts at.
+pub fn column_to_display(line: &str, column: usize, tab_width: usize) -> usize {
+    layout(line, tab_width)
+        .take_while(|grapheme| grapheme.column < column)
+        .map(|grapheme| grapheme.width)
+        .sum()
+}
+
+/// The char column of the grapheme covering a screen column, or the end
+/// of the line if the line is shorter.
+pub fn display_to_column(line: &str, display: usize, tab_width: usize) -> usize {
+    let mut start = 0;
+
+    for grapheme in layout(line, tab_width) {
+        if start + grapheme.width > display {
+            return grapheme.column;
+        }
+
+        start += grapheme.width;
+    }
+
+    line.chars().count()
+}
+
+/// The char column of the grapheme boundary after `column`.
+pub fn next_boundary(line: &str, column: usize) -> usize {
+    graphemes(line)
+        .map(|grapheme| grapheme.column + grapheme.len_chars())
+        .find(|end| *end > column)
+        .unwrap_or_else(|| line.chars().count())
+}
+
+/// The char column of the grapheme boundary before `column`.
+pub fn prev_boundary(line: &str, column: usize) -> usize {
+    graphemes(line)
+        .map(|grapheme| grapheme.column)
+        .take_while(|start| *start < column)
+        .last()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_graphemes() {
+        assert_eq!(width("a"), 1);
+        assert_eq!(width("日"), 2);
+        assert_eq!(width("e\u{301}"), 1);
+        assert_eq!(width("👩\u{200d}💻"), 2);
+        assert_eq!(width("\t"), 1);
+        assert_eq!(width("\u{7}"), 1);
+
+        let columns: Vec<_> = graphemes("e\u{301}日x").map(|grapheme| grapheme.column).collect();
+        assert_eq!(columns, [0, 2, 3]);
+    }
+
+    #[test]
+    fn lays_out_tabs_to_stops() {
+        let widths: Vec<_> = layout("a\t日\tb", 4).map(|grapheme| grapheme.width).collect();
+        assert_eq!(widths, [1, 3, 2, 2, 1]);
+    }
+
+    #[test]
+    fn converts_between_columns() {
+        let line = "a\t日b";
+
+        assert_eq!(column_to_display(line, 2, 4), 4);
+        assert_eq!(column_to_display(line, 3, 4), 6);
+        assert_eq!(column_to_display(line, 3, 8), 10);
+
+        assert_eq!(display_to_column(line, 2, 4), 1);
+        assert_eq!(display_to_column(line, 4, 4), 2);
+        assert_eq!(display_to_column(line, 5, 4), 2);
+        assert_eq!(display_to_column(line, 6, 4), 3);
+        assert_eq!(display_to_column(line, 20, 4), 4);
+    }
+
+    #[test]
+    fn finds_grapheme_boundaries() {
+        let line = "ae\u{301}日";
+
+        assert_eq!(next_boundary(line, 0), 1);
+        assert_eq!(next_boundary(line, 1), 3);
+        assert_eq!(next_boundary(line, 2), 3);
+        assert_eq!(next_boundary(line, 4), 4);
+        assert_eq!(prev_boundary(line, 3), 1);
+        assert_eq!(prev_boundary(line, 2), 1);
+        assert_eq!(prev_boundary(line, 0), 0);
+    }
+}
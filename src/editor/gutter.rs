@@ -0,0 +1,144 @@
+use std::{rc::Rc, cell::RefCell};
+
+use crate::{
+    config::LineNumbers,
+    renderer::cell::{Cell, CellStyle},
+    theme::Theme,
+    tui::{drawer::Drawer, widget::Widget},
+};
+
+use super::view::EditorView;
+
+/// Digits line numbers take at least, so the gutter does not grow with
+/// every new digit of a short buffer.
+const MIN_NUMBER_WIDTH: usize = 3;
+
+/// The column beside an editor view with the line numbers and signs of the
+/// lines it shows, drawn in a node next to the view's.
+pub struct Gutter {
+    view: Rc<RefCell<EditorView>>,
+    line_numbers: LineNumbers,
+    style: CellStyle,
+    current_style: CellStyle,
+}
+
+impl Gutter {
+    pub fn new(view: Rc<RefCell<EditorView>>, line_numbers: LineNumbers) -> Self {
+        let theme = Theme::default();
+
+        Gutter {
+            view,
+            line_numbers,
+            style: theme.gutter,
+            current_style: theme.current_line_number,
+        }
+    }
+
+    pub fn get_line_numbers(&self) -> LineNumbers {
+        self.line_numbers
+    }
+
+    pub fn set_line_numbers(&mut self, line_numbers: LineNumbers) -> &mut Self {
+        self.line_numbers = line_numbers;
+        self
+    }
+
+    /// Columns the gutter takes: one for signs once there are any, then
+    /// the widest line number and a space before the text. Nothing at all
+    /// without either.
+    pub fn width(&self) -> u16 {
+        let (sign_width, number_width) = self.column_widths();
+
+        match sign_width + number_width {
+            0 => 0,
+            width => (width + 1).min(u16::MAX as usize) as u16,
+        }
+    }
+
+    fn column_widths(&self) -> (usize, usize) {
+        let document = self.view.borrow().get_document();
+        let mut document = document.borrow_mut();
+        let len_lines = document.get_buffer().len_lines();
+
+        let sign_width = !document.signs().is_empty() as usize;
+        let number_width = match self.line_numbers {
+            LineNumbers::None => 0,
+            _ => len_lines.to_string().len().max(MIN_NUMBER_WIDTH),
+        };
+
+        (sign_width, number_width)
+    }
+}
+
+impl Widget for Gutter {
+    fn draw(&mut self, drawer: &mut Drawer, _scroll: (u16, u16)) {
+        let (sign_width, number_width) = self.column_widths();
+        let width = drawer.boundaries.width as usize;
+
+        if width == 0 {
+            return;
+        }
+
+        // the view is drawn after the gutter, so it is scrolled to where
+        // it will be first
+        let mut view = self.view.borrow_mut();
+        view.update_scroll();
+
+        let rows = view.visible_rows();
+        let cursor_line = view.cursor_point().line;
+        let document = view.get_document();
+        drop(view);
+
+        let mut document = document.borrow_mut();
+        let signs = document.signs();
+
+        for row in 0..drawer.boundaries.height as usize {
+            // rows a line continues on are left blank, as are those past
+            // the end
+            let line = match rows.get(row) {
+                Some((line, 0)) => *line,
+                _ => {
+                    drawer.draw_text(0, row as u16, &" ".repeat(width), self.style);
+                    continue;
+                }
+            };
+
+            let mut x = 0_u16;
+
+            if sign_width > 0 {
+                let cell = match signs.top(line) {
+                    Some(sign) => Cell { c: sign.symbol, style: self.style.patch(sign.style) },
+                    None => Cell { c: ' ', style: self.style },
+                };
+
+                drawer.draw_cell(0, row as u16, cell);
+                x += 1;
+            }
+
+            let number = match self.line_numbers {
+                LineNumbers::None => None,
+                LineNumbers::Absolute => Some(line + 1),
+                LineNumbers::Relative => Some(line.abs_diff(cursor_line)),
+                LineNumbers::Hybrid if line == cursor_line => Some(line + 1),
+                LineNumbers::Hybrid => Some(line.abs_diff(cursor_line)),
+            };
+
+            let style = match line == cursor_line {
+                true => self.current_style,
+                false => self.style,
+            };
+
+            let text = match number {
+                Some(number) => format!("{:>width$} ", number, width = number_width),
+                None => " ".to_string(),
+            };
+
+            drawer.draw_text(x, row as u16, &text, style);
+        }
+    }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        self.style = theme.gutter;
+        self.current_style = theme.current_line_number;
+    }
+}
@@ -0,0 +1,350 @@
+use std::{collections::HashSet, time::{Duration, SystemTime}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::{Buffer, Change};
+
+use super::selection::Selection;
+
+/// Where the cursor and selection were around an edit, to put them back
+/// when the edit is undone or redone.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CursorState {
+    pub position: usize,
+    pub selection: Option<Selection>,
+    /// Any other cursors, in buffer order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub others: Vec<CursorState>,
+}
+
+/// One step in the history: the changes made going from its parent to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    parent: usize,
+    /// The child that redo goes to, the one made or left most recently.
+    last_child: Option<usize>,
+    changes: Vec<Change>,
+    before: CursorState,
+    after: CursorState,
+    time: SystemTime,
+}
+
+impl Revision {
+    pub fn get_time(&self) -> SystemTime {
+        self.time
+    }
+}
+
+/// Undo history kept as a tree, so undoing and then editing starts a new
+/// branch rather than losing the undone edits.
+///
+/// Revisions are numbered in the order they were made, starting with the
+/// root at 0 which holds no changes. Besides undo and redo along a branch,
+/// `earlier` and `later` move through the revisions in that order, jumping
+/// between branches as needed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct History {
+    revisions: Vec<Revision>,
+    current: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            revisions: vec![Revision {
+                parent: 0,
+                last_child: None,
+                changes: Vec::new(),
+                before: CursorState::default(),
+                after: CursorState::default(),
+                time: SystemTime::now(),
+            }],
+            current: 0,
+        }
+    }
+}
+
+impl History {
+    pub fn get_current(&self) -> usize {
+        self.current
+    }
+
+    pub fn get_revision(&self, revision: usize) -> Option<&Revision> {
+        self.revisions.get(revision)
+    }
+
+    pub fn len(&self) -> usize {
+        self.revisions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.revisions.len() == 1
+    }
+
+    /// Whether every revision links to ones that exist, as one read from a
+    /// file might not.
+    pub fn is_valid(&self) -> bool {
+        let len = self.revisions.len();
+
+        len > 0 && self.current < len && self.revisions.iter().enumerate().all(|(index, revision)| {
+            (index == 0 || revision.parent < index) && revision.last_child.is_none_or(|child| child < len)
+        })
+    }
+
+    /// Adds changes as a child of the current revision. If `merge_into`
+    /// is the current revision and nothing branches from it yet, they are
+    /// added to it instead, as when typing. Returns the revision holding
+    /// them.
+    pub fn commit(&mut self, changes: Vec<Change>, before: CursorState, after: CursorState, merge_into: Option<usize>) -> usize {
+        let current = &mut self.revisions[self.current];
+
+        if merge_into == Some(self.current) && self.current != 0 && current.last_child.is_none() {
+            for change in changes {
+                let merged = current.changes.last_mut()
+                    .is_some_and(|last| last.merge(&change));
+
+                if !merged {
+                    current.changes.push(change);
+                }
+            }
+
+            current.after = after;
+            current.time = SystemTime::now();
+            return self.current;
+        }
+
+        let mut merged: Vec<Change> = Vec::with_capacity(changes.len());
+
+        for change in changes {
+            let continues = merged.last_mut()
+                .is_some_and(|last| last.merge(&change));
+
+            if !continues {
+                merged.push(change);
+            }
+        }
+
+        let revision = self.revisions.len();
+        self.revisions[self.current].last_child = Some(revision);
+
+        self.revisions.push(Revision {
+            parent: self.current,
+            last_child: None,
+            changes: merged,
+            before,
+            after,
+            time: SystemTime::now(),
+        });
+
+        self.current = revision;
+        revision
+    }
+
+    /// Reverts the current revision, returning the state before it.
+    pub fn undo(&mut self, buffer: &mut Buffer) -> Option<CursorState> {
+        if self.current == 0 {
+            return None;
+        }
+
+        let revision = &self.revisions[self.current];
+
+        for change in revision.changes.iter().rev() {
+            buffer.apply(&change.invert());
+        }
+
+        let (parent, before) = (revision.parent, revision.before.clone());
+
+        self.revisions[parent].last_child = Some(self.current);
+        self.current = parent;
+        buffer.take_changes();
+
+        Some(before)
+    }
+
+    /// Reapplies the most recent child of the current revision, returning
+    /// the state after it.
+    pub fn redo(&mut self, buffer: &mut Buffer) -> Option<CursorState> {
+        let child = self.revisions[self.current].last_child?;
+        self.redo_to(buffer, child);
+
+        Some(self.revisions[child].after.clone())
+    }
+
+    fn redo_to(&mut self, buffer: &mut Buffer, child: usize) {
+        for change in self.revisions[child].changes.iter() {
+            buffer.apply(change);
+        }
+
+        self.revisions[self.current].last_child = Some(child);
+        self.current = child;
+        buffer.take_changes();
+    }
+
+    /// Undoes and redoes along the tree to reach `target`, returning the
+    /// state after the last step.
+    pub fn goto(&mut self, buffer: &mut Buffer, target: usize) -> Option<CursorState> {
+        let target = target.min(self.revisions.len() - 1);
+
+        if target == self.current {
+            return None;
+        }
+
+        let mut path = vec![target];
+        let mut revision = target;
+
+        while revision != 0 {
+            revision = self.revisions[revision].parent;
+            path.push(revision);
+        }
+
+        let ancestors: HashSet<usize> = path.iter().copied().collect();
+        let mut state = None;
+
+        while !ancestors.contains(&self.current) {
+            state = self.undo(buffer);
+        }
+
+        // the path runs from the target up to the root, so redo it backwards
+        let common = path.iter().position(|revision| *revision == self.current).unwrap_or(0);
+
+        for revision in path[..common].iter().rev() {
+            self.redo_to(buffer, *revision);
+            state = Some(self.revisions[*revision].after.clone());
+        }
+
+        state
+    }
+
+    /// Goes back `steps` revisions in the order they were made.
+    pub fn earlier(&mut self, buffer: &mut Buffer, steps: usize) -> Option<CursorState> {
+        self.goto(buffer, self.current.saturating_sub(steps))
+    }
+
+    /// Goes forward `steps` revisions in the order they were made.
+    pub fn later(&mut self, buffer: &mut Buffer, steps: usize) -> Option<CursorState> {
+        self.goto(buffer, self.current.saturating_add(steps))
+    }
+
+    /// Goes to the text as it was `duration` before the current revision.
+    pub fn earlier_by(&mut self, buffer: &mut Buffer, duration: Duration) -> Option<CursorState> {
+        let time = self.revisions[self.current].time;
+        let cutoff = time.checked_sub(duration).unwrap_or(SystemTime::UNIX_EPOCH);
+        let target = self.last_revision_before(cutoff).unwrap_or(0);
+
+        self.goto(buffer, target)
+    }
+
+    /// Goes to the text as it was `duration` after the current revision.
+    pub fn later_by(&mut self, buffer: &mut Buffer, duration: Duration) -> Option<CursorState> {
+        let time = self.revisions[self.current].time;
+        let target = match time.checked_add(duration) {
+            Some(cutoff) => self.last_revision_before(cutoff).unwrap_or(self.current),
+            None => self.revisions.len() - 1,
+        };
+
+        self.goto(buffer, target.max(self.current))
+    }
+
+    /// The latest revision made no later than `cutoff`.
+    fn last_revision_before(&self, cutoff: SystemTime) -> Option<usize> {
+        self.revisions.iter()
+            .rposition(|revision| revision.time <= cutoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(position: usize) -> CursorState {
+        CursorState { position, ..CursorState::default() }
+    }
+
+    /// Inserts `text` at `position` and commits it as a revision.
+    fn edit(history: &mut History, buffer: &mut Buffer, position: usize, text: &str, merge_into: Option<usize>) -> usize {
+        buffer.insert(position, text);
+        history.commit(buffer.take_changes(), state(position), state(position + text.len()), merge_into)
+    }
+
+    fn text(buffer: &Buffer) -> String {
+        buffer.get_rope().to_string()
+    }
+
+    #[test]
+    fn undoes_and_redoes() {
+        let mut buffer = Buffer::new();
+        let mut history = History::default();
+        edit(&mut history, &mut buffer, 0, "one", None);
+        edit(&mut history, &mut buffer, 3, " two", None);
+
+        assert_eq!(history.undo(&mut buffer), Some(state(3)));
+        assert_eq!(text(&buffer), "one");
+        assert_eq!(history.undo(&mut buffer), Some(state(0)));
+        assert_eq!(history.undo(&mut buffer), None);
+        assert_eq!(text(&buffer), "");
+
+        assert_eq!(history.redo(&mut buffer), Some(state(3)));
+        assert_eq!(history.redo(&mut buffer), Some(state(7)));
+        assert_eq!(history.redo(&mut buffer), None);
+        assert_eq!(text(&buffer), "one two");
+        assert!(!buffer.has_changes());
+    }
+
+    #[test]
+    fn typing_merges_into_one_revision() {
+        let mut buffer = Buffer::new();
+        let mut history = History::default();
+        let revision = edit(&mut history, &mut buffer, 0, "a", None);
+
+        assert_eq!(edit(&mut history, &mut buffer, 1, "b", Some(revision)), revision);
+        assert_eq!(history.get_revision(revision).unwrap().changes.len(), 1);
+
+        history.undo(&mut buffer);
+        assert_eq!(text(&buffer), "");
+    }
+
+    #[test]
+    fn editing_after_undo_branches() {
+        let mut buffer = Buffer::new();
+        let mut history = History::default();
+        edit(&mut history, &mut buffer, 0, "a", None);
+        edit(&mut history, &mut buffer, 1, "b", None);
+        history.undo(&mut buffer);
+        edit(&mut history, &mut buffer, 1, "c", None);
+
+        assert_eq!(history.len(), 4);
+        assert_eq!(text(&buffer), "ac");
+
+        // earlier and later go by the order revisions were made
+        history.earlier(&mut buffer, 1);
+        assert_eq!(text(&buffer), "ab");
+        history.earlier(&mut buffer, 2);
+        assert_eq!(text(&buffer), "");
+        history.later(&mut buffer, 10);
+        assert_eq!(text(&buffer), "ac");
+        assert!(history.is_valid());
+    }
+
+    #[test]
+    fn travels_by_time() {
+        let mut buffer = Buffer::new();
+        let mut history = History::default();
+        let start = SystemTime::now() - Duration::from_secs(600);
+
+        for (index, minutes) in [0, 2, 4, 6].into_iter().enumerate() {
+            let revision = edit(&mut history, &mut buffer, index, &index.to_string(), None);
+            history.revisions[revision].time = start + Duration::from_secs(minutes * 60);
+        }
+
+        history.revisions[0].time = start - Duration::from_secs(60);
+        assert_eq!(text(&buffer), "0123");
+
+        // back to the last revision at least three minutes older
+        history.earlier_by(&mut buffer, Duration::from_secs(180));
+        assert_eq!(text(&buffer), "01");
+        history.earlier_by(&mut buffer, Duration::from_secs(3600));
+        assert_eq!(text(&buffer), "");
+        history.later_by(&mut buffer, Duration::from_secs(240));
+        assert_eq!(text(&buffer), "01");
+    }
+}
@@ -0,0 +1,15 @@
+pub mod view;
+pub mod gutter;
+pub mod sign;
+pub mod wrap;
+pub mod fold;
+pub mod bracket;
+pub mod status_line;
+pub mod cursor;
+pub mod grapheme;
+pub mod selection;
+pub mod register;
+pub mod history;
+pub mod undo_store;
+pub mod prompt;
+pub mod search;
@@ -0,0 +1,83 @@
+use std::ops::Range;
+
+use super::grapheme;
+
+/// A line of text typed into the bottom of a view, such as a pattern.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Prompt {
+    label: String,
+    text: String,
+    /// Char index into the text.
+    cursor: usize,
+}
+
+impl Prompt {
+    pub fn new(label: impl Into<String>) -> Self {
+        Prompt {
+            label: label.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn set_label(&mut self, label: impl Into<String>) -> &mut Self {
+        self.label = label.into();
+        self
+    }
+
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn get_cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        let byte = self.byte_index(self.cursor);
+
+        self.text.insert_str(byte, text);
+        self.cursor += text.chars().count();
+    }
+
+    pub fn delete_backward(&mut self) {
+        let start = grapheme::prev_boundary(&self.text, self.cursor);
+        self.remove(start..self.cursor);
+        self.cursor = start;
+    }
+
+    pub fn delete_forward(&mut self) {
+        let end = grapheme::next_boundary(&self.text, self.cursor);
+        self.remove(self.cursor..end);
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = grapheme::prev_boundary(&self.text, self.cursor);
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = grapheme::next_boundary(&self.text, self.cursor);
+    }
+
+    pub fn move_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.text.chars().count();
+    }
+
+    fn remove(&mut self, range: Range<usize>) {
+        let (start, end) = (self.byte_index(range.start), self.byte_index(range.end));
+        self.text.replace_range(start..end, "");
+    }
+
+    fn byte_index(&self, char_idx: usize) -> usize {
+        self.text.char_indices()
+            .nth(char_idx)
+            .map_or(self.text.len(), |(byte, _)| byte)
+    }
+}
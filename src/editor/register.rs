@@ -0,0 +1,224 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::clipboard::Clipboard;
+
+/// Registers kept besides the named ones, newest first.
+const RING_SIZE: usize = 10;
+
+/// Text that was copied or cut.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Register {
+    pub text: String,
+    /// Whole lines, pasted as lines rather than into one.
+    pub linewise: bool,
+    /// What each cursor copied, when there were several, so pasting with
+    /// as many cursors gives each its own part.
+    pub parts: Vec<String>,
+}
+
+impl Register {
+    pub fn new(text: String, linewise: bool) -> Self {
+        Register { text, linewise, parts: Vec::new() }
+    }
+
+    /// Joins the copies made by several cursors, in buffer order.
+    pub fn join(registers: Vec<Register>) -> Option<Register> {
+        if registers.len() < 2 {
+            return registers.into_iter().next();
+        }
+
+        let linewise = registers.iter().any(|register| register.linewise);
+        let parts: Vec<String> = registers.into_iter().map(|register| register.text).collect();
+        let text = match linewise {
+            true => parts.concat(),
+            false => parts.join("\n"),
+        };
+
+        Some(Register { text, linewise, parts })
+    }
+
+    /// The text cursor `index` of `total` pastes.
+    pub fn text_for(&self, index: usize, total: usize) -> &str {
+        match self.parts.len() == total {
+            true => &self.parts[index],
+            false => &self.text,
+        }
+    }
+}
+
+/// Registers shared by all editor views.
+///
+/// Every copy goes onto a ring of recent registers. The unnamed register
+/// `"` is the newest of them and `0` to `9` index into it. Registers `a`
+/// to `z` keep text until replaced, and copying into `A` to `Z` appends to
+/// them instead. `+` and `*` are the system clipboard, which the unnamed
+/// register is as well when synced.
+#[derive(Debug, Clone, Default)]
+pub struct Registers {
+    named: HashMap<char, Register>,
+    ring: VecDeque<Register>,
+    clipboard: Clipboard,
+    sync: bool,
+    /// The last clipboard failure, until taken to show it.
+    error: Option<String>,
+}
+
+impl Registers {
+    pub fn new(clipboard: Clipboard, sync: bool) -> Self {
+        Registers {
+            clipboard,
+            sync,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_valid_name(name: char) -> bool {
+        matches!(name, '"' | '+' | '*') || name.is_ascii_alphanumeric()
+    }
+
+    fn is_clipboard(&self, name: Option<char>) -> bool {
+        match name {
+            Some('+' | '*') => true,
+            None | Some('"') => self.sync,
+            _ => false,
+        }
+    }
+
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    /// Stores a copy, into the named register as well as the ring if a
+    /// letter is given.
+    pub fn copy(&mut self, name: Option<char>, register: Register) {
+        match name {
+            Some(name) if name.is_ascii_uppercase() => {
+                let named = self.named.entry(name.to_ascii_lowercase())
+                    .or_insert(Register::new(String::new(), register.linewise));
+
+                named.text.push_str(&register.text);
+                named.linewise |= register.linewise;
+                named.parts.clear();
+            }
+            Some(name) if name.is_ascii_lowercase() => {
+                self.named.insert(name, register.clone());
+            }
+            _ => (),
+        }
+
+        if self.is_clipboard(name) && self.clipboard.can_copy() {
+            if let Err(err) = self.clipboard.copy(&register.text) {
+                self.error = Some(format!("Could not copy to the clipboard: {}", err));
+            }
+        }
+
+        self.ring.push_front(register);
+        self.ring.truncate(RING_SIZE);
+    }
+
+    pub fn get(&mut self, name: Option<char>) -> Option<Register> {
+        if self.is_clipboard(name) && self.clipboard.can_paste() {
+            match self.clipboard.paste() {
+                Ok(Some(text)) => return Some(self.clipboard_register(text)),
+                Ok(None) => (),
+                Err(err) => self.error = Some(format!("Could not paste from the clipboard: {}", err)),
+            }
+        }
+
+        match name {
+            None | Some('"' | '+' | '*') => self.ring.front().cloned(),
+            Some(name) if name.is_ascii_digit() => self.ring.get(name.to_digit(10)? as usize).cloned(),
+            Some(name) => self.named.get(&name.to_ascii_lowercase()).cloned(),
+        }
+    }
+
+    /// Clipboard text as a register, keeping it linewise if it is what was
+    /// last copied here.
+    fn clipboard_register(&self, text: String) -> Register {
+        match self.ring.front() {
+            Some(register) if register.text == text => register.clone(),
+            _ => {
+                let linewise = text.ends_with('\n');
+                Register::new(text, linewise)
+            }
+        }
+    }
+
+    /// The register `index` copies back in the ring.
+    pub fn get_ring(&self, index: usize) -> Option<&Register> {
+        self.ring.get(index)
+    }
+
+    pub fn ring_len(&self) -> usize {
+        self.ring.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(text: &str) -> Register {
+        Register::new(text.to_string(), false)
+    }
+
+    #[test]
+    fn uppercase_names_append() {
+        let mut registers = Registers::default();
+
+        registers.copy(Some('a'), register("one"));
+        registers.copy(Some('A'), Register::new("two\n".to_string(), true));
+        assert_eq!(registers.get(Some('a')), Some(Register::new("onetwo\n".to_string(), true)));
+        assert_eq!(registers.get(Some('A')), registers.get(Some('a')));
+
+        // appending to an empty register starts it
+        registers.copy(Some('B'), register("three"));
+        assert_eq!(registers.get(Some('b')), Some(register("three")));
+
+        // lowercase replaces
+        registers.copy(Some('a'), register("four"));
+        assert_eq!(registers.get(Some('a')), Some(register("four")));
+    }
+
+    #[test]
+    fn the_ring_keeps_the_newest_copies() {
+        let mut registers = Registers::default();
+
+        for index in 0..12 {
+            registers.copy(None, register(&index.to_string()));
+        }
+
+        assert_eq!(registers.ring_len(), RING_SIZE);
+        assert_eq!(registers.get(None), Some(register("11")));
+        assert_eq!(registers.get(Some('0')), Some(register("11")));
+        assert_eq!(registers.get(Some('9')), Some(register("2")));
+        assert_eq!(registers.get_ring(1), Some(&register("10")));
+        assert_eq!(registers.get_ring(RING_SIZE), None);
+
+        // named copies go round the ring as well
+        registers.copy(Some('x'), register("named"));
+        assert_eq!(registers.get(Some('1')), Some(register("11")));
+        assert_eq!(registers.get(None), Some(register("named")));
+    }
+
+    #[test]
+    fn each_cursor_pastes_its_own_part() {
+        let joined = Register::join(vec![
+            Register::new("a\n".to_string(), true),
+            Register::new("b\n".to_string(), true),
+        ]).unwrap();
+
+        // whole lines already end in line endings
+        assert!(joined.linewise);
+        assert_eq!(joined.text, "a\nb\n");
+        assert_eq!(joined.text_for(1, 2), "b\n");
+        assert_eq!(joined.text_for(0, 3), "a\nb\n");
+
+        let joined = Register::join(vec![register("a"), Register::new("b\n".to_string(), true)]).unwrap();
+        assert!(joined.linewise);
+        assert_eq!(joined.text_for(0, 2), "a");
+
+        let single = Register::join(vec![register("c")]).unwrap();
+        assert_eq!(single.text_for(0, 2), "c");
+    }
+}
@@ -0,0 +1,418 @@
+use std::{iter, ops::Range, time::{Duration, Instant}};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::{buffer::{self, Buffer}, config::SearchConfig};
+
+/// Matches counted for the status line before giving up, so a pattern
+/// matching nearly everywhere in a huge buffer stays cheap.
+const MAX_COUNT: usize = 999;
+const COUNT_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// Lines of the buffer searched at once, doubling up to the most while
+/// nothing turns up, so a match near the cursor is found without copying
+/// the whole buffer. A match can't span the edge between two windows.
+const WINDOW_LINES: usize = 256;
+const MAX_WINDOW_LINES: usize = 16_384;
+
+/// A pattern compiled with the options it was typed with.
+#[derive(Debug, Clone)]
+pub struct Search {
+    pattern: String,
+    config: SearchConfig,
+    regex: Regex,
+}
+
+impl Search {
+    /// Compiles a pattern, failing with a one line reason when a regex
+    /// does not parse.
+    pub fn new(pattern: &str, config: SearchConfig) -> Result<Search, String> {
+        let mut source = match config.regex {
+            true => pattern.to_string(),
+            false => regex::escape(pattern),
+        };
+
+        if config.whole_word {
+            // literal text only needs a boundary where it starts or ends
+            // with a word char, or punctuation could never match
+            let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+
+            source = match config.regex {
+                true => format!(r"\b(?:{})\b", source),
+                false => format!(
+                    "{}{}{}",
+                    if is_word(pattern.chars().next()) { r"\b" } else { "" },
+                    source,
+                    if is_word(pattern.chars().last()) { r"\b" } else { "" },
+                ),
+            };
+        }
+
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(config.smart_case && !has_uppercase(pattern, config.regex))
+            .multi_line(true)
+            .build()
+            .map_err(|err| match err {
+                regex::Error::Syntax(message) => message.lines()
+                    .last()
+                    .unwrap_or_default()
+                    .trim_start_matches("error: ")
+                    .to_string(),
+                err => err.to_string(),
+            })?;
+
+        Ok(Search { pattern: pattern.to_string(), config, regex })
+    }
+
+    pub fn get_pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn get_config(&self) -> SearchConfig {
+        self.config
+    }
+
+    /// The chars of the first match starting after `position`, wrapping
+    /// around to the start of the buffer if allowed.
+    pub fn find_after(&self, buffer: &Buffer, position: usize) -> Option<Range<usize>> {
+        let byte = buffer.char_to_byte(position.min(buffer.len_chars()));
+        let line = buffer.char_to_line(position.min(buffer.len_chars()));
+
+        let found = windows(buffer, line..buffer.len_lines(), false).find_map(|(start, text)| {
+            let from = byte.checked_sub(start).map_or(0, |offset| buffer::next_char(&text, offset));
+            self.next_match(&text, from).map(|found| start + found.start..start + found.end)
+        });
+
+        let found = match (found, self.config.wrap_around) {
+            (None, true) => windows(buffer, 0..line + 1, false).find_map(|(start, text)| {
+                self.next_match(&text, 0).map(|found| start + found.start..start + found.end)
+            }),
+            (found, _) => found,
+        }?;
+
+        Some(buffer.byte_to_char(found.start)..buffer.byte_to_char(found.end))
+    }
+
+    /// The chars of the last match starting before `position`, wrapping
+    /// around to the end of the buffer if allowed.
+    pub fn find_before(&self, buffer: &Buffer, position: usize) -> Option<Range<usize>> {
+        let byte = buffer.char_to_byte(position.min(buffer.len_chars()));
+        let line = buffer.char_to_line(position.min(buffer.len_chars()));
+
+        let found = windows(buffer, 0..line + 1, true).find_map(|(start, text)| {
+            self.last_match(&text, byte - start).map(|found| start + found.start..start + found.end)
+        });
+
+        let found = match (found, self.config.wrap_around) {
+            (None, true) => windows(buffer, line..buffer.len_lines(), true).find_map(|(start, text)| {
+                self.last_match(&text, usize::MAX).map(|found| start + found.start..start + found.end)
+            }),
+            (found, _) => found,
+        }?;
+
+        Some(buffer.byte_to_char(found.start)..buffer.byte_to_char(found.end))
+    }
+
+    /// Each match in `text` with what replaces it: `replacement` with
+    /// groups like `$1` or `${name}` filled in for a regex, and given the
+    /// match's case if preserving it.
+    pub fn replacements(&self, text: &str, replacement: &str) -> Vec<(Range<usize>, String)> {
+        self.regex.captures_iter(text)
+            .filter_map(|captures| {
+                let found = captures.get(0).filter(|found| !found.is_empty())?;
+                let mut with = String::new();
+
+                match self.config.regex {
+                    true => captures.expand(replacement, &mut with),
+                    false => with.push_str(replacement),
+                }
+
+                if self.config.preserve_case {
+                    with = preserve_case(found.as_str(), &with);
+                }
+
+                Some((found.range(), with))
+            })
+            .collect()
+    }
+
+    /// Every match in `text`, which should start at the start of a line.
+    pub fn matches(&self, text: &str) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        let mut start = 0;
+
+        while let Some(found) = self.next_match(text, start) {
+            start = found.end;
+            matches.push(found);
+        }
+
+        matches
+    }
+
+    /// Counts the matches in the buffer by the chars they start at,
+    /// stopping early if there are very many or counting takes too long.
+    pub fn count(&self, buffer: &Buffer) -> MatchCount {
+        let deadline = Instant::now() + COUNT_TIMEOUT;
+        let mut starts = Vec::new();
+
+        for (start, text) in windows(buffer, 0..buffer.len_lines(), false) {
+            let mut from = 0;
+
+            while let Some(found) = self.next_match(&text, from) {
+                if starts.len() == MAX_COUNT || Instant::now() > deadline {
+                    return MatchCount { starts, complete: false };
+                }
+
+                from = found.end;
+                starts.push(buffer.byte_to_char(start + found.start));
+            }
+        }
+
+        MatchCount { starts, complete: true }
+    }
+
+    /// The first non-empty match starting at or after `start`.
+    fn next_match(&self, text: &str, mut start: usize) -> Option<Range<usize>> {
+        while start <= text.len() {
+            let found = self.regex.find_at(text, start)?;
+
+            if !found.is_empty() {
+                return Some(found.range());
+            }
+
+            start = buffer::next_char(text, found.end());
+        }
+
+        None
+    }
+
+    /// The last non-empty match in `text` starting before `limit`.
+    fn last_match(&self, text: &str, limit: usize) -> Option<Range<usize>> {
+        let mut last = None;
+        let mut start = 0;
+
+        while let Some(found) = self.next_match(text, start).filter(|found| found.start < limit) {
+            start = buffer::next_char(text, found.start);
+            last = Some(found);
+        }
+
+        last
+    }
+}
+
+/// The text of whole lines in `lines` a window at a time, forward from the
+/// first or backward from the last, each with the byte it starts at.
+fn windows(buffer: &Buffer, mut lines: Range<usize>, backward: bool) -> impl Iterator<Item = (usize, String)> + '_ {
+    let mut size = WINDOW_LINES;
+
+    iter::from_fn(move || {
+        if lines.is_empty() {
+            return None;
+        }
+
+        let window = match backward {
+            true => lines.end.saturating_sub(size).max(lines.start)..lines.end,
+            false => lines.start..(lines.start + size).min(lines.end),
+        };
+
+        match backward {
+            true => lines.end = window.start,
+            false => lines.start = window.end,
+        }
+
+        size = (size * 2).min(MAX_WINDOW_LINES);
+
+        let start = buffer.line_to_char(window.start);
+        let end = buffer.line_to_char(window.end);
+        Some((buffer.char_to_byte(start), buffer.slice(start..end).to_string()))
+    })
+}
+
+/// Where the match at or before a position falls among all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchCount {
+    starts: Vec<usize>,
+    /// False when counting stopped early.
+    complete: bool,
+}
+
+impl MatchCount {
+    /// Like `3/17`, for the last match starting at or before `position`,
+    /// or `?/>999` past the matches counted.
+    pub fn describe(&self, position: usize) -> String {
+        let index = self.starts.partition_point(|start| *start <= position);
+
+        match (self.complete, index == self.starts.len()) {
+            (true, _) => format!("{}/{}", index, self.starts.len()),
+            (false, true) => format!("?/>{}", self.starts.len()),
+            (false, false) => format!("{}/>{}", index, self.starts.len()),
+        }
+    }
+}
+
+/// The matches last counted in a buffer, kept until the buffer or search
+/// changes.
+#[derive(Debug, Clone, Default)]
+pub struct SearchCache {
+    count: Option<(u64, String, SearchConfig, MatchCount)>,
+}
+
+impl SearchCache {
+    pub fn count(&mut self, buffer: &Buffer, search: &Search) -> &MatchCount {
+        let stale = self.count.as_ref().is_none_or(|(version, pattern, config, _)| {
+            *version != buffer.get_version() || pattern != search.get_pattern() || *config != search.get_config()
+        });
+
+        if stale {
+            let count = search.count(buffer);
+            self.count = Some((buffer.get_version(), search.get_pattern().to_string(), search.get_config(), count));
+        }
+
+        &self.count.as_ref().expect("Counted above").3
+    }
+}
+
+/// A prompt label like `search [regex] [word]: `, naming the options that
+/// are on. Preserving case only matters when replacing.
+pub fn prompt_label(name: &str, config: SearchConfig, replacing: bool) -> String {
+    let flags = [
+        (config.regex, "regex"),
+        (config.whole_word, "word"),
+        (!config.smart_case, "case"),
+        (replacing && config.preserve_case, "preserve"),
+    ];
+
+    let mut label = name.to_string();
+
+    for (_, flag) in flags.iter().filter(|(on, _)| *on) {
+        label.push_str(&format!(" [{}]", flag));
+    }
+
+    label.push_str(": ");
+    label
+}
+
+/// Changes `replacement` to all upper or lower case, or capitalised, to
+/// match the text it replaces.
+fn preserve_case(matched: &str, replacement: &str) -> String {
+    let letters: Vec<char> = matched.chars().filter(|c| c.is_alphabetic()).collect();
+
+    match letters.as_slice() {
+        [] => replacement.to_string(),
+        [_, _, ..] if letters.iter().all(|c| c.is_uppercase()) => replacement.to_uppercase(),
+        _ if letters.iter().all(|c| c.is_lowercase()) => replacement.to_lowercase(),
+        [first, ..] if first.is_uppercase() => {
+            let mut chars = replacement.chars();
+
+            chars.next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        }
+        _ => replacement.to_string(),
+    }
+}
+
+/// Whether a pattern asks for case to matter, ignoring escapes like `\W`
+/// in a regex.
+fn has_uppercase(pattern: &str, regex: bool) -> bool {
+    let mut escaped = false;
+
+    pattern.chars().any(|c| {
+        let uppercase = c.is_uppercase() && !escaped;
+        escaped = regex && c == '\\' && !escaped;
+        uppercase
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(pattern: &str, config: SearchConfig) -> Search {
+        Search::new(pattern, config).expect("Valid pattern")
+    }
+
+    fn found(search: &Search, text: &str) -> Vec<String> {
+        search.matches(text).into_iter().map(|range| text[range].to_string()).collect()
+    }
+
+    #[test]
+    fn ignores_case_until_pattern_has_uppercase() {
+        let config = SearchConfig { smart_case: true, ..SearchConfig::default() };
+        let text = "Foo foo FOO";
+
+        assert_eq!(found(&search("foo", config), text), ["Foo", "foo", "FOO"]);
+        assert_eq!(found(&search("Foo", config), text), ["Foo"]);
+        assert_eq!(found(&search(r"\Wfoo", SearchConfig { regex: true, ..config }), text), [" foo", " FOO"]);
+        assert_eq!(found(&search("foo", SearchConfig { smart_case: false, ..config }), text), ["foo"]);
+    }
+
+    #[test]
+    fn matches_whole_words() {
+        let config = SearchConfig { whole_word: true, ..SearchConfig::default() };
+        let text = "cat concat cat_ cat. (cat)";
+
+        assert_eq!(search("cat", config).matches(text), [0..3, 16..19, 22..25]);
+        assert_eq!(found(&search("(cat)", config), text), ["(cat)"]);
+        assert_eq!(search("ca.", SearchConfig { regex: true, ..config }).matches(text), [0..3, 16..19, 22..25]);
+    }
+
+    #[test]
+    fn finds_around_position_and_wraps() {
+        let buffer = Buffer::from_text("one\ntwo one\none\n");
+        let config = SearchConfig { wrap_around: true, ..SearchConfig::default() };
+        let wrapping = search("one", config);
+        let stopping = search("one", SearchConfig { wrap_around: false, ..config });
+
+        assert_eq!(wrapping.find_after(&buffer, 0), Some(8..11));
+        assert_eq!(wrapping.find_after(&buffer, 12), Some(0..3));
+        assert_eq!(stopping.find_after(&buffer, 12), None);
+        assert_eq!(wrapping.find_before(&buffer, 12), Some(8..11));
+        assert_eq!(wrapping.find_before(&buffer, 0), Some(12..15));
+        assert_eq!(stopping.find_before(&buffer, 0), None);
+    }
+
+    #[test]
+    fn searches_past_the_first_window() {
+        let mut text = "needle\n".to_string();
+        text.push_str(&"hay\n".repeat(WINDOW_LINES * 3));
+        text.push_str("needle\n");
+        let buffer = Buffer::from_text(&text);
+        let needle = search("needle", SearchConfig { wrap_around: true, ..SearchConfig::default() });
+        let last = buffer.len_chars() - 7;
+
+        assert_eq!(needle.find_after(&buffer, 0), Some(last..last + 6));
+        assert_eq!(needle.find_before(&buffer, last), Some(0..6));
+        assert_eq!(needle.count(&buffer).describe(last), "2/2");
+    }
+
+    #[test]
+    fn caps_the_count() {
+        let buffer = Buffer::from_text(&"x\n".repeat(MAX_COUNT + 1));
+        let count = search("x", SearchConfig::default()).count(&buffer);
+
+        assert_eq!(count.describe(0), "1/>999");
+        assert_eq!(count.describe(buffer.len_chars()), "?/>999");
+    }
+
+    #[test]
+    fn fills_in_groups_when_replacing() {
+        let config = SearchConfig { regex: true, ..SearchConfig::default() };
+        let swap = search(r"(\w+)=(?<value>\w+)", config);
+
+        assert_eq!(swap.replacements("a=1 b=2", "${value}=$1"), [(0..3, "1=a".to_string()), (4..7, "2=b".to_string())]);
+        assert_eq!(search("a", SearchConfig::default()).replacements("a", "$1"), [(0..1, "$1".to_string())]);
+    }
+
+    #[test]
+    fn preserves_case_when_replacing() {
+        let config = SearchConfig { preserve_case: true, ..SearchConfig::default() };
+        let replaced = |text: &str| -> Vec<String> {
+            search("old", config).replacements(text, "new value").into_iter().map(|(_, with)| with).collect()
+        };
+
+        assert_eq!(replaced("old Old OLD oLd"), ["new value", "New value", "NEW VALUE", "new value"]);
+        assert_eq!(preserve_case("O", "new"), "New");
+        assert_eq!(preserve_case("_1", "new"), "new");
+    }
+}
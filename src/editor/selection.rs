@@ -0,0 +1,140 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::{Buffer, Point};
+
+use super::grapheme;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SelectionKind {
+    /// From grapheme to grapheme, both included.
+    Char,
+    /// Whole lines, line endings included.
+    Line,
+}
+
+/// A range between where it started, the anchor, and where the cursor is,
+/// the head. Either may come first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Selection {
+    anchor: usize,
+    head: usize,
+    kind: SelectionKind,
+}
+
+impl Selection {
+    pub fn new(anchor: usize, head: usize, kind: SelectionKind) -> Self {
+        Selection { anchor, head, kind }
+    }
+
+    pub fn get_anchor(&self) -> usize {
+        self.anchor
+    }
+
+    pub fn get_head(&self) -> usize {
+        self.head
+    }
+
+    pub fn set_head(&mut self, head: usize) -> &mut Self {
+        self.head = head;
+        self
+    }
+
+    pub fn get_kind(&self) -> SelectionKind {
+        self.kind
+    }
+
+    pub fn set_kind(&mut self, kind: SelectionKind) -> &mut Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Swaps the anchor and the head.
+    pub fn flip(&mut self) -> &mut Self {
+        std::mem::swap(&mut self.anchor, &mut self.head);
+        self
+    }
+
+    /// A selection covering both, running the same way as this one.
+    pub fn union(&self, other: &Selection) -> Selection {
+        let start = self.anchor.min(self.head).min(other.anchor).min(other.head);
+        let last = self.anchor.max(self.head).max(other.anchor).max(other.head);
+
+        let kind = match (self.kind, other.kind) {
+            (SelectionKind::Char, SelectionKind::Char) => SelectionKind::Char,
+            _ => SelectionKind::Line,
+        };
+
+        match self.anchor <= self.head {
+            true => Selection::new(start, last, kind),
+            false => Selection::new(last, start, kind),
+        }
+    }
+
+    /// The chars selected in `buffer`.
+    pub fn range(&self, buffer: &Buffer) -> Range<usize> {
+        let start = self.anchor.min(self.head).min(buffer.len_chars());
+        let last = self.anchor.max(self.head).min(buffer.len_chars());
+
+        match self.kind {
+            SelectionKind::Char => {
+                let point = buffer.char_to_point(last);
+                let line = buffer.line_text(point.line);
+
+                // past the end of the line, the line ending is selected
+                let end = match point.column < line.chars().count() {
+                    true => buffer.point_to_char(Point::new(point.line, grapheme::next_boundary(&line, point.column))),
+                    false => last + 1,
+                };
+
+                start..end.min(buffer.len_chars())
+            }
+            SelectionKind::Line => {
+                let last_line = buffer.char_to_line(last);
+
+                let end = match last_line + 1 < buffer.len_lines() {
+                    true => buffer.line_to_char(last_line + 1),
+                    false => buffer.len_chars(),
+                };
+
+                buffer.line_to_char(buffer.char_to_line(start))..end
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_run_either_way() {
+        let buffer = Buffer::from_text("hello\nworld");
+
+        assert_eq!(Selection::new(2, 7, SelectionKind::Char).range(&buffer), 2..8);
+        assert_eq!(Selection::new(7, 2, SelectionKind::Char).range(&buffer), 2..8);
+        assert_eq!(Selection::new(8, 1, SelectionKind::Line).range(&buffer), 0..11);
+        assert_eq!(Selection::new(7, 7, SelectionKind::Line).range(&buffer), 6..11);
+    }
+
+    #[test]
+    fn char_ranges_end_after_a_grapheme() {
+        let buffer = Buffer::from_text("ae\u{301}x\nb");
+
+        assert_eq!(Selection::new(1, 0, SelectionKind::Char).range(&buffer), 0..3);
+
+        // past the end of the line takes the line ending
+        assert_eq!(Selection::new(4, 3, SelectionKind::Char).range(&buffer), 3..5);
+        assert_eq!(Selection::new(6, 6, SelectionKind::Char).range(&buffer), 6..6);
+    }
+
+    #[test]
+    fn unions_keep_the_direction() {
+        let mut backward = Selection::new(5, 3, SelectionKind::Char);
+        let union = backward.union(&Selection::new(8, 9, SelectionKind::Line));
+
+        assert_eq!(union, Selection::new(9, 3, SelectionKind::Line));
+        assert_eq!(backward.flip().union(&Selection::new(1, 2, SelectionKind::Char)), Selection::new(1, 5, SelectionKind::Char));
+    }
+}
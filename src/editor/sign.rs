@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{buffer::{Buffer, LineEdit}, renderer::cell::CellStyle};
+
+/// A marker shown in the gutter beside a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sign {
+    pub symbol: char,
+    pub style: CellStyle,
+    /// Of the signs on a line, the one with the highest priority shows.
+    pub priority: i32,
+}
+
+/// Signs placed on lines of a buffer by sources such as diagnostics or
+/// bookmarks, each keeping to its line as lines are added and removed
+/// above it.
+#[derive(Debug, Clone, Default)]
+pub struct Signs {
+    sources: HashMap<String, BTreeMap<usize, Sign>>,
+    /// The buffer version the lines are for.
+    version: Option<u64>,
+}
+
+impl Signs {
+    /// Places a sign from `source` on a line, replacing any it had there.
+    pub fn set(&mut self, source: &str, line: usize, sign: Sign) -> &mut Self {
+        self.sources.entry(source.to_string()).or_default().insert(line, sign);
+        self
+    }
+
+    pub fn get(&self, source: &str, line: usize) -> Option<Sign> {
+        self.sources.get(source)?.get(&line).copied()
+    }
+
+    pub fn remove(&mut self, source: &str, line: usize) -> Option<Sign> {
+        self.sources.get_mut(source)?.remove(&line)
+    }
+
+    /// Removes every sign from `source`, as when it has new ones to place.
+    pub fn clear(&mut self, source: &str) -> &mut Self {
+        self.sources.remove(source);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.values().all(BTreeMap::is_empty)
+    }
+
+    /// The lines `source` has signs on, in order.
+    pub fn lines(&self, source: &str) -> impl Iterator<Item = usize> + '_ {
+        self.sources.get(source).into_iter().flat_map(|signs| signs.keys().copied())
+    }
+
+    /// The sign that shows on a line.
+    pub fn top(&self, line: usize) -> Option<Sign> {
+        self.sources.values()
+            .filter_map(|signs| signs.get(&line))
+            .max_by_key(|sign| sign.priority)
+            .copied()
+    }
+
+    /// Moves the signs along with the edits made to `buffer` since the
+    /// last sync.
+    pub fn sync(&mut self, buffer: &Buffer) {
+        if self.version == Some(buffer.get_version()) {
+            return;
+        }
+
+        let edits: Option<Vec<LineEdit>> = self.version
+            .and_then(|version| buffer.line_edits_since(version))
+            .map(Iterator::collect);
+
+        // signs stay put when the edits are no longer known
+        for edit in edits.into_iter().flatten() {
+            self.edit(edit);
+        }
+
+        let len_lines = buffer.len_lines();
+
+        for signs in self.sources.values_mut() {
+            signs.retain(|line, _| *line < len_lines);
+        }
+
+        self.version = Some(buffer.get_version());
+    }
+
+    /// Drops the signs on lines an edit removed and shifts those after it.
+    fn edit(&mut self, edit: LineEdit) {
+        for signs in self.sources.values_mut() {
+            *signs = std::mem::take(signs).into_iter()
+                .filter_map(|(line, sign)| Some((edit.map_line(line)?, sign)))
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(symbol: char, priority: i32) -> Sign {
+        Sign { symbol, style: CellStyle::default(), priority }
+    }
+
+    #[test]
+    fn highest_priority_shows() {
+        let mut signs = Signs::default();
+        signs.set("bookmark", 1, sign('*', 10)).set("error", 1, sign('E', 20)).set("bookmark", 2, sign('*', 10));
+
+        assert_eq!(signs.top(1), Some(sign('E', 20)));
+        assert_eq!(signs.top(2), Some(sign('*', 10)));
+        assert_eq!(signs.top(3), None);
+
+        signs.clear("error");
+        assert_eq!(signs.top(1), Some(sign('*', 10)));
+    }
+
+    #[test]
+    fn follows_lines_added_and_removed() {
+        let mut buffer = Buffer::from_text("a\nb\nc\nd\ne");
+        let mut signs = Signs::default();
+        let lines = |signs: &Signs| signs.lines("bookmark").collect::<Vec<_>>();
+        signs.sync(&buffer);
+        signs.set("bookmark", 0, sign('*', 0)).set("bookmark", 2, sign('*', 0)).set("bookmark", 4, sign('*', 0));
+
+        // lines opened above a line push its sign down with it
+        buffer.insert(buffer.line_to_char(2), "x\ny\n");
+        buffer.insert(0, "\n");
+        signs.sync(&buffer);
+        assert_eq!(lines(&signs), [1, 5, 7]);
+
+        // a line break inside a line leaves the sign where the line starts
+        buffer.insert(buffer.line_to_char(5) + 1, "\n");
+        signs.sync(&buffer);
+        assert_eq!(lines(&signs), [1, 5, 8]);
+
+        // deleting a line drops its sign, joining keeps the first line's
+        buffer.remove(buffer.line_to_char(1)..buffer.line_to_char(2));
+        let end = buffer.line_to_char(5) - 1;
+        buffer.remove(end..end + 1);
+        signs.sync(&buffer);
+        assert_eq!(lines(&signs), [4, 6]);
+
+        let len = buffer.len_chars();
+        buffer.remove(buffer.line_to_char(6) - 1..len);
+        signs.sync(&buffer);
+        assert_eq!(lines(&signs), [4]);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{
+    buffer::Point,
+    renderer::cell::Cell,
+    theme::Theme,
+    tui::{drawer::Drawer, widget::Widget},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StatusMessage {
+    text: String,
+    is_error: bool,
+}
+
+/// One row showing the focused file and the latest message.
+#[derive(Debug, Default)]
+pub struct StatusLine {
+    file_name: String,
+    modified: bool,
+    mode: &'static str,
+    point: Point,
+    cursors: usize,
+    /// Where the cursor is among the search's matches.
+    search: Option<String>,
+    message: Option<StatusMessage>,
+    theme: Theme,
+}
+
+impl StatusLine {
+    pub fn set_file(&mut self, file_name: String, modified: bool) -> &mut Self {
+        self.file_name = file_name;
+        self.modified = modified;
+        self
+    }
+
+    pub fn set_mode(&mut self, mode: &'static str) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn set_point(&mut self, point: Point) -> &mut Self {
+        self.point = point;
+        self
+    }
+
+    pub fn set_cursors(&mut self, cursors: usize) -> &mut Self {
+        self.cursors = cursors;
+        self
+    }
+
+    pub fn set_search(&mut self, search: Option<String>) -> &mut Self {
+        self.search = search;
+        self
+    }
+
+    pub fn set_message(&mut self, text: String) -> &mut Self {
+        self.message = Some(StatusMessage { text, is_error: false });
+        self
+    }
+
+    pub fn set_error(&mut self, text: String) -> &mut Self {
+        self.message = Some(StatusMessage { text, is_error: true });
+        self
+    }
+
+    pub fn clear_message(&mut self) -> &mut Self {
+        self.message = None;
+        self
+    }
+}
+
+impl Widget for StatusLine {
+    fn draw(&mut self, drawer: &mut Drawer, _scroll: (u16, u16)) {
+        let bar_style = self.theme.status_line;
+
+        for x in 0..drawer.boundaries.width {
+            drawer.draw_cell(x, 0, Cell { c: ' ', style: bar_style });
+        }
+
+        let mut file = format!(" {} {}", self.mode.to_uppercase(), self.file_name);
+
+        if self.modified {
+            file.push_str(" [+]");
+        }
+
+        let x = drawer.draw_text(0, 0, &file, bar_style);
+
+        let mut position = format!("{}:{} ", self.point.line + 1, self.point.column + 1);
+
+        if let Some(search) = &self.search {
+            position.insert_str(0, &format!("[{}]  ", search));
+        }
+
+        if self.cursors > 1 {
+            position.insert_str(0, &format!("{} cursors  ", self.cursors));
+        }
+
+        let position_x = drawer.boundaries.width.saturating_sub(position.chars().count() as u16);
+        drawer.draw_text(position_x, 0, &position, bar_style);
+
+        if let Some(message) = &self.message {
+            let style = match message.is_error {
+                true => self.theme.status_error,
+                false => bar_style,
+            };
+
+            drawer.draw_text(x + 2, 0, &message.text, style);
+        }
+    }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        self.theme = theme.clone();
+    }
+}
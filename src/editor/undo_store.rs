@@ -0,0 +1,222 @@
+use std::{fs, io, path::{self, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{buffer::Buffer, config::{self, UndoConfig}, document::{self, Document}};
+
+use super::history::History;
+
+const UNDO_DIR: &str = "undo";
+/// Bumped when the stored format changes, discarding older files.
+const STORE_VERSION: u32 = 1;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+#[derive(Serialize, Deserialize)]
+struct StoredHistory {
+    version: u32,
+    path: PathBuf,
+    /// Hash of the text the current revision leaves.
+    hash: u64,
+    history: History,
+}
+
+/// Keeps each file's undo history in the data dir between sessions.
+///
+/// Histories are stored when a file is saved, under a name derived from
+/// its absolute path, with a hash of the text saved. A history whose hash
+/// no longer matches the file was made stale by an edit elsewhere and is
+/// thrown away.
+#[derive(Debug, Clone)]
+pub struct UndoStore {
+    dir: PathBuf,
+    max_file_size: u64,
+}
+
+impl UndoStore {
+    /// A store in the data dir, or None if history is not kept.
+    pub fn new(config: &UndoConfig) -> Option<UndoStore> {
+        if !config.persist {
+            return None;
+        }
+
+        let dir = config::data_dir()?.join(UNDO_DIR);
+        Some(UndoStore::with_dir(dir, config.max_file_size))
+    }
+
+    pub fn with_dir(dir: PathBuf, max_file_size: u64) -> Self {
+        UndoStore { dir, max_file_size }
+    }
+
+    /// The history stored for a freshly opened document, if it matches
+    /// the document's text.
+    pub fn load(&self, document: &Document) -> io::Result<Option<History>> {
+        let Some((path, store_path)) = self.paths(document) else {
+            return Ok(None);
+        };
+
+        if document.get_buffer().len_bytes() as u64 > self.max_file_size {
+            return Ok(None);
+        }
+
+        let bytes = match fs::read(&store_path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let buffer = document.get_buffer();
+
+        let stored = serde_json::from_slice::<StoredHistory>(&bytes).ok().filter(|stored| {
+            stored.version == STORE_VERSION
+                && stored.path == path
+                && stored.hash == content_hash(buffer)
+                && stored.history.is_valid()
+        });
+
+        match stored {
+            Some(stored) => Ok(Some(stored.history)),
+            None => {
+                fs::remove_file(&store_path)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Stores a document's history as of its last save.
+    pub fn save(&self, document: &Document) -> io::Result<()> {
+        let Some((path, store_path)) = self.paths(document) else {
+            return Ok(());
+        };
+
+        if document.get_buffer().len_bytes() as u64 > self.max_file_size {
+            return match fs::remove_file(&store_path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+
+        let stored = StoredHistory {
+            version: STORE_VERSION,
+            path,
+            hash: content_hash(document.get_buffer()),
+            history: document.get_history().clone(),
+        };
+
+        let bytes = serde_json::to_vec(&stored)?;
+
+        fs::create_dir_all(&self.dir)?;
+        document::write_atomic(&store_path, &bytes)
+    }
+
+    /// The document's absolute path and where its history is stored, if
+    /// it has a path.
+    fn paths(&self, document: &Document) -> Option<(PathBuf, PathBuf)> {
+        let path = document.get_path()?;
+        let path = fs::canonicalize(path)
+            .or_else(|_| path::absolute(path))
+            .ok()?;
+
+        let name = format!("{:016x}.json", fnv1a(FNV_OFFSET, path.as_os_str().as_encoded_bytes()));
+        Some((path, self.dir.join(name)))
+    }
+}
+
+/// FNV-1a over the buffer's text, stable across builds unlike std's
+/// default hasher.
+pub fn content_hash(buffer: &Buffer) -> u64 {
+    buffer.get_rope()
+        .chunks()
+        .fold(FNV_OFFSET, |hash, chunk| fnv1a(hash, chunk.as_bytes()))
+}
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use crate::editor::history::CursorState;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("text-editor-undo-{}-{}", process::id(), name))
+    }
+
+    /// Opens a file holding "one", types " two" into it and saves.
+    fn edited_document(path: &std::path::Path) -> Document {
+        fs::write(path, "one\n").unwrap();
+
+        let mut document = Document::open(path).unwrap();
+        document.get_buffer_mut().insert(3, " two");
+        document.commit(CursorState::default(), CursorState::default(), None);
+        document.save().unwrap();
+        document
+    }
+
+    #[test]
+    fn restores_history_for_the_same_text() {
+        let dir = temp_path("same-dir");
+        let path = temp_path("same.txt");
+        let store = UndoStore::with_dir(dir.clone(), u64::MAX);
+        store.save(&edited_document(&path)).unwrap();
+
+        let mut document = Document::open(&path).unwrap();
+        let history = store.load(&document).unwrap().expect("History was stored");
+        document.set_history(history);
+
+        document.undo();
+        assert_eq!(document.get_buffer().get_rope().to_string(), "one");
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn drops_history_the_file_no_longer_matches() {
+        let dir = temp_path("stale-dir");
+        let path = temp_path("stale.txt");
+        let store = UndoStore::with_dir(dir.clone(), u64::MAX);
+        store.save(&edited_document(&path)).unwrap();
+
+        // edited elsewhere since
+        fs::write(&path, "one three\n").unwrap();
+        let document = Document::open(&path).unwrap();
+
+        assert!(store.load(&document).unwrap().is_none());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skips_large_files() {
+        let dir = temp_path("large-dir");
+        let path = temp_path("large.txt");
+        let store = UndoStore::with_dir(dir.clone(), 4);
+        let document = edited_document(&path);
+        store.save(&document).unwrap();
+
+        assert!(!dir.exists());
+        assert!(store.load(&document).unwrap().is_none());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn hashes_the_text_however_it_is_chunked() {
+        let text = "line\n".repeat(10_000);
+        let mut built = Buffer::new();
+
+        for (index, line) in text.split_inclusive('\n').enumerate() {
+            built.insert(index * 5, line);
+        }
+
+        assert_eq!(content_hash(&built), content_hash(&Buffer::from_text(&text)));
+        assert_ne!(content_hash(&built), content_hash(&Buffer::from_text("other")));
+    }
+}
@@ -0,0 +1,3239 @@
+use std::{rc::Rc, cell::RefCell, collections::VecDeque, ops::Range, time::Duration};
+
+use crossterm::{cursor::CursorShape, event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind}, style::Attribute};
+use regex::Regex;
+use unicode_width::UnicodeWidthChar;
+
+use crate::{
+    buffer::{Change, Point},
+    command::Command,
+    config::{EditorConfig, LanguageConfig, SearchConfig},
+    document::{Document, Indent},
+    keymap::{INSERT_CONTEXT, NORMAL_CONTEXT, PROMPT_CONTEXT, VISUAL_CONTEXT},
+    renderer::cell::{Cell, CellStyle},
+    syntax::{grammar::Grammar, highlighter::Highlighter},
+    theme::Theme,
+    tui::{drawer::Drawer, event::{NodeEvent, NodeMouseEvent, Phase}, widget::Widget},
+};
+
+use super::{
+    bracket,
+    cursor::{self, Cursor, Motion},
+    fold::{Fold, Folds},
+    grapheme,
+    history::CursorState,
+    prompt::Prompt,
+    register::{Register, Registers},
+    search::{self, Search, SearchCache},
+    sign::Sign,
+    wrap::{WrapCache, WrapRow, WrapSettings},
+    selection::{Selection, SelectionKind},
+};
+
+const WHEEL_LINES: isize = 3;
+const BOOKMARK_SIGNS: &str = "bookmark";
+const BOOKMARK_SYMBOL: char = '*';
+/// Below signs that say something about the code, like diagnostics.
+const BOOKMARK_PRIORITY: i32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Normal => NORMAL_CONTEXT,
+            Mode::Insert => INSERT_CONTEXT,
+            Mode::Visual => VISUAL_CONTEXT,
+        }
+    }
+}
+
+/// Draws a document's buffer into a node and edits it at one or more
+/// cursors.
+///
+/// `cursor` and `selection` belong to the primary cursor, the one the view
+/// follows. Edits and motions run for every cursor in buffer order, with
+/// the others following the text as it changes.
+pub struct EditorView {
+    document: Rc<RefCell<Document>>,
+    cursor: Cursor,
+    mode: Mode,
+    /// Set in visual mode, with the head following the cursor.
+    selection: Option<Selection>,
+    /// Cursors besides the primary one, with their selections, in buffer
+    /// order.
+    others: Vec<(Cursor, Option<Selection>)>,
+    /// Which cursor of how many a command is running for.
+    active: (usize, usize),
+    /// What each cursor copied during a command, joined once all ran.
+    copied: Vec<Register>,
+    count: Option<usize>,
+    registers: Rc<RefCell<Registers>>,
+    /// The register named for the next copy or paste.
+    register: Option<char>,
+    /// Set after `"` until the register name is typed.
+    awaiting_register: bool,
+    last_paste: Option<LastPaste>,
+    /// The revision that typing goes into until something else happens.
+    typing_revision: Option<usize>,
+    /// First visible line and screen column.
+    scroll: (usize, usize),
+    /// Set when the cursor moved and the view should scroll to it.
+    follow_cursor: bool,
+    /// The scroll at the last draw, to hint the renderer when it changes.
+    drawn_scroll: Option<(usize, usize)>,
+    /// Where a drag with the left button started, until it is released.
+    drag_anchor: Option<usize>,
+    size: (u16, u16),
+    config: EditorConfig,
+    prompt: Option<(PromptAction, Prompt)>,
+    /// The last failure, until taken to show it.
+    error: Option<String>,
+    search_config: SearchConfig,
+    /// The last search, which `n` and `N` repeat.
+    search: Option<Search>,
+    /// Whether the last search went backward, which `n` repeats.
+    search_backward: bool,
+    /// Whether the search's matches are highlighted, until leaving normal
+    /// mode again.
+    highlight_search: bool,
+    search_cache: SearchCache,
+    /// Set while stepping through a replace that asks before each match.
+    replacing: Option<PendingReplace>,
+    /// The last notice, until taken to show it.
+    message: Option<String>,
+    highlighter: Option<Highlighter>,
+    theme: Theme,
+    /// Rows of the wrapped lines, filled in as they are drawn or moved
+    /// through.
+    wrap: RefCell<WrapCache>,
+    folds: RefCell<Folds>,
+    language: LanguageConfig,
+}
+
+/// What is done with the prompt's text once accepted.
+#[derive(Debug, Clone)]
+enum PromptAction {
+    SelectMatches,
+    TabWidth,
+    /// Moves to matches while the pattern is typed, going back to where
+    /// the cursor, scroll and previous search were if cancelled.
+    Search {
+        backward: bool,
+        origin: Cursor,
+        scroll: (usize, usize),
+        previous: Option<Search>,
+        highlight: bool,
+        /// Set when the pattern is for a replace rather than a search.
+        replace: Option<ReplaceSetup>,
+    },
+    /// The text replacing the matches of the search just made.
+    Replacement(ReplaceSetup),
+}
+
+impl PromptAction {
+    /// The label of a prompt for a pattern or replacement, naming the
+    /// search options that are on.
+    fn label(&self, config: SearchConfig) -> String {
+        let name = match self {
+            PromptAction::TabWidth => return "tab width: ".to_string(),
+            PromptAction::SelectMatches => "select",
+            PromptAction::Search { replace: Some(_), .. } => "replace",
+            PromptAction::Search { backward: true, .. } => "search backward",
+            PromptAction::Search { .. } => "search",
+            PromptAction::Replacement(_) => "with",
+        };
+
+        let replacing = matches!(self, PromptAction::Search { replace: Some(_), .. } | PromptAction::Replacement(_));
+        search::prompt_label(name, config, replacing)
+    }
+}
+
+/// How a replace was asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ReplaceSetup {
+    confirm: bool,
+    /// The selections to replace in, or empty for the whole buffer.
+    scope: Vec<Range<usize>>,
+}
+
+/// Matches a replace is asking about one at a time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PendingReplace {
+    /// Matches not yet answered for, with their replacements, in buffer
+    /// order.
+    matches: VecDeque<(Range<usize>, String)>,
+    total: usize,
+    replaced: usize,
+    /// The buffer's version after the last step, to stop if it is edited
+    /// elsewhere.
+    version: u64,
+}
+
+/// What the last paste inserted, so it can be swapped for an older copy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LastPaste {
+    range: Range<usize>,
+    cursor: usize,
+    before: bool,
+    count: usize,
+    ring_index: usize,
+    /// The buffer's version right after the paste.
+    version: u64,
+}
+
+/// Where to put the cursor line when scrolling to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Top,
+    Center,
+    Bottom,
+}
+
+impl EditorView {
+    pub fn new(document: Rc<RefCell<Document>>, registers: Rc<RefCell<Registers>>, config: EditorConfig) -> Self {
+        EditorView {
+            document,
+            cursor: Cursor::default(),
+            mode: Mode::Normal,
+            selection: None,
+            others: Vec::new(),
+            active: (0, 1),
+            copied: Vec::new(),
+            count: None,
+            registers,
+            register: None,
+            awaiting_register: false,
+            last_paste: None,
+            typing_revision: None,
+            scroll: (0, 0),
+            follow_cursor: true,
+            drawn_scroll: None,
+            drag_anchor: None,
+            size: (0, 0),
+            config,
+            prompt: None,
+            error: None,
+            search_config: SearchConfig::default(),
+            search: None,
+            search_backward: false,
+            highlight_search: false,
+            search_cache: SearchCache::default(),
+            replacing: None,
+            message: None,
+            highlighter: None,
+            theme: Theme::default(),
+            wrap: RefCell::new(WrapCache::default()),
+            folds: RefCell::new(Folds::default()),
+            language: LanguageConfig::default(),
+        }
+    }
+
+    pub fn set_search_config(&mut self, config: SearchConfig) -> &mut Self {
+        self.search_config = config;
+        self
+    }
+
+    /// Highlights the text with `grammar`, or not at all if None.
+    pub fn set_grammar(&mut self, grammar: Option<Rc<Grammar>>) -> &mut Self {
+        self.highlighter = grammar.map(|grammar| Highlighter::new(grammar, &self.theme.syntax));
+        self
+    }
+
+    pub fn set_language(&mut self, language: LanguageConfig) -> &mut Self {
+        self.language = language;
+        self
+    }
+
+    pub fn get_document(&self) -> Rc<RefCell<Document>> {
+        self.document.clone()
+    }
+
+    pub fn get_mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn get_selection(&self) -> Option<Selection> {
+        self.selection
+    }
+
+    pub fn get_scroll(&self) -> (usize, usize) {
+        self.scroll
+    }
+
+    /// Columns and rows of text shown at the last draw.
+    pub fn get_size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    pub fn get_cursor(&self) -> Cursor {
+        self.cursor
+    }
+
+    pub fn cursor_count(&self) -> usize {
+        self.others.len() + 1
+    }
+
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    pub fn take_message(&mut self) -> Option<String> {
+        self.message.take()
+    }
+
+    /// Which match the cursor is at of how many, like `3/17`, while
+    /// matches are highlighted.
+    pub fn search_status(&mut self) -> Option<String> {
+        let search = self.search.as_ref().filter(|_| self.highlight_search)?;
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let position = self.cursor.get_position();
+
+        Some(self.search_cache.count(buffer, search).describe(position))
+    }
+
+    pub fn cursor_point(&self) -> Point {
+        self.cursor.point(self.document.borrow().get_buffer())
+    }
+
+    pub fn set_cursor_point(&mut self, point: Point) {
+        let position = self.document.borrow().get_buffer().point_to_char(point);
+        self.cursor.set_position(position);
+        self.clamp_cursor();
+    }
+
+    /// Replaces each char range, in buffer order and not overlapping, as
+    /// one step in the history.
+    pub fn replace_ranges(&mut self, edits: &[(Range<usize>, String)]) {
+        let before = self.cursor_state();
+        let mut document = self.document.borrow_mut();
+
+        // from the last range back, so the earlier ones stay put
+        for (range, with) in edits.iter().rev() {
+            document.get_buffer_mut().replace(range.clone(), with);
+        }
+
+        drop(document);
+        self.others.clear();
+        self.clamp_cursor();
+        self.commit(before, false, true);
+    }
+
+    pub fn move_cursor(&mut self, motion: Motion) {
+        let count = self.count.take().unwrap_or(1);
+        let page_height = self.size.1 as usize;
+
+        // a closed fold counts as one line
+        if self.has_hidden() {
+            let lines = match motion {
+                Motion::Up => Some(-(count as isize)),
+                Motion::Down => Some(count as isize),
+                Motion::PageUp => Some(-((count * page_height.max(1)) as isize)),
+                Motion::PageDown => Some((count * page_height.max(1)) as isize),
+                _ => None,
+            };
+
+            if let Some(lines) = lines {
+                return self.move_lines(lines);
+            }
+        }
+
+        let document = self.document.borrow();
+        self.cursor.apply(document.get_buffer(), motion, count, page_height, document.get_indent().width);
+        drop(document);
+
+        self.clamp_cursor();
+    }
+
+    /// Normal mode keeps the cursor on a char rather than past the end of
+    /// the line.
+    fn clamp_cursor(&mut self) {
+        if self.mode == Mode::Normal {
+            self.cursor.clamp_to_line(self.document.borrow().get_buffer());
+        }
+
+        if let Some(selection) = &mut self.selection {
+            selection.set_head(self.cursor.get_position());
+        }
+
+        self.follow_cursor = true;
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        if mode != Mode::Visual {
+            self.selection = None;
+
+            for (_, selection) in &mut self.others {
+                *selection = None;
+            }
+        }
+
+        self.mode = mode;
+        self.count = None;
+        self.clamp_cursor();
+    }
+
+    fn cursor_state(&self) -> CursorState {
+        let others = self.others.iter()
+            .map(|(cursor, selection)| CursorState {
+                position: cursor.get_position(),
+                selection: *selection,
+                others: Vec::new(),
+            })
+            .collect();
+
+        CursorState {
+            position: self.cursor.get_position(),
+            selection: self.selection,
+            others,
+        }
+    }
+
+    /// Puts the cursors and selections back as they were around an edit.
+    fn restore_cursor_state(&mut self, state: CursorState) {
+        self.cursor.set_position(state.position);
+        self.others = state.others.iter()
+            .map(|other| (Cursor::new(other.position), other.selection))
+            .collect();
+
+        match state.selection {
+            Some(selection) => {
+                self.selection = Some(selection);
+                self.mode = Mode::Visual;
+                self.clamp_cursor();
+            }
+            None => self.set_mode(Mode::Normal),
+        }
+
+        self.merge_cursors();
+    }
+
+    /// Runs an edit or motion for every cursor in buffer order, each time
+    /// with the count and register the command was given. Cursors not
+    /// running are moved along with the changes each run makes.
+    fn for_each_cursor(&mut self, mut f: impl FnMut(&mut Self)) {
+        if self.others.is_empty() {
+            self.active = (0, 1);
+            f(self);
+            return;
+        }
+
+        let (count, register, mode) = (self.count, self.register, self.mode);
+        let mut cursors = std::mem::take(&mut self.others);
+        let primary = cursors.partition_point(|(cursor, _)| cursor.get_position() < self.cursor.get_position());
+        cursors.insert(primary, (self.cursor, self.selection));
+
+        for i in 0..cursors.len() {
+            (self.cursor, self.selection) = cursors[i];
+            (self.count, self.register, self.mode) = (count, register, mode);
+            self.active = (i, cursors.len());
+
+            let start = self.document.borrow().get_buffer().changes_len();
+            f(self);
+            cursors[i] = (self.cursor, self.selection);
+
+            let document = self.document.borrow();
+            let changes = document.get_buffer().changes_since(start);
+
+            if changes.is_empty() {
+                continue;
+            }
+
+            for (j, (cursor, selection)) in cursors.iter_mut().enumerate() {
+                if j != i {
+                    map_cursor(changes, cursor, selection);
+                }
+            }
+        }
+
+        (self.cursor, self.selection) = cursors.remove(primary);
+        self.others = cursors;
+        self.active = (0, 1);
+        self.merge_cursors();
+    }
+
+    /// Tidies the cursors after they moved: drops the other selections
+    /// outside visual mode, sorts the cursors and merges any on the same
+    /// char or with overlapping selections.
+    fn merge_cursors(&mut self) {
+        if self.others.is_empty() {
+            return;
+        }
+
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let len = buffer.len_chars();
+
+        // the primary cursor is marked so it outlives merging
+        let mut cursors: Vec<(Cursor, Option<Selection>, bool)> = self.others.drain(..)
+            .map(|(cursor, selection)| (cursor, selection, false))
+            .chain([(self.cursor, self.selection, true)])
+            .collect();
+
+        for (cursor, selection, _) in &mut cursors {
+            if cursor.get_position() > len {
+                cursor.set_position(len);
+            }
+
+            if self.mode == Mode::Normal {
+                cursor.clamp_to_line(buffer);
+            }
+
+            if self.mode != Mode::Visual {
+                *selection = None;
+            }
+        }
+
+        // a cursor takes up the char it is on
+        let range_of = |cursor: &Cursor, selection: &Option<Selection>| match selection {
+            Some(selection) => {
+                let range = selection.range(buffer);
+                range.start..range.end.max(range.start + 1)
+            }
+            None => cursor.get_position()..cursor.get_position() + 1,
+        };
+
+        cursors.sort_by_key(|(cursor, selection, _)| range_of(cursor, selection).start);
+
+        let mut merged: Vec<(Cursor, Option<Selection>, bool)> = Vec::with_capacity(cursors.len());
+
+        for (cursor, selection, primary) in cursors {
+            let Some(last) = merged.last_mut()
+                .filter(|last| range_of(&cursor, &selection).start < range_of(&last.0, &last.1).end) else {
+                merged.push((cursor, selection, primary));
+                continue;
+            };
+
+            last.1 = match (last.1, selection) {
+                (Some(last), Some(selection)) => Some(last.union(&selection)),
+                (last, selection) => last.or(selection),
+            };
+
+            if let Some(head) = last.1.map(|selection| selection.get_head()) {
+                if head != last.0.get_position() {
+                    last.0.set_position(head);
+                }
+            }
+
+            last.2 |= primary;
+        }
+
+        drop(document);
+
+        let primary = merged.iter().position(|(_, _, primary)| *primary).unwrap_or(0);
+        let (cursor, selection, _) = merged.remove(primary);
+
+        self.cursor = cursor;
+        self.selection = selection;
+        self.others = merged.into_iter().map(|(cursor, selection, _)| (cursor, selection)).collect();
+    }
+
+    /// Makes a new cursor the primary one, keeping the old one as another.
+    fn add_cursor(&mut self, cursor: Cursor) {
+        self.others.push((self.cursor, self.selection));
+        self.cursor = cursor;
+
+        self.selection = match self.mode {
+            Mode::Visual => Some(Selection::new(cursor.get_position(), cursor.get_position(), SelectionKind::Char)),
+            _ => None,
+        };
+
+        self.clamp_cursor();
+        self.merge_cursors();
+    }
+
+    /// Adds cursors on the lines below or above the furthest cursor that
+    /// way, `count` times.
+    fn add_cursor_line(&mut self, down: bool) {
+        let count = self.count.take().unwrap_or(1);
+        let motion = if down { Motion::Down } else { Motion::Up };
+
+        for _ in 0..count {
+            let furthest = match down {
+                true => self.others.last(),
+                false => self.others.first(),
+            };
+
+            let mut cursor = match furthest {
+                Some((other, _)) if (other.get_position() > self.cursor.get_position()) == down => *other,
+                _ => self.cursor,
+            };
+
+            let document = self.document.borrow();
+            let line = cursor.point(document.get_buffer()).line;
+            cursor.apply(document.get_buffer(), motion, 1, 0, document.get_indent().width);
+            let moved = cursor.point(document.get_buffer()).line != line;
+            drop(document);
+
+            if !moved {
+                break;
+            }
+
+            self.add_cursor(cursor);
+        }
+    }
+
+    /// Selects the next occurrence of the primary selection's text after
+    /// it, wrapping around, with a new cursor. Without a selection, selects
+    /// the word under the cursor first.
+    fn add_next_match(&mut self) {
+        let Some(selection) = self.selection else {
+            self.select_word();
+            return;
+        };
+
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let range = selection.range(buffer);
+
+        if range.is_empty() {
+            return;
+        }
+
+        let needle = buffer.slice(range.clone()).to_string();
+
+        // the selected text exactly, wherever it is
+        let config = SearchConfig { regex: false, smart_case: false, whole_word: false, wrap_around: true, preserve_case: false };
+        let search = Search::new(&needle, config).expect("Escaped text is a valid pattern");
+
+        let selected: Vec<usize> = self.others.iter()
+            .filter_map(|(_, selection)| selection.map(|selection| selection.range(buffer).start))
+            .collect();
+
+        let mut position = range.end - 1;
+        let mut found = None;
+
+        // each step past a selected match, so this stops once around
+        for _ in 0..=selected.len() {
+            let Some(next) = search.find_after(buffer, position).filter(|next| next.start != range.start) else {
+                break;
+            };
+
+            if !selected.contains(&next.start) {
+                found = Some(next.start);
+                break;
+            }
+
+            position = next.start;
+        }
+
+        drop(document);
+
+        let Some(start) = found else {
+            return;
+        };
+
+        let head = start + needle.chars().count() - 1;
+
+        self.others.push((self.cursor, self.selection));
+        self.cursor = Cursor::new(head);
+        self.selection = Some(Selection::new(start, head, SelectionKind::Char));
+        self.clamp_cursor();
+        self.merge_cursors();
+    }
+
+    /// Replaces each selection with one for each line it covers.
+    fn split_selection_lines(&mut self) {
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let mut selections = Vec::new();
+
+        let cursors = self.others.iter()
+            .copied()
+            .chain([(self.cursor, self.selection)]);
+
+        for (_, selection) in cursors {
+            let Some(selection) = selection else {
+                continue;
+            };
+
+            let range = selection.range(buffer);
+            let last_line = buffer.char_to_line(range.end.saturating_sub(1).max(range.start));
+
+            for line in buffer.char_to_line(range.start)..=last_line {
+                let line_start = buffer.line_to_char(line);
+                let start = range.start.max(line_start);
+                let end = range.end.min(line_start + buffer.line_len(line));
+
+                if end > start {
+                    selections.push(Selection::new(start, end - 1, SelectionKind::Char));
+                }
+            }
+        }
+
+        drop(document);
+        self.set_selections(selections);
+    }
+
+    /// Replaces each selection, or the whole buffer outside visual mode,
+    /// with a selection for each match of `pattern` in it.
+    fn select_matches(&mut self, pattern: &str) {
+        let regex = match Regex::new(pattern) {
+            Ok(regex) => regex,
+            Err(err) => {
+                self.error = Some(format!("Invalid pattern: {}", err));
+                return;
+            }
+        };
+
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+
+        let ranges: Vec<Range<usize>> = match self.mode {
+            Mode::Visual => self.others.iter()
+                .copied()
+                .chain([(self.cursor, self.selection)])
+                .filter_map(|(_, selection)| selection.map(|selection| selection.range(buffer)))
+                .collect(),
+            _ => std::iter::once(0..buffer.len_chars()).collect(),
+        };
+
+        let mut selections = Vec::new();
+
+        for range in ranges {
+            let start_byte = buffer.char_to_byte(range.start);
+            let text = buffer.slice(range).to_string();
+
+            for found in regex.find_iter(&text).filter(|found| !found.is_empty()) {
+                let start = buffer.byte_to_char(start_byte + found.start());
+                let end = buffer.byte_to_char(start_byte + found.end());
+                selections.push(Selection::new(start, end - 1, SelectionKind::Char));
+            }
+        }
+
+        drop(document);
+
+        if selections.is_empty() {
+            self.error = Some(format!("No matches for '{}'", pattern));
+            return;
+        }
+
+        self.set_selections(selections);
+    }
+
+    /// Opens the prompt for a search from the cursor, or for the pattern
+    /// a replace looks for.
+    fn start_search(&mut self, backward: bool, replace: Option<ReplaceSetup>) {
+        let action = PromptAction::Search {
+            backward,
+            origin: self.cursor,
+            scroll: self.scroll,
+            previous: self.search.clone(),
+            highlight: self.highlight_search,
+            replace,
+        };
+
+        self.count = None;
+        self.prompt = Some((action.clone(), Prompt::new(action.label(self.search_config))));
+    }
+
+    /// Asks for a pattern and replacement for it in the selections, or in
+    /// the whole buffer outside visual mode.
+    fn start_replace(&mut self, confirm: bool) {
+        let document = self.document.borrow();
+
+        let mut scope: Vec<Range<usize>> = match self.mode {
+            Mode::Visual => self.others.iter()
+                .filter_map(|(_, selection)| *selection)
+                .chain(self.selection)
+                .map(|selection| selection.range(document.get_buffer()))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        drop(document);
+        scope.sort_by_key(|range| range.start);
+        self.start_search(false, Some(ReplaceSetup { confirm, scope }));
+    }
+
+    /// Moves to the first match of the pattern typed so far, or back to
+    /// where the search started without one. The pattern for a replace is
+    /// only highlighted.
+    fn preview_search(&mut self) {
+        let Some((PromptAction::Search { backward, origin, replace, .. }, prompt)) = &self.prompt else {
+            return;
+        };
+
+        if replace.is_some() {
+            self.search = Search::new(prompt.get_text(), self.search_config).ok()
+                .filter(|_| !prompt.get_text().is_empty());
+            self.highlight_search = self.search.is_some();
+            return;
+        }
+
+        let (backward, origin) = (*backward, *origin);
+        let search = match prompt.get_text() {
+            "" => None,
+            pattern => Search::new(pattern, self.search_config).ok(),
+        };
+
+        self.cursor = origin;
+
+        if let Some(found) = search.as_ref().and_then(|search| self.find_match(search, origin.get_position(), backward)) {
+            self.cursor.set_position(found.start);
+        }
+
+        self.highlight_search = search.is_some();
+        self.search = search;
+        self.clamp_cursor();
+    }
+
+    /// Keeps the search typed into the prompt. An empty pattern repeats
+    /// the previous search. Returns whether the search has any matches.
+    fn accept_search(&mut self, pattern: &str, backward: bool, origin: Cursor, previous: Option<Search>) -> bool {
+        self.search_backward = backward;
+
+        if pattern.is_empty() {
+            self.search = previous;
+            self.search_next(false);
+            return self.search.is_some() && self.error.is_none();
+        }
+
+        let found = match Search::new(pattern, self.search_config) {
+            Ok(search) => {
+                let found = self.find_match(&search, origin.get_position(), backward).is_some();
+
+                if !found {
+                    self.error = Some(format!("Pattern not found: {}", pattern));
+                }
+
+                self.search = Some(search);
+                self.highlight_search = true;
+                found
+            }
+            Err(err) => {
+                self.error = Some(format!("Invalid pattern: {}", err));
+                self.search = previous;
+                self.highlight_search = false;
+                false
+            }
+        };
+
+        self.merge_cursors();
+        found
+    }
+
+    /// Replaces the search's matches in the scope, at once or, to confirm
+    /// each, one at a time as they are answered for.
+    fn replace(&mut self, replacement: &str, setup: ReplaceSetup) {
+        let Some(search) = self.search.clone() else {
+            return;
+        };
+
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let mut matches = VecDeque::new();
+
+        let to_chars = |start_byte: usize, (range, with): (Range<usize>, String)| {
+            (buffer.byte_to_char(start_byte + range.start)..buffer.byte_to_char(start_byte + range.end), with)
+        };
+
+        match setup.scope.is_empty() {
+            true => {
+                let text = buffer.get_rope().to_string();
+                matches.extend(search.replacements(&text, replacement).into_iter().map(|found| to_chars(0, found)));
+            }
+            false => {
+                for scope in &setup.scope {
+                    let start_byte = buffer.char_to_byte(scope.start);
+                    let text = buffer.slice(scope.clone()).to_string();
+                    matches.extend(search.replacements(&text, replacement).into_iter().map(|found| to_chars(start_byte, found)));
+                }
+            }
+        }
+
+        let version = buffer.get_version();
+        drop(document);
+
+        if matches.is_empty() {
+            self.error = Some(format!("Pattern not found: {}", search.get_pattern()));
+            return;
+        }
+
+        self.others.clear();
+        self.set_mode(Mode::Normal);
+        self.replacing = Some(PendingReplace { total: matches.len(), matches, replaced: 0, version });
+
+        match setup.confirm {
+            true => self.replace_next(None),
+            false => self.replace_rest(),
+        }
+    }
+
+    /// Replaces or skips the match being asked about, then moves to the
+    /// next one. Without an answer, only moves to the first.
+    fn replace_next(&mut self, replace: Option<bool>) {
+        let Some(pending) = &mut self.replacing else {
+            return;
+        };
+
+        let mut document = self.document.borrow_mut();
+        let buffer = document.get_buffer_mut();
+
+        if buffer.get_version() != pending.version {
+            self.error = Some("Stopped replacing as the buffer changed".to_string());
+            self.replacing = None;
+            return;
+        }
+
+        if let Some(replace) = replace {
+            let Some((range, with)) = pending.matches.pop_front() else {
+                return;
+            };
+
+            if replace {
+                buffer.replace(range.clone(), &with);
+
+                // the matches after it move by the change in length
+                let delta = with.chars().count() as isize - range.len() as isize;
+
+                for (later, _) in &mut pending.matches {
+                    *later = later.start.saturating_add_signed(delta)..later.end.saturating_add_signed(delta);
+                }
+
+                pending.replaced += 1;
+                pending.version = buffer.get_version();
+                self.cursor.set_position(range.start);
+            }
+        }
+
+        let next = pending.matches.front().map(|(range, _)| range.start);
+        drop(document);
+
+        match next {
+            Some(position) => {
+                self.cursor.set_position(position);
+                self.highlight_search = true;
+            }
+            None => self.finish_replace(),
+        }
+
+        self.clamp_cursor();
+    }
+
+    /// Replaces every match not yet answered for.
+    fn replace_rest(&mut self) {
+        let Some(pending) = &mut self.replacing else {
+            return;
+        };
+
+        let mut document = self.document.borrow_mut();
+        let buffer = document.get_buffer_mut();
+
+        if buffer.get_version() == pending.version {
+            // from the last match back, so the earlier ones stay put
+            for (range, with) in pending.matches.iter().rev() {
+                buffer.replace(range.clone(), with);
+            }
+
+            if let Some((range, _)) = pending.matches.front() {
+                self.cursor.set_position(range.start);
+            }
+
+            pending.replaced += pending.matches.len();
+            pending.matches.clear();
+        }
+
+        drop(document);
+        self.finish_replace();
+        self.clamp_cursor();
+    }
+
+    fn finish_replace(&mut self) {
+        if let Some(pending) = self.replacing.take() {
+            self.message = Some(format!("Replaced {} of {} matches", pending.replaced, pending.total));
+        }
+    }
+
+    /// Answers the question asked about the current match of a replace.
+    fn handle_replace_key(&mut self, event: &KeyEvent) {
+        match event.code {
+            KeyCode::Char('y') => self.replace_next(Some(true)),
+            KeyCode::Char('n') => self.replace_next(Some(false)),
+            KeyCode::Char('a') => self.replace_rest(),
+            KeyCode::Char('q') | KeyCode::Esc => self.finish_replace(),
+            _ => (),
+        }
+    }
+
+    /// Moves to the `count`th next match of the last search, in the
+    /// direction it went, or the other way if `reverse`.
+    fn search_next(&mut self, reverse: bool) {
+        let count = self.count.take().unwrap_or(1);
+
+        let Some(search) = self.search.clone() else {
+            self.error = Some("No previous search".to_string());
+            return;
+        };
+
+        let backward = self.search_backward != reverse;
+        let mut position = self.cursor.get_position();
+
+        for _ in 0..count {
+            match self.find_match(&search, position, backward) {
+                Some(found) => position = found.start,
+                None => {
+                    self.error = Some(match search.get_config().wrap_around {
+                        true => format!("Pattern not found: {}", search.get_pattern()),
+                        false => format!("No more matches for {}", search.get_pattern()),
+                    });
+                    break;
+                }
+            }
+        }
+
+        self.highlight_search = true;
+
+        if position != self.cursor.get_position() {
+            self.cursor.set_position(position);
+            self.clamp_cursor();
+            self.merge_cursors();
+        }
+    }
+
+    /// The chars of the next match after `position`, or the one before it.
+    fn find_match(&self, search: &Search, position: usize, backward: bool) -> Option<Range<usize>> {
+        let document = self.document.borrow();
+
+        match backward {
+            true => search.find_before(document.get_buffer(), position),
+            false => search.find_after(document.get_buffer(), position),
+        }
+    }
+
+    /// Puts a cursor at the head of each selection, the first one primary.
+    fn set_selections(&mut self, selections: Vec<Selection>) {
+        let mut cursors = selections.into_iter()
+            .map(|selection| (Cursor::new(selection.get_head()), Some(selection)));
+
+        let Some((cursor, selection)) = cursors.next() else {
+            return;
+        };
+
+        self.others = cursors.collect();
+        self.cursor = cursor;
+        self.selection = selection;
+        self.mode = Mode::Visual;
+        self.count = None;
+        self.clamp_cursor();
+        self.merge_cursors();
+    }
+
+    /// Moves through the document's history, `count` times for steps or
+    /// by `count` minutes.
+    fn travel(&mut self, command: Command) {
+        let count = self.count.take().unwrap_or(1);
+        let minutes = Duration::from_secs(60 * count as u64);
+        let mut document = self.document.borrow_mut();
+
+        let state = match command {
+            Command::Undo => (0..count).map_while(|_| document.undo()).last(),
+            Command::Redo => (0..count).map_while(|_| document.redo()).last(),
+            Command::Earlier => document.earlier(count),
+            Command::Later => document.later(count),
+            Command::EarlierMinutes => document.earlier_by(minutes),
+            _ => document.later_by(minutes),
+        };
+
+        drop(document);
+        self.typing_revision = None;
+
+        if let Some(state) = state {
+            self.restore_cursor_state(state);
+        }
+    }
+
+    /// Records the edits an event made as one step in the history, with
+    /// typing added to the step before it.
+    fn commit(&mut self, before: CursorState, typing: bool, handled: bool) {
+        let after = self.cursor_state();
+        let merge_into = if typing { self.typing_revision } else { None };
+        let revision = self.document.borrow_mut().commit(before, after, merge_into);
+
+        // an edit that enters insert mode, like opening a line, starts
+        // the group the typing after it joins
+        match revision {
+            Some(revision) if typing || self.mode == Mode::Insert => self.typing_revision = Some(revision),
+            None if typing || !handled => (),
+            _ => self.typing_revision = None,
+        }
+    }
+
+    /// Starts selecting from the cursor, or changes the kind of the
+    /// selection. Selecting again with the same kind stops selecting.
+    fn select(&mut self, kind: SelectionKind) {
+        let position = self.cursor.get_position();
+
+        match &mut self.selection {
+            Some(selection) if selection.get_kind() == kind => self.set_mode(Mode::Normal),
+            Some(selection) => {
+                selection.set_kind(kind);
+            }
+            None => {
+                self.selection = Some(Selection::new(position, position, kind));
+                self.set_mode(Mode::Visual);
+            }
+        }
+    }
+
+    /// Selects `anchor` to `head` and puts the cursor at the head.
+    fn select_range(&mut self, anchor: usize, head: usize, kind: SelectionKind) {
+        self.selection = Some(Selection::new(anchor, head, kind));
+        self.cursor.set_position(head);
+        self.set_mode(Mode::Visual);
+    }
+
+    fn select_word(&mut self) {
+        let range = cursor::word_range(self.document.borrow().get_buffer(), self.cursor.get_position());
+
+        if !range.is_empty() {
+            self.select_range(range.start, range.end - 1, SelectionKind::Char);
+        }
+    }
+
+    fn flip_selection(&mut self) {
+        if let Some(selection) = &mut self.selection {
+            self.cursor.set_position(selection.flip().get_head());
+            self.clamp_cursor();
+        }
+    }
+
+    /// Copies the selected text, removing it from the buffer if `cut`, and
+    /// goes back to normal mode. The copy is stored by `finish_copy`.
+    fn copy_selection(&mut self, selection: Selection, cut: bool) {
+        let mut document = self.document.borrow_mut();
+        let buffer = document.get_buffer_mut();
+        let range = selection.range(buffer);
+        let linewise = selection.get_kind() == SelectionKind::Line;
+        let mut text = buffer.slice(range.clone()).to_string();
+
+        if linewise && !text.ends_with('\n') {
+            text.push('\n');
+        }
+
+        self.copied.push(Register::new(text, linewise));
+
+        if cut {
+            // cutting the last lines takes the line ending before them
+            let start = match linewise && range.end == buffer.len_chars() {
+                true => range.start.saturating_sub(1),
+                false => range.start,
+            };
+
+            buffer.remove(start..range.end);
+        }
+
+        drop(document);
+        self.cursor.set_position(range.start);
+        self.set_mode(Mode::Normal);
+    }
+
+    /// Stores what the cursors copied into the named register.
+    fn finish_copy(&mut self, name: Option<char>) {
+        if let Some(register) = Register::join(std::mem::take(&mut self.copied)) {
+            self.registers.borrow_mut().copy(name, register);
+        }
+    }
+
+    /// Copies or cuts at every cursor into one register.
+    fn copy_all(&mut self, cut: bool) {
+        let name = self.register.take();
+
+        self.for_each_cursor(|view| {
+            let selection = view.selection_or_lines();
+            view.copy_selection(selection, cut);
+        });
+
+        self.finish_copy(name);
+    }
+
+    /// The selection, or one made of `count` lines from the cursor.
+    fn selection_or_lines(&mut self) -> Selection {
+        if let Some(selection) = self.selection {
+            return selection;
+        }
+
+        let count = self.count.take().unwrap_or(1);
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let line = (self.cursor_point().line + count - 1).min(buffer.len_lines() - 1);
+
+        Selection::new(self.cursor.get_position(), buffer.line_to_char(line), SelectionKind::Line)
+    }
+
+    /// Pastes the named register after or before each cursor, replacing
+    /// the selections in visual mode. A register copied by as many cursors
+    /// pastes each cursor's part at the matching cursor.
+    fn paste(&mut self, before: bool) {
+        let name = self.register.take();
+        let Some(register) = self.registers.borrow_mut().get(name) else {
+            return;
+        };
+
+        let mut last_paste = None;
+
+        self.for_each_cursor(|view| {
+            let count = view.count.take().unwrap_or(1);
+
+            let before = match view.selection {
+                Some(selection) => {
+                    let range = selection.range(view.document.borrow().get_buffer());
+                    view.document.borrow_mut().get_buffer_mut().remove(range.clone());
+                    view.cursor.set_position(range.start);
+                    view.set_mode(Mode::Normal);
+                    true
+                }
+                None => before,
+            };
+
+            let cursor = view.cursor.get_position();
+            let (index, total) = view.active;
+            let range = view.paste_register(register.text_for(index, total), register.linewise, before, count);
+
+            last_paste = Some(LastPaste {
+                range,
+                cursor,
+                before,
+                count,
+                ring_index: 0,
+                version: view.document.borrow().get_buffer().get_version(),
+            });
+        });
+
+        // only the unnamed register can cycle through the ring, and only
+        // with one cursor
+        self.last_paste = match name {
+            None | Some('"') if self.others.is_empty() => last_paste,
+            _ => None,
+        };
+    }
+
+    /// Swaps the text just pasted for the copy before it in the ring.
+    fn cycle_paste(&mut self) {
+        let version = self.document.borrow().get_buffer().get_version();
+        let ring_len = self.registers.borrow().ring_len();
+
+        let Some(last) = self.last_paste.take() else {
+            return;
+        };
+
+        if last.version != version || ring_len == 0 {
+            return;
+        }
+
+        let ring_index = (last.ring_index + 1) % ring_len;
+        let Some(register) = self.registers.borrow().get_ring(ring_index).cloned() else {
+            return;
+        };
+
+        self.document.borrow_mut().get_buffer_mut().remove(last.range.clone());
+        self.cursor.set_position(last.cursor);
+
+        let range = self.paste_register(&register.text, register.linewise, last.before, last.count);
+
+        self.last_paste = Some(LastPaste {
+            range,
+            ring_index,
+            version: self.document.borrow().get_buffer().get_version(),
+            ..last
+        });
+    }
+
+    /// Inserts copied text `count` times: whole lines go below or above the
+    /// cursor line, other text after or at the cursor. Returns the range
+    /// inserted.
+    fn paste_register(&mut self, text: &str, linewise: bool, before: bool, count: usize) -> Range<usize> {
+        let mut text = text.repeat(count.max(1));
+        let len = text.chars().count();
+        let mut document = self.document.borrow_mut();
+        let buffer = document.get_buffer_mut();
+        let point = self.cursor.point(buffer);
+
+        let (position, cursor) = match (linewise, before) {
+            (true, true) => {
+                let position = buffer.line_to_char(point.line);
+                (position, position)
+            }
+            (true, false) if point.line + 1 < buffer.len_lines() => {
+                let position = buffer.line_to_char(point.line + 1);
+                (position, position)
+            }
+            (true, false) => {
+                // below the last line, the line ending goes first
+                text.pop();
+                text.insert(0, '\n');
+                (buffer.len_chars(), buffer.len_chars() + 1)
+            }
+            (false, true) => (self.cursor.get_position(), self.cursor.get_position() + len.saturating_sub(1)),
+            (false, false) => {
+                let line = buffer.line_text(point.line);
+                let column = grapheme::next_boundary(&line, point.column);
+                let position = buffer.point_to_char(Point::new(point.line, column));
+                (position, position + len.saturating_sub(1))
+            }
+        };
+
+        buffer.insert(position, &text);
+        drop(document);
+
+        self.cursor.set_position(cursor);
+        self.clamp_cursor();
+        position..position + len
+    }
+
+    pub fn insert_text(&mut self, text: &str) {
+        let position = self.cursor.get_position();
+
+        self.document.borrow_mut().get_buffer_mut().insert(position, text);
+        self.cursor.set_position(position + text.chars().count());
+        self.follow_cursor = true;
+    }
+
+    /// Types a char in insert mode. Opening brackets and quotes come with
+    /// their closing one, a closing one is typed over if it is next, and
+    /// a closing bracket first on its line takes the indent of the line
+    /// it closes.
+    fn type_char(&mut self, c: char) {
+        let position = self.cursor.get_position();
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let next = (position < buffer.len_chars()).then(|| buffer.char(position));
+        let previous = position.checked_sub(1).map(|position| buffer.char(position));
+        drop(document);
+
+        let pairs = &self.language.auto_pairs;
+
+        if next == Some(c) && pairs.iter().any(|(_, close)| *close == c) {
+            self.cursor.set_position(position + 1);
+            self.follow_cursor = true;
+            return;
+        }
+
+        if let Some(&(_, close)) = pairs.iter().find(|(open, _)| *open == c) {
+            // not before a word, and a quote after one is an apostrophe
+            let before_word = next.is_some_and(|next| !next.is_whitespace() && !pairs.iter().any(|(_, close)| *close == next));
+            let after_word = c == close && previous.is_some_and(|previous| previous.is_alphanumeric() || previous == '_');
+
+            if !before_word && !after_word {
+                self.insert_text(&format!("{}{}", c, close));
+                self.cursor.set_position(position + 1);
+                return;
+            }
+        }
+
+        self.insert_text(c.encode_utf8(&mut [0; 4]));
+
+        if self.language.auto_indent && self.language.dedent_on.contains(&c) {
+            self.dedent(position);
+        }
+    }
+
+    /// Gives the line of the closing bracket at `position` the indent of
+    /// the line with its opening one, if nothing comes before it.
+    fn dedent(&mut self, position: usize) {
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let point = buffer.char_to_point(position);
+        let text = buffer.line_text(point.line);
+
+        if !text.chars().take(point.column).all(char::is_whitespace) {
+            return;
+        }
+
+        let Some(open) = bracket::find_match(buffer, position, &self.language.brackets, self.highlighter.as_mut()) else {
+            return;
+        };
+
+        let indent = leading_whitespace(&buffer.line_text(buffer.char_to_line(open))).to_string();
+        let line_start = buffer.line_to_char(point.line);
+        drop(document);
+
+        self.document.borrow_mut().get_buffer_mut().replace(line_start..position, &indent);
+        self.cursor.set_position(line_start + indent.chars().count() + 1);
+    }
+
+    /// What indents a line one level.
+    fn indent_unit(&self) -> String {
+        self.document.borrow().get_indent().unit()
+    }
+
+    /// Cells between tab stops.
+    fn tab_width(&self) -> usize {
+        self.document.borrow().get_indent().width
+    }
+
+    /// Types a tab, or with spaces for indenting as many as reach the next
+    /// tab stop.
+    fn insert_tab(&mut self) {
+        let document = self.document.borrow();
+        let indent = document.get_indent();
+        let display = self.cursor.display_column(document.get_buffer(), indent.width);
+        drop(document);
+
+        match indent.tabs {
+            true => self.insert_text("\t"),
+            false => self.insert_text(&" ".repeat(indent.width - display % indent.width)),
+        }
+    }
+
+    /// Indents every line with tabs, or with spaces, keeping how far in it
+    /// starts, and goes on indenting that way.
+    fn convert_indent(&mut self, tabs: bool) {
+        let mut document = self.document.borrow_mut();
+        let indent = Indent { tabs, ..document.get_indent() };
+        let buffer = document.get_buffer_mut();
+        let point = self.cursor.point(buffer);
+        let mut column = point.column;
+        let mut converted = 0;
+
+        for line in 0..buffer.len_lines() {
+            let text = buffer.line_text(line);
+            let old = leading_whitespace(&text);
+            let len = old.chars().count();
+            let width = grapheme::column_to_display(&text, len, indent.width);
+
+            let new = match tabs {
+                true => "\t".repeat(width / indent.width) + &" ".repeat(width % indent.width),
+                false => " ".repeat(width),
+            };
+
+            if new == old {
+                continue;
+            }
+
+            // the cursor keeps to its char, or the end of the indent
+            if line == point.line {
+                column = match column >= len {
+                    true => column - len + new.len(),
+                    false => column.min(new.len()),
+                };
+            }
+
+            let start = buffer.line_to_char(line);
+            buffer.replace(start..start + len, &new);
+            converted += 1;
+        }
+
+        let position = buffer.point_to_char(Point::new(point.line, column));
+        document.set_indent(indent);
+        drop(document);
+
+        self.count = None;
+        self.others.clear();
+        self.set_mode(Mode::Normal);
+        self.cursor.set_position(position);
+        self.clamp_cursor();
+        self.message = Some(format!("Indented {} lines with {}", converted, if tabs { "tabs" } else { "spaces" }));
+    }
+
+    /// Sets the cells between tab stops, and the spaces to a level when
+    /// indenting with spaces, leaving the text as it is.
+    fn set_tab_width(&mut self, width: usize) {
+        if width == 0 {
+            self.error = Some("The tab width must be at least 1".to_string());
+            return;
+        }
+
+        let mut document = self.document.borrow_mut();
+        let indent = Indent { width, ..document.get_indent() };
+        document.set_indent(indent);
+
+        self.follow_cursor = true;
+        self.message = Some(format!("Tab width {}", width));
+    }
+
+    /// Goes on indenting with tabs instead of spaces or the other way,
+    /// leaving the lines already indented as they are.
+    fn toggle_use_tabs(&mut self) {
+        let mut document = self.document.borrow_mut();
+        let indent = Indent { tabs: !document.get_indent().tabs, ..document.get_indent() };
+        document.set_indent(indent);
+
+        self.message = Some(format!("Indenting with {}", if indent.tabs { "tabs" } else { "spaces" }));
+    }
+
+    /// Whether the line after `text` is indented once more than it.
+    fn opens_indent(&self, text: &str) -> bool {
+        let text = text.trim_end();
+        self.language.indent_after.iter().any(|after| text.ends_with(after.as_str()))
+    }
+
+    /// Breaks the line at the cursor. The new line takes the indent of the
+    /// one before, once more after an opening bracket, and a closing
+    /// bracket right after the cursor goes on a line of its own.
+    fn insert_newline(&mut self) {
+        if !self.language.auto_indent {
+            return self.insert_text("\n");
+        }
+
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let point = self.cursor.point(buffer);
+        let text = buffer.line_text(point.line);
+        drop(document);
+
+        let before: String = text.chars().take(point.column).collect();
+        let after: String = text.chars().skip(point.column).collect();
+        let indent = leading_whitespace(&before).to_string();
+        let opens = self.opens_indent(&before);
+        let closes = after.trim_start().starts_with(|c| self.language.dedent_on.contains(&c));
+
+        match (opens, closes) {
+            (true, true) => {
+                let inner = format!("\n{}{}", indent, self.indent_unit());
+                self.insert_text(&format!("{}\n{}", inner, indent));
+                self.cursor.set_position(self.cursor.get_position() - indent.chars().count() - 1);
+            }
+            (true, false) => self.insert_text(&format!("\n{}{}", indent, self.indent_unit())),
+            (false, _) => self.insert_text(&format!("\n{}", indent)),
+        }
+    }
+
+    /// Moves to the bracket matching the one at the cursor, or the first
+    /// one after the cursor on its line.
+    fn goto_matching_bracket(&mut self) {
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let brackets = &self.language.brackets;
+
+        let found = bracket::next_on_line(buffer, self.cursor.get_position(), brackets)
+            .and_then(|position| bracket::find_match(buffer, position, brackets, self.highlighter.as_mut()));
+        drop(document);
+
+        self.count = None;
+
+        if let Some(position) = found {
+            self.cursor.set_position(position);
+            self.clamp_cursor();
+        }
+    }
+
+    fn delete_backward(&mut self) {
+        let position = self.cursor.get_position();
+
+        if position == 0 {
+            return;
+        }
+
+        // between a pair typed together, both go
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let pair = (buffer.char(position - 1), (position < buffer.len_chars()).then(|| buffer.char(position)));
+        drop(document);
+
+        if self.language.auto_pairs.iter().any(|(open, close)| pair == (*open, Some(*close))) {
+            self.document.borrow_mut().get_buffer_mut().remove(position - 1..position + 1);
+            self.cursor.set_position(position - 1);
+            self.follow_cursor = true;
+            return;
+        }
+
+        let mut start = self.cursor;
+        start.apply(self.document.borrow().get_buffer(), Motion::Left, 1, 0, self.tab_width());
+
+        // at the start of a line, join it onto the previous one
+        let start = match start.get_position() {
+            start if start == position => position - 1,
+            start => start,
+        };
+
+        self.document.borrow_mut().get_buffer_mut().remove(start..position);
+        self.cursor.set_position(start);
+        self.follow_cursor = true;
+    }
+
+    fn delete_forward(&mut self) {
+        let position = self.cursor.get_position();
+        let len = self.document.borrow().get_buffer().len_chars();
+
+        if position >= len {
+            return;
+        }
+
+        let mut end = self.cursor;
+        end.apply(self.document.borrow().get_buffer(), Motion::Right, 1, 0, self.tab_width());
+
+        // at the end of a line, join the next one onto it, except in
+        // normal mode where the cursor never sits there
+        let end = match end.get_position() {
+            end if end == position && self.mode == Mode::Insert => position + 1,
+            end if end == position => return,
+            end => end,
+        };
+
+        self.document.borrow_mut().get_buffer_mut().remove(position..end);
+        self.clamp_cursor();
+    }
+
+    fn open_line(&mut self, below: bool) {
+        let line = self.cursor_point().line;
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let text = buffer.line_text(line);
+
+        let position = match below {
+            true => buffer.line_to_char(line) + buffer.line_len(line),
+            false => buffer.line_to_char(line),
+        };
+
+        drop(document);
+
+        let indent = match (self.language.auto_indent, below && self.opens_indent(&text)) {
+            (true, true) => format!("{}{}", leading_whitespace(&text), self.indent_unit()),
+            (true, false) => leading_whitespace(&text).to_string(),
+            (false, _) => String::new(),
+        };
+
+        self.mode = Mode::Insert;
+        self.cursor.set_position(position);
+
+        match below {
+            true => self.insert_text(&format!("\n{}", indent)),
+            false => {
+                self.insert_text(&format!("{}\n", indent));
+                self.cursor.set_position(position + indent.chars().count());
+            }
+        }
+    }
+
+    /// Marks the cursor line in the gutter, or unmarks it.
+    fn toggle_bookmark(&mut self) {
+        let line = self.cursor_point().line;
+        let mut document = self.document.borrow_mut();
+        let signs = document.signs();
+
+        if signs.remove(BOOKMARK_SIGNS, line).is_none() {
+            signs.set(BOOKMARK_SIGNS, line, Sign {
+                symbol: BOOKMARK_SYMBOL,
+                style: CellStyle::fg(self.theme.focused_border),
+                priority: BOOKMARK_PRIORITY,
+            });
+        }
+    }
+
+    /// Lines kept between the cursor and the top or bottom of the view,
+    /// at most half of it.
+    fn scroll_margin(&self) -> usize {
+        self.config.scroll_off.min((self.size.1 as usize).saturating_sub(1) / 2)
+    }
+
+    /// Scrolls by lines without moving the cursor, unless it would come
+    /// closer to the edge of the view than the scroll margin.
+    pub fn scroll_lines(&mut self, lines: isize) {
+        let top = self.step_lines(self.scroll.0, lines);
+        let margin = self.scroll_margin();
+
+        self.scroll.0 = top;
+
+        let shown: Vec<usize> = self.visible_rows().into_iter()
+            .filter(|(_, row)| *row == 0)
+            .map(|(line, _)| line)
+            .collect();
+        let last = self.step_lines(top, isize::MAX);
+
+        // the margin does not apply at the ends of the buffer
+        let min = match top {
+            0 => 0,
+            _ => shown.get(margin).or(shown.last()).copied().unwrap_or(top),
+        };
+        let max = match shown.last() {
+            Some(line) if *line == last => last,
+            _ => shown.get(shown.len().saturating_sub(margin + 1)).copied().unwrap_or(top),
+        };
+
+        let line = self.cursor_point().line;
+        let target = line.clamp(min, max.max(min));
+
+        if target != line {
+            self.move_to_line(target);
+        }
+
+        self.follow_cursor = false;
+    }
+
+    /// Moves the cursor down by lines, or up when negative, keeping its
+    /// column.
+    fn move_lines(&mut self, lines: isize) {
+        let line = self.cursor_point().line;
+        self.move_to_line(self.step_lines(line, lines));
+    }
+
+    /// Moves the cursor to another line, keeping its column.
+    fn move_to_line(&mut self, target: usize) {
+        let line = self.cursor_point().line;
+        let motion = if target > line { Motion::Down } else { Motion::Up };
+
+        if target != line {
+            self.cursor.apply(self.document.borrow().get_buffer(), motion, target.abs_diff(line), 0, self.tab_width());
+        }
+
+        self.clamp_cursor();
+    }
+
+    /// Scrolls the view and the cursor by half a page, or by the count.
+    fn scroll_half_page(&mut self, down: bool) {
+        let lines = self.count.take().unwrap_or((self.size.1 as usize / 2).max(1)) as isize;
+        let lines = if down { lines } else { -lines };
+
+        self.for_each_cursor(|view| view.move_lines(lines));
+        self.scroll_lines(lines);
+    }
+
+    /// Scrolls so the cursor line is at the top, centre or bottom of the
+    /// view, within the scroll margin.
+    fn align_cursor(&mut self, align: Align) {
+        let (point, row, _) = self.locate(self.cursor.get_position());
+        let height = self.size.1 as usize;
+        let margin = self.scroll_margin();
+
+        let rows = match align {
+            Align::Top => margin,
+            Align::Center => height / 2,
+            Align::Bottom => height.saturating_sub(margin + 1),
+        };
+
+        self.count = None;
+        self.scroll.0 = self.top_for(point.line, row, rows);
+        self.follow_cursor = false;
+    }
+
+    /// How lines break into rows, or None when they run off the side.
+    fn wrap_settings(&self) -> Option<WrapSettings> {
+        let width = self.size.0 as usize;
+
+        if !self.config.soft_wrap || width == 0 {
+            return None;
+        }
+
+        Some(WrapSettings {
+            width: self.config.wrap_column.map_or(width, |column| column.min(width)),
+            indicator: self.config.wrap_indicator,
+            preserve_indent: self.config.wrap_indent,
+            tab_width: self.tab_width(),
+        })
+    }
+
+    /// The rows `line` takes on screen, just the one when not wrapping.
+    fn line_rows(&self, line: usize) -> Rc<[WrapRow]> {
+        match self.folded(line) {
+            Some(fold) if fold.start != line => return Rc::new([]),
+            // a closed fold shows as its first line, cut off at the edge
+            Some(_) => return Rc::new([WrapRow::START]),
+            None => (),
+        }
+
+        let Some(settings) = self.wrap_settings() else {
+            return Rc::new([WrapRow::START]);
+        };
+
+        let document = self.document.borrow();
+        let mut wrap = self.wrap.borrow_mut();
+
+        wrap.sync(document.get_buffer(), settings);
+        wrap.rows(document.get_buffer(), line)
+    }
+
+    /// Where a position shows: its point, the row of its line it is on,
+    /// and its cell in that row before scrolling sideways.
+    fn locate(&self, position: usize) -> (Point, usize, usize) {
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let point = buffer.char_to_point(position);
+        let display = grapheme::column_to_display(&buffer.line_text(point.line), point.column, document.get_indent().width);
+        drop(document);
+
+        let rows = self.line_rows(point.line);
+        let index = rows.partition_point(|row| row.column <= point.column).saturating_sub(1);
+        let row = rows.get(index).copied().unwrap_or(WrapRow::START);
+
+        (point, index, row.offset + display - row.display)
+    }
+
+    /// The position on row `row` of `line` nearest to cell `x` of the row.
+    fn row_position(&self, line: usize, row: usize, x: usize) -> usize {
+        let rows = self.line_rows(line);
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let text = buffer.line_text(line);
+
+        let start = rows[row];
+        let display = start.display.saturating_add(x.saturating_sub(start.offset));
+        let mut column = grapheme::display_to_column(&text, display, document.get_indent().width);
+
+        // past the end of a row is the end of that row, not the next
+        if let Some(next) = rows.get(row + 1) {
+            if column >= next.column {
+                column = grapheme::prev_boundary(&text, next.column);
+            }
+        }
+
+        buffer.point_to_char(Point::new(line, column))
+    }
+
+    /// The line and the row of it on each screen row, from the top.
+    pub fn visible_rows(&self) -> Vec<(usize, usize)> {
+        let len_lines = self.document.borrow().get_buffer().len_lines();
+        let height = self.size.1 as usize;
+        let mut rows = Vec::with_capacity(height);
+        let mut line = self.scroll.0;
+
+        while rows.len() < height && line < len_lines {
+            let count = self.line_rows(line).len().min(height - rows.len());
+            rows.extend((0..count).map(|row| (line, row)));
+            line = self.next_line(line);
+        }
+
+        rows
+    }
+
+    /// Rows the lines from `top` up to `line` take, or None for more than
+    /// `limit`.
+    fn rows_between(&self, top: usize, line: usize, limit: usize) -> Option<usize> {
+        let mut rows = 0;
+        let mut current = top;
+
+        while current < line {
+            rows += self.line_rows(current).len();
+
+            if rows > limit {
+                return None;
+            }
+
+            current = self.next_line(current);
+        }
+
+        Some(rows)
+    }
+
+    /// The closed fold `line` shows as or is hidden in.
+    fn folded(&self, line: usize) -> Option<Fold> {
+        let document = self.document.borrow();
+        let mut folds = self.folds.borrow_mut();
+
+        folds.sync(document.get_buffer());
+        folds.folded(line)
+    }
+
+    /// Whether any lines are folded away.
+    fn has_hidden(&self) -> bool {
+        let document = self.document.borrow();
+        let mut folds = self.folds.borrow_mut();
+
+        folds.sync(document.get_buffer());
+        folds.has_hidden()
+    }
+
+    /// The next line after `line` that is not folded away, which may be
+    /// past the end.
+    fn next_line(&self, line: usize) -> usize {
+        self.folded(line).map_or(line + 1, |fold| fold.end + 1)
+    }
+
+    /// The line `lines` lines down from `line`, or up when negative, with
+    /// a closed fold counting as one line.
+    fn step_lines(&self, line: usize, lines: isize) -> usize {
+        let last = self.document.borrow().get_buffer().len_lines() - 1;
+        let last = self.folded(last).map_or(last, |fold| fold.start);
+        let mut line = self.folded(line).map_or(line, |fold| fold.start);
+
+        if !self.has_hidden() {
+            return line.saturating_add_signed(lines).min(last);
+        }
+
+        for _ in 0..lines.unsigned_abs() {
+            line = match lines > 0 {
+                true if line < last => self.next_line(line),
+                false if line > 0 => self.folded(line - 1).map_or(line - 1, |fold| fold.start),
+                _ => break,
+            };
+        }
+
+        line
+    }
+
+    /// The top line that puts row `row` of `line` as near to `rows` rows
+    /// down the view as whole lines allow, without going past it.
+    fn top_for(&self, line: usize, row: usize, rows: usize) -> usize {
+        let mut top = line;
+        let mut above = row;
+
+        while top > 0 {
+            let count = self.line_rows(top - 1).len();
+
+            if above + count > rows {
+                break;
+            }
+
+            above += count;
+            top -= 1;
+        }
+
+        // not the rest of a fold whose first line did not fit
+        match self.folded(top) {
+            Some(fold) if fold.start != top => fold.end + 1,
+            _ => top,
+        }
+    }
+
+    /// Moves the cursor by rows of wrapped lines, keeping its cell in the
+    /// row, or by lines when not wrapping.
+    fn move_display_rows(&mut self, down: bool) {
+        if self.wrap_settings().is_none() {
+            return self.move_cursor(if down { Motion::Down } else { Motion::Up });
+        }
+
+        let count = self.count.take().unwrap_or(1);
+        let len_lines = self.document.borrow().get_buffer().len_lines();
+        let (point, mut row, x) = self.locate(self.cursor.get_position());
+        let mut line = point.line;
+
+        for _ in 0..count {
+            if down && row + 1 < self.line_rows(line).len() {
+                row += 1;
+            } else if down && line + 1 < len_lines {
+                line += 1;
+                row = 0;
+            } else if !down && row > 0 {
+                row -= 1;
+            } else if !down && line > 0 {
+                line -= 1;
+                row = self.line_rows(line).len() - 1;
+            } else {
+                break;
+            }
+        }
+
+        let position = self.row_position(line, row, x);
+        self.cursor.set_position(position);
+        self.clamp_cursor();
+    }
+
+    /// Moves the cursor to the start or end of its row of a wrapped line,
+    /// or of its line when not wrapping.
+    fn move_display_line_edge(&mut self, end: bool) {
+        let (point, row, _) = self.locate(self.cursor.get_position());
+        let rows = self.line_rows(point.line);
+
+        let position = match (end, rows.get(row + 1)) {
+            (false, _) => self.row_position(point.line, row, 0),
+            (true, Some(_)) => self.row_position(point.line, row, usize::MAX),
+            (true, None) => return self.move_cursor(Motion::LineEnd),
+        };
+
+        self.count = None;
+        self.cursor.set_position(position);
+        self.clamp_cursor();
+    }
+
+    /// Scrolls to the cursor unless the view was scrolled away from it,
+    /// so that what is drawn beside the view can line up with it.
+    pub fn update_scroll(&mut self) {
+        self.update_folds();
+
+        if self.follow_cursor {
+            self.scroll_to_cursor();
+        }
+    }
+
+    /// Finds the folds again after edits while any are closed, opens those
+    /// the cursor went into other than by moving over them, and keeps the
+    /// top of the view off lines folded away.
+    fn update_folds(&mut self) {
+        if !self.has_hidden() {
+            return;
+        }
+
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let mut folds = self.folds.borrow_mut();
+        folds.find(buffer, self.config.fold_method, document.get_indent().width, self.highlighter.as_mut());
+
+        let line = self.cursor.point(buffer).line;
+
+        while let Some(fold) = folds.folded(line).filter(|fold| fold.start != line) {
+            folds.open(fold);
+        }
+
+        if let Some(fold) = folds.folded(self.scroll.0) {
+            self.scroll.0 = fold.start;
+        }
+    }
+
+    /// Finds the folds again if the text changed since they last were.
+    fn find_folds(&mut self) {
+        let document = self.document.borrow();
+        let tab_width = document.get_indent().width;
+        self.folds.borrow_mut().find(document.get_buffer(), self.config.fold_method, tab_width, self.highlighter.as_mut());
+    }
+
+    /// Opens, closes or toggles the fold at the cursor, or with
+    /// `recursive` every fold around it and inside those.
+    fn fold_at_cursor(&mut self, command: Command, recursive: bool) {
+        self.find_folds();
+
+        let line = self.cursor_point().line;
+        let mut folds = self.folds.borrow_mut();
+        let around = folds.around(line);
+
+        // the innermost fold that shows, for closing, and the outermost
+        // closed one, for opening
+        let shown = around.iter().position(|fold| folds.is_closed(*fold)).unwrap_or(around.len());
+        let innermost = around[..shown].last().copied();
+        let outermost = folds.folded(line);
+
+        let open = match command {
+            Command::FoldOpen => true,
+            Command::FoldClose => false,
+            _ => outermost.is_some(),
+        };
+
+        let targets: Vec<Fold> = match (open, recursive) {
+            (true, false) => outermost.into_iter().collect(),
+            (false, false) => innermost.into_iter().collect(),
+            // every fold in the outermost one around the cursor
+            (_, true) => match around.first() {
+                Some(outer) => folds.all().into_iter().filter(|fold| outer.contains(fold.start)).collect(),
+                None => Vec::new(),
+            },
+        };
+
+        for fold in &targets {
+            match open {
+                true => folds.open(*fold),
+                false => folds.close(*fold),
+            };
+        }
+
+        let start = folds.folded(line).map(|fold| fold.start);
+        drop(folds);
+
+        self.count = None;
+
+        if targets.is_empty() {
+            self.error = Some("No fold found".to_string());
+        }
+
+        // the cursor goes to the line a fold closed over it shows as
+        if let Some(start) = start {
+            self.move_to_line(start);
+        }
+    }
+
+    /// Adjusts the scroll so the cursor is inside the view and away from
+    /// its edges by the scroll margins.
+    fn scroll_to_cursor(&mut self) {
+        let (width, height) = (self.size.0 as usize, self.size.1 as usize);
+
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        if self.wrap_settings().is_some() {
+            self.scroll.1 = 0;
+            return self.scroll_rows_to_cursor();
+        }
+
+        // lines folded away take no rows
+        let folded = self.has_hidden();
+
+        if folded {
+            self.scroll_rows_to_cursor();
+        }
+
+        let margin = self.scroll_margin();
+        let side_margin = self.config.side_scroll_off.min((width - 1) / 2);
+
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let line = self.cursor.point(buffer).line;
+        let column = self.cursor.display_column(buffer, document.get_indent().width);
+        let len_lines = buffer.len_lines();
+        drop(document);
+
+        let (top, left) = &mut self.scroll;
+
+        if folded {
+            // scrolled already
+        } else if line < *top + margin {
+            *top = line.saturating_sub(margin);
+        } else if line + margin >= *top + height {
+            // stop once the last line is at the bottom
+            *top = (line + margin + 1 - height).min(len_lines.saturating_sub(height)).max(*top);
+        }
+
+        if column < *left + side_margin {
+            *left = column.saturating_sub(side_margin);
+        } else if column + side_margin >= *left + width {
+            *left = column + side_margin + 1 - width;
+        }
+    }
+
+    /// Scrolls by whole lines so the cursor's row is inside the view and
+    /// away from its edges by the scroll margin, in rows, for when lines
+    /// do not take a row each.
+    fn scroll_rows_to_cursor(&mut self) {
+        let height = self.size.1 as usize;
+        let margin = self.scroll_margin();
+        let len_lines = self.document.borrow().get_buffer().len_lines();
+        let (point, row, _) = self.locate(self.cursor.get_position());
+        let top = self.scroll.0;
+
+        // rows after the cursor's that the margin keeps in view, as far as
+        // there are any
+        let mut below = self.line_rows(point.line).len() - 1 - row;
+        let mut next = point.line + 1;
+
+        while below < margin && next < len_lines {
+            below += self.line_rows(next).len();
+            next = self.next_line(next);
+        }
+
+        let below = below.min(margin);
+
+        // a cursor far below the view is not counted down to
+        let above = match point.line >= top {
+            true => self.rows_between(top, point.line, height).map(|rows| rows + row),
+            false => None,
+        };
+
+        self.scroll.0 = match above {
+            None if point.line < top => self.top_for(point.line, row, margin),
+            None => self.top_for(point.line, row, height.saturating_sub(below + 1)),
+            Some(above) if above < margin => self.top_for(point.line, row, margin),
+            Some(above) if above + below >= height => self.top_for(point.line, row, height.saturating_sub(below + 1)),
+            Some(_) => top,
+        };
+    }
+
+    fn fold_all(&mut self, open: bool) {
+        self.find_folds();
+
+        let start = {
+            let mut folds = self.folds.borrow_mut();
+
+            match open {
+                true => folds.open_all(),
+                false => folds.close_all(),
+            };
+
+            folds.folded(self.cursor_point().line).map(|fold| fold.start)
+        };
+
+        self.count = None;
+
+        if let Some(start) = start {
+            self.move_to_line(start);
+        }
+    }
+
+    /// Folds the selected lines by hand, closed.
+    fn create_fold(&mut self) {
+        let Some(selection) = self.selection else {
+            return;
+        };
+
+        self.find_folds();
+
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let range = selection.range(buffer);
+        let start = buffer.char_to_point(range.start).line;
+        let end = buffer.char_to_point(range.end.saturating_sub(1).max(range.start)).line;
+        drop(document);
+
+        self.set_mode(Mode::Normal);
+
+        if end > start {
+            self.folds.borrow_mut().add(Fold { start, end });
+            self.move_to_line(start);
+        }
+    }
+
+    fn delete_fold(&mut self) {
+        self.find_folds();
+
+        let line = self.cursor_point().line;
+
+        if self.folds.borrow_mut().remove(line).is_none() {
+            self.error = Some("No fold made by hand here".to_string());
+        }
+    }
+
+    fn toggle_soft_wrap(&mut self) {
+        self.config.soft_wrap = !self.config.soft_wrap;
+        self.scroll.1 = 0;
+        self.follow_cursor = true;
+        self.message = Some(format!("Soft wrap {}", if self.config.soft_wrap { "on" } else { "off" }));
+    }
+
+    fn toggle_whitespace(&mut self) {
+        self.config.show_whitespace = !self.config.show_whitespace;
+        self.message = Some(format!("Whitespace {}", if self.config.show_whitespace { "shown" } else { "hidden" }));
+    }
+
+    fn handle_command(&mut self, command: Command) -> bool {
+        if self.prompt.is_some() {
+            return self.handle_prompt_command(command);
+        }
+
+        // a pending count makes `0` a digit rather than a motion
+        if let (Command::LineStart, Some(count)) = (command, self.count) {
+            self.count = Some(count.saturating_mul(10));
+            return true;
+        }
+
+        if command == Command::NormalMode && self.mode == Mode::Normal {
+            self.highlight_search = false;
+        }
+
+        // commands that act on the view or all cursors at once; the rest
+        // run for each cursor
+        match command {
+            Command::HalfPageUp => self.scroll_half_page(false),
+            Command::HalfPageDown => self.scroll_half_page(true),
+            Command::ScrollUp => {
+                let lines = self.count.take().unwrap_or(1) as isize;
+                self.scroll_lines(-lines);
+            }
+            Command::ScrollDown => {
+                let lines = self.count.take().unwrap_or(1) as isize;
+                self.scroll_lines(lines);
+            }
+            Command::ScrollCursorTop => self.align_cursor(Align::Top),
+            Command::ScrollCursorCenter => self.align_cursor(Align::Center),
+            Command::ScrollCursorBottom => self.align_cursor(Align::Bottom),
+            // with several cursors, leaving normal mode again keeps one
+            Command::NormalMode if self.mode == Mode::Normal && !self.others.is_empty() => {
+                self.others.clear();
+                self.count = None;
+            }
+            Command::SelectAll => {
+                let len = self.document.borrow().get_buffer().len_chars();
+                self.others.clear();
+                self.select_range(0, len, SelectionKind::Char);
+            }
+            Command::DeleteBackward | Command::DeleteForward if self.mode == Mode::Visual => self.copy_all(true),
+            Command::Copy | Command::CopyLine => self.copy_all(false),
+            Command::Cut | Command::CutLine => self.copy_all(true),
+            Command::PasteAfter => self.paste(false),
+            Command::PasteBefore => self.paste(true),
+            Command::CyclePaste => self.cycle_paste(),
+            Command::Undo | Command::Redo | Command::Earlier | Command::Later
+                | Command::EarlierMinutes | Command::LaterMinutes => self.travel(command),
+            Command::AddCursorAbove => self.add_cursor_line(false),
+            Command::AddCursorBelow => self.add_cursor_line(true),
+            Command::AddNextMatch => self.add_next_match(),
+            Command::SplitSelectionLines => self.split_selection_lines(),
+            Command::SelectMatches => {
+                self.count = None;
+                self.prompt = Some((PromptAction::SelectMatches, Prompt::new("select: ")));
+            }
+            Command::Search => self.start_search(false, None),
+            Command::SearchBackward => self.start_search(true, None),
+            Command::Replace => self.start_replace(false),
+            Command::ReplaceConfirm => self.start_replace(true),
+            Command::SearchNext => self.search_next(false),
+            Command::SearchPrevious => self.search_next(true),
+            Command::ToggleBookmark => self.toggle_bookmark(),
+            Command::ToggleSoftWrap => self.toggle_soft_wrap(),
+            Command::FoldOpen | Command::FoldClose | Command::FoldToggle => self.fold_at_cursor(command, false),
+            Command::FoldOpenRecursive => self.fold_at_cursor(Command::FoldOpen, true),
+            Command::FoldCloseRecursive => self.fold_at_cursor(Command::FoldClose, true),
+            Command::FoldToggleRecursive => self.fold_at_cursor(Command::FoldToggle, true),
+            Command::OpenAllFolds => self.fold_all(true),
+            Command::CloseAllFolds => self.fold_all(false),
+            Command::CreateFold => self.create_fold(),
+            Command::DeleteFold => self.delete_fold(),
+            Command::DeleteAllFolds => {
+                self.folds.borrow_mut().clear();
+            }
+            Command::ToggleWhitespace => self.toggle_whitespace(),
+            Command::ConvertIndentToSpaces => self.convert_indent(false),
+            Command::ConvertIndentToTabs => self.convert_indent(true),
+            Command::SetTabWidth => match self.count.take() {
+                Some(width) => self.set_tab_width(width),
+                None => self.prompt = Some((PromptAction::TabWidth, Prompt::new("tab width: "))),
+            },
+            Command::ToggleUseTabs => self.toggle_use_tabs(),
+            _ => {
+                let mut handled = true;
+                self.for_each_cursor(|view| handled &= view.handle_cursor_command(command));
+                return handled;
+            }
+        }
+
+        true
+    }
+
+    /// Runs a command for the current cursor of `for_each_cursor`.
+    fn handle_cursor_command(&mut self, command: Command) -> bool {
+        match command {
+            Command::MoveLeft => self.move_cursor(Motion::Left),
+            Command::MoveRight => self.move_cursor(Motion::Right),
+            Command::MoveUp => self.move_cursor(Motion::Up),
+            Command::MoveDown => self.move_cursor(Motion::Down),
+            Command::WordForward => self.move_cursor(Motion::WordForward),
+            Command::WordBackward => self.move_cursor(Motion::WordBackward),
+            Command::WordEnd => self.move_cursor(Motion::WordEnd),
+            Command::BigWordForward => self.move_cursor(Motion::BigWordForward),
+            Command::BigWordBackward => self.move_cursor(Motion::BigWordBackward),
+            Command::BigWordEnd => self.move_cursor(Motion::BigWordEnd),
+            Command::LineStart => self.move_cursor(Motion::LineStart),
+            Command::LineEnd => self.move_cursor(Motion::LineEnd),
+            Command::FirstNonBlank => self.move_cursor(Motion::FirstNonBlank),
+            Command::ParagraphForward => self.move_cursor(Motion::ParagraphForward),
+            Command::ParagraphBackward => self.move_cursor(Motion::ParagraphBackward),
+            Command::PageUp => self.move_cursor(Motion::PageUp),
+            Command::PageDown => self.move_cursor(Motion::PageDown),
+            Command::DisplayLineUp => self.move_display_rows(false),
+            Command::DisplayLineDown => self.move_display_rows(true),
+            Command::DisplayLineStart => self.move_display_line_edge(false),
+            Command::DisplayLineEnd => self.move_display_line_edge(true),
+            Command::GotoMatchingBracket => self.goto_matching_bracket(),
+            Command::GotoFirstLine | Command::GotoLastLine => {
+                let motion = match (self.count.take(), command) {
+                    (Some(line), _) => Motion::GotoLine(line.saturating_sub(1)),
+                    (None, Command::GotoFirstLine) => Motion::BufferStart,
+                    (None, _) => Motion::BufferEnd,
+                };
+
+                self.move_cursor(motion);
+            }
+            Command::NormalMode => {
+                if self.mode == Mode::Insert {
+                    self.move_cursor(Motion::Left);
+                }
+
+                self.set_mode(Mode::Normal);
+            }
+            Command::InsertMode => self.set_mode(Mode::Insert),
+            Command::Append => {
+                self.set_mode(Mode::Insert);
+                self.move_cursor(Motion::Right);
+            }
+            Command::InsertLineStart => {
+                self.set_mode(Mode::Insert);
+                self.move_cursor(Motion::FirstNonBlank);
+            }
+            Command::AppendLineEnd => {
+                self.set_mode(Mode::Insert);
+                self.move_cursor(Motion::LineEnd);
+            }
+            Command::OpenLineBelow => self.open_line(true),
+            Command::OpenLineAbove => self.open_line(false),
+            Command::InsertNewline => self.insert_newline(),
+            Command::InsertTab => self.insert_tab(),
+            Command::DeleteBackward => self.delete_backward(),
+            Command::DeleteForward => {
+                for _ in 0..self.count.take().unwrap_or(1) {
+                    self.delete_forward();
+                }
+            }
+            Command::VisualMode => self.select(SelectionKind::Char),
+            Command::SelectLine => self.select(SelectionKind::Line),
+            Command::SelectWord => self.select_word(),
+            Command::FlipSelection => self.flip_selection(),
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Edits the open prompt's text or search options, or accepts or
+    /// cancels it.
+    fn handle_prompt_command(&mut self, command: Command) -> bool {
+        let Some((action, prompt)) = &mut self.prompt else {
+            return false;
+        };
+
+        match command {
+            Command::DeleteBackward => prompt.delete_backward(),
+            Command::DeleteForward => prompt.delete_forward(),
+            Command::MoveLeft => prompt.move_left(),
+            Command::MoveRight => prompt.move_right(),
+            Command::LineStart => prompt.move_start(),
+            Command::LineEnd => prompt.move_end(),
+            Command::ToggleSearchRegex | Command::ToggleSearchWholeWord
+                | Command::ToggleSearchCase | Command::TogglePreserveCase => {
+                let config = &mut self.search_config;
+
+                match command {
+                    Command::ToggleSearchRegex => config.regex = !config.regex,
+                    Command::ToggleSearchWholeWord => config.whole_word = !config.whole_word,
+                    Command::ToggleSearchCase => config.smart_case = !config.smart_case,
+                    _ => config.preserve_case = !config.preserve_case,
+                }
+
+                prompt.set_label(action.label(*config));
+            }
+            Command::PromptCancel => {
+                if let Some((PromptAction::Search { origin, scroll, previous, highlight, .. }, _)) = self.prompt.take() {
+                    self.cursor = origin;
+                    self.scroll = scroll;
+                    self.search = previous;
+                    self.highlight_search = highlight;
+                    self.clamp_cursor();
+                }
+
+                return true;
+            }
+            Command::PromptAccept => {
+                let text = prompt.get_text().to_string();
+
+                match self.prompt.take().map(|(action, _)| action) {
+                    Some(PromptAction::SelectMatches) => self.select_matches(&text),
+                    Some(PromptAction::TabWidth) => match text.trim().parse() {
+                        Ok(width) => self.set_tab_width(width),
+                        Err(_) => self.error = Some(format!("'{}' is not a tab width", text)),
+                    },
+                    Some(PromptAction::Search { backward, origin, previous, replace: None, .. }) => {
+                        self.accept_search(&text, backward, origin, previous);
+                    }
+                    Some(PromptAction::Search { origin, previous, replace: Some(setup), .. }) => {
+                        let found = self.accept_search(&text, false, origin, previous);
+                        let action = PromptAction::Replacement(setup);
+
+                        // without matches there is nothing to ask a
+                        // replacement for
+                        self.prompt = found.then(|| {
+                            let label = action.label(self.search_config);
+                            (action, Prompt::new(label))
+                        });
+                    }
+                    Some(PromptAction::Replacement(setup)) => {
+                        // the options may have changed since the pattern
+                        // was typed, as for preserving case
+                        let search = self.search.as_ref()
+                            .and_then(|search| Search::new(search.get_pattern(), self.search_config).ok());
+
+                        if search.is_some() {
+                            self.search = search;
+                        }
+
+                        self.replace(&text, setup);
+                    }
+                    None => (),
+                }
+
+                return true;
+            }
+            _ => return false,
+        }
+
+        self.preview_search();
+        true
+    }
+
+    fn handle_key(&mut self, event: &KeyEvent) -> bool {
+        let plain = !event.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+
+        if self.replacing.is_some() {
+            self.handle_replace_key(event);
+            return true;
+        }
+
+        if let Some((_, prompt)) = &mut self.prompt {
+            return match event.code {
+                KeyCode::Char(c) if plain => {
+                    prompt.insert(c.encode_utf8(&mut [0; 4]));
+                    self.preview_search();
+                    true
+                }
+                _ => false,
+            };
+        }
+
+        // any other key cancels naming a register
+        if self.awaiting_register {
+            self.awaiting_register = false;
+
+            if let KeyCode::Char(name) = event.code {
+                if plain && Registers::is_valid_name(name) {
+                    self.register = Some(name);
+                }
+            }
+
+            return true;
+        }
+
+        match (self.mode, event.code) {
+            (Mode::Normal | Mode::Visual, KeyCode::Char('"')) if plain => {
+                self.awaiting_register = true;
+                true
+            }
+            (Mode::Normal | Mode::Visual, KeyCode::Char(c)) if plain && c.is_ascii_digit() => {
+                let digit = c.to_digit(10).unwrap_or(0) as usize;
+                self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                true
+            }
+            (Mode::Insert, KeyCode::Char(c)) if plain => {
+                self.for_each_cursor(|view| view.type_char(c));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn handle_mouse(&mut self, event: &NodeMouseEvent) -> bool {
+        match event.kind {
+            // alt-click adds a cursor rather than moving the cursor
+            MouseEventKind::Down(MouseButton::Left) if event.modifiers.contains(KeyModifiers::ALT) => {
+                let position = self.screen_to_char(event.x, event.y);
+                self.add_cursor(Cursor::new(position));
+                true
+            }
+            MouseEventKind::Down(MouseButton::Left) => {
+                let position = self.screen_to_char(event.x, event.y);
+                self.others.clear();
+
+                if self.mode == Mode::Visual {
+                    self.set_mode(Mode::Normal);
+                }
+
+                self.cursor.set_position(position);
+                self.clamp_cursor();
+                self.drag_anchor = Some(self.cursor.get_position());
+                true
+            }
+            // dragging selects from where the button went down, as `v` does
+            MouseEventKind::Drag(MouseButton::Left) => {
+                let Some(anchor) = self.drag_anchor else {
+                    return false;
+                };
+
+                let position = self.screen_to_char(event.x, event.y);
+
+                match self.selection {
+                    Some(_) => {
+                        self.cursor.set_position(position);
+                        self.clamp_cursor();
+                    }
+                    None if position != anchor => self.select_range(anchor, position, SelectionKind::Char),
+                    None => (),
+                }
+
+                true
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.drag_anchor.take().is_some(),
+            MouseEventKind::ScrollUp => {
+                self.scroll_lines(-WHEEL_LINES);
+                true
+            }
+            MouseEventKind::ScrollDown => {
+                self.scroll_lines(WHEEL_LINES);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The start of the grapheme under a point in the view.
+    fn screen_to_char(&self, x: i32, y: i32) -> usize {
+        let rows = self.visible_rows();
+
+        let Some((line, row)) = rows.get(y.max(0) as usize).or(rows.last()).copied() else {
+            let document = self.document.borrow();
+            return document.get_buffer().len_chars();
+        };
+
+        self.row_position(line, row, self.scroll.1 + x.max(0) as usize)
+    }
+}
+
+impl Widget for EditorView {
+    fn draw(&mut self, drawer: &mut Drawer, _scroll: (u16, u16)) {
+        // the prompt takes the bottom row
+        let prompt_rows = (self.prompt.is_some() || self.replacing.is_some()) as u16;
+        self.size = (drawer.boundaries.width, drawer.boundaries.height.saturating_sub(prompt_rows));
+        self.update_scroll();
+
+        let wrapping = self.wrap_settings().is_some();
+        let uneven = wrapping || self.has_hidden();
+
+        // wrapped and folded lines take other than a row each, so those
+        // are simply drawn again
+        if let Some((top, left)) = self.drawn_scroll.filter(|_| !uneven) {
+            if left == self.scroll.1 && top != self.scroll.0 {
+                drawer.hint_scroll((self.scroll.0 as i64 - top as i64).clamp(i32::MIN as i64, i32::MAX as i64) as i32);
+            }
+        }
+
+        self.drawn_scroll = Some(self.scroll);
+
+        // the lines that start on screen
+        let lines: Vec<usize> = self.visible_rows().into_iter()
+            .filter(|(_, row)| *row == 0)
+            .map(|(line, _)| line)
+            .collect();
+
+        let document = self.document.borrow();
+        let buffer = document.get_buffer();
+        let (top, left) = self.scroll;
+        let (width, height) = (self.size.0 as usize, self.size.1 as usize);
+
+        let mut selected: Vec<Range<usize>> = self.others.iter()
+            .filter_map(|(_, selection)| *selection)
+            .chain(self.selection)
+            .map(|selection| selection.range(buffer))
+            .collect();
+        selected.sort_by_key(|range| range.start);
+
+        let others: Vec<usize> = self.others.iter().map(|(cursor, _)| cursor.get_position()).collect();
+
+        // only the visible lines are searched for matches to highlight
+        let matches: Vec<Range<usize>> = match self.search.as_ref().filter(|_| self.highlight_search) {
+            Some(search) => {
+                let end_line = lines.last().map_or(top, |line| line + 1);
+                let start = buffer.line_to_char(top.min(buffer.len_lines() - 1));
+                let end = match end_line < buffer.len_lines() {
+                    true => buffer.line_to_char(end_line),
+                    false => buffer.len_chars(),
+                };
+                let start_byte = buffer.char_to_byte(start);
+
+                search.matches(&buffer.slice(start..end).to_string())
+                    .into_iter()
+                    .map(|found| buffer.byte_to_char(start_byte + found.start)..buffer.byte_to_char(start_byte + found.end))
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+
+        let cursor = self.cursor.get_position();
+
+        // the bracket at the cursor, or in insert mode the one before it
+        let brackets: Vec<usize> = [Some(cursor), cursor.checked_sub(1).filter(|_| self.mode == Mode::Insert)]
+            .into_iter()
+            .flatten()
+            .find_map(|position| Some(vec![position, bracket::find_match(buffer, position, &self.language.brackets, self.highlighter.as_mut())?]))
+            .unwrap_or_default();
+
+        let tokens: Vec<Vec<(Range<usize>, CellStyle)>> = match self.highlighter.as_mut() {
+            Some(highlighter) => lines.iter()
+                .map(|line| highlighter.highlight(buffer, *line..*line + 1).pop().unwrap_or_default())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        // other cursors show reversed, or underlined inside a selection
+        let style_at = |position: usize, mut style: CellStyle| {
+            let index = selected.partition_point(|range| range.end <= position);
+            let is_selected = selected.get(index).is_some_and(|range| range.start <= position);
+
+            if is_selected {
+                style = style.patch(self.theme.selection);
+            }
+
+            if brackets.contains(&position) {
+                style = style.patch(self.theme.matching_bracket);
+            }
+
+            if others.binary_search(&position).is_ok() {
+                style.set_attribute(if is_selected { Attribute::Underlined } else { Attribute::Reverse });
+            }
+
+            // the match at the cursor stands out from the rest
+            let index = matches.partition_point(|range| range.end <= position);
+
+            if let Some(range) = matches.get(index).filter(|range| range.start <= position) {
+                style = style.patch(match range.contains(&cursor) {
+                    true => self.theme.current_match,
+                    false => self.theme.search_match,
+                });
+            }
+
+            style
+        };
+
+        let tab_width = document.get_indent().width;
+        let mut y = 0;
+
+        for (index, &line) in lines.iter().enumerate() {
+            let rows = self.line_rows(line);
+            let line_start = buffer.line_to_char(line);
+            let text = buffer.line_text(line);
+            let tokens = tokens.get(index).map_or(&[][..], Vec::as_slice);
+            // the byte the whitespace at the end of the line starts at
+            let trailing = text.trim_end_matches([' ', '\t']).len();
+            let mut row = 0;
+            let mut display = 0;
+            let mut byte = 0;
+
+            // continued rows start with the indicator, right before the text
+            if let Some(indicator) = self.config.wrap_indicator {
+                let indicator_width = indicator.width().unwrap_or(1);
+
+                for (index, wrapped) in rows.iter().enumerate().skip(1).take(height - y) {
+                    if let Some(x) = wrapped.offset.checked_sub(indicator_width) {
+                        drawer.draw_cell(x as u16, (y + index) as u16, Cell { c: indicator, style: self.theme.gutter });
+
+                        for offset in 1..indicator_width {
+                            drawer.draw_cell((x + offset) as u16, (y + index) as u16, Cell::continuation());
+                        }
+                    }
+                }
+            }
+
+            for grapheme in grapheme::layout(&text, tab_width) {
+                let start = display;
+                display += grapheme.width;
+
+                let index = tokens.partition_point(|(range, _)| range.end <= byte);
+                let base = tokens.get(index)
+                    .filter(|(range, _)| range.start <= byte)
+                    .map_or(CellStyle::default(), |(_, style)| *style);
+
+                let marker = match grapheme.text {
+                    _ if !self.config.show_whitespace => None,
+                    " " | "\t" if byte >= trailing => self.config.trailing_marker,
+                    "\t" => self.config.tab_marker,
+                    _ => None,
+                };
+
+                byte += grapheme.text.len();
+
+                while rows.get(row + 1).is_some_and(|next| next.column <= grapheme.column) {
+                    row += 1;
+                }
+
+                let wrapped = rows[row];
+
+                // graphemes cut by the left edge are left blank
+                if start < wrapped.display + left {
+                    continue;
+                }
+
+                let x = wrapped.offset + start - wrapped.display - left;
+
+                if x + grapheme.width > width {
+                    match rows.len() {
+                        1 => break,
+                        _ => continue,
+                    }
+                }
+
+                if y + row >= height {
+                    break;
+                }
+
+                let c = marker.unwrap_or_else(|| match grapheme.text.chars().next() {
+                    Some(c) if c.is_control() => if c == '\t' { ' ' } else { '?' },
+                    Some(c) => c,
+                    None => ' ',
+                });
+
+                let screen_y = (y + row) as u16;
+                let style = style_at(line_start + grapheme.column, base);
+                let marker_style = if marker.is_some() { style.patch(self.theme.whitespace) } else { style };
+                drawer.draw_cell(x as u16, screen_y, Cell { c, style: marker_style });
+
+                // a tab fills the cells up to the next stop
+                for offset in 1..grapheme.width {
+                    let cell = match grapheme.text {
+                        "\t" => Cell { c: ' ', style },
+                        _ => Cell::continuation(),
+                    };
+
+                    drawer.draw_cell((x + offset) as u16, screen_y, cell);
+                }
+            }
+
+            // a selected line ending or a cursor after the last char shows
+            // as a cell past the end of the last row
+            let line_end = line_start + text.chars().count();
+            let last = rows[rows.len() - 1];
+            let end_x = (last.offset + display - last.display).checked_sub(left);
+
+            if let Some(x) = end_x.filter(|x| *x < width && y + rows.len() - 1 < height) {
+                drawer.draw_cell(x as u16, (y + rows.len() - 1) as u16, Cell { c: ' ', style: style_at(line_end, CellStyle::default()) });
+            }
+
+            // a closed fold says how many lines it has after its first
+            if let Some(fold) = self.folded(line) {
+                let summary = format!("⋯ {} lines", fold.hidden_lines() + 1);
+                let x = end_x.map_or(0, |x| x + 1);
+
+                if x < width {
+                    drawer.draw_text(x as u16, y as u16, &summary, self.theme.folded);
+                }
+            }
+
+            y += rows.len();
+        }
+
+        if let Some((_, prompt)) = &self.prompt {
+            let x = drawer.draw_text(0, self.size.1, prompt.get_label(), CellStyle::default());
+            drawer.draw_text(x, self.size.1, prompt.get_text(), CellStyle::default());
+        }
+
+        // the question shows what the current match would become
+        if let Some(pending) = &self.replacing {
+            let with = pending.matches.front().map_or("", |(_, with)| with.as_str());
+            let index = pending.total - pending.matches.len() + 1;
+            let question = format!(
+                "replace with \"{}\"? [y]es [n]o [a]ll [q]uit  {}/{}",
+                with.escape_debug(),
+                index,
+                pending.total,
+            );
+
+            drawer.draw_text(0, self.size.1, &question, CellStyle::default());
+        }
+    }
+
+    fn handle_event(&mut self, event: &NodeEvent, phase: Phase) -> bool {
+        if phase != Phase::Target {
+            return false;
+        }
+
+        let before = self.cursor_state();
+
+        // typing, like answering for each match of a replace, builds up
+        // one step in the history
+        let typing = self.mode == Mode::Insert && matches!(
+            event,
+            NodeEvent::Key(_) | NodeEvent::Command(
+                Command::InsertNewline | Command::InsertTab | Command::DeleteBackward | Command::DeleteForward
+            )
+        ) || self.replacing.is_some() && matches!(event, NodeEvent::Key(_));
+
+        let handled = match event {
+            NodeEvent::Command(command) => self.handle_command(*command),
+            NodeEvent::Key(key) => self.handle_key(key),
+            NodeEvent::Paste(text) if self.prompt.is_some() => {
+                // a pattern is one line
+                let text = text.lines().next().unwrap_or_default();
+                self.prompt.iter_mut().for_each(|(_, prompt)| prompt.insert(text));
+                self.preview_search();
+                true
+            }
+            NodeEvent::Paste(text) => {
+                let text = text.replace("\r\n", "\n").replace('\r', "\n");
+
+                self.for_each_cursor(|view| {
+                    view.insert_text(&text);
+                    view.clamp_cursor();
+                });
+
+                true
+            }
+            NodeEvent::Mouse(mouse) => self.handle_mouse(mouse),
+            _ => false,
+        };
+
+        self.commit(before, typing, handled);
+        handled
+    }
+
+    /// Leaves keys unbound while a register name is typed or a replace
+    /// asks about a match.
+    fn key_context(&self) -> Option<&'static str> {
+        match (&self.prompt, self.awaiting_register || self.replacing.is_some()) {
+            (Some(_), _) => Some(PROMPT_CONTEXT),
+            (None, true) => None,
+            (None, false) => Some(self.mode.name()),
+        }
+    }
+
+    fn cursor(&self) -> Option<(u16, u16, CursorShape)> {
+        if let Some((_, prompt)) = &self.prompt {
+            let x = prompt.get_label().chars().count() + prompt.get_cursor();
+            return Some((x.min(u16::MAX as usize) as u16, self.size.1, CursorShape::Line));
+        }
+
+        let (point, row, x) = self.locate(self.cursor.get_position());
+        let x = x.checked_sub(self.scroll.1)?;
+
+        if point.line < self.scroll.0 {
+            return None;
+        }
+
+        // only the lines on screen are counted down
+        let y = row + self.rows_between(self.scroll.0, point.line, self.size.1 as usize)?;
+
+        let shape = match self.mode {
+            Mode::Normal | Mode::Visual => CursorShape::Block,
+            Mode::Insert => CursorShape::Line,
+        };
+
+        Some((x.min(u16::MAX as usize) as u16, y.min(u16::MAX as usize) as u16, shape))
+    }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        self.theme = theme.clone();
+
+        if let Some(highlighter) = &mut self.highlighter {
+            highlighter.set_styles(&theme.syntax);
+        }
+    }
+}
+
+/// The spaces and tabs `text` starts with.
+fn leading_whitespace(text: &str) -> &str {
+    &text[..text.len() - text.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Moves a cursor and its selection along with changes made elsewhere.
+fn map_cursor(changes: &[Change], cursor: &mut Cursor, selection: &mut Option<Selection>) {
+    let map = |position: usize| changes.iter().fold(position, |position, change| change.map_position(position));
+    let position = map(cursor.get_position());
+
+    if position != cursor.get_position() {
+        cursor.set_position(position);
+    }
+
+    if let Some(selection) = selection {
+        *selection = Selection::new(map(selection.get_anchor()), map(selection.get_head()), selection.get_kind());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::clipboard::Clipboard;
+
+    /// A view of an in-memory document, `height` rows tall.
+    fn view(text: &str, height: u16) -> EditorView {
+        let mut document = Document::new();
+        document.get_buffer_mut().insert(0, text);
+
+        let registers = Registers::new(Clipboard::default(), false);
+        let mut view = EditorView::new(Rc::new(RefCell::new(document)), Rc::new(RefCell::new(registers)), EditorConfig::default());
+        view.size = (40, height);
+        view
+    }
+
+    fn numbered_lines(count: usize) -> String {
+        (0..count).map(|line| format!("line {}", line)).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Moves the cursor to the start of `line` and scrolls to it.
+    fn goto(view: &mut EditorView, line: usize) {
+        let position = view.document.borrow().get_buffer().line_to_char(line);
+        view.cursor.set_position(position);
+        view.follow_cursor = true;
+        view.update_scroll();
+    }
+
+    fn text(view: &EditorView) -> String {
+        view.document.borrow().get_buffer().get_rope().to_string()
+    }
+
+    #[test]
+    fn cycling_a_paste_goes_back_through_the_ring() {
+        let mut view = view("x", 10);
+
+        for text in ["a", "b", "c"] {
+            view.registers.borrow_mut().copy(None, Register::new(text.to_string(), false));
+        }
+
+        view.handle_command(Command::PasteAfter);
+        assert_eq!(text(&view), "xc");
+
+        view.handle_command(Command::CyclePaste);
+        assert_eq!(text(&view), "xb");
+        view.handle_command(Command::CyclePaste);
+        view.handle_command(Command::CyclePaste);
+        assert_eq!(text(&view), "xc");
+    }
+
+    /// Runs a command as if from a key, committing it to the history.
+    fn run(view: &mut EditorView, command: Command) {
+        view.handle_event(&NodeEvent::Command(command), Phase::Target);
+    }
+
+    /// The char ranges of every cursor's selection, in buffer order.
+    fn selected(view: &EditorView) -> Vec<Range<usize>> {
+        let document = view.document.borrow();
+        let mut ranges: Vec<_> = view.others.iter()
+            .chain([&(view.cursor, view.selection)])
+            .filter_map(|(_, selection)| selection.map(|selection| selection.range(document.get_buffer())))
+            .collect();
+
+        ranges.sort_by_key(|range| range.start);
+        ranges
+    }
+
+    #[test]
+    fn merges_cursors_that_meet() {
+        let mut view = view("ab\ncd", 10);
+
+        // past the end of the line is the last char in normal mode
+        view.cursor = Cursor::new(1);
+        view.others = vec![(Cursor::new(2), None), (Cursor::new(4), None), (Cursor::new(1), None)];
+        view.merge_cursors();
+        assert_eq!(view.cursor.get_position(), 1);
+        assert_eq!(view.others, [(Cursor::new(4), None)]);
+
+        // overlapping selections join up around the primary cursor
+        view.mode = Mode::Visual;
+        view.cursor = Cursor::new(3);
+        view.selection = Some(Selection::new(1, 3, SelectionKind::Char));
+        view.others = vec![
+            (Cursor::new(2), Some(Selection::new(0, 2, SelectionKind::Char))),
+            (Cursor::new(4), Some(Selection::new(4, 4, SelectionKind::Char))),
+        ];
+        view.merge_cursors();
+        assert_eq!(view.selection, Some(Selection::new(0, 3, SelectionKind::Char)));
+        assert_eq!(view.cursor.get_position(), 3);
+        assert_eq!(selected(&view), [0..4, 4..5]);
+    }
+
+    #[test]
+    fn adds_cursors_on_next_matches() {
+        let mut view = view("foo bar foo baz foo", 10);
+
+        // the first selects the word under the cursor
+        run(&mut view, Command::AddNextMatch);
+        assert_eq!(selected(&view).first(), Some(&(0..3)));
+        assert_eq!(view.cursor_count(), 1);
+
+        run(&mut view, Command::AddNextMatch);
+        run(&mut view, Command::AddNextMatch);
+        assert_eq!(selected(&view), [0..3, 8..11, 16..19]);
+        assert_eq!(view.cursor.get_position(), 18);
+
+        // once every match has a cursor, wrapping around adds none
+        run(&mut view, Command::AddNextMatch);
+        assert_eq!(view.cursor_count(), 3);
+    }
+
+    #[test]
+    fn splits_selections_into_lines() {
+        let mut view = view("ab\ncd\nef", 10);
+        view.mode = Mode::Visual;
+        view.selection = Some(Selection::new(1, 6, SelectionKind::Char));
+        view.cursor = Cursor::new(6);
+
+        run(&mut view, Command::SplitSelectionLines);
+        assert_eq!(selected(&view), [1..2, 3..5, 6..7]);
+        assert_eq!(view.cursor_count(), 3);
+    }
+
+    #[test]
+    fn selects_matches_of_a_pattern() {
+        let mut view = view("a1 b22 c333", 10);
+
+        view.select_matches("[0-9]+");
+        assert_eq!(view.mode, Mode::Visual);
+        assert_eq!(selected(&view), [1..2, 4..6, 8..11]);
+
+        // in visual mode, only within the selections
+        view.select_matches("3");
+        assert_eq!(selected(&view), [8..9, 9..10, 10..11]);
+
+        view.select_matches("x");
+        assert_eq!(view.error.take().as_deref(), Some("No matches for 'x'"));
+        view.select_matches("(");
+        assert!(view.error.take().is_some_and(|err| err.starts_with("Invalid pattern")));
+        assert_eq!(view.cursor_count(), 3);
+    }
+
+    #[test]
+    fn edits_with_several_cursors_undo_in_one_step() {
+        let mut view = view("one\ntwo\nthree", 10);
+
+        run(&mut view, Command::AddCursorBelow);
+        run(&mut view, Command::AddCursorBelow);
+        assert_eq!(view.cursor_count(), 3);
+
+        run(&mut view, Command::DeleteForward);
+        assert_eq!(text(&view), "ne\nwo\nhree");
+
+        run(&mut view, Command::Undo);
+        assert_eq!(text(&view), "one\ntwo\nthree");
+        run(&mut view, Command::Redo);
+        assert_eq!(text(&view), "ne\nwo\nhree");
+    }
+
+    #[test]
+    fn scrolls_to_the_cursor_within_the_margin() {
+        let mut view = view(&numbered_lines(100), 10);
+
+        goto(&mut view, 6);
+        assert_eq!(view.scroll.0, 0);
+        goto(&mut view, 7);
+        assert_eq!(view.scroll.0, 1);
+
+        // the margin stops at the end of the buffer
+        goto(&mut view, 99);
+        assert_eq!(view.scroll.0, 90);
+        goto(&mut view, 92);
+        assert_eq!(view.scroll.0, 89);
+
+        // and at the start
+        goto(&mut view, 1);
+        assert_eq!(view.scroll.0, 0);
+    }
+
+    #[test]
+    fn scrolls_without_moving_the_cursor() {
+        let mut view = view(&numbered_lines(100), 10);
+        goto(&mut view, 5);
+
+        view.scroll_lines(1);
+        view.update_scroll();
+        assert_eq!((view.scroll.0, view.cursor_point().line), (1, 5));
+
+        // until the cursor would come within the margin
+        view.scroll_lines(4);
+        view.update_scroll();
+        assert_eq!((view.scroll.0, view.cursor_point().line), (5, 8));
+
+        view.scroll_lines(-5);
+        view.update_scroll();
+        assert_eq!((view.scroll.0, view.cursor_point().line), (0, 6));
+
+        // no margin is kept at the end of the buffer
+        view.scroll_lines(1000);
+        assert_eq!((view.scroll.0, view.cursor_point().line), (99, 99));
+    }
+
+    #[test]
+    fn aligns_the_cursor_line() {
+        let mut view = view(&numbered_lines(100), 10);
+        goto(&mut view, 50);
+        assert_eq!(view.scroll.0, 44);
+
+        view.align_cursor(Align::Top);
+        view.update_scroll();
+        assert_eq!(view.scroll.0, 47);
+
+        view.align_cursor(Align::Center);
+        view.update_scroll();
+        assert_eq!(view.scroll.0, 45);
+
+        view.align_cursor(Align::Bottom);
+        view.update_scroll();
+        assert_eq!(view.scroll.0, 44);
+        assert_eq!(view.cursor_point().line, 50);
+
+        // the start of the buffer stays at the top
+        goto(&mut view, 1);
+        view.align_cursor(Align::Bottom);
+        assert_eq!(view.scroll.0, 0);
+    }
+
+    /// Types `text` key by key in insert mode, from char `position`.
+    fn type_at(view: &mut EditorView, position: usize, text: &str) {
+        view.cursor.set_position(position);
+        run(view, Command::InsertMode);
+
+        for c in text.chars() {
+            let key = KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+            view.handle_event(&NodeEvent::Key(key), Phase::Target);
+        }
+    }
+
+    #[test]
+    fn pairs_brackets_and_quotes() {
+        let mut view = view("", 10);
+
+        type_at(&mut view, 0, "f(x");
+        assert_eq!(text(&view), "f(x)");
+
+        // the closing bracket is typed over
+        type_at(&mut view, 3, ")\"a");
+        assert_eq!(text(&view), "f(x)\"a\"");
+        assert_eq!(view.cursor.get_position(), 6);
+
+        type_at(&mut view, 6, "\"");
+        assert_eq!(text(&view), "f(x)\"a\"");
+        assert_eq!(view.cursor.get_position(), 7);
+    }
+
+    #[test]
+    fn does_not_pair_before_a_word_or_after_one() {
+        let mut before = view("word", 10);
+        type_at(&mut before, 0, "(");
+        assert_eq!(text(&before), "(word");
+
+        // a quote after a word is an apostrophe
+        let mut after = view("", 10);
+        type_at(&mut after, 0, "don't 'x");
+        assert_eq!(text(&after), "don't 'x'");
+    }
+
+    #[test]
+    fn indents_after_an_opening_bracket() {
+        let mut view = view("fn f() {}", 10);
+
+        type_at(&mut view, 8, "");
+        run(&mut view, Command::InsertNewline);
+        assert_eq!(text(&view), "fn f() {\n    \n}");
+        assert_eq!(view.cursor.get_position(), 13);
+
+        // the next line keeps the indent
+        type_at(&mut view, 13, "a");
+        run(&mut view, Command::InsertNewline);
+        assert_eq!(text(&view), "fn f() {\n    a\n    \n}");
+    }
+
+    #[test]
+    fn indents_after_a_colon_in_python() {
+        let mut view = view("if x:", 10);
+        view.set_language(LanguageConfig::builtin("python").unwrap());
+
+        type_at(&mut view, 5, "");
+        run(&mut view, Command::InsertNewline);
+        assert_eq!(text(&view), "if x:\n    ");
+    }
+
+    #[test]
+    fn dedents_a_closing_bracket_first_on_its_line() {
+        let mut first = view("fn f() {\n    a\n        ", 10);
+        type_at(&mut first, 23, "}");
+        assert_eq!(text(&first), "fn f() {\n    a\n}");
+        assert_eq!(first.cursor.get_position(), 16);
+
+        // not after other text
+        let mut after = view("fn f() {\n    a", 10);
+        type_at(&mut after, 14, "}");
+        assert_eq!(text(&after), "fn f() {\n    a}");
+    }
+
+    #[test]
+    fn sets_the_tab_width_from_the_count_or_a_prompt() {
+        let mut view = view("a", 10);
+        let width = |view: &EditorView| view.document.borrow().get_indent().width;
+
+        view.count = Some(2);
+        run(&mut view, Command::SetTabWidth);
+        assert_eq!(width(&view), 2);
+
+        run(&mut view, Command::SetTabWidth);
+        assert_eq!(view.prompt.as_ref().map(|(_, prompt)| prompt.get_label()), Some("tab width: "));
+
+        for c in "8".chars() {
+            let key = KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+            view.handle_event(&NodeEvent::Key(key), Phase::Target);
+        }
+
+        run(&mut view, Command::PromptAccept);
+        assert_eq!(width(&view), 8);
+        assert!(view.prompt.is_none());
+    }
+
+    #[test]
+    fn rejects_a_tab_width_of_zero() {
+        let mut view = view("a", 10);
+
+        view.count = Some(0);
+        run(&mut view, Command::SetTabWidth);
+        assert_eq!(view.error.as_deref(), Some("The tab width must be at least 1"));
+        assert_eq!(view.document.borrow().get_indent().width, 4);
+
+        // tabs still go to the next stop
+        type_at(&mut view, 0, "");
+        run(&mut view, Command::InsertTab);
+        assert_eq!(text(&view), "    a");
+    }
+}
@@ -0,0 +1,183 @@
+use std::rc::Rc;
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::buffer::{Buffer, LineEdit};
+
+use super::grapheme;
+
+/// How lines break into rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrapSettings {
+    /// Cells in a row.
+    pub width: usize,
+    /// Shown at the start of each row a line continues on.
+    pub indicator: Option<char>,
+    /// Whether continued rows line up with the indent of their line.
+    pub preserve_indent: bool,
+    /// Cells between tab stops.
+    pub tab_width: usize,
+}
+
+/// One screen row of a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrapRow {
+    /// The char column the row starts at.
+    pub column: usize,
+    /// The screen column the row starts at, were the line not wrapped.
+    pub display: usize,
+    /// Cells before the text, taken by the kept indent and the indicator.
+    pub offset: usize,
+}
+
+impl WrapRow {
+    /// Where a line that is not wrapped starts.
+    pub const START: WrapRow = WrapRow { column: 0, display: 0, offset: 0 };
+}
+
+/// Breaks a line into rows at the last whitespace that fits, or wherever
+/// it has to for words longer than a row.
+pub fn wrap_line(text: &str, settings: WrapSettings) -> Vec<WrapRow> {
+    let mut rows = vec![WrapRow::START];
+
+    if settings.width == 0 {
+        return rows;
+    }
+
+    let indent = match settings.preserve_indent {
+        true => grapheme::layout(text, settings.tab_width)
+            .take_while(|grapheme| grapheme.text == " " || grapheme.text == "\t")
+            .map(|grapheme| grapheme.width)
+            .sum(),
+        false => 0,
+    };
+    let indicator = settings.indicator.map_or(0, |c| c.width().unwrap_or(1));
+
+    // continued rows keep at least half the width for text
+    let offset = (indent + indicator).min(settings.width / 2);
+    let mut row_start = 0;
+    let mut capacity = settings.width;
+    let mut break_at: Option<(usize, usize)> = None;
+    let mut display = 0;
+
+    for grapheme in grapheme::layout(text, settings.tab_width) {
+        let end = display + grapheme.width;
+
+        while end - row_start > capacity && display > row_start {
+            // back to the last whitespace if the row has one
+            let (column, start) = break_at.take()
+                .filter(|(_, start)| *start > row_start)
+                .unwrap_or((grapheme.column, display));
+
+            rows.push(WrapRow { column, display: start, offset });
+            row_start = start;
+            capacity = settings.width - offset;
+        }
+
+        if grapheme.text.starts_with(char::is_whitespace) {
+            break_at = Some((grapheme.column + grapheme.len_chars(), end));
+        }
+
+        display = end;
+    }
+
+    rows
+}
+
+/// The rows of each line at the current settings, kept across draws and
+/// worked out again only for lines that were edited.
+#[derive(Debug, Clone, Default)]
+pub struct WrapCache {
+    settings: Option<WrapSettings>,
+    /// The buffer version the lines are for.
+    version: Option<u64>,
+    lines: Vec<Option<Rc<[WrapRow]>>>,
+}
+
+impl WrapCache {
+    /// Catches up with edits to `buffer` since the last sync, dropping
+    /// the rows of the lines they touched, or of all lines when the
+    /// settings changed.
+    pub fn sync(&mut self, buffer: &Buffer, settings: WrapSettings) {
+        if self.settings != Some(settings) {
+            self.settings = Some(settings);
+            self.lines.clear();
+        }
+
+        if self.version != Some(buffer.get_version()) {
+            let edits: Option<Vec<LineEdit>> = self.version
+                .and_then(|version| buffer.line_edits_since(version))
+                .map(Iterator::collect);
+
+            match edits {
+                Some(edits) => edits.into_iter().for_each(|edit| self.edit(edit)),
+                None => self.lines.clear(),
+            }
+
+            self.version = Some(buffer.get_version());
+        }
+
+        self.lines.resize(buffer.len_lines(), None);
+    }
+
+    fn edit(&mut self, edit: LineEdit) {
+        let LineEdit { start, old_end, new_end, .. } = edit;
+
+        if start >= self.lines.len() {
+            return;
+        }
+
+        let end = (old_end + 1).min(self.lines.len());
+        self.lines.splice(start..end, (start..=new_end).map(|_| None));
+    }
+
+    /// The rows of `line`, from `buffer` as of the last sync.
+    pub fn rows(&mut self, buffer: &Buffer, line: usize) -> Rc<[WrapRow]> {
+        let settings = self.settings.expect("Synced before use");
+        let slot = &mut self.lines[line];
+
+        slot.get_or_insert_with(|| wrap_line(&buffer.line_text(line), settings).into())
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(width: usize) -> WrapSettings {
+        WrapSettings { width, indicator: None, preserve_indent: false, tab_width: 4 }
+    }
+
+    fn row(column: usize, display: usize, offset: usize) -> WrapRow {
+        WrapRow { column, display, offset }
+    }
+
+    #[test]
+    fn breaks_after_whitespace() {
+        assert_eq!(wrap_line("aaa bbb ccc", settings(8)), [WrapRow::START, row(8, 8, 0)]);
+        assert_eq!(wrap_line("aaaaaaaaaa", settings(4)), [WrapRow::START, row(4, 4, 0), row(8, 8, 0)]);
+        assert_eq!(wrap_line("aaa bbb ccc", settings(0)), [WrapRow::START]);
+    }
+
+    #[test]
+    fn keeps_wide_chars_whole() {
+        assert_eq!(wrap_line("漢字漢字漢", settings(5)), [WrapRow::START, row(2, 4, 0), row(4, 8, 0)]);
+    }
+
+    #[test]
+    fn lines_continued_rows_up_with_the_indent() {
+        let settings = WrapSettings { indicator: Some('↪'), preserve_indent: true, ..settings(8) };
+        assert_eq!(wrap_line("  aaaa bbbb", settings), [WrapRow::START, row(7, 7, 3)]);
+
+        // never more than half the row
+        assert_eq!(wrap_line("      aa bb", settings), [WrapRow::START, row(6, 6, 4), row(9, 9, 4)]);
+    }
+
+    #[test]
+    fn tabs_fill_to_the_next_stop() {
+        let settings = WrapSettings { preserve_indent: true, ..settings(10) };
+        assert_eq!(wrap_line("\tfoo bar baz", settings), [WrapRow::START, row(5, 8, 4), row(9, 12, 4)]);
+        assert_eq!(wrap_line("ab\tcd\tef", WrapSettings { tab_width: 8, ..settings }), [WrapRow::START, row(3, 8, 0)]);
+    }
+}
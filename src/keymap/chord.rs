@@ -0,0 +1,177 @@
+use std::fmt;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// A single key press together with its modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+const KEY_NAMES: &[(KeyCode, &str)] = &[
+    (KeyCode::Char(' '), "space"),
+    (KeyCode::Tab, "tab"),
+    (KeyCode::BackTab, "backtab"),
+    (KeyCode::Enter, "enter"),
+    (KeyCode::Esc, "esc"),
+    (KeyCode::Backspace, "backspace"),
+    (KeyCode::Delete, "delete"),
+    (KeyCode::Insert, "insert"),
+    (KeyCode::Left, "left"),
+    (KeyCode::Right, "right"),
+    (KeyCode::Up, "up"),
+    (KeyCode::Down, "down"),
+    (KeyCode::Home, "home"),
+    (KeyCode::End, "end"),
+    (KeyCode::PageUp, "pageup"),
+    (KeyCode::PageDown, "pagedown"),
+];
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        KeyChord { code, modifiers }.normalise()
+    }
+
+    pub fn from_event(event: &KeyEvent) -> Self {
+        KeyChord::new(event.code, event.modifiers)
+    }
+
+    /// Shift is folded into the key for characters and back tab, as
+    /// terminals already report those shifted.
+    fn normalise(mut self) -> Self {
+        match self.code {
+            KeyCode::Char(c) if self.modifiers.contains(KeyModifiers::SHIFT) => {
+                self.code = KeyCode::Char(c.to_ascii_uppercase());
+                self.modifiers.remove(KeyModifiers::SHIFT);
+            }
+            KeyCode::BackTab => self.modifiers.remove(KeyModifiers::SHIFT),
+            _ => ()
+        }
+
+        self
+    }
+
+    /// Parses chords like `q`, `ctrl-k`, `alt-left`, `shift-tab` or `f5`.
+    pub fn parse(text: &str) -> Result<KeyChord, String> {
+        let (modifier_text, key) = match text.rfind('-') {
+            // a trailing `-` is the key itself, as in `ctrl--`
+            Some(index) if index + 1 == text.len() && index > 0 => (&text[..index - 1], "-"),
+            Some(index) if index + 1 < text.len() => (&text[..index], &text[index + 1..]),
+            _ => ("", text),
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+
+        for modifier in modifier_text.split('-').filter(|modifier| !modifier.is_empty()) {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "c" => KeyModifiers::CONTROL,
+                "alt" | "a" | "m" => KeyModifiers::ALT,
+                "shift" | "s" => KeyModifiers::SHIFT,
+                _ => return Err(format!("Unknown modifier '{}' in '{}'", modifier, text)),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => KeyChord::parse_key_name(&key.to_ascii_lowercase())
+                .ok_or_else(|| format!("Unknown key '{}' in '{}'", key, text))?,
+        };
+
+        if code == KeyCode::Tab && modifiers.contains(KeyModifiers::SHIFT) {
+            return Ok(KeyChord::new(KeyCode::BackTab, modifiers));
+        }
+
+        Ok(KeyChord::new(code, modifiers))
+    }
+
+    /// Parses a space separated sequence of chords, like `ctrl-k ctrl-s`.
+    pub fn parse_sequence(text: &str) -> Result<Vec<KeyChord>, String> {
+        let sequence = text.split_whitespace()
+            .map(KeyChord::parse)
+            .collect::<Result<Vec<KeyChord>, String>>()?;
+
+        if sequence.is_empty() {
+            return Err("Empty key sequence".to_string());
+        }
+
+        Ok(sequence)
+    }
+
+    fn parse_key_name(name: &str) -> Option<KeyCode> {
+        if let Some(number) = name.strip_prefix('f') {
+            if let Ok(number) = number.parse::<u8>() {
+                return Some(KeyCode::F(number));
+            }
+        }
+
+        KEY_NAMES.iter()
+            .find(|(_, key_name)| *key_name == name)
+            .map(|(code, _)| *code)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl-")?;
+        }
+
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt-")?;
+        }
+
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift-")?;
+        }
+
+        let name = KEY_NAMES.iter()
+            .find(|(code, _)| *code == self.code)
+            .map(|(_, name)| *name);
+
+        match (name, self.code) {
+            (Some(name), _) => write!(f, "{}", name),
+            (None, KeyCode::Char(c)) => write!(f, "{}", c),
+            (None, KeyCode::F(number)) => write!(f, "f{}", number),
+            (None, code) => write!(f, "{:?}", code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modifiers_and_names() {
+        assert_eq!(KeyChord::parse("q"), Ok(KeyChord::new(KeyCode::Char('q'), KeyModifiers::NONE)));
+        assert_eq!(KeyChord::parse("ctrl-k"), Ok(KeyChord::new(KeyCode::Char('k'), KeyModifiers::CONTROL)));
+        assert_eq!(
+            KeyChord::parse("C-alt-left"),
+            Ok(KeyChord::new(KeyCode::Left, KeyModifiers::CONTROL | KeyModifiers::ALT)),
+        );
+        assert_eq!(KeyChord::parse("f5"), Ok(KeyChord::new(KeyCode::F(5), KeyModifiers::NONE)));
+        assert_eq!(KeyChord::parse("ctrl--"), Ok(KeyChord::new(KeyCode::Char('-'), KeyModifiers::CONTROL)));
+    }
+
+    #[test]
+    fn folds_shift_into_the_key() {
+        assert_eq!(KeyChord::parse("shift-a"), Ok(KeyChord::new(KeyCode::Char('A'), KeyModifiers::NONE)));
+        assert_eq!(KeyChord::parse("shift-tab"), Ok(KeyChord::new(KeyCode::BackTab, KeyModifiers::NONE)));
+    }
+
+    #[test]
+    fn rejects_unknown_parts() {
+        assert!(KeyChord::parse("hyper-a").is_err());
+        assert!(KeyChord::parse("ctrl-nokey").is_err());
+        assert!(KeyChord::parse_sequence("  ").is_err());
+    }
+
+    #[test]
+    fn displays_as_parsed() {
+        for text in ["ctrl-k", "alt-left", "space", "f12", "x"] {
+            assert_eq!(KeyChord::parse(text).unwrap().to_string(), text);
+        }
+    }
+}
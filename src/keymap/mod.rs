@@ -0,0 +1,494 @@
+use std::{collections::HashMap, fs, path::Path, time::{Duration, Instant}};
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+use crate::{command::Command, config};
+
+use self::chord::KeyChord;
+
+pub mod chord;
+
+pub const GLOBAL_CONTEXT: &str = "global";
+pub const NORMAL_CONTEXT: &str = "normal";
+pub const INSERT_CONTEXT: &str = "insert";
+pub const VISUAL_CONTEXT: &str = "visual";
+pub const PROMPT_CONTEXT: &str = "prompt";
+pub const RESULTS_CONTEXT: &str = "results";
+const KEYMAP_FILE: &str = "keymap.toml";
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// What a key resolved to once fed into the keymap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyResult {
+    Command(Command),
+    /// The keys so far are the prefix of a longer binding.
+    Pending,
+    /// The keys are not bound and should be handled as plain input.
+    Unbound(Vec<KeyChord>),
+}
+
+/// Keys typed so far towards a multi-key binding.
+#[derive(Debug, Clone, Default)]
+pub struct PendingKeys {
+    keys: Vec<KeyChord>,
+    since: Option<Instant>,
+}
+
+impl PendingKeys {
+    pub fn get_keys(&self) -> &[KeyChord] {
+        &self.keys
+    }
+
+    fn take(&mut self) -> Vec<KeyChord> {
+        self.since = None;
+        std::mem::take(&mut self.keys)
+    }
+}
+
+/// Key bindings grouped by context, such as an editor mode.
+///
+/// Contexts are looked up most specific first, so a binding in an earlier
+/// context shadows the same keys in a later one.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    contexts: HashMap<String, HashMap<Vec<KeyChord>, Option<Command>>>,
+    timeout: Duration,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Keymap {
+            contexts: HashMap::new(),
+            timeout: DEFAULT_TIMEOUT,
+        };
+
+        let bindings = [
+            (KeyCode::Char('s'), KeyModifiers::CONTROL, Command::Save),
+            (KeyCode::Tab, KeyModifiers::NONE, Command::FocusNext),
+            (KeyCode::BackTab, KeyModifiers::NONE, Command::FocusPrevious),
+            (KeyCode::Left, KeyModifiers::CONTROL, Command::FocusLeft),
+            (KeyCode::Right, KeyModifiers::CONTROL, Command::FocusRight),
+            (KeyCode::Up, KeyModifiers::CONTROL, Command::FocusUp),
+            (KeyCode::Down, KeyModifiers::CONTROL, Command::FocusDown),
+            (KeyCode::Left, KeyModifiers::ALT, Command::ShrinkWidth),
+            (KeyCode::Right, KeyModifiers::ALT, Command::GrowWidth),
+            (KeyCode::Up, KeyModifiers::ALT, Command::ShrinkHeight),
+            (KeyCode::Down, KeyModifiers::ALT, Command::GrowHeight),
+        ];
+
+        for (code, modifiers, command) in bindings {
+            keymap.bind(GLOBAL_CONTEXT, vec![KeyChord::new(code, modifiers)], command);
+        }
+
+        let sequences = [
+            (GLOBAL_CONTEXT, "ctrl-w v", Command::SplitRight),
+            (GLOBAL_CONTEXT, "ctrl-w s", Command::SplitDown),
+            (GLOBAL_CONTEXT, "ctrl-k ctrl-t", Command::NextTheme),
+            (GLOBAL_CONTEXT, "ctrl-k ctrl-n", Command::CycleLineNumbers),
+            (GLOBAL_CONTEXT, "ctrl-k ctrl-w", Command::ToggleSoftWrap),
+            (GLOBAL_CONTEXT, "ctrl-k ctrl-b", Command::ToggleWhitespace),
+            (GLOBAL_CONTEXT, "ctrl-k s", Command::ConvertIndentToSpaces),
+            (GLOBAL_CONTEXT, "ctrl-k t", Command::ConvertIndentToTabs),
+            (GLOBAL_CONTEXT, "ctrl-k w", Command::SetTabWidth),
+            (GLOBAL_CONTEXT, "ctrl-k i", Command::ToggleUseTabs),
+            (NORMAL_CONTEXT, "q", Command::Quit),
+            (NORMAL_CONTEXT, "Q", Command::ForceQuit),
+            (NORMAL_CONTEXT, "r", Command::Redraw),
+            (NORMAL_CONTEXT, "i", Command::InsertMode),
+            (NORMAL_CONTEXT, "a", Command::Append),
+            (NORMAL_CONTEXT, "I", Command::InsertLineStart),
+            (NORMAL_CONTEXT, "A", Command::AppendLineEnd),
+            (NORMAL_CONTEXT, "o", Command::OpenLineBelow),
+            (NORMAL_CONTEXT, "O", Command::OpenLineAbove),
+            (NORMAL_CONTEXT, "x", Command::DeleteForward),
+            (NORMAL_CONTEXT, "esc", Command::NormalMode),
+            (NORMAL_CONTEXT, "v", Command::VisualMode),
+            (NORMAL_CONTEXT, "V", Command::SelectLine),
+            (NORMAL_CONTEXT, "%", Command::SelectAll),
+            (NORMAL_CONTEXT, "y y", Command::CopyLine),
+            (NORMAL_CONTEXT, "d d", Command::CutLine),
+            (NORMAL_CONTEXT, "p", Command::PasteAfter),
+            (NORMAL_CONTEXT, "P", Command::PasteBefore),
+            (NORMAL_CONTEXT, "alt-p", Command::CyclePaste),
+            (NORMAL_CONTEXT, "u", Command::Undo),
+            (NORMAL_CONTEXT, "ctrl-r", Command::Redo),
+            (NORMAL_CONTEXT, "g -", Command::Earlier),
+            (NORMAL_CONTEXT, "g +", Command::Later),
+            (NORMAL_CONTEXT, "alt-u", Command::EarlierMinutes),
+            (NORMAL_CONTEXT, "alt-r", Command::LaterMinutes),
+            (NORMAL_CONTEXT, "C", Command::AddCursorBelow),
+            (NORMAL_CONTEXT, "alt-C", Command::AddCursorAbove),
+            (NORMAL_CONTEXT, "ctrl-n", Command::AddNextMatch),
+            (NORMAL_CONTEXT, "s", Command::SelectMatches),
+            (NORMAL_CONTEXT, "g /", Command::WorkspaceSearch),
+            (NORMAL_CONTEXT, "m m", Command::ToggleBookmark),
+            (VISUAL_CONTEXT, "esc", Command::NormalMode),
+            (VISUAL_CONTEXT, "v", Command::VisualMode),
+            (VISUAL_CONTEXT, "V", Command::SelectLine),
+            (VISUAL_CONTEXT, "i w", Command::SelectWord),
+            (VISUAL_CONTEXT, "%", Command::SelectAll),
+            (VISUAL_CONTEXT, "o", Command::FlipSelection),
+            (VISUAL_CONTEXT, "y", Command::Copy),
+            (VISUAL_CONTEXT, "d", Command::Cut),
+            (VISUAL_CONTEXT, "x", Command::Cut),
+            (VISUAL_CONTEXT, "p", Command::PasteAfter),
+            (VISUAL_CONTEXT, "P", Command::PasteBefore),
+            (VISUAL_CONTEXT, "C", Command::AddCursorBelow),
+            (VISUAL_CONTEXT, "alt-C", Command::AddCursorAbove),
+            (VISUAL_CONTEXT, "ctrl-n", Command::AddNextMatch),
+            (VISUAL_CONTEXT, "alt-s", Command::SplitSelectionLines),
+            (VISUAL_CONTEXT, "s", Command::SelectMatches),
+            (VISUAL_CONTEXT, "z f", Command::CreateFold),
+            (INSERT_CONTEXT, "esc", Command::NormalMode),
+            (INSERT_CONTEXT, "enter", Command::InsertNewline),
+            (INSERT_CONTEXT, "tab", Command::InsertTab),
+            (INSERT_CONTEXT, "backspace", Command::DeleteBackward),
+            (PROMPT_CONTEXT, "esc", Command::PromptCancel),
+            (PROMPT_CONTEXT, "enter", Command::PromptAccept),
+            (PROMPT_CONTEXT, "backspace", Command::DeleteBackward),
+            (PROMPT_CONTEXT, "delete", Command::DeleteForward),
+            (PROMPT_CONTEXT, "left", Command::MoveLeft),
+            (PROMPT_CONTEXT, "right", Command::MoveRight),
+            (PROMPT_CONTEXT, "home", Command::LineStart),
+            (PROMPT_CONTEXT, "end", Command::LineEnd),
+            (PROMPT_CONTEXT, "alt-r", Command::ToggleSearchRegex),
+            (PROMPT_CONTEXT, "alt-w", Command::ToggleSearchWholeWord),
+            (PROMPT_CONTEXT, "alt-c", Command::ToggleSearchCase),
+            (PROMPT_CONTEXT, "alt-p", Command::TogglePreserveCase),
+            (RESULTS_CONTEXT, "k", Command::MoveUp),
+            (RESULTS_CONTEXT, "j", Command::MoveDown),
+            (RESULTS_CONTEXT, "up", Command::MoveUp),
+            (RESULTS_CONTEXT, "down", Command::MoveDown),
+            (RESULTS_CONTEXT, "pageup", Command::PageUp),
+            (RESULTS_CONTEXT, "pagedown", Command::PageDown),
+            (RESULTS_CONTEXT, "ctrl-u", Command::HalfPageUp),
+            (RESULTS_CONTEXT, "ctrl-d", Command::HalfPageDown),
+            (RESULTS_CONTEXT, "g g", Command::GotoFirstLine),
+            (RESULTS_CONTEXT, "G", Command::GotoLastLine),
+            (RESULTS_CONTEXT, "enter", Command::OpenResult),
+            (RESULTS_CONTEXT, "/", Command::WorkspaceSearch),
+            (RESULTS_CONTEXT, "R", Command::WorkspaceReplace),
+            (RESULTS_CONTEXT, "q", Command::CloseResults),
+            (RESULTS_CONTEXT, "esc", Command::CloseResults),
+        ];
+
+        for (context, keys, command) in sequences {
+            let keys = KeyChord::parse_sequence(keys).expect("Default bindings parse");
+            keymap.bind(context, keys, command);
+        }
+
+        let motions = [
+            ("h", Command::MoveLeft),
+            ("l", Command::MoveRight),
+            ("k", Command::MoveUp),
+            ("j", Command::MoveDown),
+            ("w", Command::WordForward),
+            ("b", Command::WordBackward),
+            ("e", Command::WordEnd),
+            ("W", Command::BigWordForward),
+            ("B", Command::BigWordBackward),
+            ("E", Command::BigWordEnd),
+            ("0", Command::LineStart),
+            ("^", Command::FirstNonBlank),
+            ("$", Command::LineEnd),
+            ("}", Command::ParagraphForward),
+            ("{", Command::ParagraphBackward),
+            ("ctrl-b", Command::PageUp),
+            ("ctrl-f", Command::PageDown),
+            ("g g", Command::GotoFirstLine),
+            ("G", Command::GotoLastLine),
+            ("g k", Command::DisplayLineUp),
+            ("g j", Command::DisplayLineDown),
+            ("g 0", Command::DisplayLineStart),
+            ("g $", Command::DisplayLineEnd),
+            ("g m", Command::GotoMatchingBracket),
+            ("ctrl-u", Command::HalfPageUp),
+            ("ctrl-d", Command::HalfPageDown),
+            ("ctrl-y", Command::ScrollUp),
+            ("ctrl-e", Command::ScrollDown),
+            ("z t", Command::ScrollCursorTop),
+            ("z z", Command::ScrollCursorCenter),
+            ("z b", Command::ScrollCursorBottom),
+            ("z o", Command::FoldOpen),
+            ("z c", Command::FoldClose),
+            ("z a", Command::FoldToggle),
+            ("z O", Command::FoldOpenRecursive),
+            ("z C", Command::FoldCloseRecursive),
+            ("z A", Command::FoldToggleRecursive),
+            ("z R", Command::OpenAllFolds),
+            ("z M", Command::CloseAllFolds),
+            ("z d", Command::DeleteFold),
+            ("z E", Command::DeleteAllFolds),
+            ("/", Command::Search),
+            ("?", Command::SearchBackward),
+            ("n", Command::SearchNext),
+            ("N", Command::SearchPrevious),
+            ("R", Command::Replace),
+            ("alt-R", Command::ReplaceConfirm),
+        ];
+
+        for (keys, command) in motions {
+            let keys = KeyChord::parse_sequence(keys).expect("Default bindings parse");
+            keymap.bind(NORMAL_CONTEXT, keys.clone(), command);
+            keymap.bind(VISUAL_CONTEXT, keys, command);
+        }
+
+        // keys that move the cursor without typing work in every mode
+        let shared = [
+            ("left", Command::MoveLeft),
+            ("right", Command::MoveRight),
+            ("up", Command::MoveUp),
+            ("down", Command::MoveDown),
+            ("home", Command::LineStart),
+            ("end", Command::LineEnd),
+            ("pageup", Command::PageUp),
+            ("pagedown", Command::PageDown),
+            ("delete", Command::DeleteForward),
+        ];
+
+        for (keys, command) in shared {
+            let keys = KeyChord::parse_sequence(keys).expect("Default bindings parse");
+            keymap.bind(NORMAL_CONTEXT, keys.clone(), command);
+            keymap.bind(VISUAL_CONTEXT, keys.clone(), command);
+            keymap.bind(INSERT_CONTEXT, keys, command);
+        }
+
+        keymap
+    }
+}
+
+impl Keymap {
+    /// The default keymap with the user's keymap file applied over it.
+    pub fn load() -> Result<Keymap, String> {
+        let mut keymap = Keymap::default();
+
+        if let Some(path) = config::config_dir().map(|dir| dir.join(KEYMAP_FILE)) {
+            if path.exists() {
+                keymap.load_file(&path)?;
+            }
+        }
+
+        Ok(keymap)
+    }
+
+    /// Applies bindings from a TOML file with a table per context:
+    ///
+    /// ```toml
+    /// timeout = 500
+    ///
+    /// [global]
+    /// "ctrl-k ctrl-s" = "save"
+    /// "q" = "unbind"
+    /// ```
+    pub fn load_file(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+
+        self.load_str(&text)
+            .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn load_str(&mut self, text: &str) -> Result<(), String> {
+        let table: toml::Table = text.parse()
+            .map_err(|err: toml::de::Error| err.message().to_string())?;
+
+        for (key, value) in table.iter() {
+            match (key.as_str(), value) {
+                ("timeout", toml::Value::Integer(timeout)) => {
+                    self.timeout = Duration::from_millis((*timeout).max(0) as u64);
+                }
+                (context, toml::Value::Table(bindings)) => {
+                    for (sequence, command) in bindings.iter() {
+                        let keys = KeyChord::parse_sequence(sequence)?;
+
+                        let command = match command.as_str() {
+                            Some("unbind") => None,
+                            Some(name) => Some(Command::from_name(name)
+                                .ok_or_else(|| format!("Unknown command '{}'", name))?),
+                            None => return Err(format!("Command for '{}' is not a string", sequence)),
+                        };
+
+                        self.contexts.entry(context.to_string())
+                            .or_default()
+                            .insert(keys, command);
+                    }
+                }
+                (key, _) => return Err(format!("Unexpected entry '{}'", key)),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn bind(&mut self, context: &str, keys: Vec<KeyChord>, command: Command) {
+        self.contexts.entry(context.to_string())
+            .or_default()
+            .insert(keys, Some(command));
+    }
+
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Feeds a key towards a binding in the first of `contexts` that has
+    /// one. A sequence that is both bound and the prefix of a longer binding
+    /// stays pending until the next key or the timeout decides it.
+    pub fn feed(&self, pending: &mut PendingKeys, contexts: &[&str], chord: KeyChord) -> KeyResult {
+        pending.keys.push(chord);
+        pending.since = Some(Instant::now());
+
+        let (exact, longer) = self.lookup(contexts, &pending.keys);
+
+        if longer {
+            return KeyResult::Pending;
+        }
+
+        if let Some(command) = exact {
+            pending.take();
+            return KeyResult::Command(command);
+        }
+
+        // a sequence that goes nowhere is handed back whole as input
+        KeyResult::Unbound(pending.take())
+    }
+
+    /// Resolves pending keys that have waited longer than the timeout.
+    pub fn check_timeout(&self, pending: &mut PendingKeys, contexts: &[&str]) -> Option<KeyResult> {
+        let since = pending.since?;
+
+        if since.elapsed() < self.timeout {
+            return None;
+        }
+
+        let (exact, _) = self.lookup(contexts, &pending.keys);
+        let keys = pending.take();
+
+        Some(match exact {
+            Some(command) => KeyResult::Command(command),
+            None => KeyResult::Unbound(keys),
+        })
+    }
+
+    /// The command bound to exactly `keys`, and whether any longer binding
+    /// starts with them.
+    fn lookup(&self, contexts: &[&str], keys: &[KeyChord]) -> (Option<Command>, bool) {
+        let mut exact = None;
+        let mut longer = false;
+
+        for context in contexts.iter().rev() {
+            let Some(bindings) = self.contexts.get(*context) else {
+                continue;
+            };
+
+            if let Some(command) = bindings.get(keys) {
+                exact = *command;
+            }
+
+            longer |= bindings.iter().any(|(binding, command)| {
+                command.is_some() && binding.len() > keys.len() && binding.starts_with(keys)
+            });
+        }
+
+        (exact, longer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(bindings: &[(&str, &str, Command)]) -> Keymap {
+        let mut keymap = Keymap { contexts: HashMap::new(), timeout: Duration::ZERO };
+
+        for (context, keys, command) in bindings {
+            keymap.bind(context, KeyChord::parse_sequence(keys).unwrap(), *command);
+        }
+
+        keymap
+    }
+
+    fn chord(text: &str) -> KeyChord {
+        KeyChord::parse(text).unwrap()
+    }
+
+    #[test]
+    fn feeds_chords_and_sequences() {
+        let keymap = keymap(&[
+            (GLOBAL_CONTEXT, "ctrl-s", Command::Save),
+            (GLOBAL_CONTEXT, "ctrl-k ctrl-w", Command::ToggleSoftWrap),
+        ]);
+        let mut pending = PendingKeys::default();
+
+        assert_eq!(keymap.feed(&mut pending, &[GLOBAL_CONTEXT], chord("ctrl-s")), KeyResult::Command(Command::Save));
+        assert_eq!(keymap.feed(&mut pending, &[GLOBAL_CONTEXT], chord("ctrl-k")), KeyResult::Pending);
+        assert_eq!(pending.get_keys(), &[chord("ctrl-k")]);
+        assert_eq!(
+            keymap.feed(&mut pending, &[GLOBAL_CONTEXT], chord("ctrl-w")),
+            KeyResult::Command(Command::ToggleSoftWrap),
+        );
+        assert!(pending.get_keys().is_empty());
+    }
+
+    #[test]
+    fn hands_back_sequences_that_go_nowhere() {
+        let keymap = keymap(&[(GLOBAL_CONTEXT, "ctrl-k ctrl-w", Command::ToggleSoftWrap)]);
+        let mut pending = PendingKeys::default();
+
+        keymap.feed(&mut pending, &[GLOBAL_CONTEXT], chord("ctrl-k"));
+
+        assert_eq!(
+            keymap.feed(&mut pending, &[GLOBAL_CONTEXT], chord("x")),
+            KeyResult::Unbound(vec![chord("ctrl-k"), chord("x")]),
+        );
+    }
+
+    #[test]
+    fn earlier_contexts_shadow_later_ones() {
+        let keymap = keymap(&[
+            (GLOBAL_CONTEXT, "q", Command::Quit),
+            (INSERT_CONTEXT, "q", Command::Redraw),
+        ]);
+        let mut pending = PendingKeys::default();
+
+        assert_eq!(
+            keymap.feed(&mut pending, &[INSERT_CONTEXT, GLOBAL_CONTEXT], chord("q")),
+            KeyResult::Command(Command::Redraw),
+        );
+        assert_eq!(keymap.feed(&mut pending, &[GLOBAL_CONTEXT], chord("q")), KeyResult::Command(Command::Quit));
+    }
+
+    #[test]
+    fn timeout_settles_a_prefix() {
+        let keymap = keymap(&[
+            (NORMAL_CONTEXT, "g", Command::Redraw),
+            (NORMAL_CONTEXT, "g g", Command::GotoFirstLine),
+        ]);
+        let mut pending = PendingKeys::default();
+
+        assert_eq!(keymap.check_timeout(&mut pending, &[NORMAL_CONTEXT]), None);
+        assert_eq!(keymap.feed(&mut pending, &[NORMAL_CONTEXT], chord("g")), KeyResult::Pending);
+        assert_eq!(keymap.check_timeout(&mut pending, &[NORMAL_CONTEXT]), Some(KeyResult::Command(Command::Redraw)));
+        assert!(pending.get_keys().is_empty());
+
+        keymap.feed(&mut pending, &[NORMAL_CONTEXT], chord("g"));
+        assert_eq!(
+            keymap.feed(&mut pending, &[NORMAL_CONTEXT], chord("g")),
+            KeyResult::Command(Command::GotoFirstLine),
+        );
+    }
+
+    #[test]
+    fn unbinding_in_a_file() {
+        let mut keymap = keymap(&[(GLOBAL_CONTEXT, "q", Command::Quit)]);
+        keymap.load_str("timeout = 250\n[global]\nq = \"unbind\"\n\"ctrl-k ctrl-s\" = \"save\"\n").unwrap();
+        let mut pending = PendingKeys::default();
+
+        assert_eq!(keymap.get_timeout(), Duration::from_millis(250));
+        assert_eq!(keymap.feed(&mut pending, &[GLOBAL_CONTEXT], chord("q")), KeyResult::Unbound(vec![chord("q")]));
+        assert!(keymap.load_str("[global]\nq = \"no_such_command\"\n").is_err());
+        assert_eq!(
+            keymap.load_str("[global]\n\"ctrl-k x\" = 1\n"),
+            Err("Command for 'ctrl-k x' is not a string".to_string()),
+        );
+    }
+}
@@ -0,0 +1,14 @@
+pub mod app;
+pub mod tui;
+pub mod renderer;
+pub mod command;
+pub mod keymap;
+pub mod config;
+pub mod buffer;
+pub mod editor;
+pub mod document;
+pub mod args;
+pub mod clipboard;
+pub mod workspace_search;
+pub mod syntax;
+pub mod theme;
@@ -1,9 +1,12 @@
-use app::App;
+use std::{env, process};
 
-mod app;
-mod tui;
-mod renderer;
+use text_editor::{app::App, args};
 
 fn main() {
-    App::new().start();
+    let files = args::parse_args(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(2);
+    });
+
+    App::new(files).start();
 }
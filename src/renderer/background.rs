@@ -0,0 +1,157 @@
+use std::{env, time::Duration};
+
+/// Whether the terminal shows light text on a dark background, or the
+/// other way around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    Light,
+    Dark,
+}
+
+impl Background {
+    /// Asks the terminal for its background colour with OSC 11, falling
+    /// back to `COLORFGBG`. Must run in raw mode, before anything else
+    /// reads input, as the reply comes in on the terminal's input.
+    pub fn detect(timeout: Duration) -> Option<Background> {
+        query_osc_11(timeout)
+            .as_deref()
+            .and_then(parse_osc_11)
+            .or_else(from_colorfgbg)
+    }
+
+    /// Light for a colour brighter than mid grey.
+    pub fn of_rgb(r: f64, g: f64, b: f64) -> Background {
+        match 0.2126 * r + 0.7152 * g + 0.0722 * b > 0.5 {
+            true => Background::Light,
+            false => Background::Dark,
+        }
+    }
+}
+
+/// The terminal's reply to OSC 11, if it gives one in time.
+///
+/// The query is followed by one for the primary device attributes, which
+/// every terminal answers, so a terminal that ignores OSC 11 is not
+/// waited on, and its answer is read up so it does not show up as keys.
+#[cfg(unix)]
+fn query_osc_11(timeout: Duration) -> Option<String> {
+    use std::{fs::OpenOptions, io::{Read, Write}, os::unix::io::AsRawFd, time::Instant};
+
+    let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty").ok()?;
+    tty.write_all(b"\x1b]11;?\x1b\\\x1b[c").ok()?;
+    tty.flush().ok()?;
+
+    let deadline = Instant::now() + timeout;
+    let mut reply = Vec::new();
+
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+
+        if left.is_zero() {
+            break;
+        }
+
+        let mut poll = libc::pollfd { fd: tty.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+
+        // SAFETY: the pointer is to one valid pollfd for the length of the call
+        let ready = unsafe { libc::poll(&mut poll, 1, left.as_millis().max(1) as libc::c_int) };
+
+        if ready <= 0 {
+            break;
+        }
+
+        let mut buf = [0; 256];
+
+        match tty.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(len) => reply.extend_from_slice(&buf[..len]),
+        }
+
+        // the device attributes come last
+        let text = String::from_utf8_lossy(&reply);
+
+        if let Some(start) = text.rfind("\x1b[?") {
+            if text[start..].ends_with('c') {
+                break;
+            }
+        }
+    }
+
+    Some(String::from_utf8_lossy(&reply).into_owned())
+}
+
+#[cfg(not(unix))]
+fn query_osc_11(_timeout: Duration) -> Option<String> {
+    None
+}
+
+/// Reads a reply like `ESC ] 11 ; rgb:1d1d/1f1f/2121 ESC \`.
+fn parse_osc_11(reply: &str) -> Option<Background> {
+    let start = reply.find("]11;")? + 4;
+    let rest = &reply[start..];
+    let end = rest.find(['\x1b', '\x07']).unwrap_or(rest.len());
+    let color = rest[..end].strip_prefix("rgb:").or_else(|| rest[..end].strip_prefix("rgba:"))?;
+
+    // channels have 1 to 4 hex digits, scaled to their own range
+    let mut channels = color.split('/').map(|channel| {
+        let value = u32::from_str_radix(channel, 16).ok()?;
+        let max = 16_u32.checked_pow(channel.len().clamp(1, 4) as u32)? - 1;
+        Some(value as f64 / max as f64)
+    });
+
+    let (r, g, b) = (channels.next()??, channels.next()??, channels.next()??);
+    Some(Background::of_rgb(r, g, b))
+}
+
+/// `COLORFGBG`, set by some terminals to the palette indices of the text
+/// and background, like `15;0`.
+fn from_colorfgbg() -> Option<Background> {
+    let value = env::var("COLORFGBG").ok()?;
+    let background: u8 = value.rsplit(';').next()?.parse().ok()?;
+
+    match background {
+        0..=6 | 8 => Some(Background::Dark),
+        _ => Some(Background::Light),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_four_digit_replies() {
+        assert_eq!(parse_osc_11("\x1b]11;rgb:1d1d/1f1f/2121\x1b\\"), Some(Background::Dark));
+        assert_eq!(parse_osc_11("\x1b]11;rgb:ffff/ffff/f0f0\x07"), Some(Background::Light));
+    }
+
+    #[test]
+    fn reads_two_digit_replies() {
+        assert_eq!(parse_osc_11("\x1b]11;rgb:fd/f6/e3\x1b\\\x1b[?62;22c"), Some(Background::Light));
+        assert_eq!(parse_osc_11("\x1b]11;rgba:00/2b/36/ff\x07"), Some(Background::Dark));
+    }
+
+    #[test]
+    fn ignores_other_replies() {
+        assert_eq!(parse_osc_11("\x1b[?62;22c"), None);
+        assert_eq!(parse_osc_11("\x1b]11;#ffffff\x07"), None);
+        assert_eq!(parse_osc_11("\x1b]11;rgb:ff/ff\x07"), None);
+    }
+
+    #[test]
+    fn reads_colorfgbg() {
+        // the only test to touch the variable
+        for (value, background) in [
+            ("15;0", Some(Background::Dark)),
+            ("0;15", Some(Background::Light)),
+            ("12;default;8", Some(Background::Dark)),
+            ("default;default", None),
+        ] {
+            env::set_var("COLORFGBG", value);
+            assert_eq!(from_colorfgbg(), background, "{}", value);
+        }
+
+        env::remove_var("COLORFGBG");
+        assert_eq!(from_colorfgbg(), None);
+    }
+}
@@ -0,0 +1,85 @@
+use crossterm::style::{Attribute, Attributes, Color};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellStyle {
+    pub fg: Color,
+    pub bg: Color,
+    pub attributes: Attributes,
+}
+
+impl Default for CellStyle {
+    fn default() -> Self {
+        CellStyle {
+            fg: Color::Reset,
+            bg: Color::Reset,
+            attributes: Attributes::default(),
+        }
+    }
+}
+
+impl CellStyle {
+    pub fn fg(fg: Color) -> Self {
+        CellStyle { fg, ..Default::default() }
+    }
+
+    pub fn set_fg(&mut self, fg: Color) -> &mut Self {
+        self.fg = fg;
+        self
+    }
+
+    pub fn set_bg(&mut self, bg: Color) -> &mut Self {
+        self.bg = bg;
+        self
+    }
+
+    pub fn set_attribute(&mut self, attribute: Attribute) -> &mut Self {
+        self.attributes.set(attribute);
+        self
+    }
+
+    /// `other` drawn over this style, keeping the colours it leaves unset
+    /// and adding its attributes.
+    pub fn patch(&self, other: CellStyle) -> CellStyle {
+        let mut style = *self;
+
+        if other.fg != Color::Reset {
+            style.fg = other.fg;
+        }
+
+        if other.bg != Color::Reset {
+            style.bg = other.bg;
+        }
+
+        style.attributes.extend(other.attributes);
+        style
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub c: char,
+    pub style: CellStyle,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            c: ' ',
+            style: CellStyle::default(),
+        }
+    }
+}
+
+impl Cell {
+    /// Fills the cell after a wide char, which the wide char covers.
+    pub fn continuation() -> Cell {
+        Cell {
+            c: '\0',
+            style: CellStyle::default(),
+        }
+    }
+
+    pub fn is_continuation(&self) -> bool {
+        self.c == '\0'
+    }
+}
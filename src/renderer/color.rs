@@ -0,0 +1,206 @@
+use std::env;
+
+use crossterm::style::Color;
+
+use super::terminfo::Terminfo;
+
+/// Terminals known to show 24-bit colour whatever their terminfo says.
+const TRUECOLOR_TERMS: [&str; 6] = ["xterm-kitty", "alacritty", "wezterm", "foot", "xterm-ghostty", "contour"];
+const TRUECOLOR_PROGRAMS: [&str; 3] = ["iTerm.app", "WezTerm", "vscode"];
+
+/// The 16 colours as xterm shows them by default, in palette order.
+const ANSI_COLORS: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Levels of each channel in the 6x6x6 colour cube of the 256 colours.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// How many colours the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// Works out the colour depth from `COLORTERM`, `TERM` and the
+    /// terminal's terminfo entry, falling back to 16 colours.
+    pub fn detect() -> ColorDepth {
+        let var = |name: &str| env::var(name).unwrap_or_default();
+
+        if matches!(var("COLORTERM").as_str(), "truecolor" | "24bit") {
+            return ColorDepth::TrueColor;
+        }
+
+        let term = var("TERM");
+
+        if term.is_empty() {
+            // the Windows console takes colours through its own API
+            return match cfg!(windows) {
+                true => ColorDepth::TrueColor,
+                false => ColorDepth::Ansi16,
+            };
+        }
+
+        if term == "dumb" {
+            return ColorDepth::Ansi16;
+        }
+
+        if TRUECOLOR_TERMS.contains(&term.as_str())
+            || term.ends_with("-direct")
+            || term.ends_with("-truecolor")
+            || TRUECOLOR_PROGRAMS.contains(&var("TERM_PROGRAM").as_str())
+        {
+            return ColorDepth::TrueColor;
+        }
+
+        if let Some(terminfo) = Terminfo::load(&term) {
+            if terminfo.has_flag("RGB") || terminfo.has_flag("Tc") {
+                return ColorDepth::TrueColor;
+            }
+
+            match terminfo.get_colors() {
+                Some(colors) if colors >= 1 << 24 => return ColorDepth::TrueColor,
+                Some(colors) if colors >= 256 => return ColorDepth::Ansi256,
+                Some(_) => return ColorDepth::Ansi16,
+                None => (),
+            }
+        }
+
+        match term.ends_with("256color") {
+            true => ColorDepth::Ansi256,
+            false => ColorDepth::Ansi16,
+        }
+    }
+
+    /// The colour the terminal can show that is nearest to `color`.
+    pub fn quantize(&self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::TrueColor, color) => color,
+            (ColorDepth::Ansi256, Color::Rgb { r, g, b }) => Color::AnsiValue(nearest_256((r, g, b))),
+            (ColorDepth::Ansi16, Color::Rgb { r, g, b }) => nearest_16((r, g, b)),
+            (ColorDepth::Ansi16, Color::AnsiValue(index)) => match ANSI_COLORS.get(index as usize) {
+                Some((color, _)) => *color,
+                None => nearest_16(ansi_256_rgb(index)),
+            },
+            (_, color) => color,
+        }
+    }
+}
+
+/// The 256 colour palette index nearest to a colour, from the colour cube
+/// or the grey ramp. The first 16 are left out as terminals differ on
+/// them.
+fn nearest_256(rgb: (u8, u8, u8)) -> u8 {
+    let level = |value: u8| {
+        CUBE_LEVELS.iter()
+            .enumerate()
+            .min_by_key(|(_, level)| (**level as i32 - value as i32).abs())
+            .map(|(index, _)| index as u8)
+            .expect("There are cube levels")
+    };
+
+    let (r, g, b) = (level(rgb.0), level(rgb.1), level(rgb.2));
+    let cube = 16 + 36 * r + 6 * g + b;
+
+    // greys run from 8 to 238 in steps of 10
+    let average = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let grey = 232 + ((average.saturating_sub(3)) / 10).min(23) as u8;
+
+    match distance(rgb, ansi_256_rgb(grey)) < distance(rgb, ansi_256_rgb(cube)) {
+        true => grey,
+        false => cube,
+    }
+}
+
+fn nearest_16(rgb: (u8, u8, u8)) -> Color {
+    ANSI_COLORS.iter()
+        .min_by_key(|(_, other)| distance(rgb, *other))
+        .map(|(color, _)| *color)
+        .expect("There are ANSI colours")
+}
+
+fn ansi_256_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_COLORS[index as usize].1,
+        16..=231 => {
+            let index = index - 16;
+            (CUBE_LEVELS[(index / 36) as usize], CUBE_LEVELS[(index / 6 % 6) as usize], CUBE_LEVELS[(index % 6) as usize])
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+/// How far apart two colours look, weighting the channels by how much
+/// red there is ("redmean").
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let mean = (a.0 as i32 + b.0 as i32) / 2;
+    let (dr, dg, db) = (a.0 as i32 - b.0 as i32, a.1 as i32 - b.1 as i32, a.2 as i32 - b.2 as i32);
+
+    ((((512 + mean) * dr * dr) >> 8) + 4 * dg * dg + (((767 - mean) * db * db) >> 8)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color::Rgb { r, g, b }
+    }
+
+    #[test]
+    fn picks_from_the_colour_cube_and_grey_ramp() {
+        assert_eq!(nearest_256((255, 0, 0)), 196);
+        assert_eq!(nearest_256((95, 135, 175)), 67);
+        assert_eq!(nearest_256((0, 0, 0)), 16);
+        assert_eq!(nearest_256((128, 128, 128)), 244);
+        assert_eq!(nearest_256((29, 31, 33)), 234);
+    }
+
+    #[test]
+    fn quantizes_to_256_colours() {
+        let depth = ColorDepth::Ansi256;
+
+        assert_eq!(depth.quantize(rgb(250, 5, 5)), Color::AnsiValue(196));
+        assert_eq!(depth.quantize(Color::AnsiValue(42)), Color::AnsiValue(42));
+        assert_eq!(depth.quantize(Color::DarkBlue), Color::DarkBlue);
+    }
+
+    #[test]
+    fn quantizes_to_16_colours() {
+        let depth = ColorDepth::Ansi16;
+
+        assert_eq!(depth.quantize(rgb(250, 5, 5)), Color::Red);
+        assert_eq!(depth.quantize(rgb(190, 10, 0)), Color::DarkRed);
+        assert_eq!(depth.quantize(rgb(120, 125, 130)), Color::DarkGrey);
+        assert_eq!(depth.quantize(Color::AnsiValue(9)), Color::Red);
+        assert_eq!(depth.quantize(Color::AnsiValue(196)), Color::Red);
+        assert_eq!(depth.quantize(Color::AnsiValue(244)), Color::DarkGrey);
+        assert_eq!(depth.quantize(Color::Reset), Color::Reset);
+    }
+
+    #[test]
+    fn true_colour_is_left_alone() {
+        assert_eq!(ColorDepth::TrueColor.quantize(rgb(1, 2, 3)), rgb(1, 2, 3));
+    }
+}
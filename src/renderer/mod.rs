@@ -1,19 +1,29 @@
-use std::{io::{stdout, Stdout, BufWriter, Write}, collections::HashMap};
+use std::{io::{stdout, Stdout, BufWriter, Write}, collections::HashMap, fmt};
 
 use crossterm::{
     terminal::{
-        self
+        self, Clear, ClearType, ScrollUp, ScrollDown
     },
-    cursor::MoveTo, 
-    style::Print,
-    queue, 
+    cursor::{MoveTo, Show, Hide, SetCursorShape, CursorShape}, 
+    style::{Color, Print, SetForegroundColor, SetBackgroundColor, SetAttribute, SetAttributes, Attribute},
+    queue, Command,
 };
 
+use self::{cell::{Cell, CellStyle}, color::ColorDepth};
+
+pub mod background;
+pub mod cell;
+pub mod color;
+mod terminfo;
+
 struct BoxChar {
     pub c: char,
     pub i: usize
 }
 
+/// Cells that scrolling must save before it is worth the escape sequences.
+const SCROLL_COST: usize = 8;
+
 const BOX_LEFT: usize = 1;
 const BOX_BOTTOM: usize = 2;
 const BOX_RIGHT: usize = 4;
@@ -65,7 +75,7 @@ impl PartialEq for CharCouple {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Rect {
     pub x: u16,
     pub y: u16,
@@ -73,52 +83,335 @@ pub struct Rect {
     pub height: u16,
 }
 
+impl Rect {
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.x && x < self.x + self.width &&
+        y >= self.y && y < self.y + self.height
+    }
+
+    /// The part of the rect that is also inside `other`, empty when they
+    /// do not overlap.
+    pub fn intersection(&self, other: Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+
+        Rect {
+            x,
+            y,
+            width: right.saturating_sub(x),
+            height: bottom.saturating_sub(y),
+        }
+    }
+}
+
+/// Rows whose content moved by `lines` since the last refresh, positive
+/// when it moved up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ScrollHint {
+    top: u16,
+    bottom: u16,
+    lines: i32,
+}
+
+/// Sets the rows that SU and SD scroll (DECSTBM). Resetting it moves the
+/// cursor home.
+struct SetScrollRegion(Option<(u16, u16)>);
+
+impl Command for SetScrollRegion {
+    fn write_ansi(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        match self.0 {
+            Some((top, bottom)) => write!(f, "\x1b[{};{}r", top + 1, bottom + 1),
+            None => write!(f, "\x1b[r"),
+        }
+    }
+
+    #[cfg(windows)]
+    fn execute_winapi(&self) -> crossterm::Result<()> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Scroll regions need ANSI support"))
+    }
+}
+
+/// Gives the cursor back the shape the terminal had it in (DECSCUSR 0).
+pub struct ResetCursorShape;
+
+impl Command for ResetCursorShape {
+    fn write_ansi(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        f.write_str("\x1b[0 q")
+    }
+
+    #[cfg(windows)]
+    fn execute_winapi(&self) -> crossterm::Result<()> {
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct Renderer {
     box_chars: HashMap<(u16, u16), usize>,
-    offscreen_buf: Vec<Vec<char>>,
+    offscreen_buf: Vec<Vec<Cell>>,
+    /// What the terminal shows, or empty when unknown.
+    onscreen_buf: Vec<Vec<Cell>>,
+    scroll_hints: Vec<ScrollHint>,
     stdout_buf: BufWriter<Stdout>,
+    cursor: Option<(u16, u16, CursorShape)>,
+    /// Drawing is limited to this part of the screen.
+    clip: Rect,
+    /// How far up and left of where they are given things are drawn, for
+    /// content scrolled within a node.
+    offset: (u16, u16),
+    /// Colours of cells that leave them unset.
+    base_style: CellStyle,
+    /// Colours are brought down to what the terminal can show as cells are
+    /// drawn.
+    color_depth: ColorDepth,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer::new()
+    }
 }
 
 impl Renderer {
     pub fn new() -> Self {
         Renderer {
             offscreen_buf: Vec::new(),
+            onscreen_buf: Vec::new(),
+            scroll_hints: Vec::new(),
             stdout_buf: BufWriter::with_capacity(u16::MAX as usize, stdout()),
             box_chars: HashMap::new(),
+            cursor: None,
+            clip: Rect::default(),
+            offset: (0, 0),
+            base_style: CellStyle::default(),
+            color_depth: ColorDepth::detect(),
         }
     }
 
+    pub fn get_color_depth(&self) -> ColorDepth {
+        self.color_depth
+    }
+
+    pub fn set_color_depth(&mut self, color_depth: ColorDepth) -> &mut Self {
+        self.color_depth = color_depth;
+        self.set_base_style(self.base_style)
+    }
+
+    /// Sets the colours that cells drawn without their own take, and that
+    /// the screen is cleared to.
+    pub fn set_base_style(&mut self, style: CellStyle) -> &mut Self {
+        self.base_style = CellStyle {
+            fg: self.color_depth.quantize(style.fg),
+            bg: self.color_depth.quantize(style.bg),
+            attributes: Default::default(),
+        };
+
+        self
+    }
+
     pub fn get_stdout_buf(&mut self) -> &mut BufWriter<Stdout> {
         &mut self.stdout_buf
     }
 
+    /// Forgets what the terminal shows, so the next refresh repaints it all.
+    pub fn invalidate(&mut self) {
+        self.onscreen_buf.clear();
+    }
+
+    /// Hints that rows `top..=bottom` show the same content as at the last
+    /// refresh, moved up by `lines` (down when negative). The refresh may
+    /// then scroll them on the terminal rather than print them again.
+    pub fn hint_scroll(&mut self, top: u16, bottom: u16, lines: i32) {
+        if lines != 0 && top < bottom {
+            self.scroll_hints.push(ScrollHint { top, bottom, lines });
+        }
+    }
+
+    /// Prints the cells that changed since the last refresh.
     pub fn refresh(&mut self) {
-        for line in 0..self.offscreen_buf.len() {
-            queue!(self.stdout_buf, MoveTo(0, line as u16))
-                .expect("Could not move cursor");
+        queue!(self.stdout_buf, Hide, SetAttribute(Attribute::Reset))
+            .expect("Could not reset style");
+
+        let height = self.offscreen_buf.len();
+        let width = self.offscreen_buf.first().map_or(0, |line| line.len());
+        let onscreen_width = self.onscreen_buf.first().map_or(0, |line| line.len());
+
+        if self.onscreen_buf.len() != height || onscreen_width != width {
+            queue!(self.stdout_buf, Clear(ClearType::All))
+                .expect("Could not clear terminal");
+
+            self.onscreen_buf = vec![vec![Cell::default(); width]; height];
+        }
+
+        for hint in std::mem::take(&mut self.scroll_hints) {
+            self.scroll_if_cheaper(hint);
+        }
 
-            for c in self.offscreen_buf[line].iter() {
-                queue!(self.stdout_buf, Print(c))
-                    .expect("Could not move cursor");
+        let mut current_style = CellStyle::default();
+        let mut position = None;
+
+        for y in 0..height {
+            let mut x = 0;
+
+            while x < width {
+                let cell = self.offscreen_buf[y][x];
+                let cell_width = 1 + self.offscreen_buf[y][x + 1..].iter()
+                    .take_while(|cell| cell.is_continuation())
+                    .count();
+                let cells = x..x + cell_width;
+
+                // the terminal skips past a wide char's continuation cells
+                // when it prints the char
+                if cell.is_continuation() || self.offscreen_buf[y][cells.clone()] == self.onscreen_buf[y][cells] {
+                    x += cell_width;
+                    continue;
+                }
+
+                if position != Some((x, y)) {
+                    queue!(self.stdout_buf, MoveTo(x as u16, y as u16))
+                        .expect("Could not move cursor");
+                }
+
+                if cell.style != current_style {
+                    Renderer::queue_style(&mut self.stdout_buf, &current_style, &cell.style);
+                    current_style = cell.style;
+                }
+
+                queue!(self.stdout_buf, Print(cell.c))
+                    .expect("Could not print cell");
+
+                // terminals disagree on the width of some wide chars
+                position = if cell_width == 1 { Some((x + 1, y)) } else { None };
+                x += cell_width;
             }
         }
 
+        self.onscreen_buf.clone_from(&self.offscreen_buf);
+
+        queue!(self.stdout_buf, SetAttribute(Attribute::Reset))
+            .expect("Could not reset style");
+
+        if let Some((x, y, shape)) = self.cursor {
+            queue!(self.stdout_buf, MoveTo(x, y), SetCursorShape(shape), Show)
+                .expect("Could not show cursor");
+        }
+
         self.stdout_buf.flush().unwrap();
     }
 
+    /// Scrolls the hinted rows on the terminal if fewer cells are left to
+    /// print afterwards. Scroll regions span whole rows, so cells beside
+    /// the hinted area count as well.
+    fn scroll_if_cheaper(&mut self, hint: ScrollHint) {
+        let top = hint.top as usize;
+        let bottom = (hint.bottom as usize).min(self.offscreen_buf.len().saturating_sub(1));
+        let lines = hint.lines.unsigned_abs() as usize;
+
+        if top >= bottom || lines > bottom - top {
+            return;
+        }
+
+        let mut scrolled = self.onscreen_buf[top..=bottom].to_vec();
+        let blank = vec![Cell::default(); scrolled[0].len()];
+
+        if hint.lines > 0 {
+            scrolled.rotate_left(lines);
+            scrolled[bottom - top + 1 - lines..].fill(blank);
+        } else {
+            scrolled.rotate_right(lines);
+            scrolled[..lines].fill(blank);
+        }
+
+        let changed = |rows: &[Vec<Cell>]| -> usize {
+            rows.iter()
+                .zip(&self.offscreen_buf[top..=bottom])
+                .map(|(old, new)| old.iter().zip(new).filter(|(old, new)| old != new).count())
+                .sum()
+        };
+
+        // the escape sequences cost about as much as a few cells
+        if changed(&scrolled) + SCROLL_COST >= changed(&self.onscreen_buf[top..=bottom]) {
+            return;
+        }
+
+        queue!(self.stdout_buf, SetScrollRegion(Some((top as u16, bottom as u16))))
+            .expect("Could not set scroll region");
+
+        if hint.lines > 0 {
+            queue!(self.stdout_buf, ScrollUp(lines as u16))
+        } else {
+            queue!(self.stdout_buf, ScrollDown(lines as u16))
+        }.expect("Could not scroll");
+
+        queue!(self.stdout_buf, SetScrollRegion(None))
+            .expect("Could not reset scroll region");
+
+        self.onscreen_buf.splice(top..=bottom, scrolled);
+    }
+
+    fn queue_style(stdout_buf: &mut BufWriter<Stdout>, from: &CellStyle, to: &CellStyle) {
+        if from.attributes != to.attributes {
+            queue!(stdout_buf, SetAttribute(Attribute::Reset), SetAttributes(to.attributes))
+                .expect("Could not set attributes");
+
+            // resetting attributes also resets colours
+            queue!(stdout_buf, SetForegroundColor(to.fg), SetBackgroundColor(to.bg))
+                .expect("Could not set colours");
+
+            return;
+        }
+
+        if from.fg != to.fg {
+            queue!(stdout_buf, SetForegroundColor(to.fg))
+                .expect("Could not set foreground colour");
+        }
+
+        if from.bg != to.bg {
+            queue!(stdout_buf, SetBackgroundColor(to.bg))
+                .expect("Could not set background colour");
+        }
+    }
+
+    /// Where to show the terminal's cursor after the next refresh. It is
+    /// hidden unless set again after each clear.
+    pub fn set_cursor(&mut self, x: u16, y: u16, shape: CursorShape) {
+        if let Some((x, y)) = self.onscreen_position(x, y) {
+            self.cursor = Some((x, y, shape));
+        }
+    }
+
+    /// Limits drawing to `clip` and moves everything drawn after up and
+    /// left by `offset`, until the next clear.
+    pub fn set_clip(&mut self, clip: Rect, offset: (u16, u16)) {
+        self.clip = clip;
+        self.offset = offset;
+    }
+
+    fn onscreen_position(&self, x: u16, y: u16) -> Option<(u16, u16)> {
+        let x = x.checked_sub(self.offset.0)?;
+        let y = y.checked_sub(self.offset.1)?;
+
+        self.clip.contains(x, y).then_some((x, y))
+    }
+
     pub fn clear (&mut self) {
         self.box_chars.clear();
+        self.cursor = None;
 
         let boundaries = self.boundaries();
+        self.set_clip(boundaries, (0, 0));
         let width = boundaries.width;
         let height = boundaries.height;
 
         self.offscreen_buf.resize(height as usize, Vec::new());
 
+        let blank = Cell { c: ' ', style: self.base_style };
+
         for line in self.offscreen_buf.iter_mut() {
-            line.resize(width as usize, ' ');
-            line.fill(' ');
+            line.resize(width as usize, blank);
+            line.fill(blank);
         }
     }
 
@@ -135,33 +428,53 @@ impl Renderer {
     }
 
     pub fn draw_char_at (&mut self, x: u16, y: u16, c: char) {
+        self.draw_cell_at(x, y, Cell { c, style: CellStyle::default() });
+    }
+
+    pub fn draw_cell_at (&mut self, x: u16, y: u16, cell: Cell) {
+        if let Some((x, y)) = self.onscreen_position(x, y) {
+            self.set_cell(x, y, cell);
+        }
+    }
+
+    fn set_cell(&mut self, x: u16, y: u16, mut cell: Cell) {
+        cell.style.fg = match cell.style.fg {
+            Color::Reset => self.base_style.fg,
+            fg => self.color_depth.quantize(fg),
+        };
+
+        cell.style.bg = match cell.style.bg {
+            Color::Reset => self.base_style.bg,
+            bg => self.color_depth.quantize(bg),
+        };
+
         let x = x as usize;
         let y = y as usize;
 
         let offscreen_height = self.offscreen_buf.len();
-        let offscreen_width: usize = match self.offscreen_buf.get(0) {
+        let offscreen_width: usize = match self.offscreen_buf.first() {
             None => 0,
             Some(val) => val.len()
         };
 
         if x < offscreen_width && y < offscreen_height {
-            self.offscreen_buf[y][x] = c;
+            self.offscreen_buf[y][x] = cell;
         }
     }
 
-    pub fn draw_h_line(&mut self, x_start: u16, x_end: u16, y: u16) {
+    pub fn draw_h_line(&mut self, x_start: u16, x_end: u16, y: u16, style: CellStyle) {
         for i in x_start..=x_end {
-            self.draw_box_char_at(i, y, BOX_H);
+            self.draw_box_char_at(i, y, BOX_H, style);
         }
     }
 
-    pub fn draw_v_line(&mut self, y_start: u16, y_end: u16, x: u16) {
+    pub fn draw_v_line(&mut self, y_start: u16, y_end: u16, x: u16, style: CellStyle) {
         for i in y_start..=y_end {
-            self.draw_box_char_at(x, i, BOX_V);
+            self.draw_box_char_at(x, i, BOX_V, style);
         }
     }
 
-    pub fn draw_box (&mut self, rect: Rect) {
+    pub fn draw_box (&mut self, rect: Rect, style: CellStyle) {
         let x = rect.x;
         let y = rect.y;
         let w = rect.width;
@@ -171,20 +484,24 @@ impl Renderer {
             return
         }
 
-        self.draw_v_line(y+1, y+h-2, x);
-        self.draw_v_line(y+1, y+h-2, x+w-1);
+        self.draw_v_line(y+1, y+h-2, x, style);
+        self.draw_v_line(y+1, y+h-2, x+w-1, style);
 
-        self.draw_h_line(x+1, x+w-2, y);
-        self.draw_h_line(x+1, x+w-2, y+h-1);
+        self.draw_h_line(x+1, x+w-2, y, style);
+        self.draw_h_line(x+1, x+w-2, y+h-1, style);
 
-        self.draw_box_char_at(x, y, BOX_TL);
-        self.draw_box_char_at(x+w-1, y, BOX_TR);
-        self.draw_box_char_at(x, y+h-1, BOX_BL);
-        self.draw_box_char_at(x+w-1, y+h-1, BOX_BR);
+        self.draw_box_char_at(x, y, BOX_TL, style);
+        self.draw_box_char_at(x+w-1, y, BOX_TR, style);
+        self.draw_box_char_at(x, y+h-1, BOX_BL, style);
+        self.draw_box_char_at(x+w-1, y+h-1, BOX_BR, style);
 
     }
 
-    fn draw_box_char_at(&mut self, x: u16, y: u16, box_char: BoxChar) {
+    fn draw_box_char_at(&mut self, x: u16, y: u16, box_char: BoxChar, style: CellStyle) {
+        let Some((x, y)) = self.onscreen_position(x, y) else {
+            return;
+        };
+
         let combine_layout = match self.box_chars.get(&(x, y)) {
             None => box_char.i,
             Some(val) => box_char.i | val
@@ -192,7 +509,7 @@ impl Renderer {
 
         let box_char = BOX_CHARS[combine_layout];
 
-        self.draw_char_at(x, y, box_char);
+        self.set_cell(x, y, Cell { c: box_char, style });
         self.box_chars.insert((x, y), combine_layout);
     }
 }
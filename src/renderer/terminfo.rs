@@ -0,0 +1,217 @@
+use std::{env, fs, path::PathBuf};
+
+/// Magic numbers of compiled terminfo files, with 16 and 32-bit numbers.
+const MAGIC_16: i16 = 0o432;
+const MAGIC_32: i16 = 0o1036;
+/// Index of `colors` among the standard numeric capabilities.
+const COLORS_INDEX: usize = 13;
+const DIRS: [&str; 4] = ["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo", "/usr/lib/terminfo"];
+
+/// The parts of a compiled terminfo entry about colour: the number of
+/// colours and the extended flags, like `RGB` or `Tc` for 24-bit colour.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Terminfo {
+    colors: Option<i32>,
+    flags: Vec<String>,
+}
+
+impl Terminfo {
+    /// The entry for `term` from the places ncurses looks.
+    pub fn load(term: &str) -> Option<Terminfo> {
+        let first = term.chars().next()?;
+
+        if term.contains('/') {
+            return None;
+        }
+
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        dirs.extend(env::var_os("TERMINFO").map(PathBuf::from));
+        dirs.extend(env::var_os("HOME").map(|home| PathBuf::from(home).join(".terminfo")));
+
+        if let Some(list) = env::var_os("TERMINFO_DIRS") {
+            dirs.extend(env::split_paths(&list).filter(|dir| !dir.as_os_str().is_empty()));
+        }
+
+        dirs.extend(DIRS.iter().map(PathBuf::from));
+
+        // entries go in a dir named by their first letter, or its hex code
+        dirs.iter()
+            .flat_map(|dir| [
+                dir.join(first.to_string()).join(term),
+                dir.join(format!("{:x}", first as u32)).join(term),
+            ])
+            .find_map(|path| fs::read(path).ok())
+            .and_then(|bytes| Terminfo::parse(&bytes))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Option<Terminfo> {
+        let mut reader = Reader { bytes, position: 0 };
+
+        let number_size = match reader.short()? {
+            MAGIC_16 => 2,
+            MAGIC_32 => 4,
+            _ => return None,
+        };
+
+        let names_size = reader.count()?;
+        let bool_count = reader.count()?;
+        let number_count = reader.count()?;
+        let string_count = reader.count()?;
+        let table_size = reader.count()?;
+
+        reader.skip(names_size + bool_count)?;
+        reader.align();
+
+        let numbers = reader.numbers(number_count, number_size)?;
+        let colors = numbers.get(COLORS_INDEX).copied().filter(|colors| *colors >= 0);
+
+        reader.skip(string_count * 2 + table_size)?;
+        reader.align();
+
+        let mut terminfo = Terminfo { colors, flags: Vec::new() };
+
+        // older entries have no extended capabilities
+        if reader.position >= bytes.len() {
+            return Some(terminfo);
+        }
+
+        let ext_bool_count = reader.count()?;
+        let ext_number_count = reader.count()?;
+        let ext_string_count = reader.count()?;
+        let _ext_offset_count = reader.count()?;
+        let ext_table_size = reader.count()?;
+
+        let ext_bools = reader.take(ext_bool_count)?.to_vec();
+        reader.align();
+        reader.skip(ext_number_count * number_size)?;
+
+        let name_count = ext_bool_count + ext_number_count + ext_string_count;
+        reader.skip((ext_string_count + name_count) * 2)?;
+
+        // the names come last in the table, flags first
+        let table = reader.take(ext_table_size)?;
+        let strings: Vec<&[u8]> = table.split(|byte| *byte == 0).collect();
+        let strings = &strings[..strings.len().saturating_sub(1)];
+        let names = &strings[strings.len().checked_sub(name_count)?..];
+
+        terminfo.flags = names.iter()
+            .zip(ext_bools)
+            .filter(|(_, value)| *value == 1)
+            .map(|(name, _)| String::from_utf8_lossy(name).into_owned())
+            .collect();
+
+        Some(terminfo)
+    }
+
+    pub fn get_colors(&self) -> Option<i32> {
+        self.colors
+    }
+
+    pub fn has_flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.take(len).map(|_| ())
+    }
+
+    /// Sections start on even offsets.
+    fn align(&mut self) {
+        self.position += self.position % 2;
+    }
+
+    fn short(&mut self) -> Option<i16> {
+        let bytes = self.take(2)?;
+        Some(i16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// A count or size, where -1 means none.
+    fn count(&mut self) -> Option<usize> {
+        match self.short()? {
+            -1 => Some(0),
+            count => usize::try_from(count).ok(),
+        }
+    }
+
+    fn numbers(&mut self, count: usize, size: usize) -> Option<Vec<i32>> {
+        let bytes = self.take(count.checked_mul(size)?)?;
+
+        let numbers = bytes.chunks_exact(size)
+            .map(|chunk| match chunk {
+                [a, b] => i16::from_le_bytes([*a, *b]) as i32,
+                [a, b, c, d] => i32::from_le_bytes([*a, *b, *c, *d]),
+                _ => unreachable!("Numbers are 2 or 4 bytes"),
+            })
+            .collect();
+
+        Some(numbers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // compiled by `tic -x` from
+    //
+    //     t-direct|terminal with direct colour,
+    //         am, RGB, colors#0x1000000, cols#80, bel=^G,
+    //     t-256|terminal with 256 colours,
+    //         am, colors#256, cols#80, bel=^G,
+    const DIRECT: [u8; 132] = [
+        0x1e, 0x02, 0x25, 0x00, 0x02, 0x00, 0x0e, 0x00, 0x02, 0x00, 0x02, 0x00, 0x74, 0x2d, 0x64, 0x69,
+        0x72, 0x65, 0x63, 0x74, 0x7c, 0x74, 0x65, 0x72, 0x6d, 0x69, 0x6e, 0x61, 0x6c, 0x20, 0x77, 0x69,
+        0x74, 0x68, 0x20, 0x64, 0x69, 0x72, 0x65, 0x63, 0x74, 0x20, 0x63, 0x6f, 0x6c, 0x6f, 0x75, 0x72,
+        0x00, 0x00, 0x01, 0x00, 0x50, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01, 0xff, 0xff, 0x00, 0x00,
+        0x07, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x52, 0x47, 0x42, 0x00,
+    ];
+
+    const ANSI_256: [u8; 80] = [
+        0x1a, 0x01, 0x20, 0x00, 0x02, 0x00, 0x0e, 0x00, 0x02, 0x00, 0x02, 0x00, 0x74, 0x2d, 0x32, 0x35,
+        0x36, 0x7c, 0x74, 0x65, 0x72, 0x6d, 0x69, 0x6e, 0x61, 0x6c, 0x20, 0x77, 0x69, 0x74, 0x68, 0x20,
+        0x32, 0x35, 0x36, 0x20, 0x63, 0x6f, 0x6c, 0x6f, 0x75, 0x72, 0x73, 0x00, 0x00, 0x01, 0x50, 0x00,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x01, 0xff, 0xff, 0x00, 0x00, 0x07, 0x00,
+    ];
+
+    #[test]
+    fn reads_colours_and_extended_flags() {
+        let terminfo = Terminfo::parse(&DIRECT).unwrap();
+
+        assert_eq!(terminfo.get_colors(), Some(1 << 24));
+        assert!(terminfo.has_flag("RGB"));
+        assert!(!terminfo.has_flag("Tc"));
+    }
+
+    #[test]
+    fn reads_entries_without_extended_capabilities() {
+        let terminfo = Terminfo::parse(&ANSI_256).unwrap();
+
+        assert_eq!(terminfo.get_colors(), Some(256));
+        assert!(!terminfo.has_flag("RGB"));
+    }
+
+    #[test]
+    fn rejects_broken_entries() {
+        assert_eq!(Terminfo::parse(&DIRECT[..40]), None);
+        assert_eq!(Terminfo::parse(&DIRECT[..DIRECT.len() - 6]), None);
+        assert_eq!(Terminfo::parse(b"#!/bin/sh\n"), None);
+    }
+}
@@ -0,0 +1,691 @@
+//! Grammars for highlighting, written in TOML in the style of Sublime
+//! Text's syntax files. Besides the built-in ones, a grammar can be put in
+//! the `syntaxes` dir of the config dir, where it replaces a built-in one
+//! of the same name.
+//!
+//! ```toml
+//! name = "Example"
+//! file_extensions = ["ex"]
+//! file_names = ["Examplefile"]
+//!
+//! [variables]
+//! ident = '[A-Za-z_][A-Za-z0-9_]*'
+//!
+//! [[contexts.main]]
+//! match = '//.*$'
+//! scope = "comment.line"
+//!
+//! [[contexts.main]]
+//! match = '\b(fn)\s+({{ident}})'
+//! captures = { 1 = "keyword", 2 = "entity.name.function" }
+//!
+//! [[contexts.main]]
+//! match = '"'
+//! push = "string"
+//!
+//! [[contexts.string]]
+//! meta_scope = "string.quoted"
+//!
+//! [[contexts.string]]
+//! match = '"'
+//! pop = true
+//! ```
+//!
+//! A grammar is used for files with one of its `file_extensions`, or named
+//! exactly one of its `file_names`. `variables` are regex snippets filled
+//! in wherever a `match` has `{{name}}`, and can use each other.
+//!
+//! # Contexts
+//!
+//! Each of `contexts` is an array of entries, and lexing starts in `main`.
+//! An entry is one of:
+//!
+//! - a rule, with a `match` regex and any of `scope`, `captures` and one
+//!   of `push`, `set` or `pop`,
+//! - `meta_scope = "..."`, the scope of everything lexed while the context
+//!   is open, its opening and closing matches included,
+//! - `include = "other"`, which puts the rules of another context here.
+//!
+//! At each point of a line the rule of the innermost context whose match
+//! starts first wins, the earlier rule on a tie, and the text it skips has
+//! no scope of its own. Regexes only ever see one line without its line
+//! ending, so a context that should end with its line pops on `$`.
+//!
+//! # The context stack
+//!
+//! `push = "other"` opens a context inside the current one, `set = "other"`
+//! replaces the current one with it, and `pop = true` closes the current
+//! one, going back to the one it was opened from; `main` is never popped.
+//! The contexts open at the end of a line carry on to the next, which is
+//! how strings and comments span lines. Contexts nest at most 64 deep.
+//!
+//! # Scopes
+//!
+//! Scopes are dotted names like `string.quoted.double`. `scope` covers a
+//! rule's whole match, and `captures` maps numbered groups to scopes for
+//! their parts of it, over the rule's own scope. Themes style a scope by
+//! the longest dotted prefix they have a style for, so `string` covers
+//! `string.quoted.double`; a token's own style is drawn over the style of
+//! the context it is in.
+//!
+//! # Folds
+//!
+//! There are no fold markers in a grammar. Folding by syntax folds between
+//! matching `()`, `[]` and `{}` on different lines and over runs of whole
+//! comment lines, so a grammar only has to give comments a scope starting
+//! with `comment` and strings one starting with `string` for brackets in
+//! them to be left out.
+
+use std::{collections::HashMap, ops::Range};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::buffer;
+
+/// Lines longer than this in bytes are only lexed up to it.
+const MAX_LINE_LEN: usize = 16 * 1024;
+/// Contexts nested deeper than this are not pushed.
+const MAX_DEPTH: usize = 64;
+
+/// Index of a scope name in a grammar.
+pub type ScopeId = usize;
+
+/// What a rule does to the context stack when it matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    None,
+    Push(usize),
+    Pop,
+    Set(usize),
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    regex: Regex,
+    scope: Option<ScopeId>,
+    /// Scopes for capture groups, by group index.
+    captures: Vec<(usize, ScopeId)>,
+    action: Action,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Context {
+    /// Scope of everything matched inside the context.
+    meta_scope: Option<ScopeId>,
+    rules: Vec<Rule>,
+}
+
+/// The contexts open at the start of a line, innermost last.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineState {
+    stack: Vec<usize>,
+}
+
+/// A run of a line's text with the scopes it was matched as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    /// Byte range within the line.
+    pub range: Range<usize>,
+    pub scope: Option<ScopeId>,
+    /// Scope of the context the token is in.
+    pub meta_scope: Option<ScopeId>,
+}
+
+/// A language's syntax, as contexts of regex rules in the style of
+/// Sublime Text's syntax files, written in TOML. See the module docs for
+/// the format.
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    name: String,
+    file_extensions: Vec<String>,
+    file_names: Vec<String>,
+    scopes: Vec<String>,
+    contexts: Vec<Context>,
+}
+
+impl Grammar {
+    pub fn parse(text: &str) -> Result<Grammar, String> {
+        let table: toml::Table = text.parse()
+            .map_err(|err: toml::de::Error| err.message().to_string())?;
+
+        let name = match table.get("name") {
+            Some(toml::Value::String(name)) => name.clone(),
+            _ => return Err("'name' must be a string".to_string()),
+        };
+
+        let file_extensions = string_list(&table, "file_extensions")?;
+        let file_names = string_list(&table, "file_names")?;
+
+        let mut variables = HashMap::new();
+
+        if let Some(value) = table.get("variables") {
+            let toml::Value::Table(entries) = value else {
+                return Err("'variables' must be a table".to_string());
+            };
+
+            for (key, value) in entries.iter() {
+                let toml::Value::String(pattern) = value else {
+                    return Err(format!("'variables.{}' must be a string", key));
+                };
+
+                variables.insert(key.clone(), pattern.clone());
+            }
+        }
+
+        let Some(toml::Value::Table(contexts)) = table.get("contexts") else {
+            return Err("'contexts' must be a table of contexts".to_string());
+        };
+
+        for key in table.keys() {
+            if !["name", "file_extensions", "file_names", "variables", "contexts"].contains(&key.as_str()) {
+                return Err(format!("Unexpected entry '{}'", key));
+            }
+        }
+
+        // main comes first so lexing starts in context 0
+        let mut names: Vec<&String> = contexts.keys().collect();
+        names.sort_by_key(|name| name.as_str() != "main");
+
+        if names.first().is_none_or(|name| name.as_str() != "main") {
+            return Err("There must be a 'main' context".to_string());
+        }
+
+        let mut builder = Builder {
+            names: &names,
+            variables: &variables,
+            scopes: Vec::new(),
+        };
+
+        let mut compiled = Vec::new();
+
+        for name in names.iter() {
+            let entries = contexts.get(name.as_str()).expect("Named from the table");
+            compiled.push(builder.context(name, entries, contexts, 0)?);
+        }
+
+        Ok(Grammar {
+            name,
+            file_extensions,
+            file_names,
+            scopes: builder.scopes,
+            contexts: compiled,
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_scopes(&self) -> &[String] {
+        &self.scopes
+    }
+
+    /// Whether the grammar is for files with this name.
+    pub fn matches_file(&self, file_name: &str) -> bool {
+        let extension = file_name.rsplit_once('.').map(|(_, extension)| extension);
+
+        self.file_names.iter().any(|name| name == file_name)
+            || extension.is_some_and(|extension| self.file_extensions.iter().any(|other| other == extension))
+    }
+
+    /// The state at the start of the text.
+    pub fn start_state(&self) -> LineState {
+        LineState { stack: vec![0] }
+    }
+
+    /// Splits a line, without its line ending, into tokens, and moves the
+    /// state on to the start of the next line.
+    pub fn lex_line(&self, line: &str, state: &mut LineState) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let end = floor_char_boundary(line, MAX_LINE_LEN);
+        let line = &line[..end];
+
+        let mut position = 0;
+        // the first match of each rule of the current context, kept while
+        // it stays ahead
+        let mut found: Vec<Option<Range<usize>>> = Vec::new();
+        let mut context = usize::MAX;
+        let mut steps = 0;
+
+        while position <= line.len() {
+            let current = *state.stack.last().expect("The stack is never empty");
+            let rules = &self.contexts[current].rules;
+
+            if current != context {
+                context = current;
+                found = vec![None; rules.len()];
+            }
+
+            // a guard against rules that match nothing over and over
+            steps += 1;
+
+            let best = match steps > line.len() * 4 + 64 {
+                true => None,
+                false => self.first_match(rules, line, position, &mut found),
+            };
+
+            let Some((index, range)) = best else {
+                self.push_token(&mut tokens, position..line.len(), None, state);
+                break;
+            };
+
+            self.push_token(&mut tokens, position..range.start, None, state);
+
+            let rule = &rules[index];
+
+            // delimiters take the scope of the context they open or close
+            match rule.action {
+                Action::Push(next) | Action::Set(next) => {
+                    if let Action::Set(_) = rule.action {
+                        state.stack.pop();
+                    }
+
+                    if state.stack.len() < MAX_DEPTH {
+                        state.stack.push(next);
+                    }
+
+                    self.push_match(&mut tokens, line, &range, rule, state);
+                }
+                Action::Pop => {
+                    self.push_match(&mut tokens, line, &range, rule, state);
+
+                    if state.stack.len() > 1 {
+                        state.stack.pop();
+                    }
+                }
+                Action::None => self.push_match(&mut tokens, line, &range, rule, state),
+            }
+
+            position = range.end;
+        }
+
+        tokens
+    }
+
+    /// The rule whose match starts first at or after `position`.
+    fn first_match(&self, rules: &[Rule], line: &str, position: usize, found: &mut [Option<Range<usize>>]) -> Option<(usize, Range<usize>)> {
+        let mut best: Option<(usize, Range<usize>)> = None;
+
+        for (index, rule) in rules.iter().enumerate() {
+            let stale = found[index].as_ref().is_none_or(|range| range.start < position);
+
+            if stale {
+                found[index] = Some(find_rule(rule, line, position).unwrap_or(usize::MAX..usize::MAX));
+            }
+
+            let range = found[index].clone().expect("Filled in above");
+
+            if range.start != usize::MAX && best.as_ref().is_none_or(|(_, best)| range.start < best.start) {
+                best = Some((index, range));
+            }
+        }
+
+        best
+    }
+
+    fn push_token(&self, tokens: &mut Vec<Token>, range: Range<usize>, scope: Option<ScopeId>, state: &LineState) {
+        if range.is_empty() {
+            return;
+        }
+
+        let context = *state.stack.last().expect("The stack is never empty");
+
+        tokens.push(Token {
+            range,
+            scope,
+            meta_scope: self.contexts[context].meta_scope,
+        });
+    }
+
+    /// Adds a rule's match, split up by any scoped capture groups.
+    fn push_match(&self, tokens: &mut Vec<Token>, line: &str, range: &Range<usize>, rule: &Rule, state: &LineState) {
+        if rule.captures.is_empty() {
+            self.push_token(tokens, range.clone(), rule.scope, state);
+            return;
+        }
+
+        let Some(captures) = rule.regex.captures_at(line, range.start) else {
+            return;
+        };
+
+        let mut position = range.start;
+
+        for (group, scope) in rule.captures.iter() {
+            let Some(group) = captures.get(*group).filter(|group| group.start() >= position) else {
+                continue;
+            };
+
+            self.push_token(tokens, position..group.start(), rule.scope, state);
+            self.push_token(tokens, group.range(), Some(*scope), state);
+            position = group.end();
+        }
+
+        self.push_token(tokens, position..range.end, rule.scope, state);
+    }
+}
+
+/// The first match of a rule at or after `position`, skipping empty matches
+/// that would not move lexing on.
+fn find_rule(rule: &Rule, line: &str, mut position: usize) -> Option<Range<usize>> {
+    while position <= line.len() {
+        let found = rule.regex.find_at(line, position)?;
+
+        if !found.is_empty() || rule.action != Action::None {
+            return Some(found.range());
+        }
+
+        position = buffer::next_char(line, found.end());
+    }
+
+    None
+}
+
+/// Compiles the contexts of a grammar, interning scope names as it goes.
+struct Builder<'a> {
+    names: &'a [&'a String],
+    variables: &'a HashMap<String, String>,
+    scopes: Vec<String>,
+}
+
+impl Builder<'_> {
+    fn context(&mut self, name: &str, entries: &toml::Value, contexts: &toml::Table, depth: usize) -> Result<Context, String> {
+        let toml::Value::Array(entries) = entries else {
+            return Err(format!("'contexts.{}' must be an array of rules", name));
+        };
+
+        if depth > MAX_DEPTH {
+            return Err(format!("'contexts.{}' includes itself", name));
+        }
+
+        let mut context = Context::default();
+
+        for entry in entries.iter() {
+            let toml::Value::Table(entry) = entry else {
+                return Err(format!("'contexts.{}' must be an array of rules", name));
+            };
+
+            if let Some(meta_scope) = entry.get("meta_scope") {
+                context.meta_scope = Some(self.scope(name, meta_scope)?);
+                continue;
+            }
+
+            if let Some(include) = entry.get("include") {
+                let included = include.as_str()
+                    .and_then(|include| Some((include, contexts.get(include)?)))
+                    .ok_or_else(|| format!("'contexts.{}' includes a context that does not exist", name))?;
+
+                let (include, entries) = included;
+                let included = self.context(include, entries, contexts, depth + 1)?;
+                context.rules.extend(included.rules);
+                continue;
+            }
+
+            context.rules.push(self.rule(name, entry)?);
+        }
+
+        Ok(context)
+    }
+
+    fn rule(&mut self, context: &str, entry: &toml::Table) -> Result<Rule, String> {
+        let error = |message: &str| format!("A rule in 'contexts.{}' {}", context, message);
+
+        let Some(toml::Value::String(pattern)) = entry.get("match") else {
+            return Err(error("needs a 'match' regex"));
+        };
+
+        let regex = RegexBuilder::new(&self.expand(pattern))
+            .build()
+            .map_err(|err| error(&format!("has an invalid regex: {}", err)))?;
+
+        let scope = entry.get("scope")
+            .map(|scope| self.scope(context, scope))
+            .transpose()?;
+
+        let mut captures = Vec::new();
+
+        if let Some(value) = entry.get("captures") {
+            let toml::Value::Table(groups) = value else {
+                return Err(error("has 'captures' that are not a table"));
+            };
+
+            for (group, scope) in groups.iter() {
+                let group = group.parse::<usize>()
+                    .map_err(|_| error("has 'captures' keyed by something other than a group number"))?;
+
+                captures.push((group, self.scope(context, scope)?));
+            }
+
+            // lower numbered groups start first, unless nested in another
+            captures.sort_by_key(|(group, _)| *group);
+        }
+
+        let context_index = |key: &str| -> Result<Option<usize>, String> {
+            match entry.get(key) {
+                Some(toml::Value::String(name)) => self.names.iter()
+                    .position(|other| other.as_str() == name)
+                    .map(Some)
+                    .ok_or_else(|| error(&format!("goes to '{}', which does not exist", name))),
+                Some(_) => Err(error(&format!("has a '{}' that is not a context name", key))),
+                None => Ok(None),
+            }
+        };
+
+        let action = match (context_index("push")?, context_index("set")?, entry.get("pop")) {
+            (Some(next), None, None) => Action::Push(next),
+            (None, Some(next), None) => Action::Set(next),
+            (None, None, Some(toml::Value::Boolean(true))) => Action::Pop,
+            (None, None, None | Some(toml::Value::Boolean(false))) => Action::None,
+            _ => return Err(error("can only do one of 'push', 'set' and 'pop'")),
+        };
+
+        Ok(Rule { regex, scope, captures, action })
+    }
+
+    fn scope(&mut self, context: &str, value: &toml::Value) -> Result<ScopeId, String> {
+        let toml::Value::String(scope) = value else {
+            return Err(format!("A scope in 'contexts.{}' is not a string", context));
+        };
+
+        match self.scopes.iter().position(|other| other == scope) {
+            Some(id) => Ok(id),
+            None => {
+                self.scopes.push(scope.clone());
+                Ok(self.scopes.len() - 1)
+            }
+        }
+    }
+
+    /// Fills in `{{name}}` variables.
+    fn expand(&self, pattern: &str) -> String {
+        let mut pattern = pattern.to_string();
+
+        // variables can refer to each other, so go a few rounds
+        for _ in 0..8 {
+            if !pattern.contains("{{") {
+                break;
+            }
+
+            for (name, value) in self.variables.iter() {
+                pattern = pattern.replace(&format!("{{{{{}}}}}", name), value);
+            }
+        }
+
+        pattern
+    }
+}
+
+fn string_list(table: &toml::Table, key: &str) -> Result<Vec<String>, String> {
+    let Some(value) = table.get(key) else {
+        return Ok(Vec::new());
+    };
+
+    value.as_array()
+        .and_then(|values| values.iter().map(|value| value.as_str().map(str::to_string)).collect())
+        .ok_or_else(|| format!("'{}' must be an array of strings", key))
+}
+
+fn floor_char_boundary(text: &str, len: usize) -> usize {
+    let mut end = len.min(text.len());
+
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A token's text, scope and meta scope.
+    type Named = (String, Option<String>, Option<String>);
+
+    /// Lexes lines one after another, naming each token's text and scopes.
+    fn lex(grammar: &Grammar, lines: &[&str]) -> Vec<Vec<Named>> {
+        let mut state = grammar.start_state();
+        let name = |scope: Option<ScopeId>| scope.map(|scope| grammar.get_scopes()[scope].clone());
+
+        lines.iter()
+            .map(|line| {
+                grammar.lex_line(line, &mut state)
+                    .into_iter()
+                    .map(|token| (line[token.range].to_string(), name(token.scope), name(token.meta_scope)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn token(text: &str, scope: Option<&str>, meta_scope: Option<&str>) -> Named {
+        (text.to_string(), scope.map(str::to_string), meta_scope.map(str::to_string))
+    }
+
+    #[test]
+    fn rejects_a_grammar_without_main() {
+        let text = "name = 'Test'\n[[contexts.other]]\nmatch = 'a'\n";
+
+        assert_eq!(Grammar::parse(text).err().as_deref(), Some("There must be a 'main' context"));
+    }
+
+    #[test]
+    fn rejects_going_to_a_missing_context() {
+        let text = "name = 'Test'\n[[contexts.main]]\nmatch = 'a'\npush = 'string'\n";
+
+        assert_eq!(
+            Grammar::parse(text).err().as_deref(),
+            Some("A rule in 'contexts.main' goes to 'string', which does not exist"),
+        );
+    }
+
+    #[test]
+    fn rejects_a_context_including_itself() {
+        let text = "name = 'Test'\n[[contexts.main]]\ninclude = 'other'\n[[contexts.other]]\ninclude = 'main'\n";
+        let error = Grammar::parse(text).err().unwrap_or_default();
+
+        assert!(error.ends_with("includes itself"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_captures_not_keyed_by_group() {
+        let text = "name = 'Test'\n[[contexts.main]]\nmatch = '(a)'\ncaptures = { first = 'keyword' }\n";
+
+        assert_eq!(
+            Grammar::parse(text).err().as_deref(),
+            Some("A rule in 'contexts.main' has 'captures' keyed by something other than a group number"),
+        );
+    }
+
+    #[test]
+    fn pushes_sets_and_pops_contexts_across_lines() {
+        let grammar = Grammar::parse(r#"
+            name = "Test"
+
+            [[contexts.main]]
+            match = '"'
+            push = "string"
+
+            [[contexts.string]]
+            meta_scope = "string"
+
+            [[contexts.string]]
+            match = '\\.'
+            scope = "escape"
+
+            [[contexts.string]]
+            match = '#'
+            set = "comment"
+
+            [[contexts.string]]
+            match = '"'
+            pop = true
+
+            [[contexts.comment]]
+            meta_scope = "comment"
+
+            [[contexts.comment]]
+            match = '$'
+            pop = true
+        "#).unwrap();
+
+        let tokens = lex(&grammar, &["a \"b\\n", "c\" d", "\"e#f", "g"]);
+
+        assert_eq!(tokens, vec![
+            vec![
+                token("a ", None, None),
+                token("\"", None, Some("string")),
+                token("b", None, Some("string")),
+                token("\\n", Some("escape"), Some("string")),
+            ],
+            vec![
+                token("c", None, Some("string")),
+                token("\"", None, Some("string")),
+                token(" d", None, None),
+            ],
+            vec![
+                token("\"", None, Some("string")),
+                token("e", None, Some("string")),
+                token("#", None, Some("comment")),
+                token("f", None, Some("comment")),
+            ],
+            vec![token("g", None, None)],
+        ]);
+    }
+
+    #[test]
+    fn splits_a_match_by_its_captures() {
+        let grammar = Grammar::parse(r#"
+            name = "Test"
+
+            [variables]
+            ident = '[a-z]+'
+
+            [[contexts.main]]
+            match = '(fn) +({{ident}})\('
+            scope = "meta.function"
+            captures = { 1 = "keyword", 2 = "entity.name" }
+        "#).unwrap();
+
+        assert_eq!(lex(&grammar, &["x fn  run()"]), vec![vec![
+            token("x ", None, None),
+            token("fn", Some("keyword"), None),
+            token("  ", Some("meta.function"), None),
+            token("run", Some("entity.name"), None),
+            token("(", Some("meta.function"), None),
+            token(")", None, None),
+        ]]);
+    }
+
+    #[test]
+    fn gives_up_on_rules_that_never_move_on() {
+        // an empty pop in main matches in the same place forever
+        let grammar = Grammar::parse(r#"
+            name = "Test"
+
+            [[contexts.main]]
+            match = ''
+            pop = true
+        "#).unwrap();
+
+        assert_eq!(lex(&grammar, &["abc"]), vec![vec![token("abc", None, None)]]);
+    }
+}
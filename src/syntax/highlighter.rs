@@ -0,0 +1,242 @@
+use std::{ops::Range, rc::Rc};
+
+use crate::{buffer::{Buffer, LineEdit}, renderer::cell::CellStyle};
+
+use super::{grammar::{Grammar, LineState, Token}, ScopeStyles};
+
+/// Styles a buffer's lines with a grammar, lexing only as far as the lines
+/// asked for and, after an edit, only from the edited line on until the
+/// state at the start of a line comes out as it was before.
+#[derive(Debug, Clone)]
+pub struct Highlighter {
+    grammar: Rc<Grammar>,
+    /// Style of each of the grammar's scopes.
+    styles: Vec<Option<CellStyle>>,
+    /// The state at the start of each line lexed so far.
+    states: Vec<LineState>,
+    /// Lines whose start state may be out of date, in order.
+    dirty: Vec<usize>,
+    version: Option<u64>,
+}
+
+impl Highlighter {
+    pub fn new(grammar: Rc<Grammar>, styles: &ScopeStyles) -> Self {
+        let mut highlighter = Highlighter {
+            states: vec![grammar.start_state()],
+            grammar,
+            styles: Vec::new(),
+            dirty: Vec::new(),
+            version: None,
+        };
+
+        highlighter.set_styles(styles);
+        highlighter
+    }
+
+    pub fn get_grammar(&self) -> &Rc<Grammar> {
+        &self.grammar
+    }
+
+    pub fn set_styles(&mut self, styles: &ScopeStyles) -> &mut Self {
+        self.styles = self.grammar.get_scopes().iter()
+            .map(|scope| styles.get(scope))
+            .collect();
+        self
+    }
+
+    /// Styled byte ranges for each line in `lines`.
+    pub fn highlight(&mut self, buffer: &Buffer, lines: Range<usize>) -> Vec<Vec<(Range<usize>, CellStyle)>> {
+        self.tokens(buffer, lines).into_iter().map(|tokens| {
+            tokens.into_iter()
+                .filter_map(|token| {
+                    let meta = token.meta_scope.and_then(|scope| self.styles[scope]);
+                    let style = token.scope.and_then(|scope| self.styles[scope]);
+
+                    // a token's own style wins over the context it is in
+                    let style = match (meta, style) {
+                        (Some(meta), Some(style)) => Some(meta.patch(style)),
+                        (meta, style) => style.or(meta),
+                    };
+
+                    Some((token.range, style?))
+                })
+                .collect()
+        }).collect()
+    }
+
+    /// The tokens of each line in `lines`, with scopes named by the
+    /// grammar's `get_scopes`.
+    pub fn tokens(&mut self, buffer: &Buffer, lines: Range<usize>) -> Vec<Vec<Token>> {
+        let lines = lines.start.min(buffer.len_lines())..lines.end.min(buffer.len_lines());
+
+        self.sync(buffer);
+        self.lex_to(buffer, lines.end);
+
+        lines.map(|line| {
+            let mut state = self.states[line].clone();
+            self.grammar.lex_line(&buffer.line_text(line), &mut state)
+        }).collect()
+    }
+
+    /// Catches up with the edits made to the buffer since the last time.
+    fn sync(&mut self, buffer: &Buffer) {
+        if self.version == Some(buffer.get_version()) {
+            return;
+        }
+
+        let edits: Option<Vec<LineEdit>> = self.version
+            .and_then(|version| buffer.line_edits_since(version))
+            .map(Iterator::collect);
+
+        match edits {
+            Some(edits) => edits.into_iter().for_each(|edit| self.edit(edit)),
+            None => {
+                self.states.truncate(1);
+                self.dirty.clear();
+            }
+        }
+
+        self.version = Some(buffer.get_version());
+    }
+
+    /// Shifts the states to follow an edit, marking those it may have
+    /// changed as dirty.
+    fn edit(&mut self, edit: LineEdit) {
+        let LineEdit { start, old_end, new_end, .. } = edit;
+
+        // only the states before the edit are kept when it reaches past
+        // what has been lexed
+        if old_end + 1 >= self.states.len() {
+            self.states.truncate(start + 1);
+            self.dirty.retain(|line| *line <= start);
+            return;
+        }
+
+        let placeholder = self.states[start].clone();
+        self.states.splice(start + 1..old_end + 1, (start..new_end).map(|_| placeholder.clone()));
+
+        let delta = new_end as isize - old_end as isize;
+
+        self.dirty.retain(|line| *line <= start || *line > old_end);
+
+        for line in self.dirty.iter_mut().filter(|line| **line > old_end) {
+            *line = line.saturating_add_signed(delta);
+        }
+
+        // the start of every line after the first edited one, up to and
+        // including the line after the edit
+        let len = self.states.len();
+        self.dirty.extend((start + 1..=new_end + 1).filter(|line| *line < len));
+        self.dirty.sort_unstable();
+        self.dirty.dedup();
+    }
+
+    /// Makes sure the states up to line `end` are known.
+    fn lex_to(&mut self, buffer: &Buffer, end: usize) {
+        let end = end.min(buffer.len_lines().saturating_sub(1));
+
+        loop {
+            // the first line whose start state is unknown or may be stale
+            let first = match self.dirty.first() {
+                Some(line) if *line <= end => *line,
+                _ if self.states.len() <= end => self.states.len(),
+                _ => return,
+            };
+
+            let mut line = first - 1;
+            let mut state = self.states[line].clone();
+
+            loop {
+                self.grammar.lex_line(&buffer.line_text(line), &mut state);
+                line += 1;
+
+                if line >= buffer.len_lines() {
+                    self.states.truncate(line);
+                    self.dirty.clear();
+                    return;
+                }
+
+                if line == self.states.len() {
+                    self.states.push(state.clone());
+
+                    if line >= end {
+                        return;
+                    }
+
+                    continue;
+                }
+
+                let was_dirty = self.dirty.first() == Some(&line);
+
+                if was_dirty {
+                    self.dirty.remove(0);
+                }
+
+                // the rest follows as before once a clean line starts the
+                // same
+                if !was_dirty && self.states[line] == state {
+                    break;
+                }
+
+                self.states[line] = state.clone();
+
+                if line > end {
+                    // what follows is stale until lexed again
+                    if line + 1 < self.states.len() && self.dirty.first() != Some(&(line + 1)) {
+                        self.dirty.insert(0, line + 1);
+                    }
+
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::syntax::SyntaxSet;
+
+    fn rust() -> Highlighter {
+        let grammar = SyntaxSet::default().for_path(Path::new("main.rs")).expect("Rust is built in");
+        Highlighter::new(grammar, &ScopeStyles::default())
+    }
+
+    fn assert_relexed(highlighter: &mut Highlighter, buffer: &Buffer) {
+        let lines = 0..buffer.len_lines();
+        assert_eq!(highlighter.tokens(buffer, lines.clone()), rust().tokens(buffer, lines));
+    }
+
+    #[test]
+    fn edits_match_a_full_relex() {
+        let text: Vec<String> = (0..60).map(|line| format!("fn f{}() {{ let s = \"{}\"; }}", line, line)).collect();
+        let mut buffer = Buffer::from_text(&text.join("\n"));
+        let mut highlighter = rust();
+
+        highlighter.tokens(&buffer, 0..20);
+
+        // opening a comment changes every line after it, closing it puts
+        // them back
+        let line_start = |buffer: &Buffer, line: usize| buffer.line_to_char(line);
+        buffer.insert(line_start(&buffer, 5), "/* ");
+        highlighter.tokens(&buffer, 0..10);
+        assert_relexed(&mut highlighter, &buffer);
+
+        buffer.insert(line_start(&buffer, 30), " */");
+        assert_relexed(&mut highlighter, &buffer);
+
+        // several edits between looks, including lines going away
+        buffer.insert(line_start(&buffer, 40), "\"\n\n");
+        let start = line_start(&buffer, 10);
+        buffer.remove(start..line_start(&buffer, 35));
+        buffer.insert(line_start(&buffer, 2), "let s = \"a\nb\";\n");
+        assert_relexed(&mut highlighter, &buffer);
+
+        let len = buffer.len_chars();
+        buffer.remove(line_start(&buffer, 3)..len);
+        assert_relexed(&mut highlighter, &buffer);
+    }
+}
@@ -0,0 +1,168 @@
+use std::{fs, path::Path, rc::Rc};
+
+use crossterm::style::{Attribute, Color};
+
+use crate::{config, renderer::cell::CellStyle};
+
+pub mod grammar;
+pub mod highlighter;
+
+use grammar::Grammar;
+
+const SYNTAX_DIR: &str = "syntaxes";
+
+const BUILTIN_GRAMMARS: [&str; 4] = [
+    include_str!("grammars/rust.toml"),
+    include_str!("grammars/toml.toml"),
+    include_str!("grammars/markdown.toml"),
+    include_str!("grammars/json.toml"),
+];
+
+/// The grammars files can be highlighted with.
+#[derive(Debug, Clone)]
+pub struct SyntaxSet {
+    grammars: Vec<Rc<Grammar>>,
+}
+
+impl Default for SyntaxSet {
+    fn default() -> Self {
+        let grammars = BUILTIN_GRAMMARS.iter()
+            .map(|text| Rc::new(Grammar::parse(text).expect("Built-in grammars are valid")))
+            .collect();
+
+        SyntaxSet { grammars }
+    }
+}
+
+impl SyntaxSet {
+    /// The built-in grammars along with those in the `syntaxes` dir of the
+    /// config dir, which replace built-in ones of the same name.
+    pub fn load() -> Result<SyntaxSet, String> {
+        let mut syntax_set = SyntaxSet::default();
+
+        let Some(dir) = config::config_dir().map(|dir| dir.join(SYNTAX_DIR)) else {
+            return Ok(syntax_set);
+        };
+
+        let Ok(entries) = fs::read_dir(&dir) else {
+            return Ok(syntax_set);
+        };
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+            .collect();
+
+        paths.sort();
+
+        for path in paths {
+            syntax_set.load_file(&path)?;
+        }
+
+        Ok(syntax_set)
+    }
+
+    pub fn load_file(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+
+        let grammar = Grammar::parse(&text)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        self.add(grammar);
+        Ok(())
+    }
+
+    /// Adds a grammar, in place of any with the same name.
+    pub fn add(&mut self, grammar: Grammar) -> &mut Self {
+        let grammar = Rc::new(grammar);
+
+        match self.grammars.iter().position(|other| other.get_name() == grammar.get_name()) {
+            Some(index) => self.grammars[index] = grammar,
+            None => self.grammars.push(grammar),
+        }
+
+        self
+    }
+
+    /// The grammar for the file at `path`, going by its name. Grammars
+    /// added later win.
+    pub fn for_path(&self, path: &Path) -> Option<Rc<Grammar>> {
+        let file_name = path.file_name()?.to_str()?;
+
+        self.grammars.iter()
+            .rev()
+            .find(|grammar| grammar.matches_file(file_name))
+            .cloned()
+    }
+}
+
+/// Styles for scopes. A scope takes the style of the longest of these that
+/// it starts with, whole dotted parts at a time, so `string` covers
+/// `string.quoted.double` unless that has a style of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopeStyles {
+    styles: Vec<(String, CellStyle)>,
+}
+
+impl Default for ScopeStyles {
+    fn default() -> Self {
+        let bold = |fg: Color| *CellStyle::fg(fg).set_attribute(Attribute::Bold);
+        let italic = |fg: Color| *CellStyle::fg(fg).set_attribute(Attribute::Italic);
+
+        let styles = [
+            ("comment", italic(Color::DarkGrey)),
+            ("string", CellStyle::fg(Color::Green)),
+            ("constant", CellStyle::fg(Color::Yellow)),
+            ("constant.character.escape", CellStyle::fg(Color::Cyan)),
+            ("keyword", CellStyle::fg(Color::Magenta)),
+            ("storage", CellStyle::fg(Color::Magenta)),
+            ("storage.type", CellStyle::fg(Color::Cyan)),
+            ("storage.modifier.lifetime", CellStyle::fg(Color::Yellow)),
+            ("entity.name.function", CellStyle::fg(Color::Blue)),
+            ("entity.name.type", CellStyle::fg(Color::Cyan)),
+            ("entity.name.section", bold(Color::Blue)),
+            ("variable.other.key", CellStyle::fg(Color::Blue)),
+            ("meta.attribute", CellStyle::fg(Color::DarkYellow)),
+            ("punctuation.definition", CellStyle::fg(Color::DarkGrey)),
+            ("markup.heading", bold(Color::Blue)),
+            ("markup.bold", bold(Color::Reset)),
+            ("markup.italic", italic(Color::Reset)),
+            ("markup.raw", CellStyle::fg(Color::Green)),
+            ("markup.link.text", CellStyle::fg(Color::Blue)),
+            ("markup.link.url", *CellStyle::fg(Color::DarkCyan).set_attribute(Attribute::Underlined)),
+        ];
+
+        ScopeStyles {
+            styles: styles.into_iter().map(|(scope, style)| (scope.to_string(), style)).collect(),
+        }
+    }
+}
+
+impl ScopeStyles {
+    /// No styles at all.
+    pub fn empty() -> Self {
+        ScopeStyles { styles: Vec::new() }
+    }
+
+    /// Styles `scope` and everything under it, in place of any style it
+    /// had.
+    pub fn set(&mut self, scope: &str, style: CellStyle) -> &mut Self {
+        match self.styles.iter_mut().find(|(other, _)| other == scope) {
+            Some((_, old)) => *old = style,
+            None => self.styles.push((scope.to_string(), style)),
+        }
+
+        self
+    }
+
+    pub fn get(&self, scope: &str) -> Option<CellStyle> {
+        self.styles.iter()
+            .filter(|(prefix, _)| {
+                scope.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, style)| *style)
+    }
+}
@@ -0,0 +1,432 @@
+use crossterm::style::{Attribute, Color};
+use serde_json::Value;
+
+use crate::{renderer::cell::CellStyle, syntax::ScopeStyles};
+
+use super::{parse_color_str, Theme};
+
+/// Scopes styled by a Base16 scheme, with the colour and any attribute
+/// each takes, after the Base16 styling guidelines.
+const BASE16_SCOPES: [(&str, usize, Option<Attribute>); 24] = [
+    ("comment", 0x03, Some(Attribute::Italic)),
+    ("string", 0x0b, None),
+    ("constant", 0x09, None),
+    ("constant.character.escape", 0x0c, None),
+    ("keyword", 0x0e, None),
+    ("storage", 0x0e, None),
+    ("storage.type", 0x0a, None),
+    ("storage.modifier.lifetime", 0x08, None),
+    ("variable", 0x08, None),
+    ("variable.other.key", 0x0d, None),
+    ("entity.name.function", 0x0d, None),
+    ("entity.name.type", 0x0a, None),
+    ("entity.name.section", 0x0d, Some(Attribute::Bold)),
+    ("meta.attribute", 0x0c, None),
+    ("punctuation.definition", 0x03, None),
+    ("markup.heading", 0x0d, Some(Attribute::Bold)),
+    ("markup.bold", 0x0a, Some(Attribute::Bold)),
+    ("markup.italic", 0x0e, Some(Attribute::Italic)),
+    ("markup.raw", 0x0b, None),
+    ("markup.link.text", 0x08, None),
+    ("markup.link.url", 0x09, Some(Attribute::Underlined)),
+    ("markup.list", 0x08, None),
+    ("markup.quote", 0x0c, None),
+    ("invalid.deprecated", 0x0f, None),
+];
+
+/// Builds a theme from a Base16 scheme, which gives the 16 colours
+/// `base00` to `base0F` as YAML:
+///
+/// ```yaml
+/// scheme: "Tomorrow Night"
+/// base00: "1d1f21"
+/// base01: "282a2e"
+/// ```
+///
+/// Only those lines are read, wherever they are nested, so schemes with
+/// the colours under a `palette` key load as well.
+pub fn base16(text: &str) -> Result<Theme, String> {
+    let mut colors = [None; 16];
+
+    for line in text.lines() {
+        let Some((key, value)) = line.trim().split_once(':') else {
+            continue;
+        };
+
+        let Some(index) = key.trim().strip_prefix("base").and_then(|index| usize::from_str_radix(index, 16).ok()) else {
+            continue;
+        };
+
+        // drop a trailing comment and the quotes
+        let value = value.split(" #").next().unwrap_or_default();
+        let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+        let value = value.strip_prefix('#').unwrap_or(value);
+
+        let color = parse_color_str(&format!("#{}", value))
+            .ok_or_else(|| format!("'{}' is not a hex colour", value))?;
+
+        if let Some(slot) = colors.get_mut(index) {
+            *slot = Some(color);
+        }
+    }
+
+    let mut base = [Color::Reset; 16];
+
+    for (index, color) in colors.iter().enumerate() {
+        base[index] = color.ok_or_else(|| format!("There is no 'base{:02X}' colour", index))?;
+    }
+
+    let style = |fg: usize, bg: usize| *CellStyle::fg(base[fg]).set_bg(base[bg]);
+
+    let mut syntax = ScopeStyles::empty();
+
+    for (scope, color, attribute) in BASE16_SCOPES {
+        let mut style = CellStyle::fg(base[color]);
+
+        if let Some(attribute) = attribute {
+            style.set_attribute(attribute);
+        }
+
+        syntax.set(scope, style);
+    }
+
+    Ok(Theme {
+        name: String::new(),
+        editor: style(0x05, 0x00),
+        gutter: style(0x03, 0x01),
+        current_line_number: style(0x04, 0x01),
+        selection: *CellStyle::default().set_bg(base[0x02]),
+        search_match: style(0x00, 0x0a),
+        current_match: style(0x00, 0x09),
+        folded: CellStyle::fg(base[0x03]),
+        matching_bracket: *CellStyle::default().set_bg(base[0x02]),
+        whitespace: CellStyle::fg(base[0x03]),
+        border: base[0x02],
+        hovered_border: base[0x03],
+        focused_border: base[0x0d],
+        status_line: style(0x04, 0x01),
+        status_error: style(0x00, 0x08),
+        results_file: *CellStyle::fg(base[0x0d]).set_attribute(Attribute::Bold),
+        diff_header: CellStyle::fg(base[0x0c]),
+        diff_added: CellStyle::fg(base[0x0b]),
+        diff_removed: CellStyle::fg(base[0x08]),
+        syntax,
+    })
+}
+
+/// Builds a theme from a VS Code colour theme, taking what it can from
+/// the `colors` of the workbench and the scopes of `tokenColors`. Scope
+/// selectors that look at parent scopes are left out. Comments and
+/// trailing commas, which VS Code allows, are fine.
+pub fn vscode(text: &str) -> Result<Theme, String> {
+    let json: Value = serde_json::from_str(&strip_jsonc(text))
+        .map_err(|err| err.to_string())?;
+
+    let colors = json.get("colors").and_then(Value::as_object);
+    let token_colors = json.get("tokenColors").and_then(Value::as_array);
+
+    // tokens without a scope give the defaults for the editor
+    let default_settings = token_colors
+        .and_then(|tokens| tokens.iter().find(|token| token.get("scope").is_none()))
+        .and_then(|token| token.get("settings"));
+
+    let default_fg = default_settings.and_then(|settings| settings.get("foreground")).and_then(Value::as_str);
+    let default_bg = default_settings.and_then(|settings| settings.get("background")).and_then(Value::as_str);
+
+    // the first of the keys the theme has
+    let raw = |keys: &[&str]| -> Option<&str> {
+        keys.iter().find_map(|key| colors?.get(*key)?.as_str())
+    };
+
+    let background = raw(&["editor.background"]).or(default_bg).and_then(|hex| vscode_color(hex, None));
+    let blend = |keys: &[&str]| raw(keys).and_then(|hex| vscode_color(hex, background));
+    let style = |fg: &[&str], bg: &[&str]| CellStyle {
+        fg: blend(fg).unwrap_or(Color::Reset),
+        bg: blend(bg).unwrap_or(Color::Reset),
+        ..Default::default()
+    };
+
+    let defaults = Theme::default();
+
+    let mut editor = style(&["editor.foreground"], &["editor.background"]);
+
+    if editor.fg == Color::Reset {
+        editor.fg = default_fg.and_then(|hex| vscode_color(hex, None)).unwrap_or(Color::Reset);
+    }
+
+    editor.bg = background.unwrap_or(Color::Reset);
+
+    let mut selection = style(&["editor.selectionForeground"], &["editor.selectionBackground"]);
+
+    if selection == CellStyle::default() {
+        selection = defaults.selection;
+    }
+
+    let mut syntax = ScopeStyles::empty();
+
+    for token in token_colors.into_iter().flatten() {
+        let Some(settings) = token.get("settings") else {
+            continue;
+        };
+
+        let scopes: Vec<&str> = match token.get("scope") {
+            Some(Value::String(scopes)) => scopes.split(',').collect(),
+            Some(Value::Array(scopes)) => scopes.iter().filter_map(Value::as_str).collect(),
+            _ => continue,
+        };
+
+        let mut style = CellStyle::default();
+
+        if let Some(fg) = settings.get("foreground").and_then(Value::as_str).and_then(|hex| vscode_color(hex, background)) {
+            style.fg = fg;
+        }
+
+        if let Some(bg) = settings.get("background").and_then(Value::as_str).and_then(|hex| vscode_color(hex, background)) {
+            style.bg = bg;
+        }
+
+        for font_style in settings.get("fontStyle").and_then(Value::as_str).unwrap_or_default().split_whitespace() {
+            match font_style {
+                "bold" => style.set_attribute(Attribute::Bold),
+                "italic" => style.set_attribute(Attribute::Italic),
+                "underline" => style.set_attribute(Attribute::Underlined),
+                "strikethrough" => style.set_attribute(Attribute::CrossedOut),
+                _ => &mut style,
+            };
+        }
+
+        for scope in scopes.iter().map(|scope| scope.trim()).filter(|scope| !scope.is_empty() && !scope.contains(' ')) {
+            syntax.set(scope, style);
+        }
+    }
+
+    let or_default = |style: CellStyle, default: CellStyle| if style == CellStyle::default() { default } else { style };
+
+    Ok(Theme {
+        name: json.get("name").and_then(Value::as_str).unwrap_or_default().to_string(),
+        editor,
+        gutter: style(&["editorLineNumber.foreground"], &["editorGutter.background"]),
+        current_line_number: style(&["editorLineNumber.activeForeground", "editor.foreground"], &["editorGutter.background"]),
+        selection,
+        search_match: or_default(
+            style(&[], &["editor.findMatchHighlightBackground"]),
+            defaults.search_match,
+        ),
+        current_match: or_default(
+            style(&[], &["editor.findMatchBackground"]),
+            defaults.current_match,
+        ),
+        folded: or_default(
+            style(&["editorCodeLens.foreground", "editorLineNumber.foreground"], &[]),
+            defaults.folded,
+        ),
+        matching_bracket: or_default(
+            style(&[], &["editorBracketMatch.background"]),
+            defaults.matching_bracket,
+        ),
+        whitespace: or_default(
+            style(&["editorWhitespace.foreground"], &[]),
+            defaults.whitespace,
+        ),
+        border: blend(&["editorGroup.border", "panel.border", "contrastBorder"]).unwrap_or(defaults.border),
+        hovered_border: blend(&["editorIndentGuide.activeBackground", "editorLineNumber.foreground"])
+            .unwrap_or(defaults.hovered_border),
+        focused_border: blend(&["focusBorder", "contrastActiveBorder"]).unwrap_or(defaults.focused_border),
+        status_line: or_default(
+            style(&["statusBar.foreground"], &["statusBar.background"]),
+            defaults.status_line,
+        ),
+        status_error: or_default(
+            style(&["statusBarItem.errorForeground"], &["statusBarItem.errorBackground", "errorForeground"]),
+            defaults.status_error,
+        ),
+        results_file: blend(&["textLink.foreground", "focusBorder"])
+            .map(|fg| *CellStyle::fg(fg).set_attribute(Attribute::Bold))
+            .unwrap_or(defaults.results_file),
+        diff_header: or_default(
+            style(&["terminal.ansiCyan"], &[]),
+            defaults.diff_header,
+        ),
+        diff_added: or_default(
+            style(&["gitDecoration.addedResourceForeground", "terminal.ansiGreen"], &[]),
+            defaults.diff_added,
+        ),
+        diff_removed: or_default(
+            style(&["gitDecoration.deletedResourceForeground", "terminal.ansiRed"], &[]),
+            defaults.diff_removed,
+        ),
+        syntax,
+    })
+}
+
+/// A `#rrggbb` or `#rrggbbaa` colour, with any alpha mixed into
+/// `background` since terminals cannot blend.
+fn vscode_color(hex: &str, background: Option<Color>) -> Option<Color> {
+    let color = parse_color_str(hex)?;
+    let alpha = hex.strip_prefix('#')
+        .filter(|hex| hex.len() == 8 && hex.is_ascii())
+        .and_then(|hex| u8::from_str_radix(&hex[6..], 16).ok());
+
+    match (color, alpha, background) {
+        (Color::Rgb { r, g, b }, Some(alpha), Some(Color::Rgb { r: br, g: bg, b: bb })) => {
+            let mix = |front: u8, back: u8| ((front as u32 * alpha as u32 + back as u32 * (255 - alpha as u32)) / 255) as u8;
+            Some(Color::Rgb { r: mix(r, br), g: mix(g, bg), b: mix(b, bb) })
+        }
+        (color, _, _) => Some(color),
+    }
+}
+
+/// JSON with comments and trailing commas taken out.
+fn strip_jsonc(text: &str) -> String {
+    let mut json = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            json.push(c);
+
+            match c {
+                '\\' => json.extend(chars.next()),
+                '"' => in_string = false,
+                _ => (),
+            }
+
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                json.push(c);
+            }
+            ('/', Some('/')) => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut last = ' ';
+
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+
+                    last = c;
+                }
+            }
+            (']' | '}', _) => {
+                // a comma before the closing bracket is dropped
+                let trimmed = json.trim_end().len();
+
+                if json[..trimmed].ends_with(',') {
+                    json.truncate(trimmed - 1);
+                }
+
+                json.push(c);
+            }
+            _ => json.push(c),
+        }
+    }
+
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEME: &str = r##"
+scheme: "Test"
+author: "Someone"
+palette:
+  base00: "000000"
+  base01: "111111"
+  base02: "222222"
+  base03: "333333"
+  base04: "444444"
+  base05: "555555"
+  base06: "666666"
+  base07: "777777"
+  base08: "#880000" # red
+  base09: "999999"
+  base0A: 'aaaaaa'
+  base0B: "00bb00"
+  base0C: "00cccc"
+  base0D: "0000dd"
+  base0E: "eeeeee"
+  base0F: "ffffff"
+"##;
+
+    fn grey(value: u8) -> Color {
+        Color::Rgb { r: value, g: value, b: value }
+    }
+
+    #[test]
+    fn builds_a_theme_from_base16() {
+        let theme = base16(SCHEME).unwrap();
+
+        assert_eq!(theme.editor, *CellStyle::fg(grey(0x55)).set_bg(grey(0x00)));
+        assert_eq!(theme.search_match, *CellStyle::fg(grey(0x00)).set_bg(grey(0xaa)));
+        assert_eq!(theme.diff_removed, CellStyle::fg(Color::Rgb { r: 0x88, g: 0, b: 0 }));
+        assert_eq!(theme.diff_added, CellStyle::fg(Color::Rgb { r: 0, g: 0xbb, b: 0 }));
+        assert_eq!(theme.syntax.get("comment.line"), Some(*CellStyle::fg(grey(0x33)).set_attribute(Attribute::Italic)));
+    }
+
+    #[test]
+    fn base16_needs_every_colour() {
+        let scheme = SCHEME.replace("  base0F: \"ffffff\"\n", "");
+
+        assert_eq!(base16(&scheme).err().as_deref(), Some("There is no 'base0F' colour"));
+        assert_eq!(
+            base16(&SCHEME.replace("999999", "99999z")).err().as_deref(),
+            Some("'99999z' is not a hex colour"),
+        );
+    }
+
+    #[test]
+    fn builds_a_theme_from_vscode() {
+        let theme = vscode(r##"{
+            // comments and trailing commas are fine
+            "name": "Test",
+            "colors": {
+                "editor.background": "#000000",
+                "editor.foreground": "#cccccc",
+                /* half of white over the background */
+                "editor.selectionBackground": "#ffffff80",
+                "gitDecoration.addedResourceForeground": "#00ff00",
+            },
+            "tokenColors": [
+                { "settings": { "foreground": "#eeeeee" } },
+                { "scope": "comment, string.quoted", "settings": { "foreground": "#808080", "fontStyle": "italic" } },
+                { "scope": ["keyword", "meta.tag keyword"], "settings": { "foreground": "#ff0000", "fontStyle": "bold underline" } },
+            ],
+        }"##).unwrap();
+
+        assert_eq!(theme.name, "Test");
+        assert_eq!(theme.editor, *CellStyle::fg(grey(0xcc)).set_bg(grey(0x00)));
+        assert_eq!(theme.selection.bg, grey(0x80));
+        assert_eq!(theme.diff_added, CellStyle::fg(Color::Rgb { r: 0, g: 0xff, b: 0 }));
+        assert_eq!(theme.diff_removed, Theme::default().diff_removed);
+
+        let comment = *CellStyle::fg(grey(0x80)).set_attribute(Attribute::Italic);
+        let keyword = *CellStyle::fg(Color::Rgb { r: 0xff, g: 0, b: 0 })
+            .set_attribute(Attribute::Bold)
+            .set_attribute(Attribute::Underlined);
+
+        assert_eq!(theme.syntax.get("comment.block"), Some(comment));
+        assert_eq!(theme.syntax.get("string.quoted.double"), Some(comment));
+        assert_eq!(theme.syntax.get("string.unquoted"), None);
+        assert_eq!(theme.syntax.get("keyword.control"), Some(keyword));
+        // selectors on parent scopes are left out
+        assert_eq!(theme.syntax.get("meta.tag"), None);
+    }
+
+    #[test]
+    fn vscode_editor_colours_fall_back_to_the_default_token() {
+        let theme = vscode(r##"{ "tokenColors": [{ "settings": { "foreground": "#111111", "background": "#222222" } }] }"##).unwrap();
+
+        assert_eq!(theme.editor, *CellStyle::fg(grey(0x11)).set_bg(grey(0x22)));
+        assert_eq!(theme.selection, Theme::default().selection);
+    }
+}
@@ -0,0 +1,417 @@
+use std::{fs, path::Path};
+
+use crossterm::style::{Attribute, Color};
+
+use crate::{config, renderer::cell::CellStyle, syntax::ScopeStyles};
+
+pub mod import;
+
+const THEME_DIR: &str = "themes";
+pub const DEFAULT_THEME: &str = "default";
+pub const DEFAULT_LIGHT_THEME: &str = "tomorrow";
+pub const DEFAULT_DARK_THEME: &str = "tomorrow-night";
+
+const BUILTIN_THEMES: [(&str, &str); 2] = [
+    ("tomorrow-night", include_str!("themes/tomorrow-night.yaml")),
+    ("tomorrow", include_str!("themes/tomorrow.yaml")),
+];
+
+/// The formats a theme file can be in, going by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThemeFormat {
+    Toml,
+    /// A Base16 scheme in YAML.
+    Base16,
+    /// A VS Code colour theme in JSON.
+    VsCode,
+}
+
+impl ThemeFormat {
+    pub fn from_path(path: &Path) -> Option<ThemeFormat> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(ThemeFormat::Toml),
+            "yaml" | "yml" => Some(ThemeFormat::Base16),
+            "json" => Some(ThemeFormat::VsCode),
+            _ => None,
+        }
+    }
+}
+
+/// The colours everything is drawn in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    pub name: String,
+    /// Text and background of editors, and of whatever is drawn without
+    /// colours of its own.
+    pub editor: CellStyle,
+    pub gutter: CellStyle,
+    /// The cursor line's number in the gutter.
+    pub current_line_number: CellStyle,
+    pub selection: CellStyle,
+    pub search_match: CellStyle,
+    /// The search match at the cursor.
+    pub current_match: CellStyle,
+    /// What a closed fold says after its first line.
+    pub folded: CellStyle,
+    /// The bracket matching the one at the cursor, and that one.
+    pub matching_bracket: CellStyle,
+    /// Markers shown for tabs and trailing whitespace.
+    pub whitespace: CellStyle,
+    pub border: Color,
+    pub hovered_border: Color,
+    pub focused_border: Color,
+    pub status_line: CellStyle,
+    pub status_error: CellStyle,
+    /// The file headers of workspace search results.
+    pub results_file: CellStyle,
+    /// The line a hunk of a diff starts at.
+    pub diff_header: CellStyle,
+    pub diff_added: CellStyle,
+    pub diff_removed: CellStyle,
+    pub syntax: ScopeStyles,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            name: DEFAULT_THEME.to_string(),
+            editor: CellStyle::default(),
+            gutter: CellStyle::fg(Color::DarkGrey),
+            current_line_number: *CellStyle::default().set_attribute(Attribute::Bold),
+            selection: *CellStyle::default().set_attribute(Attribute::Reverse),
+            search_match: *CellStyle::fg(Color::Black).set_bg(Color::DarkYellow),
+            current_match: *CellStyle::fg(Color::Black).set_bg(Color::Yellow),
+            folded: CellStyle::fg(Color::DarkGrey),
+            matching_bracket: *CellStyle::default().set_attribute(Attribute::Underlined),
+            whitespace: CellStyle::fg(Color::DarkGrey),
+            border: Color::Reset,
+            hovered_border: Color::DarkGrey,
+            focused_border: Color::Cyan,
+            status_line: *CellStyle::default().set_attribute(Attribute::Reverse),
+            status_error: *CellStyle::fg(Color::White).set_bg(Color::Red),
+            results_file: *CellStyle::fg(Color::Cyan).set_attribute(Attribute::Bold),
+            diff_header: CellStyle::fg(Color::Cyan),
+            diff_added: CellStyle::fg(Color::Green),
+            diff_removed: CellStyle::fg(Color::Red),
+            syntax: ScopeStyles::default(),
+        }
+    }
+}
+
+impl Theme {
+    /// The names of the themes there are, built-in ones and those in the
+    /// `themes` dir of the config dir, in order.
+    pub fn names() -> Vec<String> {
+        let mut names: Vec<String> = BUILTIN_THEMES.iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+
+        if let Some(entries) = config::config_dir().and_then(|dir| fs::read_dir(dir.join(THEME_DIR)).ok()) {
+            names.extend(entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| ThemeFormat::from_path(path).is_some())
+                .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string())));
+        }
+
+        names.sort();
+        names.dedup();
+        names.retain(|name| name != DEFAULT_THEME);
+        names.insert(0, DEFAULT_THEME.to_string());
+        names
+    }
+
+    /// The theme called `name`, from the `themes` dir of the config dir if
+    /// it has one of that name, or else a built-in one.
+    pub fn load(name: &str) -> Result<Theme, String> {
+        let dir = config::config_dir().map(|dir| dir.join(THEME_DIR));
+
+        for extension in ["toml", "yaml", "yml", "json"] {
+            let Some(path) = dir.as_ref().map(|dir| dir.join(format!("{}.{}", name, extension))) else {
+                break;
+            };
+
+            if path.exists() {
+                return Theme::load_file(&path);
+            }
+        }
+
+        if name == DEFAULT_THEME {
+            return Ok(Theme::default());
+        }
+
+        let (_, text) = BUILTIN_THEMES.iter()
+            .find(|(builtin, _)| *builtin == name)
+            .ok_or_else(|| format!("There is no theme called '{}'", name))?;
+
+        Theme::parse(name, text, ThemeFormat::Base16)
+    }
+
+    pub fn load_file(path: &Path) -> Result<Theme, String> {
+        let format = ThemeFormat::from_path(path)
+            .ok_or_else(|| format!("{}: Themes must be .toml, .yaml or .json files", path.display()))?;
+
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+
+        let name = path.file_stem().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+
+        Theme::parse(&name, &text, format)
+            .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn parse(name: &str, text: &str, format: ThemeFormat) -> Result<Theme, String> {
+        let mut theme = match format {
+            ThemeFormat::Toml => Theme::parse_toml(text)?,
+            ThemeFormat::Base16 => import::base16(text)?,
+            ThemeFormat::VsCode => import::vscode(text)?,
+        };
+
+        theme.name = name.to_string();
+        Ok(theme)
+    }
+
+    /// Reads a theme in TOML, where colours are names like `dark_grey`,
+    /// hex like `#1d1f21`, 256 colour indices, or names from the palette:
+    ///
+    /// ```toml
+    /// [palette]
+    /// background = "#1d1f21"
+    ///
+    /// [ui]
+    /// editor = { fg = "#c5c8c6", bg = "background" }
+    /// selection = { bg = "#373b41" }
+    /// focused_border = "blue"
+    ///
+    /// [syntax]
+    /// comment = { fg = "#969896", modifiers = ["italic"] }
+    /// keyword = "#b294bb"
+    /// ```
+    ///
+    /// Slots left out keep their default style.
+    pub fn parse_toml(text: &str) -> Result<Theme, String> {
+        let table: toml::Table = text.parse()
+            .map_err(|err: toml::de::Error| err.message().to_string())?;
+
+        let mut theme = Theme::default();
+        let empty = toml::Table::new();
+
+        let palette = match table.get("palette") {
+            Some(toml::Value::Table(palette)) => palette,
+            Some(_) => return Err("'palette' must be a table".to_string()),
+            None => &empty,
+        };
+
+        for (key, value) in table.iter() {
+            match (key.as_str(), value) {
+                ("palette", _) => (),
+                ("ui", toml::Value::Table(ui)) => {
+                    for (slot, value) in ui.iter() {
+                        let style = parse_style(value, palette)
+                            .map_err(|err| format!("'ui.{}': {}", slot, err))?;
+
+                        match slot.as_str() {
+                            "editor" => theme.editor = style,
+                            "gutter" => theme.gutter = style,
+                            "current_line_number" => theme.current_line_number = style,
+                            "selection" => theme.selection = style,
+                            "search_match" => theme.search_match = style,
+                            "current_match" => theme.current_match = style,
+                            "folded" => theme.folded = style,
+                            "matching_bracket" => theme.matching_bracket = style,
+                            "whitespace" => theme.whitespace = style,
+                            "border" => theme.border = style.fg,
+                            "hovered_border" => theme.hovered_border = style.fg,
+                            "focused_border" => theme.focused_border = style.fg,
+                            "status_line" => theme.status_line = style,
+                            "status_error" => theme.status_error = style,
+                            "results_file" => theme.results_file = style,
+                            "diff_header" => theme.diff_header = style,
+                            "diff_added" => theme.diff_added = style,
+                            "diff_removed" => theme.diff_removed = style,
+                            slot => return Err(format!("Unexpected entry 'ui.{}'", slot)),
+                        }
+                    }
+                }
+                ("syntax", toml::Value::Table(syntax)) => {
+                    for (scope, value) in syntax.iter() {
+                        let style = parse_style(value, palette)
+                            .map_err(|err| format!("'syntax.{}': {}", scope, err))?;
+
+                        theme.syntax.set(scope, style);
+                    }
+                }
+                (key, _) => return Err(format!("Unexpected entry '{}'", key)),
+            }
+        }
+
+        Ok(theme)
+    }
+}
+
+/// A style given as just a foreground colour, or as a table of `fg`, `bg`
+/// and `modifiers`.
+fn parse_style(value: &toml::Value, palette: &toml::Table) -> Result<CellStyle, String> {
+    let entries = match value {
+        toml::Value::Table(entries) => entries,
+        color => return Ok(CellStyle::fg(parse_color(color, palette)?)),
+    };
+
+    let mut style = CellStyle::default();
+
+    for (key, value) in entries.iter() {
+        match (key.as_str(), value) {
+            ("fg", color) => style.fg = parse_color(color, palette)?,
+            ("bg", color) => style.bg = parse_color(color, palette)?,
+            ("modifiers", toml::Value::Array(modifiers)) => {
+                for modifier in modifiers {
+                    let attribute = modifier.as_str()
+                        .and_then(parse_modifier)
+                        .ok_or_else(|| format!("Unknown modifier {}", modifier))?;
+
+                    style.set_attribute(attribute);
+                }
+            }
+            (key, _) => return Err(format!("Unexpected entry '{}'", key)),
+        }
+    }
+
+    Ok(style)
+}
+
+fn parse_color(value: &toml::Value, palette: &toml::Table) -> Result<Color, String> {
+    match value {
+        toml::Value::Integer(index) => u8::try_from(*index)
+            .map(Color::AnsiValue)
+            .map_err(|_| format!("Colour {} is not from 0 to 255", index)),
+        toml::Value::String(name) => {
+            if let Some(value) = palette.get(name) {
+                // palette entries cannot refer to each other
+                return parse_color(value, &toml::Table::new());
+            }
+
+            parse_color_str(name).ok_or_else(|| format!("Unknown colour '{}'", name))
+        }
+        value => Err(format!("Colour {} is not a name or number", value)),
+    }
+}
+
+/// A colour written as `#rgb`, `#rrggbb` or a name like `dark_grey`.
+pub fn parse_color_str(text: &str) -> Option<Color> {
+    if let Some(hex) = text.strip_prefix('#') {
+        return parse_hex(hex);
+    }
+
+    match text {
+        "reset" | "default" => Some(Color::Reset),
+        name => Color::try_from(name).ok(),
+    }
+}
+
+/// `rgb`, `rrggbb`, or `rrggbbaa` with the alpha left off.
+fn parse_hex(hex: &str) -> Option<Color> {
+    if !hex.is_ascii() {
+        return None;
+    }
+
+    let channel = |range: std::ops::Range<usize>| u8::from_str_radix(&hex[range], 16).ok();
+
+    match hex.len() {
+        3 => {
+            let short = |index: usize| channel(index..index + 1).map(|value| value * 17);
+            Some(Color::Rgb { r: short(0)?, g: short(1)?, b: short(2)? })
+        }
+        6 | 8 => Some(Color::Rgb { r: channel(0..2)?, g: channel(2..4)?, b: channel(4..6)? }),
+        _ => None,
+    }
+}
+
+fn parse_modifier(name: &str) -> Option<Attribute> {
+    match name {
+        "bold" => Some(Attribute::Bold),
+        "dim" => Some(Attribute::Dim),
+        "italic" => Some(Attribute::Italic),
+        "underlined" => Some(Attribute::Underlined),
+        "reverse" => Some(Attribute::Reverse),
+        "crossed_out" => Some(Attribute::CrossedOut),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color::Rgb { r, g, b }
+    }
+
+    #[test]
+    fn parses_hex_colours() {
+        assert_eq!(parse_color_str("#1d1f21"), Some(rgb(0x1d, 0x1f, 0x21)));
+        assert_eq!(parse_color_str("#f80"), Some(rgb(0xff, 0x88, 0x00)));
+        assert_eq!(parse_color_str("#1d1f2180"), Some(rgb(0x1d, 0x1f, 0x21)));
+        assert_eq!(parse_hex("1d1f2"), None);
+        assert_eq!(parse_hex("ggg"), None);
+        assert_eq!(parse_hex("é1"), None);
+    }
+
+    #[test]
+    fn parses_colour_names_and_indices() {
+        let palette = toml::Table::new();
+
+        assert_eq!(parse_color(&toml::Value::from("dark_grey"), &palette), Ok(Color::DarkGrey));
+        assert_eq!(parse_color(&toml::Value::from("default"), &palette), Ok(Color::Reset));
+        assert_eq!(parse_color(&toml::Value::from(208), &palette), Ok(Color::AnsiValue(208)));
+        assert_eq!(
+            parse_color(&toml::Value::from(256), &palette),
+            Err("Colour 256 is not from 0 to 255".to_string()),
+        );
+        assert_eq!(
+            parse_color(&toml::Value::from("mauve"), &palette),
+            Err("Unknown colour 'mauve'".to_string()),
+        );
+    }
+
+    #[test]
+    fn fills_in_colours_from_the_palette() {
+        let theme = Theme::parse_toml(r##"
+            [palette]
+            background = "#1d1f21"
+            green = "#b5bd68"
+
+            [ui]
+            editor = { fg = "#c5c8c6", bg = "background" }
+            diff_added = "green"
+            focused_border = "blue"
+
+            [syntax]
+            comment = { fg = "#969896", modifiers = ["italic"] }
+        "##).unwrap();
+
+        assert_eq!(theme.editor, *CellStyle::fg(rgb(0xc5, 0xc8, 0xc6)).set_bg(rgb(0x1d, 0x1f, 0x21)));
+        assert_eq!(theme.diff_added, CellStyle::fg(rgb(0xb5, 0xbd, 0x68)));
+        assert_eq!(theme.focused_border, Color::Blue);
+        assert_eq!(theme.syntax.get("comment.line"), Some(*CellStyle::fg(rgb(0x96, 0x98, 0x96)).set_attribute(Attribute::Italic)));
+        // slots left out keep their default
+        assert_eq!(theme.diff_removed, Theme::default().diff_removed);
+    }
+
+    #[test]
+    fn palette_entries_cannot_refer_to_each_other() {
+        let text = "[palette]\naccent = '#ff0000'\nerror = 'accent'\n[ui]\nstatus_error = 'error'\n";
+
+        assert_eq!(Theme::parse_toml(text).err().as_deref(), Some("'ui.status_error': Unknown colour 'accent'"));
+    }
+
+    #[test]
+    fn rejects_unknown_slots_and_modifiers() {
+        assert_eq!(
+            Theme::parse_toml("[ui]\ngutters = 'red'\n").err().as_deref(),
+            Some("Unexpected entry 'ui.gutters'"),
+        );
+        assert_eq!(
+            Theme::parse_toml("[ui]\ngutter = { modifiers = ['blink'] }\n").err().as_deref(),
+            Some("'ui.gutter': Unknown modifier \"blink\""),
+        );
+    }
+}
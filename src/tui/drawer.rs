@@ -1,13 +1,19 @@
-use super::{style::{border::{Border}, Style, size::{Size2D, Size}, position::{Position2D, Position}, display::{Display, FlexDirection}, flex::Flex}};
+use super::style::{border::{Border}, Style, size::{Size2D, Size}, position::{Position2D, Position}, display::FlexDirection};
 
-use std::{rc::Rc, cell::{RefCell}};
+use std::{rc::Rc, cell::{RefCell, RefMut}};
 
-use crate::renderer::{Renderer, Rect};
+use crossterm::cursor::CursorShape;
+
+use crate::renderer::{Renderer, Rect, cell::{Cell, CellStyle}};
 
 #[derive(Debug, Clone)]
 pub struct Drawer {
     pub boundaries: Rect,
     current_position: (u16, u16),
+    /// Part of the screen the drawer may draw on.
+    clip: Rect,
+    /// How far the nodes above have scrolled what the drawer draws.
+    offset: (u16, u16),
     renderer: Rc<RefCell<Renderer>>,
 }
 
@@ -15,24 +21,39 @@ impl Drawer {
     pub fn new(renderer: Rc<RefCell<Renderer>>) -> Self {
         let boundaries = renderer.borrow().boundaries();
 
+        Drawer::with_boundaries(renderer, boundaries)
+    }
+
+    /// A drawer for one part of the renderer's screen.
+    pub fn with_boundaries(renderer: Rc<RefCell<Renderer>>, boundaries: Rect) -> Self {
         Drawer { 
             boundaries,
             current_position: (0, 0),
+            clip: boundaries,
+            offset: (0, 0),
             renderer,
         }
     }
 
     pub fn draw(&mut self, style: &Style) {
-        let boundaries = self.calc_onscreen_boundaries(style);
+        let boundaries = self.layout(style);
 
         if style.get_border() == Border::Line {
-            self.renderer.borrow_mut().draw_box(boundaries);
+            self.renderer().draw_box(boundaries, Drawer::border_style(style));
         }
+    }
+
+    /// Works out where `draw` puts a node with the style and moves past it
+    /// the same way, without drawing anything.
+    pub fn layout(&mut self, style: &Style) -> Rect {
+        let boundaries = self.calc_onscreen_boundaries(style);
 
         self.current_position = (
             boundaries.x + boundaries.width,
             boundaries.y + boundaries.height
         );
+
+        boundaries
     }
 
     pub fn inner_drawer(&mut self, style: &Style) -> Drawer {
@@ -42,6 +63,100 @@ impl Drawer {
         drawer
     }
 
+    /// A drawer for content scrolled by `scroll` within this one's
+    /// boundaries. Its boundaries stay where they were laid out, but what
+    /// it draws is moved up and left and clipped to this drawer.
+    pub fn scrolled(&self, scroll: (u16, u16)) -> Drawer {
+        let mut drawer = self.clone();
+        drawer.clip = self.onscreen(self.boundaries);
+        drawer.offset = (self.offset.0 + scroll.0, self.offset.1 + scroll.1);
+
+        drawer
+    }
+
+    /// How far the nodes above have scrolled what the drawer draws.
+    pub fn get_offset(&self) -> (u16, u16) {
+        self.offset
+    }
+
+    /// Where a rect laid out by the drawer shows on the screen, cut down
+    /// to the part that is not scrolled or clipped away.
+    pub fn onscreen(&self, rect: Rect) -> Rect {
+        let (offset_x, offset_y) = self.offset;
+        let right = (rect.x + rect.width).saturating_sub(offset_x);
+        let bottom = (rect.y + rect.height).saturating_sub(offset_y);
+        let x = rect.x.saturating_sub(offset_x).min(right);
+        let y = rect.y.saturating_sub(offset_y).min(bottom);
+
+        Rect { x, y, width: right - x, height: bottom - y }.intersection(self.clip)
+    }
+
+    fn renderer(&self) -> RefMut<'_, Renderer> {
+        let mut renderer = self.renderer.borrow_mut();
+        renderer.set_clip(self.clip, self.offset);
+
+        renderer
+    }
+
+    /// Draws a cell relative to the drawer's boundaries, clipped to them.
+    pub fn draw_cell(&mut self, x: u16, y: u16, cell: Cell) {
+        if x >= self.boundaries.width || y >= self.boundaries.height {
+            return;
+        }
+
+        self.renderer().draw_cell_at(self.boundaries.x + x, self.boundaries.y + y, cell);
+    }
+
+    /// Draws text on one row relative to the drawer's boundaries, clipped
+    /// to them. Returns the column after the last char.
+    pub fn draw_text(&mut self, x: u16, y: u16, text: &str, style: CellStyle) -> u16 {
+        let mut x = x;
+
+        for c in text.chars() {
+            self.draw_cell(x, y, Cell { c, style });
+            x = x.saturating_add(1);
+        }
+
+        x
+    }
+
+    /// Shows the terminal cursor relative to the drawer's boundaries, if
+    /// it falls inside them.
+    pub fn set_cursor(&mut self, x: u16, y: u16, shape: CursorShape) {
+        if x >= self.boundaries.width || y >= self.boundaries.height {
+            return;
+        }
+
+        self.renderer().set_cursor(self.boundaries.x + x, self.boundaries.y + y, shape);
+    }
+
+    /// Hints that everything within the drawer's boundaries moved up by
+    /// `lines` since the last frame, or down when negative.
+    pub fn hint_scroll(&mut self, lines: i32) {
+        let onscreen = self.onscreen(self.boundaries);
+
+        // rows partly scrolled or clipped away do not move as a whole
+        if onscreen.height == 0 || onscreen.height != self.boundaries.height || onscreen.width != self.boundaries.width {
+            return;
+        }
+
+        let top = onscreen.y;
+        let bottom = top + onscreen.height - 1;
+
+        self.renderer.borrow_mut().hint_scroll(top, bottom, lines);
+    }
+
+    pub fn outer_boundaries(&mut self, style: &Style) -> Rect {
+        self.calc_onscreen_boundaries(style)
+    }
+
+    fn border_style(style: &Style) -> CellStyle {
+        match style.get_border_color() {
+            Some(color) => CellStyle::fg(color),
+            None => CellStyle::default(),
+        }
+    }
+
     fn calc_inner_onscreen_boundaries(&mut self, style: &Style) -> Rect {
         let mut boundaries = self.calc_onscreen_boundaries(style);
 
@@ -136,21 +251,33 @@ impl Drawer {
         }
     }
 
-    pub fn draw_flex_border(&mut self, style: &Style, x: u16) {
+    /// Draws the border between flex children at `offset` along the main
+    /// axis, joining it to the node's own border if it has one.
+    pub fn draw_flex_border(&mut self, style: &Style, direction: FlexDirection, offset: u16) {
         let boundaries = self.calc_onscreen_boundaries(style);
+        let inner_boundaries = self.calc_inner_onscreen_boundaries(style);
+        let border_style = Drawer::border_style(style);
+        let mut renderer = self.renderer();
 
-        if style.get_border() != Border::None {
-            self.renderer.borrow_mut().draw_box(Rect {
-                x: boundaries.x,
-                y: boundaries.y,
-                width: boundaries.x + x + 2,
-                height: boundaries.height
-            });
-        } else {
-            self.renderer.borrow_mut().draw_v_line(
-                boundaries.y, 
-                boundaries.y + boundaries.height,
-                boundaries.x + x);
+        match (direction, style.get_border() != Border::None) {
+            (FlexDirection::Row, true) => renderer.draw_box(Rect {
+                width: offset + 2,
+                ..boundaries
+            }, border_style),
+            (FlexDirection::Column, true) => renderer.draw_box(Rect {
+                height: offset + 2,
+                ..boundaries
+            }, border_style),
+            (FlexDirection::Row, false) => renderer.draw_v_line(
+                boundaries.y,
+                boundaries.y + boundaries.height.saturating_sub(1),
+                inner_boundaries.x + offset,
+                border_style),
+            (FlexDirection::Column, false) => renderer.draw_h_line(
+                boundaries.x,
+                boundaries.x + boundaries.width.saturating_sub(1),
+                inner_boundaries.y + offset,
+                border_style),
         }
     }
 }
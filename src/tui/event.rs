@@ -0,0 +1,173 @@
+use std::{rc::Rc, cell::RefCell};
+
+use crossterm::event::{KeyEvent, KeyModifiers, MouseEventKind};
+
+use crate::command::Command;
+
+use super::node::Node;
+
+/// A mouse event translated into the space of the node handling it.
+///
+/// `x` and `y` are relative to the node's inner boundaries and can be
+/// negative while a drag started in the node continues outside of it.
+/// `column` and `row` keep the terminal position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeMouseEvent {
+    pub kind: MouseEventKind,
+    pub x: i32,
+    pub y: i32,
+    pub column: u16,
+    pub row: u16,
+    pub modifiers: KeyModifiers,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeEvent {
+    Key(KeyEvent),
+    Command(Command),
+    Mouse(NodeMouseEvent),
+    Paste(String),
+    FocusGained,
+    FocusLost,
+    Resize(u16, u16),
+}
+
+/// Where in its journey along the path an event is being handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Travelling down from the root towards the target.
+    Capture,
+    Target,
+    /// Travelling back up from the target towards the root.
+    Bubble,
+}
+
+/// Dispatches `event` along `path`, which runs from the root to the target.
+///
+/// Ancestors see the event first in the capture phase, then the target,
+/// then the ancestors again in reverse while it bubbles. Dispatch stops as
+/// soon as a node consumes the event; returns true if one did.
+pub fn dispatch(path: &[Rc<RefCell<Node>>], event: &NodeEvent) -> bool {
+    let Some((target, ancestors)) = path.split_last() else {
+        return false;
+    };
+
+    for node in ancestors.iter() {
+        if node.borrow_mut().handle_event(event, Phase::Capture) {
+            return true;
+        }
+    }
+
+    if target.borrow_mut().handle_event(event, Phase::Target) {
+        return true;
+    }
+
+    for node in ancestors.iter().rev() {
+        if node.borrow_mut().handle_event(event, Phase::Bubble) {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Sends `event` to every node under `node` as a target, without bubbling.
+pub fn broadcast(node: &Rc<RefCell<Node>>, event: &NodeEvent) {
+    node.borrow_mut().handle_event(event, Phase::Target);
+
+    let children = node.borrow().get_children().clone();
+
+    for child in children.iter() {
+        broadcast(child, event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tui::{drawer::Drawer, widget::Widget};
+
+    type Log = Rc<RefCell<Vec<(&'static str, Phase)>>>;
+
+    /// Notes every event it sees, and consumes them in one phase.
+    struct Recorder {
+        name: &'static str,
+        log: Log,
+        consume: Option<Phase>,
+    }
+
+    impl Widget for Recorder {
+        fn draw(&mut self, _drawer: &mut Drawer, _scroll: (u16, u16)) {}
+
+        fn handle_event(&mut self, _event: &NodeEvent, phase: Phase) -> bool {
+            self.log.borrow_mut().push((self.name, phase));
+            self.consume == Some(phase)
+        }
+    }
+
+    fn recording(name: &'static str, log: &Log, consume: Option<Phase>) -> Rc<RefCell<Node>> {
+        let node = Rc::new(RefCell::new(Node::default()));
+        let recorder = Recorder { name, log: log.clone(), consume };
+        node.borrow_mut().set_widget(Rc::new(RefCell::new(recorder)));
+
+        node
+    }
+
+    #[test]
+    fn dispatch_captures_targets_and_bubbles() {
+        let log = Log::default();
+        let path = [recording("root", &log, None), recording("parent", &log, None), recording("child", &log, None)];
+
+        assert!(!dispatch(&path, &NodeEvent::FocusGained));
+        assert_eq!(*log.borrow(), [
+            ("root", Phase::Capture),
+            ("parent", Phase::Capture),
+            ("child", Phase::Target),
+            ("parent", Phase::Bubble),
+            ("root", Phase::Bubble),
+        ]);
+    }
+
+    #[test]
+    fn dispatch_stops_once_consumed() {
+        let log = Log::default();
+        let path = [recording("root", &log, None), recording("parent", &log, Some(Phase::Capture)), recording("child", &log, None)];
+
+        assert!(dispatch(&path, &NodeEvent::FocusGained));
+        assert_eq!(*log.borrow(), [("root", Phase::Capture), ("parent", Phase::Capture)]);
+
+        let log = Log::default();
+        let path = [recording("root", &log, None), recording("parent", &log, Some(Phase::Bubble)), recording("child", &log, Some(Phase::Capture))];
+
+        // the target only ever sees the target phase
+        assert!(dispatch(&path, &NodeEvent::FocusGained));
+        assert_eq!(*log.borrow(), [
+            ("root", Phase::Capture),
+            ("parent", Phase::Capture),
+            ("child", Phase::Target),
+            ("parent", Phase::Bubble),
+        ]);
+
+        assert!(!dispatch(&[], &NodeEvent::FocusGained));
+    }
+
+    #[test]
+    fn broadcast_reaches_every_node() {
+        let log = Log::default();
+        let root = recording("root", &log, Some(Phase::Target));
+        let parent = recording("parent", &log, Some(Phase::Target));
+
+        parent.borrow_mut().add_child(recording("first", &log, None));
+        root.borrow_mut().add_child(parent).add_child(recording("second", &log, None));
+
+        // consuming does not stop a broadcast
+        broadcast(&root, &NodeEvent::Resize(80, 24));
+        assert_eq!(*log.borrow(), [
+            ("root", Phase::Target),
+            ("parent", Phase::Target),
+            ("first", Phase::Target),
+            ("second", Phase::Target),
+        ]);
+    }
+}
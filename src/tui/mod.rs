@@ -1,3 +1,5 @@
 pub mod node;
 pub mod style;
 pub mod drawer;
+pub mod event;
+pub mod widget;
@@ -1,13 +1,35 @@
 use std::{rc::Rc, cell::RefCell};
 
-use crate::renderer::{Renderer, Rect};
+use crossterm::event::{MouseEventKind, MouseButton, KeyModifiers};
 
-use super::{style::{Style, display::{Display, FlexDirection}, flex::Flex, size::{Size2D, Size}, position::{Position2D, Position}, border::Border}, drawer::Drawer};
+use crate::{renderer::{Renderer, Rect}, theme::Theme};
+
+use super::{style::{Style, display::{Display, FlexDirection}, flex::Flex, size::{Size2D, Size}, position::{Position2D, Position}, border::Border, overflow::Overflow}, drawer::Drawer, widget::Widget, event::{NodeMouseEvent, NodeEvent, Phase}};
+
+const SCROLL_STEP: u16 = 3;
+const FLEX_ROUNDING: f64 = 1e-6;
 
 #[derive(Default)]
 pub struct Node {
     style: Style,
-    children: Vec<Rc<RefCell<Node>>>
+    children: Vec<Rc<RefCell<Node>>>,
+    widget: Option<Rc<RefCell<dyn Widget>>>,
+    rect: Rect,
+    inner_rect: Rect,
+    /// How far the nodes above had scrolled this one at the last draw.
+    offset: (u16, u16),
+    scroll: (u16, u16),
+    /// How far the children reach right and down from the inner top left
+    /// corner, as laid out at the last draw.
+    children_size: (u16, u16),
+    hovered: bool,
+    focused: bool,
+    focusable: bool,
+    focus_within: bool,
+    tab_index: Option<u16>,
+    flex_scale: f64,
+    flex_borders: Vec<u16>,
+    resizing: Option<usize>,
 }
 
 impl Node {
@@ -25,44 +47,408 @@ impl Node {
         self
     }
 
+    pub fn set_widget(&mut self, widget: Rc<RefCell<dyn Widget>>) -> &mut Self {
+        self.widget = Some(widget);
+        self
+    }
+
+    pub fn get_widget(&self) -> Option<Rc<RefCell<dyn Widget>>> {
+        self.widget.clone()
+    }
+
+    pub fn insert_child(&mut self, index: usize, child: Rc<RefCell<Node>>) -> &mut Self {
+        self.children.insert(index, child);
+        self
+    }
+
+    pub fn replace_child(&mut self, index: usize, child: Rc<RefCell<Node>>) -> Rc<RefCell<Node>> {
+        std::mem::replace(&mut self.children[index], child)
+    }
+
+    pub fn remove_child(&mut self, index: usize) -> Rc<RefCell<Node>> {
+        self.children.remove(index)
+    }
+
+    pub fn get_children(&self) -> &Vec<Rc<RefCell<Node>>> {
+        &self.children
+    }
+
+    /// Outer boundaries on the screen from the last draw, without any part
+    /// scrolled or clipped away by the nodes above.
+    pub fn get_rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// Boundaries inside the border from the last draw, where they were
+    /// laid out before the nodes above scrolled them.
+    pub fn get_inner_rect(&self) -> Rect {
+        self.inner_rect
+    }
+
+    pub fn get_scroll(&self) -> (u16, u16) {
+        self.scroll
+    }
+
+    pub fn set_scroll(&mut self, scroll: (u16, u16)) -> &mut Self {
+        self.scroll = scroll;
+        self.clamp_scroll();
+        self
+    }
+
+    pub fn is_hovered(&self) -> bool {
+        self.hovered
+    }
+
+    pub fn set_hovered(&mut self, hovered: bool) -> &mut Self {
+        self.hovered = hovered;
+        self
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn set_focused(&mut self, focused: bool) -> &mut Self {
+        self.focused = focused;
+        self
+    }
+
+    pub fn is_focusable(&self) -> bool {
+        self.focusable
+    }
+
+    pub fn set_focusable(&mut self, focusable: bool) -> &mut Self {
+        self.focusable = focusable;
+        self
+    }
+
+    pub fn is_focus_within(&self) -> bool {
+        self.focus_within
+    }
+
+    /// Makes the node show as focused while a node under it has focus,
+    /// like a frame around the node taking the keys.
+    pub fn set_focus_within(&mut self, focus_within: bool) -> &mut Self {
+        self.focus_within = focus_within;
+        self
+    }
+
+    /// Whether the node or one under it has focus.
+    pub fn has_focus(&self) -> bool {
+        self.focused || self.children.iter().any(|child| child.borrow().has_focus())
+    }
+
+    pub fn get_tab_index(&self) -> Option<u16> {
+        self.tab_index
+    }
+
+    /// Nodes with a tab index come first in the focus chain, in ascending
+    /// order, followed by the rest in tree order.
+    pub fn set_tab_index(&mut self, tab_index: u16) -> &mut Self {
+        self.tab_index = Some(tab_index);
+        self
+    }
+
+    /// Restyles the node and everything under it in a theme. Borders take
+    /// the theme's border colours, and those of focusable nodes and focus
+    /// frames change with hover and focus.
+    pub fn set_theme(&mut self, theme: &Theme) -> &mut Self {
+        if self.style.get_border() != Border::None || self.style.get_flex_border() != Border::None {
+            self.style.set_border_color(theme.border);
+
+            if self.focusable || self.focus_within {
+                self.style
+                    .set_hover_border_color(theme.hovered_border)
+                    .set_focus_border_color(theme.focused_border);
+            }
+        }
+
+        if let Some(widget) = &self.widget {
+            widget.borrow_mut().set_theme(theme);
+        }
+
+        for child in self.children.iter() {
+            child.borrow_mut().set_theme(theme);
+        }
+
+        self
+    }
+
+    /// The style with any state dependent variants applied.
+    pub fn current_style(&self) -> Style {
+        let mut style = self.style;
+
+        if self.hovered {
+            if let Some(color) = style.get_hover_border_color() {
+                style.set_border_color(color);
+            }
+        }
+
+        if self.focused || self.focus_within && self.has_focus() {
+            if let Some(color) = style.get_focus_border_color() {
+                style.set_border_color(color);
+            }
+        }
+
+        style
+    }
+
+    /// Focusable nodes under `node` in the order tab moves through them.
+    pub fn focus_chain(node: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+        let mut chain = Vec::new();
+        Node::collect_focusable(node, &mut chain);
+
+        // stable, so equal tab indices and unindexed nodes keep tree order
+        chain.sort_by_key(|node| match node.borrow().tab_index {
+            Some(tab_index) => (0, tab_index),
+            None => (1, 0),
+        });
+
+        chain
+    }
+
+    fn collect_focusable(node: &Rc<RefCell<Node>>, chain: &mut Vec<Rc<RefCell<Node>>>) {
+        if node.borrow().focusable {
+            chain.push(node.clone());
+        }
+
+        for child in node.borrow().children.iter() {
+            Node::collect_focusable(child, chain);
+        }
+    }
+
+    /// Returns the path from `node` down to the deepest descendant
+    /// containing the point, or an empty path if `node` does not contain it.
+    pub fn hit_test(node: &Rc<RefCell<Node>>, x: u16, y: u16) -> Vec<Rc<RefCell<Node>>> {
+        let mut path = Vec::new();
+
+        if !node.borrow().rect.contains(x, y) {
+            return path;
+        }
+
+        let mut current = node.clone();
+
+        loop {
+            path.push(current.clone());
+
+            // later children are drawn over earlier ones
+            let next = current.borrow().children.iter()
+                .rev()
+                .find(|child| child.borrow().rect.contains(x, y))
+                .cloned();
+
+            match next {
+                Some(child) => current = child,
+                None => break,
+            }
+        }
+
+        path
+    }
+
+    /// Moves hover from the nodes on `previous` to those on `path`, leaving
+    /// nodes on both hovered.
+    pub fn move_hover(previous: &[Rc<RefCell<Node>>], path: &[Rc<RefCell<Node>>]) {
+        for node in previous.iter() {
+            if !path.iter().any(|other| Rc::ptr_eq(node, other)) {
+                node.borrow_mut().set_hovered(false);
+            }
+        }
+
+        for node in path.iter() {
+            node.borrow_mut().set_hovered(true);
+        }
+    }
+
+    /// Translates a terminal position into the node's inner space.
+    pub fn local_position(&self, x: u16, y: u16) -> (i32, i32) {
+        (
+            x as i32 + self.offset.0 as i32 - self.inner_rect.x as i32,
+            y as i32 + self.offset.1 as i32 - self.inner_rect.y as i32,
+        )
+    }
+
+    /// Handles an event dispatched through the node in the given phase.
+    /// Returns true when the event was consumed.
+    pub fn handle_event(&mut self, event: &NodeEvent, phase: Phase) -> bool {
+        let localised;
+        let event = match event {
+            NodeEvent::Mouse(mouse) => {
+                localised = NodeEvent::Mouse(self.localise_mouse_event(mouse));
+                &localised
+            }
+            event => event,
+        };
+
+        // flex borders sit between the children, so only the parent is ever
+        // the target when one is pressed
+        if let (NodeEvent::Mouse(mouse), Phase::Target) = (event, phase) {
+            if self.handle_flex_border_mouse(mouse) {
+                return true;
+            }
+        }
+
+        if let Some(widget) = &self.widget {
+            if widget.borrow_mut().handle_event(event, phase) {
+                return true;
+            }
+        }
+
+        match (event, phase) {
+            (NodeEvent::Mouse(mouse), Phase::Target | Phase::Bubble) => self.handle_scroll_mouse(mouse),
+            _ => false,
+        }
+    }
+
+    fn localise_mouse_event(&self, event: &NodeMouseEvent) -> NodeMouseEvent {
+        let (x, y) = self.local_position(event.column, event.row);
+
+        NodeMouseEvent { x, y, ..*event }
+    }
+
+    fn handle_scroll_mouse(&mut self, event: &NodeMouseEvent) -> bool {
+        if self.style.get_overflow() != Overflow::Scroll {
+            return false;
+        }
+
+        let horizontal = event.modifiers.contains(KeyModifiers::SHIFT);
+        let (x, y) = self.scroll;
+
+        let scroll = match (event.kind, horizontal) {
+            (MouseEventKind::ScrollUp, false) => (x, y.saturating_sub(SCROLL_STEP)),
+            (MouseEventKind::ScrollDown, false) => (x, y.saturating_add(SCROLL_STEP)),
+            (MouseEventKind::ScrollUp, true) => (x.saturating_sub(SCROLL_STEP), y),
+            (MouseEventKind::ScrollDown, true) => (x.saturating_add(SCROLL_STEP), y),
+            _ => return false,
+        };
+
+        self.set_scroll(scroll);
+        true
+    }
+
+    fn handle_flex_border_mouse(&mut self, event: &NodeMouseEvent) -> bool {
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                self.resizing = self.flex_border_at(event.x, event.y);
+                self.resizing.is_some()
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                let (Some(index), Display::Flex(direction)) = (self.resizing, self.style.get_display()) else {
+                    return false;
+                };
+
+                let pointer = match direction {
+                    FlexDirection::Row => event.x,
+                    FlexDirection::Column => event.y,
+                };
+
+                self.move_flex_border(index, pointer - self.flex_borders[index] as i32);
+                true
+            }
+            MouseEventKind::Up(_) => self.resizing.take().is_some(),
+            _ => false,
+        }
+    }
+
+    /// Width and height of everything the node scrolls: its widget's
+    /// content and its children.
+    fn content_size(&self) -> (u16, u16) {
+        let (width, height) = match &self.widget {
+            Some(widget) => widget.borrow().content_size(),
+            None => (0, 0),
+        };
+
+        (width.max(self.children_size.0), height.max(self.children_size.1))
+    }
+
+    /// Lays the children out on a copy of `drawer` to find how far they
+    /// reach, the same way drawing them will.
+    fn measure_children(&self, drawer: &Drawer) -> (u16, u16) {
+        let mut drawer = drawer.clone();
+        let (x, y) = (drawer.boundaries.x, drawer.boundaries.y);
+
+        self.children.iter().fold((0, 0), |(width, height), child| {
+            let rect = drawer.layout(&child.borrow().current_style());
+
+            (
+                width.max((rect.x + rect.width).saturating_sub(x)),
+                height.max((rect.y + rect.height).saturating_sub(y)),
+            )
+        })
+    }
+
+    fn clamp_scroll(&mut self) {
+        let (content_width, content_height) = self.content_size();
+
+        let max_x = content_width.saturating_sub(self.inner_rect.width);
+        let max_y = content_height.saturating_sub(self.inner_rect.height);
+
+        self.scroll = (self.scroll.0.min(max_x), self.scroll.1.min(max_y));
+    }
+
     pub fn draw(&mut self, drawer: &mut Drawer) {
-        let mut inner_drawer = drawer.inner_drawer(&self.style);
+        let style = self.current_style();
+
+        let rect = drawer.outer_boundaries(&style);
+        self.rect = drawer.onscreen(rect);
+        self.offset = drawer.get_offset();
+
+        let mut inner_drawer = drawer.inner_drawer(&style);
+        self.inner_rect = inner_drawer.boundaries;
 
         if let Display::Flex(direction) = self.style.get_display()  {
             match direction {
-                FlexDirection::Row => self.apply_flex_row(drawer),
-                FlexDirection::Column => self.apply_flex_column(),
+                FlexDirection::Row => self.apply_flex(FlexDirection::Row, drawer),
+                FlexDirection::Column => self.apply_flex(FlexDirection::Column, drawer),
+            }
+        }
+
+        self.children_size = self.measure_children(&inner_drawer);
+        self.clamp_scroll();
+
+        if let Some(widget) = self.widget.clone() {
+            widget.borrow_mut().draw(&mut inner_drawer, self.scroll);
+
+            if self.focused {
+                if let Some((x, y, shape)) = widget.borrow().cursor() {
+                    inner_drawer.set_cursor(x, y, shape);
+                }
             }
         }
 
+        // children move with the scroll and are cut off at the border
+        let mut children_drawer = inner_drawer.scrolled(self.scroll);
+
         for child in self.children.iter() {
-            child.borrow_mut().draw(&mut inner_drawer);
+            child.borrow_mut().draw(&mut children_drawer);
         }
-         
-        drawer.draw(&self.style);
+
+        drawer.draw(&style);
     }
 
     pub fn draw_root(&mut self, renderer: Rc<RefCell<Renderer>>) {
         self.draw(&mut Drawer::new(renderer));
     }
 
-    fn apply_flex_row(&mut self, drawer: &mut Drawer) {
+    fn apply_flex(&mut self, direction: FlexDirection, drawer: &mut Drawer) {
         let boundaries = drawer.inner_drawer(&self.style).boundaries;
 
-        let total_width = boundaries.width;
+        let total_size = match direction {
+            FlexDirection::Row => boundaries.width,
+            FlexDirection::Column => boundaries.height,
+        };
 
         let mut flex_count = 0;
         let mut flex_total = 0.0;
-        let mut non_flex_width = 0;
+        let mut non_flex_size = 0;
 
         for child in self.children.iter() {
             let style = child.borrow().style;
 
             match style.get_flex() {
                 Flex::None => {
-                    let Size2D (width, _) = style.get_size();
-                    let size = Drawer::calc_onscreen_size(width, total_width);
-                    non_flex_width += size;
+                    let size = Node::main_size(style.get_size(), direction);
+                    non_flex_size += Drawer::calc_onscreen_size(size, total_size);
                 }
                 Flex::Value(val) => {
                     flex_count += 1;
@@ -71,46 +457,382 @@ impl Node {
             }
         }
 
-        let mut flex_width = total_width - non_flex_width;
+        let mut flex_size = total_size.saturating_sub(non_flex_size);
 
         if self.get_style().get_flex_border() != Border::None {
-            flex_width -= flex_count - 1;
+            flex_size = flex_size.saturating_sub(flex_count.max(1) - 1);
         }
 
-        let flex_scale = flex_width as f64 / flex_total;
-        let mut x = 0;
+        let flex_scale = flex_size as f64 / flex_total;
+        let mut position = 0;
+
+        self.flex_scale = flex_scale;
+        self.flex_borders.clear();
 
         for child in self.children.iter() {
             let mut child = child.borrow_mut();
 
             if let Flex::Value(val) = child.get_style().get_flex() {
-                let width = (val * flex_scale) as u16;
-                let Size2D (_, height) = child.get_style().get_size();
+                // values traded by moving a border can fall a hair short
+                // of a whole cell
+                let size = Size::Exact((val * flex_scale + FLEX_ROUNDING) as u16);
+                let Size2D (width, height) = child.get_style().get_size();
 
-                child.get_style()
-                    .set_size(Size2D (Size::Exact(width), height));
+                child.get_style().set_size(match direction {
+                    FlexDirection::Row => Size2D (size, height),
+                    FlexDirection::Column => Size2D (width, size),
+                });
             }
 
-            let Size2D (width, _) = child.get_style().get_size();
+            let size = Node::main_size(child.get_style().get_size(), direction);
 
-            child.get_style()
-                .set_position(Position2D (Position::Exact(x), Position::Exact(0)));
+            child.get_style().set_position(match direction {
+                FlexDirection::Row => Position2D (Position::Exact(position), Position::Exact(0)),
+                FlexDirection::Column => Position2D (Position::Exact(0), Position::Exact(position)),
+            });
 
-            x += Drawer::calc_onscreen_size(width, total_width);
+            position += Drawer::calc_onscreen_size(size, total_size);
 
             if self.style.get_flex_border() != Border::None {
-                if x < total_width {
-                    drawer.draw_flex_border(&self.style, x);
+                if position < total_size {
+                    drawer.draw_flex_border(&self.style, direction, position);
                 }
 
-                x += 1;
+                self.flex_borders.push(position);
+                position += 1;
             }
         }
     }
 
-    fn apply_flex_column(&mut self) {
-        let child_styles: Vec<Style> = self.children.iter().map(
-            |child| child.borrow_mut().style
-        ).collect();
+    fn main_size(size: Size2D, direction: FlexDirection) -> Size {
+        let Size2D (width, height) = size;
+
+        match direction {
+            FlexDirection::Row => width,
+            FlexDirection::Column => height,
+        }
+    }
+
+    /// Index of the flex border under a point in the node's inner space.
+    fn flex_border_at(&self, x: i32, y: i32) -> Option<usize> {
+        let Display::Flex(direction) = self.style.get_display() else {
+            return None;
+        };
+
+        let (main, cross, cross_size) = match direction {
+            FlexDirection::Row => (x, y, self.inner_rect.height),
+            FlexDirection::Column => (y, x, self.inner_rect.width),
+        };
+
+        if cross < 0 || cross >= cross_size as i32 {
+            return None;
+        }
+
+        self.flex_borders.iter()
+            .take(self.children.len().saturating_sub(1))
+            .position(|border| *border as i32 == main)
+    }
+
+    /// Moves the flex border after child `index` by `cells`, trading flex
+    /// value between the children on either side of it. Both children must
+    /// be flexible and each keeps at least one cell.
+    pub fn move_flex_border(&mut self, index: usize, cells: i32) {
+        if index + 1 >= self.children.len() || self.flex_scale <= 0.0 {
+            return;
+        }
+
+        let before = self.children[index].clone();
+        let after = self.children[index + 1].clone();
+
+        let (Flex::Value(before_val), Flex::Value(after_val)) = (
+            before.borrow().style.get_flex(),
+            after.borrow().style.get_flex(),
+        ) else {
+            return;
+        };
+
+        let min_val = 1.0 / self.flex_scale;
+        let lower = (min_val - before_val).min(0.0);
+        let upper = (after_val - min_val).max(0.0);
+        let delta = (cells as f64 / self.flex_scale).clamp(lower, upper);
+
+        before.borrow_mut().get_style().set_flex(Flex::Value(before_val + delta));
+        after.borrow_mut().get_style().set_flex(Flex::Value(after_val - delta));
+    }
+
+    /// Returns the path from `node` down to `target`, or an empty path if
+    /// `target` is not in the tree.
+    pub fn path_to(node: &Rc<RefCell<Node>>, target: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+        if Rc::ptr_eq(node, target) {
+            return vec![node.clone()];
+        }
+
+        for child in node.borrow().children.iter() {
+            let mut path = Node::path_to(child, target);
+
+            if !path.is_empty() {
+                path.insert(0, node.clone());
+                return path;
+            }
+        }
+
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tui::event;
+
+    fn node(x: u16, y: u16, width: u16, height: u16) -> Rc<RefCell<Node>> {
+        let node = Rc::new(RefCell::new(Node::default()));
+
+        node.borrow_mut().get_style()
+            .set_position(Position2D(Position::Exact(x), Position::Exact(y)))
+            .set_size(Size2D(Size::Exact(width), Size::Exact(height)));
+
+        node
+    }
+
+    fn draw(root: &Rc<RefCell<Node>>) {
+        let renderer = Rc::new(RefCell::new(Renderer::new()));
+        let boundaries = Rect { x: 0, y: 0, width: 40, height: 20 };
+
+        root.borrow_mut().draw(&mut Drawer::with_boundaries(renderer, boundaries));
+    }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> NodeEvent {
+        NodeEvent::Mouse(NodeMouseEvent {
+            kind,
+            x: column as i32,
+            y: row as i32,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        })
+    }
+
+    fn same(path: &[Rc<RefCell<Node>>], nodes: &[&Rc<RefCell<Node>>]) -> bool {
+        path.len() == nodes.len() && path.iter().zip(nodes).all(|(a, b)| Rc::ptr_eq(a, b))
+    }
+
+    #[test]
+    fn hit_test_finds_the_deepest_node() {
+        let root = node(0, 0, 20, 10);
+        let outer = node(2, 1, 10, 5);
+        let inner = node(0, 0, 3, 2);
+
+        outer.borrow_mut().add_child(inner.clone()).get_style().set_border(Border::Line);
+        root.borrow_mut().add_child(outer.clone());
+        draw(&root);
+
+        // the inner node sits inside the outer one's border
+        assert_eq!(inner.borrow().get_rect(), Rect { x: 3, y: 2, width: 3, height: 2 });
+        assert!(same(&Node::hit_test(&root, 3, 2), &[&root, &outer, &inner]));
+        assert!(same(&Node::hit_test(&root, 2, 1), &[&root, &outer]));
+        assert!(same(&Node::hit_test(&root, 12, 1), &[&root]));
+        assert!(Node::hit_test(&root, 20, 0).is_empty());
+    }
+
+    #[test]
+    fn hit_test_prefers_later_children() {
+        let root = node(0, 0, 20, 10);
+        let below = node(0, 0, 5, 5);
+        let above = node(3, 3, 5, 5);
+
+        root.borrow_mut().add_child(below.clone()).add_child(above.clone());
+        draw(&root);
+
+        assert!(same(&Node::hit_test(&root, 4, 4), &[&root, &above]));
+        assert!(same(&Node::hit_test(&root, 1, 1), &[&root, &below]));
+    }
+
+    #[test]
+    fn hover_moves_between_paths() {
+        let root = node(0, 0, 20, 10);
+        let outer = node(2, 1, 10, 5);
+        let inner = node(0, 0, 3, 2);
+
+        outer.borrow_mut().add_child(inner.clone());
+        root.borrow_mut().add_child(outer.clone());
+        draw(&root);
+
+        let path = Node::hit_test(&root, 8, 4);
+        Node::move_hover(&[], &path);
+        assert!(root.borrow().is_hovered() && outer.borrow().is_hovered());
+        assert!(!inner.borrow().is_hovered());
+
+        let previous = path;
+        let path = Node::hit_test(&root, 15, 8);
+        Node::move_hover(&previous, &path);
+        assert!(root.borrow().is_hovered());
+        assert!(!outer.borrow().is_hovered() && !inner.borrow().is_hovered());
+    }
+
+    #[test]
+    fn drags_stay_with_the_pressed_node() {
+        let root = node(0, 0, 21, 10);
+        let left = Rc::new(RefCell::new(Node::default()));
+        let right = Rc::new(RefCell::new(Node::default()));
+
+        for child in [&left, &right] {
+            child.borrow_mut().get_style()
+                .set_size(Size2D(Size::Auto, Size::Percent(100.0)))
+                .set_flex(Flex::Value(1.0));
+        }
+
+        root.borrow_mut()
+            .add_child(left.clone())
+            .add_child(right.clone())
+            .get_style()
+            .set_display(Display::Flex(FlexDirection::Row))
+            .set_flex_border(Border::Line);
+        draw(&root);
+
+        // the border between the two is only part of the root
+        let path = Node::hit_test(&root, 10, 5);
+        assert!(same(&path, &[&root]));
+        let target = path.last().cloned().expect("Empty path");
+        assert!(event::dispatch(&path, &mouse(MouseEventKind::Down(MouseButton::Left), 10, 5)));
+
+        // dragging over the right child still moves the border
+        assert!(same(&Node::hit_test(&root, 15, 5), &[&root, &right]));
+        let path = Node::path_to(&root, &target);
+        assert!(event::dispatch(&path, &mouse(MouseEventKind::Drag(MouseButton::Left), 15, 5)));
+        assert!(event::dispatch(&path, &mouse(MouseEventKind::Up(MouseButton::Left), 15, 5)));
+        draw(&root);
+
+        assert_eq!(left.borrow().get_rect().width, 15);
+        assert_eq!(right.borrow().get_rect(), Rect { x: 16, y: 0, width: 5, height: 10 });
+        assert!(!event::dispatch(&path, &mouse(MouseEventKind::Drag(MouseButton::Left), 5, 5)));
+    }
+
+    #[test]
+    fn focus_chain_puts_tab_indices_first() {
+        let root = Rc::new(RefCell::new(Node::default()));
+        let nodes: Vec<_> = (0..5).map(|_| Rc::new(RefCell::new(Node::default()))).collect();
+
+        // root > [0 > [1, 2], 3, 4], where 2 is not focusable
+        nodes[0].borrow_mut().add_child(nodes[1].clone()).add_child(nodes[2].clone());
+        root.borrow_mut()
+            .add_child(nodes[0].clone())
+            .add_child(nodes[3].clone())
+            .add_child(nodes[4].clone());
+
+        for index in [0, 1, 3, 4] {
+            nodes[index].borrow_mut().set_focusable(true);
+        }
+
+        assert!(same(&Node::focus_chain(&root), &[&nodes[0], &nodes[1], &nodes[3], &nodes[4]]));
+
+        nodes[4].borrow_mut().set_tab_index(2);
+        nodes[3].borrow_mut().set_tab_index(1);
+        nodes[1].borrow_mut().set_tab_index(2);
+
+        // equal indices keep tree order, and unindexed nodes come last
+        assert!(same(&Node::focus_chain(&root), &[&nodes[3], &nodes[1], &nodes[4], &nodes[0]]));
+    }
+
+    fn flex_row(width: u16, count: usize) -> (Rc<RefCell<Node>>, Vec<Rc<RefCell<Node>>>) {
+        let root = node(0, 0, width, 10);
+        let children: Vec<_> = (0..count).map(|_| Rc::new(RefCell::new(Node::default()))).collect();
+
+        for child in children.iter() {
+            child.borrow_mut().get_style()
+                .set_size(Size2D(Size::Auto, Size::Percent(100.0)))
+                .set_flex(Flex::Value(1.0));
+            root.borrow_mut().add_child(child.clone());
+        }
+
+        root.borrow_mut().get_style()
+            .set_display(Display::Flex(FlexDirection::Row))
+            .set_flex_border(Border::Line);
+        draw(&root);
+
+        (root, children)
+    }
+
+    fn widths(children: &[Rc<RefCell<Node>>]) -> Vec<u16> {
+        children.iter().map(|child| child.borrow().get_rect().width).collect()
+    }
+
+    #[test]
+    fn finds_flex_borders_between_children() {
+        let (root, children) = flex_row(32, 3);
+        assert_eq!(widths(&children), [10, 10, 10]);
+
+        let root = root.borrow();
+        assert_eq!(root.flex_border_at(10, 0), Some(0));
+        assert_eq!(root.flex_border_at(21, 9), Some(1));
+        assert_eq!(root.flex_border_at(32, 0), None);
+        assert_eq!(root.flex_border_at(5, 0), None);
+        assert_eq!(root.flex_border_at(10, 10), None);
+        assert_eq!(root.flex_border_at(10, -1), None);
+    }
+
+    #[test]
+    fn moving_flex_borders_resizes_neighbours() {
+        let (root, children) = flex_row(32, 3);
+
+        root.borrow_mut().move_flex_border(0, 4);
+        draw(&root);
+        assert_eq!(widths(&children), [14, 6, 10]);
+
+        root.borrow_mut().move_flex_border(0, -2);
+        draw(&root);
+        assert_eq!(widths(&children), [12, 8, 10]);
+
+        // the last child keeps its place while the first two trade cells
+        assert_eq!(children[2].borrow().get_rect().x, 22);
+    }
+
+    #[test]
+    fn moving_flex_borders_keeps_a_cell() {
+        let (root, children) = flex_row(32, 3);
+
+        root.borrow_mut().move_flex_border(0, -100);
+        draw(&root);
+        assert_eq!(widths(&children), [1, 19, 10]);
+
+        root.borrow_mut().move_flex_border(1, 100);
+        draw(&root);
+        assert_eq!(widths(&children), [1, 28, 1]);
+
+        // there is no border after the last child
+        root.borrow_mut().move_flex_border(2, 5);
+        draw(&root);
+        assert_eq!(widths(&children), [1, 28, 1]);
+    }
+
+    #[test]
+    fn containers_scroll_their_children() {
+        let root = node(0, 0, 10, 4);
+        let rows: Vec<_> = (0..10).map(|y| node(0, y, 10, 1)).collect();
+
+        for row in rows.iter() {
+            root.borrow_mut().add_child(row.clone());
+        }
+
+        root.borrow_mut().get_style().set_overflow(Overflow::Scroll);
+        draw(&root);
+
+        // the wheel over a row bubbles up to the container
+        let path = Node::hit_test(&root, 0, 0);
+        assert!(event::dispatch(&path, &mouse(MouseEventKind::ScrollDown, 0, 0)));
+        assert_eq!(root.borrow().get_scroll(), (0, 3));
+        draw(&root);
+
+        // rows scrolled above or below the container are clipped away
+        assert_eq!(rows[3].borrow().get_rect(), Rect { x: 0, y: 0, width: 10, height: 1 });
+        assert_eq!(rows[6].borrow().get_rect(), Rect { x: 0, y: 3, width: 10, height: 1 });
+        assert_eq!(rows[2].borrow().get_rect().height, 0);
+        assert_eq!(rows[7].borrow().get_rect().height, 0);
+        assert!(same(&Node::hit_test(&root, 0, 1), &[&root, &rows[4]]));
+        assert_eq!(rows[4].borrow().local_position(2, 1), (2, 0));
+
+        root.borrow_mut().set_scroll((0, 100));
+        assert_eq!(root.borrow().get_scroll(), (0, 6));
     }
 }
@@ -4,6 +4,7 @@ pub enum Border {
     Line
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for Border {
     fn default() -> Self {
         Border::None
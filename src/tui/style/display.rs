@@ -4,6 +4,7 @@ pub enum Display {
     Flex(FlexDirection)
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for Display {
     fn default() -> Self {
         Display::Block 
@@ -4,6 +4,7 @@ pub enum Flex {
     Value(f64)
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for Flex {
     fn default() -> Self {
         Flex::None
@@ -1,10 +1,13 @@
-use self::{position::Position2D, size::Size2D, border::{Border}, display::Display, flex::Flex};
+use crossterm::style::Color;
+
+use self::{position::Position2D, size::Size2D, border::{Border}, display::Display, flex::Flex, overflow::Overflow};
 
 pub mod border;
 pub mod position;
 pub mod size;
 pub mod display;
 pub mod flex;
+pub mod overflow;
 
 #[derive(Default, Clone, Copy)]
 pub struct Style {
@@ -14,6 +17,10 @@ pub struct Style {
     border: Border,
     flex_border: Border,
     flex: Flex,
+    overflow: Overflow,
+    border_color: Option<Color>,
+    hover_border_color: Option<Color>,
+    focus_border_color: Option<Color>,
 }
 
 impl Style {
@@ -70,4 +77,40 @@ impl Style {
     pub fn get_border(&self) -> Border {
         self.border
     }
-}
\ No newline at end of file
+
+    pub fn set_overflow(&mut self, overflow: Overflow) -> &mut Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn get_overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    pub fn set_border_color(&mut self, border_color: Color) -> &mut Self {
+        self.border_color = Some(border_color);
+        self
+    }
+
+    pub fn get_border_color(&self) -> Option<Color> {
+        self.border_color
+    }
+
+    pub fn set_hover_border_color(&mut self, hover_border_color: Color) -> &mut Self {
+        self.hover_border_color = Some(hover_border_color);
+        self
+    }
+
+    pub fn get_hover_border_color(&self) -> Option<Color> {
+        self.hover_border_color
+    }
+
+    pub fn set_focus_border_color(&mut self, focus_border_color: Color) -> &mut Self {
+        self.focus_border_color = Some(focus_border_color);
+        self
+    }
+
+    pub fn get_focus_border_color(&self) -> Option<Color> {
+        self.focus_border_color
+    }
+}
@@ -0,0 +1,13 @@
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Overflow {
+    Visible,
+    Hidden,
+    Scroll
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for Overflow {
+    fn default() -> Self {
+        Overflow::Visible
+    }
+}
@@ -5,6 +5,7 @@ pub enum Position {
     Percent(f64),
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for Position {
     fn default() -> Self {
         Position::Auto
@@ -5,6 +5,7 @@ pub enum Size {
     Percent(f64),
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for Size {
     fn default() -> Self {
         Size::Auto
@@ -0,0 +1,36 @@
+use crossterm::cursor::CursorShape;
+
+use crate::theme::Theme;
+
+use super::{drawer::Drawer, event::{NodeEvent, Phase}};
+
+/// Content drawn inside a node's inner boundaries.
+pub trait Widget {
+    fn draw(&mut self, drawer: &mut Drawer, scroll: (u16, u16));
+
+    /// Width and height of the content, used to clamp scrolling.
+    fn content_size(&self) -> (u16, u16) {
+        (0, 0)
+    }
+
+    /// Called for events dispatched through the widget's node. Returns
+    /// true when the event was consumed.
+    fn handle_event(&mut self, _event: &NodeEvent, _phase: Phase) -> bool {
+        false
+    }
+
+    /// Keymap context the widget's keys are looked up in while its node is
+    /// on the focus path, such as the editor's current mode.
+    fn key_context(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Where to show the terminal cursor, relative to the node's inner
+    /// boundaries, while the widget's node has focus.
+    fn cursor(&self) -> Option<(u16, u16, CursorShape)> {
+        None
+    }
+
+    /// Called when the widget's node is given a theme to draw in.
+    fn set_theme(&mut self, _theme: &Theme) {}
+}
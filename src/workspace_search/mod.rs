@@ -0,0 +1,265 @@
+use std::{
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver, Sender}, Arc},
+    thread,
+};
+
+use ignore::WalkBuilder;
+
+use crate::editor::search::Search;
+
+use self::replace::FileReplace;
+
+pub mod replace;
+pub mod results;
+
+/// Files larger than this in bytes are not searched.
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+/// How far into a file to look for a NUL byte marking it as binary.
+const BINARY_CHECK_LEN: usize = 8 * 1024;
+/// Matched lines longer than this in bytes are cut short for display.
+const MAX_LINE_LEN: usize = 512;
+
+/// A line with at least one match in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineMatch {
+    /// Zero-based line number.
+    pub line: usize,
+    /// Char column of the first match.
+    pub column: usize,
+    pub text: String,
+    /// Byte ranges of the matches within `text`.
+    pub ranges: Vec<Range<usize>>,
+}
+
+/// The matched lines of one file, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMatches {
+    pub path: PathBuf,
+    pub lines: Vec<LineMatch>,
+}
+
+impl FileMatches {
+    pub fn match_count(&self) -> usize {
+        self.lines.iter().map(|line| line.ranges.len()).sum()
+    }
+}
+
+/// What a scan reports while it walks the tree.
+#[derive(Debug, Clone)]
+pub enum ScanEvent {
+    Matches(FileMatches),
+    /// A file with replacements to review.
+    Replace(FileReplace),
+    /// The scan finished having searched this many files.
+    Done(usize),
+}
+
+/// A search over the files under a directory, or the replacements in the
+/// files it found, run on another thread so the results can be shown as
+/// they come in.
+///
+/// Files are walked in name order, skipping whatever `.gitignore` and
+/// similar files exclude, hidden files, and files that look binary. The
+/// scan stops early once the handle is dropped.
+#[derive(Debug)]
+pub struct Scan {
+    receiver: Receiver<ScanEvent>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Scan {
+    pub fn start(root: PathBuf, search: Search) -> Scan {
+        Scan::spawn(move |sender, cancelled| scan(&root, &search, sender, cancelled))
+    }
+
+    /// Works out the replacements in each of `paths` in turn, reporting
+    /// those with anything to replace.
+    pub fn replace(paths: Vec<PathBuf>, search: Search, replacement: String) -> Scan {
+        Scan::spawn(move |sender, cancelled| {
+            for path in &paths {
+                if cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+
+                let Some(replace) = FileReplace::new(path, &search, &replacement) else {
+                    continue;
+                };
+
+                if sender.send(ScanEvent::Replace(replace)).is_err() {
+                    return;
+                }
+            }
+
+            let _ = sender.send(ScanEvent::Done(paths.len()));
+        })
+    }
+
+    fn spawn(run: impl FnOnce(&Sender<ScanEvent>, &AtomicBool) + Send + 'static) -> Scan {
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let scan_cancelled = cancelled.clone();
+
+        thread::spawn(move || run(&sender, &scan_cancelled));
+
+        Scan { receiver, cancelled }
+    }
+
+    /// What the scan has reported since the last poll, without waiting.
+    pub fn poll(&self) -> Vec<ScanEvent> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+impl Drop for Scan {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+fn scan(root: &Path, search: &Search, sender: &Sender<ScanEvent>, cancelled: &AtomicBool) {
+    let walk = WalkBuilder::new(root)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+
+    let mut searched = 0;
+
+    for entry in walk {
+        if cancelled.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let Ok(entry) = entry else {
+            continue;
+        };
+
+        if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+            continue;
+        }
+
+        let Some(text) = read_text(entry.path()) else {
+            continue;
+        };
+
+        searched += 1;
+        let lines = match_lines(search, &text);
+
+        if lines.is_empty() {
+            continue;
+        }
+
+        // shorter paths read better, and still open from the working dir
+        let path = entry.path().strip_prefix(root).unwrap_or(entry.path()).to_path_buf();
+
+        if sender.send(ScanEvent::Matches(FileMatches { path, lines })).is_err() {
+            return;
+        }
+    }
+
+    let _ = sender.send(ScanEvent::Done(searched));
+}
+
+/// The file's text, or None if it is too large, binary or not UTF-8.
+pub fn read_text(path: &Path) -> Option<String> {
+    if fs::metadata(path).ok()?.len() > MAX_FILE_SIZE {
+        return None;
+    }
+
+    let bytes = fs::read(path).ok()?;
+
+    if bytes[..bytes.len().min(BINARY_CHECK_LEN)].contains(&0) {
+        return None;
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// The lines of `text` holding matches, with each match attributed to the
+/// line it starts on.
+fn match_lines(search: &Search, text: &str) -> Vec<LineMatch> {
+    let mut lines: Vec<LineMatch> = Vec::new();
+    let mut line = 0;
+    let mut line_start = 0;
+
+    for found in search.matches(text) {
+        // move on to the line the match starts on
+        while let Some(offset) = text[line_start..found.start].find('\n') {
+            line += 1;
+            line_start += offset + 1;
+        }
+
+        let line_end = text[line_start..].find('\n').map_or(text.len(), |offset| line_start + offset);
+        let line_text = text[line_start..line_end].trim_end_matches('\r');
+        let start = found.start - line_start;
+        let end = (found.end - line_start).min(line_text.len());
+
+        match lines.last_mut() {
+            Some(last) if last.line == line => {
+                let len = last.text.len();
+                last.ranges.push(start.min(len)..end.min(len));
+            }
+            _ => {
+                // matches past a cut short line are still counted
+                let text = truncate(line_text, MAX_LINE_LEN);
+                let len = text.len();
+
+                lines.push(LineMatch {
+                    line,
+                    column: line_text[..start.min(line_text.len())].chars().count(),
+                    text: text.to_string(),
+                    ranges: std::iter::once(start.min(len)..end.min(len)).collect(),
+                });
+            }
+        }
+    }
+
+    lines
+}
+
+/// The start of `text`, at most `len` bytes long, ending on a char
+/// boundary.
+fn truncate(text: &str, len: usize) -> &str {
+    if text.len() <= len {
+        return text;
+    }
+
+    let mut end = len;
+
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SearchConfig;
+
+    fn search(pattern: &str) -> Search {
+        Search::new(pattern, SearchConfig::default()).expect("Valid pattern")
+    }
+
+    #[test]
+    fn groups_matches_by_line() {
+        let lines = match_lines(&search("ab"), "ab ab\r\nno\nxé ab\n");
+
+        assert_eq!(lines, [
+            LineMatch { line: 0, column: 0, text: "ab ab".to_string(), ranges: vec![0..2, 3..5] },
+            LineMatch { line: 2, column: 3, text: "xé ab".to_string(), ranges: std::iter::once(4..6).collect() },
+        ]);
+    }
+
+    #[test]
+    fn cuts_long_lines_short() {
+        let text = format!("{}ab", "x".repeat(MAX_LINE_LEN));
+        let lines = match_lines(&search("ab"), &text);
+
+        assert_eq!(lines[0].text.len(), MAX_LINE_LEN);
+        assert_eq!(lines[0].ranges.first(), Some(&(MAX_LINE_LEN..MAX_LINE_LEN)));
+        assert_eq!(truncate("aé", 2), "a");
+    }
+}
@@ -0,0 +1,190 @@
+use std::{fs, io, ops::Range, path::{Path, PathBuf}};
+
+use crate::{buffer::Buffer, document, editor::search::Search};
+
+/// Neighbouring lines changed together, as they read before and after.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// Zero-based number of the first line.
+    pub line: usize,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+/// The replacements a search makes in one file, kept with the text they
+/// were worked out from so they are only applied if it is unchanged.
+#[derive(Debug, Clone)]
+pub struct FileReplace {
+    path: PathBuf,
+    search: Search,
+    replacement: String,
+    original: String,
+    /// Byte ranges in the original text and what replaces them.
+    edits: Vec<(Range<usize>, String)>,
+    hunks: Vec<Hunk>,
+}
+
+impl FileReplace {
+    /// The replacements in the file at `path`, or None if it cannot be read
+    /// or has nothing to replace.
+    pub fn new(path: &Path, search: &Search, replacement: &str) -> Option<FileReplace> {
+        let original = super::read_text(path)?;
+        let edits = search.replacements(&original, replacement);
+
+        if edits.is_empty() {
+            return None;
+        }
+
+        let hunks = hunks(&original, &edits);
+
+        Some(FileReplace {
+            path: path.to_path_buf(),
+            search: search.clone(),
+            replacement: replacement.to_string(),
+            original,
+            edits,
+            hunks,
+        })
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_hunks(&self) -> &[Hunk] {
+        &self.hunks
+    }
+
+    pub fn edit_count(&self) -> usize {
+        self.edits.len()
+    }
+
+    /// Writes the replaced text over the file, unless the file changed
+    /// since the replacements were worked out.
+    pub fn apply_to_file(&self) -> io::Result<()> {
+        if fs::read_to_string(&self.path)? != self.original {
+            return Err(io::Error::other("it changed since the preview"));
+        }
+
+        let mut text = String::with_capacity(self.original.len());
+        let mut end = 0;
+
+        for (range, with) in &self.edits {
+            text.push_str(&self.original[end..range.start]);
+            text.push_str(with);
+            end = range.end;
+        }
+
+        text.push_str(&self.original[end..]);
+        document::write_atomic(&self.path, text.as_bytes())
+    }
+
+    /// The replacements as char ranges in a buffer open on the file, or
+    /// None if the buffer no longer holds the text that was previewed.
+    ///
+    /// Buffers keep LF line endings and no final newline, so the file's
+    /// text is compared in that form.
+    pub fn buffer_edits(&self, buffer: &Buffer) -> Option<Vec<(Range<usize>, String)>> {
+        let mut original = self.original.replace("\r\n", "\n");
+
+        if original.ends_with('\n') {
+            original.pop();
+        }
+
+        if *buffer.get_rope() != original.as_str() {
+            return None;
+        }
+
+        let edits = self.search.replacements(&original, &self.replacement)
+            .into_iter()
+            .map(|(range, with)| (buffer.byte_to_char(range.start)..buffer.byte_to_char(range.end), with))
+            .collect();
+
+        Some(edits)
+    }
+}
+
+/// Groups the edits by the lines they touch, with those lines as they read
+/// before and after the edits.
+fn hunks(text: &str, edits: &[(Range<usize>, String)]) -> Vec<Hunk> {
+    let newlines: Vec<usize> = text.match_indices('\n').map(|(index, _)| index).collect();
+    let line_of = |byte: usize| newlines.partition_point(|newline| *newline < byte);
+    let line_start = |line: usize| if line == 0 { 0 } else { newlines[line - 1] + 1 };
+    let line_end = |line: usize| newlines.get(line).copied().unwrap_or(text.len());
+
+    // edits on the same or touching lines share a hunk, kept as the lines
+    // and the indices of the edits
+    let mut groups: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+
+    for (index, (range, _)) in edits.iter().enumerate() {
+        let lines = line_of(range.start)..line_of(range.end) + 1;
+
+        match groups.last_mut() {
+            Some((group, members)) if lines.start <= group.end => {
+                group.end = group.end.max(lines.end);
+                members.end = index + 1;
+            }
+            _ => groups.push((lines, index..index + 1)),
+        }
+    }
+
+    let split = |text: &str| text.split('\n')
+        .map(|line| line.trim_end_matches('\r').to_string())
+        .collect();
+
+    groups.into_iter()
+        .map(|(lines, members)| {
+            let start = line_start(lines.start);
+            let end = line_end(lines.end - 1);
+
+            let mut after = String::new();
+            let mut position = start;
+
+            for (range, with) in &edits[members] {
+                after.push_str(&text[position..range.start]);
+                after.push_str(with);
+                position = range.end;
+            }
+
+            after.push_str(&text[position..end]);
+
+            Hunk {
+                line: lines.start,
+                removed: split(&text[start..end]),
+                added: split(&after),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hunk(line: usize, removed: &[&str], added: &[&str]) -> Hunk {
+        Hunk {
+            line,
+            removed: removed.iter().map(|line| line.to_string()).collect(),
+            added: added.iter().map(|line| line.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn groups_edits_on_touching_lines() {
+        let text = "a\nb\r\nc\nd\ne\n";
+        let edits = [(0..1, "A".to_string()), (2..3, "B".to_string()), (9..10, "E\nE".to_string())];
+
+        assert_eq!(hunks(text, &edits), [
+            hunk(0, &["a", "b"], &["A", "B"]),
+            hunk(4, &["e"], &["E", "E"]),
+        ]);
+    }
+
+    #[test]
+    fn edits_spanning_lines_take_them_all() {
+        let text = "one\ntwo\nthree";
+        let edits = [(2..9, String::new())];
+
+        assert_eq!(hunks(text, &edits), [hunk(0, &["one", "two", "three"], &["onhree"])]);
+    }
+}
@@ -0,0 +1,619 @@
+use std::{collections::VecDeque, ops::Range, path::PathBuf};
+
+use crossterm::{cursor::CursorShape, event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind}, style::Attribute};
+
+use crate::{
+    buffer::Point,
+    command::Command,
+    config::SearchConfig,
+    editor::{grapheme, prompt::Prompt, search::{self, Search}},
+    keymap::{PROMPT_CONTEXT, RESULTS_CONTEXT},
+    renderer::cell::{Cell, CellStyle},
+    theme::Theme,
+    tui::{drawer::Drawer, event::{NodeEvent, NodeMouseEvent, Phase}, widget::Widget},
+};
+
+use super::{replace::FileReplace, FileMatches, Scan, ScanEvent};
+
+const WHEEL_ROWS: usize = 3;
+/// Columns given to line numbers before the text of a match.
+const LINE_NUMBER_WIDTH: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PromptAction {
+    Search,
+    Replace,
+}
+
+/// A row of the results list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Row {
+    File(usize),
+    Line(usize, usize),
+}
+
+/// Files to replace in, shown one at a time to be applied or skipped as
+/// the scan works them out.
+#[derive(Debug)]
+struct Review {
+    /// Until every file has been worked out.
+    scan: Option<Scan>,
+    pending: VecDeque<FileReplace>,
+    current: Option<FileReplace>,
+    /// Whether the rest are applied without showing them.
+    accept_all: bool,
+    /// How many files there were to look at, and the number of the current
+    /// one.
+    total: usize,
+    index: usize,
+    accepted: usize,
+    scroll: usize,
+}
+
+/// Matches of a search across the files under a directory, grouped by
+/// file, which come in while the scan runs.
+///
+/// Replacing goes through the files with matches one at a time, showing
+/// a diff of each to apply or skip. Applied replacements are handed to
+/// whoever owns the pane through `take_applied`, as are results to jump
+/// to through `take_jump`.
+#[derive(Debug)]
+pub struct SearchResults {
+    root: PathBuf,
+    config: SearchConfig,
+    prompt: Option<(PromptAction, Prompt)>,
+    search: Option<Search>,
+    scan: Option<Scan>,
+    files: Vec<FileMatches>,
+    /// Files searched, once the scan has finished.
+    searched: Option<usize>,
+    selected: usize,
+    scroll: usize,
+    size: (u16, u16),
+    review: Option<Review>,
+    jump: Option<(PathBuf, Point)>,
+    applied: Vec<FileReplace>,
+    error: Option<String>,
+    message: Option<String>,
+    theme: Theme,
+}
+
+impl SearchResults {
+    pub fn new(root: PathBuf, config: SearchConfig) -> Self {
+        SearchResults {
+            root,
+            config,
+            prompt: None,
+            search: None,
+            scan: None,
+            files: Vec::new(),
+            searched: None,
+            selected: 0,
+            scroll: 0,
+            size: (0, 0),
+            review: None,
+            jump: None,
+            applied: Vec::new(),
+            error: None,
+            message: None,
+            theme: Theme::default(),
+        }
+    }
+
+    /// Asks for a pattern to search the files for.
+    pub fn start_search(&mut self) {
+        self.review = None;
+        self.prompt = Some((PromptAction::Search, Prompt::new(search::prompt_label("search files", self.config, false))));
+    }
+
+    /// Takes in whatever the scans found since the last poll.
+    pub fn poll(&mut self) {
+        for event in self.scan.as_ref().map(Scan::poll).unwrap_or_default() {
+            match event {
+                ScanEvent::Matches(matches) => self.files.push(matches),
+                ScanEvent::Replace(_) => (),
+                ScanEvent::Done(searched) => {
+                    self.searched = Some(searched);
+                    self.scan = None;
+                }
+            }
+        }
+
+        let Some(review) = &mut self.review else {
+            return;
+        };
+
+        for event in review.scan.as_ref().map(Scan::poll).unwrap_or_default() {
+            match event {
+                ScanEvent::Replace(replace) => review.pending.push_back(replace),
+                ScanEvent::Done(_) => review.scan = None,
+                ScanEvent::Matches(_) => (),
+            }
+        }
+
+        if review.current.is_none() {
+            self.review_next();
+        }
+    }
+
+    /// The file and position of a result chosen to be opened.
+    pub fn take_jump(&mut self) -> Option<(PathBuf, Point)> {
+        self.jump.take()
+    }
+
+    /// Replacements accepted since last taken, for the files to be changed.
+    pub fn take_applied(&mut self) -> Vec<FileReplace> {
+        std::mem::take(&mut self.applied)
+    }
+
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    pub fn take_message(&mut self) -> Option<String> {
+        self.message.take()
+    }
+
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+
+        for (file, matches) in self.files.iter().enumerate() {
+            rows.push(Row::File(file));
+            rows.extend((0..matches.lines.len()).map(|line| Row::Line(file, line)));
+        }
+
+        rows
+    }
+
+    /// Rows the results list has to show itself in, below the summary.
+    fn list_height(&self) -> usize {
+        (self.size.1 as usize).saturating_sub(1)
+    }
+
+    fn select(&mut self, row: isize) {
+        let len = self.rows().len();
+        self.selected = row.clamp(0, len.saturating_sub(1) as isize) as usize;
+    }
+
+    fn open_selected(&mut self) {
+        let Some(row) = self.rows().get(self.selected).copied() else {
+            return;
+        };
+
+        let (file, line) = match row {
+            Row::File(file) => (file, 0),
+            Row::Line(file, line) => (file, line),
+        };
+
+        let matches = &self.files[file];
+        let line = &matches.lines[line];
+
+        self.jump = Some((self.root.join(&matches.path), Point::new(line.line, line.column)));
+    }
+
+    fn accept_prompt(&mut self, action: PromptAction, text: &str) {
+        match action {
+            PromptAction::Search => {
+                // an empty pattern searches again, as after replacing
+                let search = match text.is_empty() {
+                    true => self.search.as_ref().map(|search| Search::new(search.get_pattern(), self.config)),
+                    false => Some(Search::new(text, self.config)),
+                };
+
+                match search {
+                    Some(Ok(search)) => {
+                        self.files.clear();
+                        self.searched = None;
+                        self.selected = 0;
+                        self.scroll = 0;
+                        self.scan = Some(Scan::start(self.root.clone(), search.clone()));
+                        self.search = Some(search);
+                    }
+                    Some(Err(err)) => self.error = Some(format!("Invalid pattern: {}", err)),
+                    None => (),
+                }
+            }
+            PromptAction::Replace => {
+                // preserving case may have been toggled since searching
+                let Some(Ok(search)) = self.search.as_ref().map(|search| Search::new(search.get_pattern(), self.config)) else {
+                    return;
+                };
+
+                let paths: Vec<PathBuf> = self.files.iter()
+                    .map(|matches| self.root.join(&matches.path))
+                    .collect();
+
+                self.review = Some(Review {
+                    total: paths.len(),
+                    scan: Some(Scan::replace(paths, search, text.to_string())),
+                    pending: VecDeque::new(),
+                    current: None,
+                    accept_all: false,
+                    index: 0,
+                    accepted: 0,
+                    scroll: 0,
+                });
+
+                self.review_next();
+            }
+        }
+    }
+
+    /// Moves the review on to the next file worked out, or applies every
+    /// one left once all were accepted, finishing the review when the scan
+    /// has none left.
+    fn review_next(&mut self) {
+        let Some(review) = &mut self.review else {
+            return;
+        };
+
+        review.current = None;
+        review.scroll = 0;
+
+        if review.accept_all {
+            review.accepted += review.pending.len();
+            self.applied.extend(review.pending.drain(..));
+        } else if let Some(replace) = review.pending.pop_front() {
+            review.index += 1;
+            review.current = Some(replace);
+            return;
+        }
+
+        if review.scan.is_none() {
+            self.finish_review();
+        }
+    }
+
+    fn finish_review(&mut self) {
+        if let Some(review) = self.review.take() {
+            self.message = Some(format!("Replacing in {} of {} files", review.accepted, review.total));
+        }
+    }
+
+    fn handle_review_key(&mut self, event: &KeyEvent) {
+        let Some(review) = &mut self.review else {
+            return;
+        };
+
+        match event.code {
+            KeyCode::Char('y') if review.current.is_some() => {
+                self.applied.extend(review.current.take());
+                review.accepted += 1;
+                self.review_next();
+            }
+            KeyCode::Char('n') if review.current.is_some() => self.review_next(),
+            KeyCode::Char('a') => {
+                review.accepted += review.current.is_some() as usize;
+                self.applied.extend(review.current.take());
+                review.accept_all = true;
+                self.review_next();
+            }
+            KeyCode::Char('q') | KeyCode::Esc => self.finish_review(),
+            KeyCode::Char('j') | KeyCode::Down => review.scroll += 1,
+            KeyCode::Char('k') | KeyCode::Up => review.scroll = review.scroll.saturating_sub(1),
+            _ => (),
+        }
+    }
+
+    fn handle_prompt_command(&mut self, command: Command) -> bool {
+        let Some((action, prompt)) = &mut self.prompt else {
+            return false;
+        };
+
+        match command {
+            Command::DeleteBackward => prompt.delete_backward(),
+            Command::DeleteForward => prompt.delete_forward(),
+            Command::MoveLeft => prompt.move_left(),
+            Command::MoveRight => prompt.move_right(),
+            Command::LineStart => prompt.move_start(),
+            Command::LineEnd => prompt.move_end(),
+            Command::ToggleSearchRegex | Command::ToggleSearchWholeWord
+                | Command::ToggleSearchCase | Command::TogglePreserveCase => {
+                let config = &mut self.config;
+
+                match command {
+                    Command::ToggleSearchRegex => config.regex = !config.regex,
+                    Command::ToggleSearchWholeWord => config.whole_word = !config.whole_word,
+                    Command::ToggleSearchCase => config.smart_case = !config.smart_case,
+                    _ => config.preserve_case = !config.preserve_case,
+                }
+
+                prompt.set_label(match action {
+                    PromptAction::Search => search::prompt_label("search files", *config, false),
+                    PromptAction::Replace => search::prompt_label("replace in files with", *config, true),
+                });
+            }
+            Command::PromptCancel => self.prompt = None,
+            Command::PromptAccept => {
+                let (action, prompt) = self.prompt.take().expect("Checked above");
+                self.accept_prompt(action, prompt.get_text());
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn handle_command(&mut self, command: Command) -> bool {
+        if self.prompt.is_some() {
+            return self.handle_prompt_command(command);
+        }
+
+        let page = self.list_height().max(1) as isize;
+        let selected = self.selected as isize;
+
+        match command {
+            Command::MoveUp => self.select(selected - 1),
+            Command::MoveDown => self.select(selected + 1),
+            Command::PageUp => self.select(selected - page),
+            Command::PageDown => self.select(selected + page),
+            Command::HalfPageUp => self.select(selected - page / 2),
+            Command::HalfPageDown => self.select(selected + page / 2),
+            Command::GotoFirstLine => self.select(0),
+            Command::GotoLastLine => self.select(isize::MAX),
+            Command::OpenResult => self.open_selected(),
+            Command::WorkspaceReplace => {
+                if self.search.is_none() || self.files.is_empty() {
+                    self.error = Some("No matches to replace".to_string());
+                    return true;
+                }
+
+                let label = search::prompt_label("replace in files with", self.config, true);
+                self.prompt = Some((PromptAction::Replace, Prompt::new(label)));
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn handle_key(&mut self, event: &KeyEvent) -> bool {
+        if self.review.is_some() {
+            self.handle_review_key(event);
+            return true;
+        }
+
+        let plain = !event.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+
+        match (&mut self.prompt, event.code) {
+            (Some((_, prompt)), KeyCode::Char(c)) if plain => {
+                prompt.insert(c.encode_utf8(&mut [0; 4]));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn handle_mouse(&mut self, event: &NodeMouseEvent) -> bool {
+        match event.kind {
+            // clicking the selected result again opens it
+            MouseEventKind::Down(MouseButton::Left) if self.review.is_none() => {
+                if event.y < 1 || event.y as usize > self.list_height() {
+                    return true;
+                }
+
+                let row = self.scroll + event.y as usize - 1;
+
+                if row < self.rows().len() {
+                    if row == self.selected {
+                        self.open_selected();
+                    }
+
+                    self.selected = row;
+                }
+
+                true
+            }
+            MouseEventKind::ScrollUp => {
+                match &mut self.review {
+                    Some(review) => review.scroll = review.scroll.saturating_sub(WHEEL_ROWS),
+                    None => self.scroll = self.scroll.saturating_sub(WHEEL_ROWS),
+                }
+
+                true
+            }
+            MouseEventKind::ScrollDown => {
+                match &mut self.review {
+                    Some(review) => review.scroll += WHEEL_ROWS,
+                    None => self.scroll = (self.scroll + WHEEL_ROWS).min(self.rows().len().saturating_sub(1)),
+                }
+
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn summary(&self) -> String {
+        let Some(search) = &self.search else {
+            return "Search the files under the working directory with /".to_string();
+        };
+
+        let matches: usize = self.files.iter().map(FileMatches::match_count).sum();
+        let found = format!("\"{}\": {} matches in {} files", search.get_pattern(), matches, self.files.len());
+
+        match self.searched {
+            Some(searched) => format!("{} ({} searched)", found, searched),
+            None => format!("{}, searching...", found),
+        }
+    }
+
+    fn draw_results(&mut self, drawer: &mut Drawer) {
+        let height = self.list_height();
+        let width = self.size.0 as usize;
+
+        // keep the selected row in view
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if height > 0 && self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+
+        drawer.draw_text(0, 0, &self.summary(), *CellStyle::default().set_attribute(Attribute::Bold));
+
+        let rows = self.rows();
+
+        for (index, row) in rows.iter().enumerate().skip(self.scroll).take(height) {
+            let y = (index - self.scroll + 1) as u16;
+            let selected = index == self.selected;
+
+            let base = match selected {
+                true => self.theme.selection,
+                false => CellStyle::default(),
+            };
+
+            if selected {
+                drawer.draw_text(0, y, &" ".repeat(width), base);
+            }
+
+            match *row {
+                Row::File(file) => {
+                    let matches = &self.files[file];
+                    let header = format!("{} ({})", matches.path.display(), matches.match_count());
+                    drawer.draw_text(0, y, &header, base.patch(self.theme.results_file));
+                }
+                Row::Line(file, line) => {
+                    let line = &self.files[file].lines[line];
+                    let number = format!("{:>width$} ", line.line + 1, width = LINE_NUMBER_WIDTH - 1);
+                    drawer.draw_text(0, y, &number, base.patch(self.theme.gutter));
+                    draw_line(drawer, LINE_NUMBER_WIDTH, y, &line.text, &line.ranges, base, self.theme.search_match);
+                }
+            }
+        }
+    }
+
+    fn draw_review(&mut self, drawer: &mut Drawer) {
+        let Some(review) = &mut self.review else {
+            return;
+        };
+
+        let Some(replace) = &review.current else {
+            let working = match review.accept_all {
+                true => "Applying the rest...",
+                false => "Working out replacements...",
+            };
+
+            drawer.draw_text(0, 0, working, *CellStyle::default().set_attribute(Attribute::Bold));
+            return;
+        };
+
+        let height = (self.size.1 as usize).saturating_sub(1);
+        let header = format!(
+            "{}: {} replacements ({}/{})",
+            replace.get_path().strip_prefix(&self.root).unwrap_or(replace.get_path()).display(),
+            replace.edit_count(),
+            review.index,
+            review.total,
+        );
+
+        drawer.draw_text(0, 0, &header, *CellStyle::default().set_attribute(Attribute::Bold));
+
+        let mut lines = Vec::new();
+
+        for hunk in replace.get_hunks() {
+            lines.push((format!("@@ line {}", hunk.line + 1), self.theme.diff_header));
+            lines.extend(hunk.removed.iter().map(|line| (format!("-{}", line), self.theme.diff_removed)));
+            lines.extend(hunk.added.iter().map(|line| (format!("+{}", line), self.theme.diff_added)));
+        }
+
+        review.scroll = review.scroll.min(lines.len().saturating_sub(height.saturating_sub(1)));
+
+        for (row, (line, style)) in lines.iter().skip(review.scroll).take(height.saturating_sub(1)).enumerate() {
+            draw_line(drawer, 0, row as u16 + 1, line, &[], *style, CellStyle::default());
+        }
+
+        drawer.draw_text(0, self.size.1, "apply? [y]es [n]o [a]ll [q]uit", CellStyle::default());
+    }
+}
+
+/// Draws a line of text from column `x`, with the byte ranges in `marked`
+/// in `marked_style`, cut off at the drawer's edge.
+fn draw_line(drawer: &mut Drawer, x: usize, y: u16, text: &str, marked: &[Range<usize>], style: CellStyle, marked_style: CellStyle) {
+    let width = drawer.boundaries.width as usize;
+    let mut display = x;
+    let mut byte = 0;
+
+    for grapheme in grapheme::graphemes(text) {
+        if display + grapheme.width > width {
+            break;
+        }
+
+        let cell_style = match marked.iter().any(|range| range.contains(&byte)) {
+            true => style.patch(marked_style),
+            false => style,
+        };
+
+        let c = match grapheme.text.chars().next() {
+            Some(c) if c.is_control() => if c == '\t' { ' ' } else { '?' },
+            Some(c) => c,
+            None => ' ',
+        };
+
+        drawer.draw_cell(display as u16, y, Cell { c, style: cell_style });
+
+        for offset in 1..grapheme.width {
+            drawer.draw_cell((display + offset) as u16, y, Cell::continuation());
+        }
+
+        display += grapheme.width;
+        byte += grapheme.text.len();
+    }
+}
+
+impl Widget for SearchResults {
+    fn draw(&mut self, drawer: &mut Drawer, _scroll: (u16, u16)) {
+        // the prompt or question takes the bottom row
+        let bottom_rows = (self.prompt.is_some() || self.review.is_some()) as u16;
+        self.size = (drawer.boundaries.width, drawer.boundaries.height.saturating_sub(bottom_rows));
+
+        match self.review {
+            Some(_) => self.draw_review(drawer),
+            None => self.draw_results(drawer),
+        }
+
+        if let Some((_, prompt)) = &self.prompt {
+            let x = drawer.draw_text(0, self.size.1, prompt.get_label(), CellStyle::default());
+            drawer.draw_text(x, self.size.1, prompt.get_text(), CellStyle::default());
+        }
+    }
+
+    fn handle_event(&mut self, event: &NodeEvent, phase: Phase) -> bool {
+        if phase != Phase::Target {
+            return false;
+        }
+
+        match event {
+            NodeEvent::Command(command) => self.handle_command(*command),
+            NodeEvent::Key(key) => self.handle_key(key),
+            NodeEvent::Paste(text) => match &mut self.prompt {
+                Some((_, prompt)) => {
+                    prompt.insert(text.lines().next().unwrap_or_default());
+                    true
+                }
+                None => false,
+            },
+            NodeEvent::Mouse(mouse) => self.handle_mouse(mouse),
+            _ => false,
+        }
+    }
+
+    /// Leaves keys unbound while a diff waits to be applied or skipped.
+    fn key_context(&self) -> Option<&'static str> {
+        match (&self.prompt, &self.review) {
+            (Some(_), _) => Some(PROMPT_CONTEXT),
+            (None, Some(_)) => None,
+            (None, None) => Some(RESULTS_CONTEXT),
+        }
+    }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        self.theme = theme.clone();
+    }
+
+    fn cursor(&self) -> Option<(u16, u16, CursorShape)> {
+        let (_, prompt) = self.prompt.as_ref()?;
+        let x = prompt.get_label().chars().count() + prompt.get_cursor();
+
+        Some((x.min(u16::MAX as usize) as u16, self.size.1, CursorShape::Line))
+    }
+}